@@ -2,13 +2,13 @@ extern crate sc_midi;
 
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
+use std::io::Read;
 
-use sc_midi::MidiEvent;
-use std::borrow::Borrow;
+use sc_midi::{MessageFilter, MessageKind, Processor};
 
 fn main() -> io::Result<()> {
     let mut midi_reader = sc_midi::MidiReader::new();
+    let mut filter = MessageFilter::block(&[MessageKind::PitchBend]);
     let mut file = File::open("/dev/midi2")?;
 
     loop {
@@ -17,13 +17,11 @@ fn main() -> io::Result<()> {
         let count = file.read(&mut buf)?;
         if count > 0 {
             for byte in buf[..count].iter() {
-                midi_reader
-                    .handle_byte(*byte)
-                    .filter(|event| match event {
-                        MidiEvent::PitchBend(_, _) => false,
-                        _ => true,
-                    })
-                    .map(|event| println!("{}", event));
+                if let Some(event) = midi_reader.handle_byte(*byte) {
+                    for event in filter.process(event).iter() {
+                        println!("{}", event);
+                    }
+                }
             }
         }
     }
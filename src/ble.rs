@@ -0,0 +1,433 @@
+use crate::read::SYSTEM_RT_MASK;
+use crate::{List, MidiEvent, MidiReader};
+
+///
+/// BLE-MIDI timestamp in milliseconds (13 bits, wraps every 8.192 seconds)
+///
+pub type BleTimestamp = u16;
+
+///
+/// Packet size for the default ATT MTU of 23 bytes
+///
+pub const BLE_DEFAULT_PACKET_SIZE: usize = 20;
+
+///
+/// Smallest packet size, fits a System Exclusive Start with a 3 byte ID
+///
+pub const BLE_MIN_PACKET_SIZE: usize = 6;
+
+///
+/// **BLE-MIDI packet**
+///
+/// Holds up to `N` bytes.
+///
+pub type BlePacket<const N: usize = BLE_DEFAULT_PACKET_SIZE> = List<u8, N>;
+
+// Header and timestamp bytes
+const HEADER: u8 = 0x80;
+const TIMESTAMP_HIGH_MASK: u8 = 0x3F;
+const TIMESTAMP_LOW_MASK: u8 = 0x7F;
+
+fn timestamp_high(timestamp: BleTimestamp) -> u8 {
+    (timestamp >> 7) as u8 & TIMESTAMP_HIGH_MASK
+}
+
+fn timestamp_low(timestamp: BleTimestamp) -> u8 {
+    timestamp as u8 & TIMESTAMP_LOW_MASK
+}
+
+///
+/// **BLE-MIDI Reader**
+///
+/// Decodes BLE-MIDI packets into timestamped events. The inner MIDI bytes are
+/// parsed by a [`MidiReader`] kept between packets so running status and
+/// System Exclusive messages continue across packets.
+///
+/// System Exclusive data continuing at the start of a packet has no
+/// timestamp byte and is given the time of the packet header.
+///
+#[derive(Default)]
+pub struct BleMidiReader {
+    reader: MidiReader,
+}
+
+impl BleMidiReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// **Handle a packet**
+    ///
+    /// Adds the events completed by the packet to `events`, eg a `Vec` or a
+    /// [`List`]; packets without a valid header are ignored.
+    ///
+    pub fn handle_packet(
+        &mut self,
+        packet: &[u8],
+        events: &mut impl Extend<(BleTimestamp, MidiEvent)>,
+    ) {
+        let (high, bytes) = match packet.split_first() {
+            Some((header, bytes)) if header & 0xC0 == HEADER => {
+                (*header & TIMESTAMP_HIGH_MASK, bytes)
+            }
+            _ => return,
+        };
+
+        let mut high = high;
+        let mut low = None;
+        let mut timestamp = (high as BleTimestamp) << 7;
+        let mut after_timestamp = false;
+        for &byte in bytes {
+            if byte & 0x80 > 0 && !after_timestamp {
+                // Timestamp, a low value less than the previous one has wrapped
+                let value = byte & TIMESTAMP_LOW_MASK;
+                if low.is_some_and(|previous| value < previous) {
+                    high = (high + 1) & TIMESTAMP_HIGH_MASK;
+                }
+                low = Some(value);
+                timestamp = ((high as BleTimestamp) << 7) | value as BleTimestamp;
+                after_timestamp = true;
+            } else {
+                after_timestamp = false;
+                if let Some(event) = self.reader.handle_byte(byte) {
+                    events.extend(Some((timestamp, event)));
+                }
+            }
+        }
+    }
+}
+
+///
+/// **BLE-MIDI Writer**
+///
+/// Packs timestamped events into BLE-MIDI packets of up to the packet size.
+/// Running status is used between channel messages within a packet (each
+/// packet starts with a full status byte) and System Exclusive messages are
+/// continued over as many packets as required.
+///
+/// A new packet is started when an event does not fit or when its timestamp
+/// cannot be expressed relative to the packet header.
+///
+/// Packets hold up to `N` bytes, the packet size can be reduced to match the
+/// connection with [`BleMidiWriter::with_packet_size`].
+///
+#[derive(Debug, Clone)]
+pub struct BleMidiWriter<const N: usize = BLE_DEFAULT_PACKET_SIZE> {
+    packet_size: usize,
+    packet: BlePacket<N>, // Packet being filled
+    high: u8,             // Timestamp high bits of the packet
+    low: u8,              // Last timestamp low bits written
+    status: Option<u8>,   // Running status within the packet
+}
+
+impl<const N: usize> Default for BleMidiWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BleMidiWriter<N> {
+    pub fn new() -> Self {
+        Self {
+            packet_size: N,
+            packet: BlePacket::default(),
+            high: 0,
+            low: 0,
+            status: None,
+        }
+    }
+
+    ///
+    /// Maximum size of a packet (ATT MTU - 3), limited to
+    /// `BLE_MIN_PACKET_SIZE..=N`
+    ///
+    pub fn with_packet_size(mut self, size: usize) -> Self {
+        self.packet_size = size.max(BLE_MIN_PACKET_SIZE).min(N);
+        self
+    }
+
+    ///
+    /// **Encode an event**
+    ///
+    /// Returns the packet completed if the event does not fit in the packet
+    /// being filled.
+    ///
+    pub fn write(&mut self, timestamp: BleTimestamp, event: &MidiEvent) -> Option<BlePacket<N>> {
+        let bytes = event.to_bytes();
+        let mut completed = None;
+
+        if let MidiEvent::SystemExclusiveData(byte) = event {
+            // Continued data has no timestamp but needs a packet header
+            if self.packet.is_empty() || self.packet.len() + 1 > self.packet_size {
+                completed = self.start_packet(timestamp);
+            }
+            self.packet.push(*byte);
+            return completed;
+        }
+
+        let status = bytes[0];
+        let running = status < 0xF0 && self.status == Some(status);
+        let len = 1 + if running {
+            bytes.len() - 1
+        } else {
+            bytes.len()
+        };
+        if self.packet.is_empty()
+            || self.packet.len() + len > self.packet_size
+            || !self.follows(timestamp)
+        {
+            completed = self.start_packet(timestamp);
+        }
+
+        self.packet.push(HEADER | timestamp_low(timestamp));
+        self.low = timestamp_low(timestamp);
+        let bytes = if running { &bytes[1..] } else { &bytes[..] };
+        self.packet.extend(bytes.iter().copied());
+
+        if status < 0xF0 {
+            self.status = Some(status);
+        } else if status & SYSTEM_RT_MASK != SYSTEM_RT_MASK {
+            self.status = None;
+        }
+        completed
+    }
+
+    ///
+    /// **Take the partially filled packet**
+    ///
+    pub fn flush(&mut self) -> Option<BlePacket<N>> {
+        let packet = self.packet;
+        self.packet.clear();
+        Some(packet).filter(|packet| !packet.is_empty())
+    }
+
+    ///
+    /// Timestamp can follow those already in the packet
+    ///
+    fn follows(&mut self, timestamp: BleTimestamp) -> bool {
+        let (high, low) = (timestamp_high(timestamp), timestamp_low(timestamp));
+        if high == self.high && low >= self.low {
+            true
+        } else if high == (self.high + 1) & TIMESTAMP_HIGH_MASK && low < self.low {
+            // Implied by the low bits wrapping
+            self.high = high;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn start_packet(&mut self, timestamp: BleTimestamp) -> Option<BlePacket<N>> {
+        let completed = self.flush();
+        self.high = timestamp_high(timestamp);
+        self.low = 0;
+        self.status = None;
+        self.packet.push(HEADER | self.high);
+        completed
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::ble::{BleMidiReader, BleMidiWriter, BleTimestamp};
+    use crate::{List, MidiEvent, SysExID};
+
+    fn sys_ex(data: &[u8]) -> Vec<MidiEvent> {
+        let mut events = vec![MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D))];
+        events.extend(
+            data.iter()
+                .map(|byte| MidiEvent::SystemExclusiveData(*byte)),
+        );
+        events.push(MidiEvent::SystemExclusiveEnd);
+        events
+    }
+
+    fn read(target: &mut BleMidiReader, packet: &[u8]) -> Vec<(BleTimestamp, MidiEvent)> {
+        let mut events = Vec::new();
+        target.handle_packet(packet, &mut events);
+        events
+    }
+
+    ///
+    /// Write events and flush, returning the packets
+    ///
+    fn write<const N: usize>(
+        target: &mut BleMidiWriter<N>,
+        events: &[(BleTimestamp, MidiEvent)],
+    ) -> Vec<Vec<u8>> {
+        let mut packets: Vec<Vec<u8>> = events
+            .iter()
+            .filter_map(|(timestamp, event)| target.write(*timestamp, event))
+            .map(|packet| packet.to_vec())
+            .collect();
+        packets.extend(target.flush().map(|packet| packet.to_vec()));
+        packets
+    }
+
+    #[test]
+    fn handle_packet__running_status_and_timestamps() {
+        let mut target = BleMidiReader::new();
+
+        // Second note uses running status, the third timestamp wraps
+        let actual = read(
+            &mut target,
+            &[0x81, 0xF0, 0x90, 60, 100, 0xF8, 62, 100, 0x82, 64, 0],
+        );
+
+        assert_eq!(
+            actual,
+            vec![
+                (0x00F0, MidiEvent::NoteOn(0, 60, 100)),
+                (0x00F8, MidiEvent::NoteOn(0, 62, 100)),
+                (0x0102, MidiEvent::NoteOn(0, 64, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_packet__running_status_across_packets() {
+        let mut target = BleMidiReader::new();
+        read(&mut target, &[0x80, 0x80, 0xB0, 7, 100]);
+
+        let mut actual = List::<(BleTimestamp, MidiEvent), 4>::with_filler((0, MidiEvent::Clock));
+        target.handle_packet(&[0x80, 0x81, 7, 90], &mut actual);
+
+        assert_eq!(*actual, [(1, MidiEvent::ControllerChange(0, 7, 90))]);
+    }
+
+    #[test]
+    fn handle_packet__sys_ex_continuation() {
+        let mut target = BleMidiReader::new();
+
+        let mut actual = read(&mut target, &[0x80, 0x80, 0xF0, 0x7D, 1, 2]);
+        actual.extend(read(&mut target, &[0x80, 3, 0x81, 0xF8, 4, 0x82, 0xF7]));
+
+        let events: Vec<MidiEvent> = actual.into_iter().map(|(_, event)| event).collect();
+        let mut expected = sys_ex(&[1, 2, 3, 4]);
+        expected.insert(4, MidiEvent::Clock);
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn handle_packet__where_invalid_header() {
+        let mut target = BleMidiReader::new();
+
+        let actual = read(&mut target, &[0x40, 0x80, 0xF8]);
+
+        assert_eq!(actual, vec![]);
+    }
+
+    #[test]
+    fn write__running_status_within_packet() {
+        let mut target = BleMidiWriter::<20>::new();
+
+        let actual = write(
+            &mut target,
+            &[
+                (0x0101, MidiEvent::NoteOn(0, 60, 100)),
+                (0x0102, MidiEvent::NoteOn(0, 62, 100)),
+                (0x0102, MidiEvent::Clock),
+                (0x0103, MidiEvent::NoteOn(0, 64, 100)),
+            ],
+        );
+
+        assert_eq!(
+            actual,
+            vec![vec![
+                0x82, 0x81, 0x90, 60, 100, 0x82, 62, 100, 0x82, 0xF8, 0x83, 64, 100
+            ]]
+        );
+    }
+
+    #[test]
+    fn write__where_timestamp_not_following() {
+        let mut target = BleMidiWriter::<20>::new();
+
+        let actual = write(
+            &mut target,
+            &[(0x0010, MidiEvent::Clock), (0x0005, MidiEvent::Clock)],
+        );
+
+        assert_eq!(actual, vec![vec![0x80, 0x90, 0xF8], vec![0x80, 0x85, 0xF8]]);
+    }
+
+    #[test]
+    fn write__where_flushed_within_sys_ex() {
+        let mut target = BleMidiWriter::<20>::new();
+        let mut actual = write(
+            &mut target,
+            &[
+                (0x0001, MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D))),
+                (0x0001, MidiEvent::SystemExclusiveData(1)),
+            ],
+        );
+
+        actual.extend(write(
+            &mut target,
+            &[
+                (0x0082, MidiEvent::SystemExclusiveData(2)),
+                (0x0082, MidiEvent::SystemExclusiveEnd),
+            ],
+        ));
+
+        assert_eq!(
+            actual,
+            vec![vec![0x80, 0x81, 0xF0, 0x7D, 1], vec![0x81, 2, 0x82, 0xF7]]
+        );
+    }
+
+    #[test]
+    fn with_packet_size__fits_sys_ex_start_with_word_id() {
+        let mut target = BleMidiWriter::<20>::new().with_packet_size(1);
+
+        let actual = write(
+            &mut target,
+            &[
+                (
+                    0x0001,
+                    MidiEvent::SystemExclusiveStart(SysExID::Word(0x0082)),
+                ),
+                (0x0001, MidiEvent::SystemExclusiveEnd),
+            ],
+        );
+
+        assert_eq!(
+            actual,
+            vec![
+                vec![0x80, 0x81, 0xF0, 0, 0x01, 0x02],
+                vec![0x80, 0x81, 0xF7]
+            ]
+        );
+    }
+
+    #[test]
+    fn write__round_trips() {
+        let mut events = vec![MidiEvent::ProgramChange(1, 3)];
+        events.extend(sys_ex(&(0..30).collect::<Vec<u8>>()));
+        events.push(MidiEvent::NoteOn(1, 60, 100));
+        events.push(MidiEvent::NoteOn(1, 61, 100));
+        let mut writer = BleMidiWriter::<20>::new();
+        let mut target = BleMidiReader::new();
+
+        let timestamped: Vec<(BleTimestamp, MidiEvent)> = events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| (0x1FF0 + index as u16, *event))
+            .collect();
+        let packets = write(&mut writer, &timestamped);
+        let actual: Vec<(u16, MidiEvent)> = packets
+            .iter()
+            .flat_map(|packet| read(&mut target, packet))
+            .collect();
+
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|packet| packet.len() <= 20));
+        assert_eq!(
+            actual.iter().map(|(_, event)| *event).collect::<Vec<_>>(),
+            events
+        );
+        assert_eq!(actual.last().unwrap().0, (0x1FF0 + 34) & 0x1FFF);
+    }
+}
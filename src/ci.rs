@@ -0,0 +1,628 @@
+use crate::{u14, u7, List, MidiEvent, MidiReader, SysExID};
+
+/// Universal Non-Real Time System Exclusive ID
+pub const UNIVERSAL_NON_REALTIME: u7 = 0x7E;
+/// Universal SysEx Sub-ID #1 for MIDI-CI
+pub const SUB_ID_MIDI_CI: u7 = 0x0D;
+/// MIDI-CI message version sent by this implementation
+pub const CI_VERSION: u7 = 0x02;
+/// Broadcast MUID
+pub const BROADCAST_MUID: Muid = 0x0FFF_FFFF;
+/// Device ID addressing the whole function block
+pub const DEVICE_ID_FUNCTION_BLOCK: u7 = 0x7F;
+/// Largest message read by a [`CiReader`], in data bytes
+pub const MAX_CI_MESSAGE_SIZE: usize = 256;
+/// Most enabled (or disabled) profiles held by a Profile Inquiry Reply
+pub const MAX_PROFILES: usize = 16;
+/// Longest NAK message text held
+pub const MAX_NAK_TEXT: usize = 64;
+
+// Capability Inquiry Category bitmap
+pub const CATEGORY_PROFILE_CONFIGURATION: u8 = 0x04;
+pub const CATEGORY_PROPERTY_EXCHANGE: u8 = 0x08;
+pub const CATEGORY_PROCESS_INQUIRY: u8 = 0x10;
+
+// Sub-ID #2
+const CI_PROFILE_INQUIRY: u7 = 0x20;
+const CI_PROFILE_INQUIRY_REPLY: u7 = 0x21;
+const CI_SET_PROFILE_ON: u7 = 0x22;
+const CI_SET_PROFILE_OFF: u7 = 0x23;
+const CI_PROFILE_ENABLED: u7 = 0x24;
+const CI_PROFILE_DISABLED: u7 = 0x25;
+const CI_PE_CAPABILITIES: u7 = 0x30;
+const CI_PE_CAPABILITIES_REPLY: u7 = 0x31;
+const CI_DISCOVERY: u7 = 0x70;
+const CI_DISCOVERY_REPLY: u7 = 0x71;
+const CI_INVALIDATE_MUID: u7 = 0x7E;
+const CI_NAK: u7 = 0x7F;
+
+///
+/// MIDI-CI Unique ID (28 bits)
+///
+pub type Muid = u32;
+
+///
+/// **Device identity and capabilities sent with Discovery**
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub manufacturer: [u7; 3], // SysEx ID (1 byte IDs are followed by two zeros)
+    pub family: u14,
+    pub model: u14,
+    pub revision: [u7; 4],
+    pub categories: u8, // Capability Inquiry Category bitmap
+    pub max_sysex: u32, // Receivable maximum SysEx message size
+    pub output_path: u7,
+}
+
+///
+/// Profile ID (5 bytes)
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ProfileId(pub [u7; 5]);
+
+///
+/// **Negative acknowledgement**
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Nak {
+    pub original: u7, // Sub-ID #2 of the message being answered
+    pub status: u7,
+    pub status_data: u7,
+    pub details: [u7; 5],
+    pub text: List<u7, MAX_NAK_TEXT>, // Longer text is truncated
+}
+
+///
+/// Property Exchange capabilities
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PeCapabilities {
+    pub requests: u7, // Number of simultaneous requests supported
+    pub major_version: u7,
+    pub minor_version: u7,
+}
+
+///
+/// **MIDI-CI message payload**
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CiPayload {
+    Discovery(DeviceInfo),
+    DiscoveryReply {
+        info: DeviceInfo,
+        function_block: u7,
+    },
+    InvalidateMuid(Muid),
+    Nak(Nak),
+    ProfileInquiry,
+    ProfileInquiryReply {
+        enabled: List<ProfileId, MAX_PROFILES>,
+        disabled: List<ProfileId, MAX_PROFILES>,
+    },
+    SetProfileOn {
+        profile: ProfileId,
+        channels: u14,
+    },
+    SetProfileOff(ProfileId),
+    ProfileEnabled {
+        profile: ProfileId,
+        channels: u14,
+    },
+    ProfileDisabled {
+        profile: ProfileId,
+        channels: u14,
+    },
+    PeCapabilities(PeCapabilities),
+    PeCapabilitiesReply(PeCapabilities),
+}
+
+///
+/// **MIDI-CI message**
+///
+/// A Universal SysEx message with Sub-ID #1 of 0x0D. Fields added by later
+/// versions of MIDI-CI are decoded as 0 when missing and only encoded when
+/// `version` is 2 or later.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CiMessage {
+    pub device_id: u7, // Channel (0x00-0x0F), Group (0x7E) or Function Block (0x7F)
+    pub version: u7,
+    pub source: Muid,
+    pub destination: Muid,
+    pub payload: CiPayload,
+}
+
+// Reads the 7-bit fields of a message body
+struct Fields<'a> {
+    data: &'a [u7],
+}
+
+impl<'a> Fields<'a> {
+    fn byte(&mut self) -> Option<u7> {
+        let (first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*first)
+    }
+
+    fn byte_or_zero(&mut self) -> u7 {
+        self.byte().unwrap_or(0)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Option<[u7; N]> {
+        if self.data.len() < N {
+            return None;
+        }
+        let mut result = [0; N];
+        result.copy_from_slice(&self.data[..N]);
+        self.data = &self.data[N..];
+        Some(result)
+    }
+
+    // Little-endian 7-bit groups
+    fn number(&mut self, len: usize) -> Option<u32> {
+        (0..len).try_fold(0, |value, index| {
+            self.byte()
+                .map(|byte| value | ((byte as u32 & 0x7F) << (7 * index)))
+        })
+    }
+
+    // Profiles beyond the capacity are dropped
+    fn profiles(&mut self) -> Option<List<ProfileId, MAX_PROFILES>> {
+        let count = self.number(2)?;
+        let mut profiles = List::default();
+        for _ in 0..count {
+            profiles.push(ProfileId(self.bytes()?));
+        }
+        Some(profiles)
+    }
+}
+
+fn push_number(bytes: &mut impl Extend<u7>, value: u32, len: usize) {
+    bytes.extend((0..len).map(|index| (value >> (7 * index)) as u7 & 0x7F));
+}
+
+impl DeviceInfo {
+    fn decode(fields: &mut Fields) -> Option<Self> {
+        Some(Self {
+            manufacturer: fields.bytes()?,
+            family: fields.number(2)? as u14,
+            model: fields.number(2)? as u14,
+            revision: fields.bytes()?,
+            categories: fields.byte()?,
+            max_sysex: fields.number(4)?,
+            output_path: fields.byte_or_zero(),
+        })
+    }
+
+    fn encode(&self, bytes: &mut impl Extend<u7>, version: u7) {
+        bytes.extend(self.manufacturer);
+        push_number(bytes, self.family as u32, 2);
+        push_number(bytes, self.model as u32, 2);
+        bytes.extend(self.revision);
+        bytes.extend(Some(self.categories & 0x7F));
+        push_number(bytes, self.max_sysex, 4);
+        if version >= 2 {
+            bytes.extend(Some(self.output_path));
+        }
+    }
+}
+
+impl PeCapabilities {
+    fn decode(fields: &mut Fields) -> Option<Self> {
+        Some(Self {
+            requests: fields.byte()?,
+            major_version: fields.byte_or_zero(),
+            minor_version: fields.byte_or_zero(),
+        })
+    }
+
+    fn encode(&self, bytes: &mut impl Extend<u7>, version: u7) {
+        bytes.extend(Some(self.requests));
+        if version >= 2 {
+            bytes.extend([self.major_version, self.minor_version]);
+        }
+    }
+}
+
+impl CiMessage {
+    ///
+    /// **Decode a message**
+    ///
+    /// `data` is the SysEx data following the Start byte and excluding the
+    /// End byte (ie starting with the Universal Non-Real Time ID). Returns
+    /// None if the data is not a supported MIDI-CI message.
+    ///
+    pub fn decode(data: &[u7]) -> Option<Self> {
+        let mut fields = Fields { data };
+        if fields.byte()? != UNIVERSAL_NON_REALTIME {
+            return None;
+        }
+        let device_id = fields.byte()?;
+        if fields.byte()? != SUB_ID_MIDI_CI {
+            return None;
+        }
+        let sub_id = fields.byte()?;
+        let version = fields.byte()?;
+        let source = fields.number(4)?;
+        let destination = fields.number(4)?;
+
+        let payload = match sub_id {
+            CI_DISCOVERY => CiPayload::Discovery(DeviceInfo::decode(&mut fields)?),
+            CI_DISCOVERY_REPLY => CiPayload::DiscoveryReply {
+                info: DeviceInfo::decode(&mut fields)?,
+                function_block: fields.byte_or_zero(),
+            },
+            CI_INVALIDATE_MUID => CiPayload::InvalidateMuid(fields.number(4)?),
+            CI_NAK => CiPayload::Nak(Nak {
+                original: fields.byte_or_zero(),
+                status: fields.byte_or_zero(),
+                status_data: fields.byte_or_zero(),
+                details: fields.bytes().unwrap_or_default(),
+                text: match fields.number(2) {
+                    Some(len) => fields.data.get(..len as usize)?.iter().copied().collect(),
+                    None => List::default(),
+                },
+            }),
+            CI_PROFILE_INQUIRY => CiPayload::ProfileInquiry,
+            CI_PROFILE_INQUIRY_REPLY => CiPayload::ProfileInquiryReply {
+                enabled: fields.profiles()?,
+                disabled: fields.profiles()?,
+            },
+            CI_SET_PROFILE_ON => CiPayload::SetProfileOn {
+                profile: ProfileId(fields.bytes()?),
+                channels: fields.number(2).unwrap_or(0) as u14,
+            },
+            CI_SET_PROFILE_OFF => CiPayload::SetProfileOff(ProfileId(fields.bytes()?)),
+            CI_PROFILE_ENABLED => CiPayload::ProfileEnabled {
+                profile: ProfileId(fields.bytes()?),
+                channels: fields.number(2).unwrap_or(0) as u14,
+            },
+            CI_PROFILE_DISABLED => CiPayload::ProfileDisabled {
+                profile: ProfileId(fields.bytes()?),
+                channels: fields.number(2).unwrap_or(0) as u14,
+            },
+            CI_PE_CAPABILITIES => CiPayload::PeCapabilities(PeCapabilities::decode(&mut fields)?),
+            CI_PE_CAPABILITIES_REPLY => {
+                CiPayload::PeCapabilitiesReply(PeCapabilities::decode(&mut fields)?)
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            device_id,
+            version,
+            source,
+            destination,
+            payload,
+        })
+    }
+
+    ///
+    /// **Encode the SysEx data of the message**
+    ///
+    /// The inverse of [`CiMessage::decode`]; excludes the Start and End bytes.
+    ///
+    pub fn encode(&self, bytes: &mut impl Extend<u7>) {
+        let version = self.version;
+        let sub_id = match self.payload {
+            CiPayload::Discovery(_) => CI_DISCOVERY,
+            CiPayload::DiscoveryReply { .. } => CI_DISCOVERY_REPLY,
+            CiPayload::InvalidateMuid(_) => CI_INVALIDATE_MUID,
+            CiPayload::Nak(_) => CI_NAK,
+            CiPayload::ProfileInquiry => CI_PROFILE_INQUIRY,
+            CiPayload::ProfileInquiryReply { .. } => CI_PROFILE_INQUIRY_REPLY,
+            CiPayload::SetProfileOn { .. } => CI_SET_PROFILE_ON,
+            CiPayload::SetProfileOff(_) => CI_SET_PROFILE_OFF,
+            CiPayload::ProfileEnabled { .. } => CI_PROFILE_ENABLED,
+            CiPayload::ProfileDisabled { .. } => CI_PROFILE_DISABLED,
+            CiPayload::PeCapabilities(_) => CI_PE_CAPABILITIES,
+            CiPayload::PeCapabilitiesReply(_) => CI_PE_CAPABILITIES_REPLY,
+        };
+
+        bytes.extend([
+            UNIVERSAL_NON_REALTIME,
+            self.device_id & 0x7F,
+            SUB_ID_MIDI_CI,
+            sub_id,
+            version,
+        ]);
+        push_number(bytes, self.source, 4);
+        push_number(bytes, self.destination, 4);
+
+        match &self.payload {
+            CiPayload::Discovery(info) => info.encode(bytes, version),
+            CiPayload::DiscoveryReply {
+                info,
+                function_block,
+            } => {
+                info.encode(bytes, version);
+                if version >= 2 {
+                    bytes.extend(Some(*function_block));
+                }
+            }
+            CiPayload::InvalidateMuid(muid) => push_number(bytes, *muid, 4),
+            CiPayload::Nak(nak) => {
+                if version >= 2 {
+                    bytes.extend([nak.original, nak.status, nak.status_data]);
+                    bytes.extend(nak.details);
+                    push_number(bytes, nak.text.len() as u32, 2);
+                    bytes.extend(nak.text.iter().map(|byte| byte & 0x7F));
+                }
+            }
+            CiPayload::ProfileInquiry => {}
+            CiPayload::ProfileInquiryReply { enabled, disabled } => {
+                for profiles in [enabled, disabled] {
+                    push_number(bytes, profiles.len() as u32, 2);
+                    profiles.iter().for_each(|profile| bytes.extend(profile.0));
+                }
+            }
+            CiPayload::SetProfileOff(profile) => {
+                bytes.extend(profile.0);
+                if version >= 2 {
+                    push_number(bytes, 0, 2);
+                }
+            }
+            CiPayload::SetProfileOn { profile, channels }
+            | CiPayload::ProfileEnabled { profile, channels }
+            | CiPayload::ProfileDisabled { profile, channels } => {
+                bytes.extend(profile.0);
+                if version >= 2 {
+                    push_number(bytes, *channels as u32, 2);
+                }
+            }
+            CiPayload::PeCapabilities(capabilities)
+            | CiPayload::PeCapabilitiesReply(capabilities) => capabilities.encode(bytes, version),
+        }
+    }
+
+    ///
+    /// **Encode as a complete SysEx byte stream**
+    ///
+    pub fn to_bytes(&self, bytes: &mut impl Extend<u8>) {
+        bytes.extend(Some(0xF0));
+        self.encode(bytes);
+        bytes.extend(Some(0xF7));
+    }
+
+    ///
+    /// **Encode as SysEx events**
+    ///
+    pub fn to_events(&self, events: &mut impl Extend<MidiEvent>) {
+        let mut bytes = List::<u8, { MAX_CI_MESSAGE_SIZE + 2 }>::default();
+        self.to_bytes(&mut bytes);
+        let mut reader = MidiReader::new();
+        events.extend(bytes.iter().filter_map(|byte| reader.handle_byte(*byte)));
+    }
+}
+
+///
+/// **MIDI-CI Reader**
+///
+/// Collects Universal Non-Real Time SysEx events (eg from a [`MidiReader`])
+/// and decodes complete MIDI-CI messages. Other SysEx messages, and those
+/// with more than [`MAX_CI_MESSAGE_SIZE`] data bytes, are ignored.
+///
+#[derive(Debug, Clone, Default)]
+pub struct CiReader {
+    data: List<u7, MAX_CI_MESSAGE_SIZE>,
+    active: bool,
+    max_size: Option<usize>,
+}
+
+impl CiReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Ignore messages with more than `max_size` data bytes
+    ///
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// Returns a message when a MIDI-CI SysEx is complete.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> Option<CiMessage> {
+        match *event {
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(UNIVERSAL_NON_REALTIME)) => {
+                self.data.clear();
+                self.data.push(UNIVERSAL_NON_REALTIME);
+                self.active = true;
+            }
+            MidiEvent::SystemExclusiveStart(_) => self.active = false,
+            MidiEvent::SystemExclusiveData(byte) if self.active => {
+                if self.data.is_full() || self.max_size.is_some_and(|max| self.data.len() >= max) {
+                    self.active = false;
+                } else {
+                    self.data.push(byte);
+                }
+            }
+            MidiEvent::SystemExclusiveEnd if self.active => {
+                self.active = false;
+                return CiMessage::decode(&self.data);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::ci::{
+        CiMessage, CiPayload, CiReader, DeviceInfo, Nak, PeCapabilities, ProfileId, BROADCAST_MUID,
+        CATEGORY_PROFILE_CONFIGURATION, CATEGORY_PROPERTY_EXCHANGE,
+    };
+    use crate::{u7, MidiReader};
+
+    fn device_info() -> DeviceInfo {
+        DeviceInfo {
+            manufacturer: [0x7D, 0x00, 0x00],
+            family: 0x0102,
+            model: 0x0304,
+            revision: [1, 2, 3, 4],
+            categories: CATEGORY_PROFILE_CONFIGURATION | CATEGORY_PROPERTY_EXCHANGE,
+            max_sysex: 512,
+            output_path: 0,
+        }
+    }
+
+    fn encode(message: &CiMessage) -> Vec<u7> {
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes);
+        bytes
+    }
+
+    fn message(payload: CiPayload) -> CiMessage {
+        CiMessage {
+            device_id: 0x7F,
+            version: 2,
+            source: 0x0123_4567,
+            destination: BROADCAST_MUID,
+            payload,
+        }
+    }
+
+    #[test]
+    fn encode__discovery() {
+        let target = message(CiPayload::Discovery(device_info()));
+
+        let actual = encode(&target);
+
+        assert_eq!(
+            actual,
+            vec![
+                0x7E, 0x7F, 0x0D, 0x70, 0x02, // Header
+                0x67, 0x0A, 0x0D, 0x09, // Source MUID
+                0x7F, 0x7F, 0x7F, 0x7F, // Destination MUID
+                0x7D, 0x00, 0x00, // Manufacturer
+                0x02, 0x02, 0x04, 0x06, // Family, Model
+                1, 2, 3, 4,    // Revision
+                0x0C, // Categories
+                0x00, 0x04, 0x00, 0x00, // Max SysEx size
+                0x00, // Output path
+            ]
+        );
+    }
+
+    #[test]
+    fn decode__round_trips() {
+        let messages = vec![
+            message(CiPayload::Discovery(device_info())),
+            message(CiPayload::DiscoveryReply {
+                info: device_info(),
+                function_block: 3,
+            }),
+            message(CiPayload::InvalidateMuid(0x0765_4321)),
+            message(CiPayload::Nak(Nak {
+                original: 0x34,
+                status: 0x01,
+                status_data: 0,
+                details: [0; 5],
+                text: b"Busy".iter().copied().collect(),
+            })),
+            message(CiPayload::ProfileInquiry),
+            message(CiPayload::ProfileInquiryReply {
+                enabled: [ProfileId([0x7E, 0, 1, 2, 3])].iter().copied().collect(),
+                disabled: [ProfileId([0x7E, 0, 1, 2, 4]), ProfileId([1, 2, 3, 4, 5])]
+                    .iter()
+                    .copied()
+                    .collect(),
+            }),
+            message(CiPayload::SetProfileOn {
+                profile: ProfileId([0x7E, 0, 1, 2, 3]),
+                channels: 1,
+            }),
+            message(CiPayload::SetProfileOff(ProfileId([0x7E, 0, 1, 2, 3]))),
+            message(CiPayload::ProfileEnabled {
+                profile: ProfileId([0x7E, 0, 1, 2, 3]),
+                channels: 16,
+            }),
+            message(CiPayload::ProfileDisabled {
+                profile: ProfileId([0x7E, 0, 1, 2, 3]),
+                channels: 0,
+            }),
+            message(CiPayload::PeCapabilities(PeCapabilities {
+                requests: 4,
+                major_version: 0,
+                minor_version: 0,
+            })),
+            message(CiPayload::PeCapabilitiesReply(PeCapabilities {
+                requests: 1,
+                major_version: 0,
+                minor_version: 0,
+            })),
+        ];
+
+        for expected in messages {
+            let actual = CiMessage::decode(&encode(&expected));
+
+            assert_eq!(actual, Some(expected));
+        }
+    }
+
+    #[test]
+    fn decode__version_1_discovery() {
+        let mut target = message(CiPayload::Discovery(DeviceInfo {
+            output_path: 5,
+            ..device_info()
+        }));
+        target.version = 1;
+
+        let actual = CiMessage::decode(&encode(&target)).unwrap();
+
+        // Output path is not part of a version 1 message
+        assert_eq!(
+            actual.payload,
+            CiPayload::Discovery(DeviceInfo {
+                output_path: 0,
+                ..device_info()
+            })
+        );
+    }
+
+    #[test]
+    fn decode__where_not_midi_ci() {
+        assert_eq!(CiMessage::decode(&[0x7E, 0x7F, 0x06, 0x01]), None);
+        assert_eq!(CiMessage::decode(&[0x7E, 0x7F, 0x0D]), None);
+    }
+
+    #[test]
+    fn handle_event__from_midi_reader() {
+        let expected = message(CiPayload::ProfileInquiry);
+        let mut bytes = vec![0xF0, 0x43, 0x01, 0xF7];
+        expected.to_bytes(&mut bytes);
+        let mut reader = MidiReader::new();
+        let mut target = CiReader::new();
+
+        let actual: Vec<CiMessage> = bytes
+            .iter()
+            .filter_map(|byte| reader.handle_byte(*byte))
+            .filter_map(|event| target.handle_event(&event))
+            .collect();
+
+        assert_eq!(actual, vec![expected]);
+    }
+
+    #[test]
+    fn handle_event__where_too_large() {
+        let mut target = CiReader::new().with_max_size(8);
+
+        let mut events = Vec::new();
+        message(CiPayload::ProfileInquiry).to_events(&mut events);
+
+        let actual: Vec<CiMessage> = events
+            .iter()
+            .filter_map(|event| target.handle_event(event))
+            .collect();
+
+        assert!(actual.is_empty());
+    }
+}
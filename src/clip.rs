@@ -0,0 +1,309 @@
+use crate::{packet_words, u4, StreamMessage, UmpEvent, UmpPacket, Utility};
+
+// File identifier
+const CLIP_FILE_ID: &[u8; 8] = b"SMF2CLIP";
+
+// Largest delta carried by a single Delta Clockstamp (20 bits)
+const MAX_DELTA_CLOCKSTAMP: u64 = 0x000F_FFFF;
+
+///
+/// Packet at a position in a clip
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ClipEvent {
+    pub ticks: u64, // Ticks since the Start of Clip
+    pub packet: UmpPacket,
+}
+
+impl ClipEvent {
+    pub fn event(&self) -> UmpEvent {
+        UmpEvent::decode(&self.packet)
+    }
+}
+
+///
+/// **MIDI Clip File**
+///
+/// A sequence of Universal MIDI Packets stored in the SMF2CLIP format. The
+/// clip header carries the tick resolution and any configuration messages
+/// (eg Stream Configuration or Function Block Info) followed by the clip
+/// sequence between Start of Clip and End of Clip.
+///
+/// Events are held in order with absolute tick positions; the file stores
+/// each packet preceded by a Delta Clockstamp.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Clip {
+    pub ticks_per_quarter: u16,
+    pub header: Vec<UmpPacket>, // Configuration messages of the clip header
+    events: Vec<ClipEvent>,     // Sorted by position
+    pub end: u64,               // Position of the End of Clip (at least the last event)
+}
+
+impl Clip {
+    pub fn new(ticks_per_quarter: u16) -> Self {
+        Self {
+            ticks_per_quarter,
+            header: Vec::new(),
+            events: Vec::new(),
+            end: 0,
+        }
+    }
+
+    ///
+    /// Add a configuration message to the clip header
+    ///
+    pub fn with_header(mut self, packet: UmpPacket) -> Self {
+        self.header.push(packet);
+        self
+    }
+
+    ///
+    /// **Add a packet**
+    ///
+    /// Placed after any existing packets at the same position.
+    ///
+    pub fn push(&mut self, ticks: u64, packet: UmpPacket) {
+        // Searched from the end so packets added in order are appended
+        let index = self
+            .events
+            .iter()
+            .rposition(|event| event.ticks <= ticks)
+            .map_or(0, |index| index + 1);
+        self.events.insert(index, ClipEvent { ticks, packet });
+        self.end = self.end.max(ticks);
+    }
+
+    ///
+    /// Packets in the order they are played
+    ///
+    pub fn events(&self) -> impl Iterator<Item = &ClipEvent> {
+        self.events.iter()
+    }
+
+    ///
+    /// **Read a clip file**
+    ///
+    /// Returns None if the data is not a clip file, is truncated before the
+    /// Start of Clip or has no Delta Clockstamp Ticks Per Quarter Note. A
+    /// clip truncated after the Start of Clip keeps the events read up to the
+    /// truncated packet, as does one missing its End of Clip.
+    ///
+    pub fn read(data: &[u8]) -> Option<Self> {
+        let data = data.strip_prefix(&CLIP_FILE_ID[..])?;
+        let words: Vec<u32> = data
+            .chunks_exact(4)
+            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+
+        let mut ticks_per_quarter = None;
+        let mut clip = Self::new(0);
+        let mut ticks = None; // Position in the clip sequence once started
+        let mut index = 0;
+        while index < words.len() {
+            let len = packet_words((words[index] >> 28) as u4);
+            let packet = match words.get(index..index + len).and_then(UmpPacket::new) {
+                Some(packet) => packet,
+                None if ticks.is_some() => break,
+                None => return None,
+            };
+            index += len;
+
+            match (UmpEvent::decode(&packet), ticks) {
+                (UmpEvent::Utility(Utility::NoOp), _) => {}
+                (UmpEvent::Utility(Utility::DeltaClockstamp(delta)), Some(position)) => {
+                    ticks = Some(position + delta as u64)
+                }
+                (UmpEvent::Utility(Utility::DeltaClockstamp(_)), None) => {}
+                (UmpEvent::Utility(Utility::DeltaClockstampTpq(value)), None) => {
+                    ticks_per_quarter = Some(value)
+                }
+                (UmpEvent::Stream(StreamMessage::StartOfClip), None) => ticks = Some(0),
+                (UmpEvent::Stream(StreamMessage::EndOfClip), Some(position)) => {
+                    clip.end = position;
+                    break;
+                }
+                (_, None) => clip.header.push(packet),
+                (_, Some(position)) => {
+                    // Positions only increase so events are read in order
+                    clip.events.push(ClipEvent {
+                        ticks: position,
+                        packet,
+                    });
+                    clip.end = position;
+                }
+            }
+        }
+
+        ticks?;
+        clip.ticks_per_quarter = ticks_per_quarter?;
+        Some(clip)
+    }
+
+    ///
+    /// **Encode as a clip file**
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = CLIP_FILE_ID.to_vec();
+        let mut write = |delta: u64, packet: &UmpPacket| {
+            let mut delta = delta;
+            // Deltas beyond 20 bits are spread over No-Op packets
+            while delta > MAX_DELTA_CLOCKSTAMP {
+                for word in [
+                    Utility::DeltaClockstamp(MAX_DELTA_CLOCKSTAMP as u32).to_packet(),
+                    Utility::NoOp.to_packet(),
+                ]
+                .iter()
+                .flat_map(|packet| packet.iter())
+                {
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+                delta -= MAX_DELTA_CLOCKSTAMP;
+            }
+            for word in Utility::DeltaClockstamp(delta as u32)
+                .to_packet()
+                .iter()
+                .chain(packet.iter())
+            {
+                bytes.extend_from_slice(&word.to_be_bytes());
+            }
+        };
+
+        write(
+            0,
+            &Utility::DeltaClockstampTpq(self.ticks_per_quarter).to_packet(),
+        );
+        for packet in &self.header {
+            write(0, packet);
+        }
+        write(0, &StreamMessage::StartOfClip.to_packet());
+        let mut position = 0;
+        for event in &self.events {
+            write(event.ticks - position, &event.packet);
+            position = event.ticks;
+        }
+        write(
+            self.end.max(position) - position,
+            &StreamMessage::EndOfClip.to_packet(),
+        );
+        bytes
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::clip::Clip;
+    use crate::{Midi2Event, MidiEvent, NoteAttribute, Protocol, StreamMessage, UmpPacket};
+
+    fn note_on(note: u8) -> UmpPacket {
+        Midi2Event::NoteOn {
+            channel: 0,
+            note,
+            velocity: 0xFFFF,
+            attribute: NoteAttribute::None,
+        }
+        .to_packet(0)
+    }
+
+    #[test]
+    fn to_bytes__layout() {
+        let mut target = Clip::new(96);
+        target.push(
+            48,
+            UmpPacket::from_midi1(0, &MidiEvent::NoteOn(0, 60, 100)).unwrap(),
+        );
+        target.end = 96;
+
+        let actual = target.to_bytes();
+
+        let mut expected = b"SMF2CLIP".to_vec();
+        for word in &[
+            0x0040_0000u32, // DCS 0
+            0x0030_0060,    // DCTPQ 96
+            0x0040_0000,
+            0xF020_0000, // Start of Clip
+            0,
+            0,
+            0,
+            0x0040_0030, // DCS 48
+            0x2090_3C64,
+            0x0040_0030,
+            0xF021_0000, // End of Clip
+            0,
+            0,
+            0,
+        ] {
+            expected.extend_from_slice(&word.to_be_bytes());
+        }
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn push__keeps_events_in_order() {
+        let mut target = Clip::new(96);
+        target.push(96, note_on(62));
+        target.push(0, note_on(60));
+        target.push(96, note_on(64));
+
+        let actual: Vec<(u64, UmpPacket)> = target
+            .events()
+            .map(|event| (event.ticks, event.packet))
+            .collect();
+
+        assert_eq!(
+            actual,
+            vec![(0, note_on(60)), (96, note_on(62)), (96, note_on(64))]
+        );
+        assert_eq!(Clip::read(&target.to_bytes()), Some(target));
+    }
+
+    #[test]
+    fn read__round_trips() {
+        let mut target = Clip::new(480).with_header(
+            StreamMessage::ConfigurationNotification {
+                protocol: Protocol::Midi2,
+                receive_jr: false,
+                transmit_jr: false,
+            }
+            .to_packet(),
+        );
+        target.push(0, note_on(60));
+        target.push(0x0012_3456, note_on(64));
+        target.push(480, note_on(62));
+        target.end = 0x0020_0000;
+
+        let actual = Clip::read(&target.to_bytes());
+
+        assert_eq!(actual, Some(target));
+    }
+
+    #[test]
+    fn read__where_not_a_clip_file() {
+        assert_eq!(Clip::read(b"MThd\0\0\0\x06"), None);
+    }
+
+    #[test]
+    fn read__where_truncated_after_start_of_clip() {
+        let mut clip = Clip::new(96);
+        clip.push(0, note_on(60));
+        clip.push(96, note_on(62));
+        let bytes = clip.to_bytes();
+
+        // Cut within the second note, dropping the End of Clip
+        let actual = Clip::read(&bytes[..bytes.len() - 24]).unwrap();
+
+        let events: Vec<(u64, UmpPacket)> = actual
+            .events()
+            .map(|event| (event.ticks, event.packet))
+            .collect();
+        assert_eq!(events, vec![(0, note_on(60))]);
+    }
+
+    #[test]
+    fn read__where_truncated_before_start_of_clip() {
+        let bytes = Clip::new(96).to_bytes();
+
+        assert_eq!(Clip::read(&bytes[..16]), None);
+    }
+}
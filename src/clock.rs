@@ -0,0 +1,321 @@
+use crate::{
+    interval_to_bpm, u14, MidiEvent, MidiEvents, Position, Timestamp, CLOCKS_PER_MIDI_BEAT,
+    CLOCKS_PER_QUARTER,
+};
+
+/// Delay between Start/Continue and the first Clock (1ms)
+pub const START_DELAY: Timestamp = 1_000;
+
+/// Largest Song Position Pointer value
+const MAX_MIDI_BEATS: u32 = 0x3FFF;
+
+// Clock times are fixed point microseconds with 16 fractional bits, relative
+// to the time the clock was started; enough for 8 years of playback
+const FRACTION_BITS: u32 = 16;
+const FRACTION_MASK: u64 = (1 << FRACTION_BITS) - 1;
+
+fn bpm_to_fixed_interval(bpm: f32) -> u64 {
+    // Tempo in thousandths of a BPM keeps the division in integers
+    let milli_bpm = (bpm.max(1.0) * 1000.0) as u64;
+    (60_000_000_000 << FRACTION_BITS) / (milli_bpm * CLOCKS_PER_QUARTER as u64)
+}
+
+fn to_fixed(time: Timestamp) -> u64 {
+    time << FRACTION_BITS
+}
+
+///
+/// **Clock Generator**
+///
+/// Clock master producing Clock events at 24 PPQN along with Start, Stop,
+/// Continue and Song Position Pointer.
+///
+/// The generator never reads a clock; call `poll` with the current time (eg
+/// from a thread loop or a timer interrupt) and use `next_clock_time` to
+/// schedule the next call. Clock times are tracked in 64-bit fixed point with
+/// sub-microsecond precision so rounding does not accumulate into tempo
+/// drift, and only integer arithmetic is used once the tempo is set.
+///
+#[derive(Debug, Clone)]
+pub struct ClockGenerator {
+    interval: u64,           // Fixed point
+    origin: Timestamp,       // Time of the first clock
+    next_clock: Option<u64>, // Fixed point, relative to origin
+    running: bool,
+    clock_while_stopped: bool,
+    position: Position, // Position played by the next clock
+}
+
+impl ClockGenerator {
+    pub fn new(bpm: f32) -> Self {
+        Self {
+            interval: bpm_to_fixed_interval(bpm),
+            origin: 0,
+            next_clock: None,
+            running: false,
+            clock_while_stopped: false,
+            position: Position::default(),
+        }
+    }
+
+    ///
+    /// Continue to send Clock while stopped so slaves can follow tempo
+    ///
+    pub fn with_clock_while_stopped(mut self, enabled: bool) -> Self {
+        self.clock_while_stopped = enabled;
+        self
+    }
+
+    pub fn bpm(&self) -> f32 {
+        interval_to_bpm(self.interval as f32 / (1 << FRACTION_BITS) as f32)
+    }
+
+    ///
+    /// **Change tempo**
+    ///
+    /// Takes effect from the next clock.
+    ///
+    pub fn set_bpm(&mut self, bpm: f32) {
+        let interval = bpm_to_fixed_interval(bpm);
+        if let Some(next_clock) = self.next_clock.as_mut() {
+            *next_clock = (*next_clock + interval).saturating_sub(self.interval);
+        }
+        self.interval = interval;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    ///
+    /// Song position that will be played by the next clock
+    ///
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    ///
+    /// Time the next clock is due; None if no clock is scheduled
+    ///
+    pub fn next_clock_time(&self) -> Option<Timestamp> {
+        self.next_clock.map(|next_clock| {
+            self.origin + ((next_clock + FRACTION_MASK) >> FRACTION_BITS) as Timestamp
+        })
+    }
+
+    ///
+    /// **Start playback from the beginning of the song at `now`**
+    ///
+    pub fn start(&mut self, now: Timestamp) -> MidiEvent {
+        self.running = true;
+        self.position = Position::default();
+        self.schedule_first_clock(now);
+        MidiEvent::Start
+    }
+
+    ///
+    /// **Continue playback from the current position at `now`**
+    ///
+    pub fn resume(&mut self, now: Timestamp) -> MidiEvent {
+        self.running = true;
+        self.schedule_first_clock(now);
+        MidiEvent::Continue
+    }
+
+    ///
+    /// **Stop playback**
+    ///
+    pub fn stop(&mut self) -> MidiEvent {
+        self.running = false;
+        if !self.clock_while_stopped {
+            self.next_clock = None;
+        }
+        MidiEvent::Stop
+    }
+
+    ///
+    /// **Locate to a song position**
+    ///
+    /// Song Position Pointer may only be sent while stopped so a running
+    /// generator is stopped, located and continued. The position is rounded
+    /// down to a whole MIDI beat (the resolution of Song Position Pointer).
+    ///
+    pub fn locate(&mut self, position: Position, now: Timestamp) -> MidiEvents {
+        let midi_beats = position.midi_beats().min(MAX_MIDI_BEATS) as u14;
+        self.position = Position::from_clocks(midi_beats as u32 * CLOCKS_PER_MIDI_BEAT);
+
+        let mut events = MidiEvents::new();
+        let running = self.running;
+        if running {
+            events.push(self.stop());
+        }
+        events.push(MidiEvent::SongPositionPointer(midi_beats));
+        if running {
+            events.push(self.resume(now));
+        }
+        events
+    }
+
+    ///
+    /// **Returns a Clock if one is due at `now`**
+    ///
+    /// If polled late, call repeatedly until None to catch up.
+    ///
+    pub fn poll(&mut self, now: Timestamp) -> Option<MidiEvent> {
+        if self.next_clock.is_none() && self.clock_while_stopped {
+            self.origin = now;
+            self.next_clock = Some(0);
+        }
+        let next_clock = self.next_clock.as_mut()?;
+        if now < self.origin || to_fixed(now - self.origin) < *next_clock {
+            return None;
+        }
+
+        *next_clock += self.interval;
+        if self.running {
+            self.position = Position::from_clocks(self.position.clocks() + 1);
+        }
+        Some(MidiEvent::Clock)
+    }
+
+    fn schedule_first_clock(&mut self, now: Timestamp) {
+        self.origin = now + START_DELAY;
+        self.next_clock = Some(0);
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::clock::ClockGenerator;
+    use crate::{MidiEvent, Position, Timestamp, Transport, TransportEvent};
+
+    fn clock_times(target: &mut ClockGenerator, until: Timestamp) -> Vec<Timestamp> {
+        let mut times = Vec::new();
+        for now in 0..until {
+            while target.poll(now).is_some() {
+                times.push(now);
+            }
+        }
+        times
+    }
+
+    #[test]
+    fn poll__where_stopped() {
+        let mut target = ClockGenerator::new(120.0);
+
+        assert_eq!(target.poll(1_000_000), None);
+        assert_eq!(target.next_clock_time(), None);
+    }
+
+    #[test]
+    fn poll__clock_rate_does_not_drift() {
+        let mut target = ClockGenerator::new(125.0);
+        target.start(0);
+
+        let actual = clock_times(&mut target, 1_000_000);
+
+        // 125 BPM is 50 clocks per second; 20ms apart
+        assert_eq!(actual.len(), 50);
+        assert_eq!(actual[0], 1_000);
+        assert_eq!(actual[49], 981_000);
+        assert_eq!(target.position(), Position::from_clocks(50));
+    }
+
+    #[test]
+    fn next_clock_time__where_interval_has_fraction() {
+        let mut target = ClockGenerator::new(120.0);
+        target.start(0);
+
+        // 2880 clocks is one minute at 120 BPM
+        for _ in 0..2880 {
+            target.poll(Timestamp::MAX);
+        }
+
+        assert_eq!(target.next_clock_time(), Some(60_001_000));
+    }
+
+    #[test]
+    fn poll__where_time_is_large() {
+        let start = u64::MAX >> 8;
+        let mut target = ClockGenerator::new(125.0);
+        target.start(start);
+
+        assert_eq!(target.poll(start), None);
+        assert_eq!(target.next_clock_time(), Some(start + 1_000));
+        assert_eq!(target.poll(start + 1_000), Some(MidiEvent::Clock));
+        assert_eq!(target.poll(start + 20_999), None);
+        assert_eq!(target.next_clock_time(), Some(start + 21_000));
+    }
+
+    #[test]
+    fn set_bpm__applies_from_next_clock() {
+        let mut target = ClockGenerator::new(125.0);
+        target.start(0);
+        target.poll(1_000);
+
+        target.set_bpm(250.0);
+
+        assert_eq!(target.next_clock_time(), Some(11_000));
+    }
+
+    #[test]
+    fn locate__where_running() {
+        let mut target = ClockGenerator::new(120.0);
+        target.start(0);
+
+        let actual = target.locate(Position::from_clocks(100), 5_000);
+
+        assert_eq!(
+            *actual,
+            [
+                MidiEvent::Stop,
+                MidiEvent::SongPositionPointer(16),
+                MidiEvent::Continue
+            ]
+        );
+        assert_eq!(target.position(), Position::from_clocks(96));
+        assert_eq!(target.next_clock_time(), Some(6_000));
+    }
+
+    #[test]
+    fn locate__where_stopped() {
+        let mut target = ClockGenerator::new(120.0);
+
+        let actual = target.locate(Position::from_clocks(12), 0);
+
+        assert_eq!(*actual, [MidiEvent::SongPositionPointer(2)]);
+        assert!(!target.is_running());
+    }
+
+    #[test]
+    fn with_clock_while_stopped__sends_clock() {
+        let mut target = ClockGenerator::new(125.0).with_clock_while_stopped(true);
+
+        let actual = clock_times(&mut target, 100_000);
+
+        assert_eq!(actual, vec![0, 20_000, 40_000, 60_000, 80_000]);
+        assert_eq!(target.position(), Position::default());
+    }
+
+    #[test]
+    fn generator_drives_transport() {
+        let mut target = ClockGenerator::new(120.0);
+        let mut transport = Transport::new();
+
+        transport.handle_event(&target.start(0));
+        let mut last = None;
+        for now in (0..1_000_000).step_by(100) {
+            if let Some(event) = target.poll(now) {
+                last = transport.handle_event(&event);
+            }
+        }
+
+        assert_eq!(
+            last,
+            Some(TransportEvent::Tick(Position::from_clocks(
+                target.position().clocks() - 1
+            )))
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use crate::u7;
+
+pub const BANK_SELECT: u7 = 0x00;
+pub const MODULATION: u7 = 0x01;
+pub const DATA_ENTRY: u7 = 0x06;
+pub const VOLUME: u7 = 0x07;
+pub const PAN: u7 = 0x0A;
+pub const EXPRESSION: u7 = 0x0B;
+pub const BANK_SELECT_LSB: u7 = 0x20;
+pub const DATA_ENTRY_LSB: u7 = 0x26;
+pub const SUSTAIN: u7 = 0x40;
+pub const PORTAMENTO: u7 = 0x41;
+pub const SOSTENUTO: u7 = 0x42;
+pub const SOFT_PEDAL: u7 = 0x43;
+pub const LEGATO: u7 = 0x44;
+pub const BRIGHTNESS: u7 = 0x4A;
+pub const PORTAMENTO_CONTROL: u7 = 0x54;
+pub const DATA_INCREMENT: u7 = 0x60;
+pub const DATA_DECREMENT: u7 = 0x61;
+pub const NRPN_LSB: u7 = 0x62;
+pub const NRPN_MSB: u7 = 0x63;
+pub const RPN_LSB: u7 = 0x64;
+pub const RPN_MSB: u7 = 0x65;
+
+/// Value used to deselect RPN/NRPN parameters
+pub const RPN_NULL: u7 = 0x7F;
+
+/// Default controller values (from RP-015) applied on Reset All Controllers
+pub(crate) const RESET_VALUES: [(u7, u7); 12] = [
+    (MODULATION, 0),
+    (EXPRESSION, 127),
+    (SUSTAIN, 0),
+    (PORTAMENTO, 0),
+    (SOSTENUTO, 0),
+    (SOFT_PEDAL, 0),
+    (LEGATO, 0),
+    (0x45, 0), // Hold 2
+    (NRPN_LSB, RPN_NULL),
+    (NRPN_MSB, RPN_NULL),
+    (RPN_LSB, RPN_NULL),
+    (RPN_MSB, RPN_NULL),
+];
@@ -0,0 +1,515 @@
+use crate::pitch_bend::{self, DEFAULT_BEND_RANGE, PITCH_BEND_CENTRE};
+use crate::{i14, u7, Channel, List, MidiEvent, Timestamp};
+
+/// Default trigger length (2ms)
+pub const DEFAULT_TRIGGER_LENGTH: Timestamp = 2_000;
+
+/// Most voices of a converter
+pub const MAX_CV_VOICES: usize = 4;
+
+/// Most controller outputs of a converter
+pub const MAX_CV_CONTROLLERS: usize = 8;
+
+// Most held keys, the oldest is forgotten beyond this
+const MAX_HELD: usize = 16;
+
+///
+/// Which held note(s) are played when more keys are held than voices
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+///
+/// Pitch CV response
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PitchScale {
+    /// 1V/oct; `base_note` produces 0V
+    VoltsPerOctave { base_note: u7 },
+    /// Hz/V (voltage doubles per octave); `reference_note` produces
+    /// `reference_volts`
+    HzPerVolt {
+        reference_note: u7,
+        reference_volts: f32,
+    },
+}
+
+impl PitchScale {
+    ///
+    /// Voltage for a (fractional) note number
+    ///
+    pub fn volts(&self, note: f32) -> f32 {
+        match *self {
+            PitchScale::VoltsPerOctave { base_note } => (note - base_note as f32) / 12.0,
+            PitchScale::HzPerVolt {
+                reference_note,
+                reference_volts,
+            } => reference_volts * exp2((note - reference_note as f32) / 12.0),
+        }
+    }
+}
+
+///
+/// 2 to the power of `x` without `std`; the nearest integer power is built
+/// from the exponent bits and the remainder (within ±0.5) from a polynomial
+///
+fn exp2(x: f32) -> f32 {
+    let x = x.clamp(-126.0, 127.0);
+    let whole = x + 0.5;
+    let mut n = whole as i32;
+    if (n as f32) > whole {
+        n -= 1; // Round towards negative infinity
+    }
+    let f = x - n as f32;
+    let fraction = 1.0
+        + f * (6.931_472e-1
+            + f * (2.402_265e-1
+                + f * (5.550_332e-2 + f * (9.618_437e-3 + f * (1.339_887e-3 + f * 1.535_336e-4)))));
+    f32::from_bits(((n + 127) as u32) << 23) * fraction
+}
+
+impl Default for PitchScale {
+    fn default() -> Self {
+        PitchScale::VoltsPerOctave { base_note: 36 }
+    }
+}
+
+///
+/// Voltage range a 7 bit value is scaled to
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoltageRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl VoltageRange {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    pub fn scale(&self, value: u7) -> f32 {
+        self.min + (self.max - self.min) * (value.min(127) as f32 / 127.0)
+    }
+}
+
+impl Default for VoltageRange {
+    fn default() -> Self {
+        Self::new(0.0, 5.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct CvVoice {
+    note: Option<u7>, // Note currently gated
+    pitch_note: u7,   // Note the pitch CV is held at (remains after release)
+    velocity: u7,
+    trigger_at: Option<Timestamp>,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct CcOutput {
+    control: u7,
+    range: VoltageRange,
+    value: u7,
+}
+
+///
+/// **MIDI to CV**
+///
+/// Converts note, pitch bend and controller events into control voltages for
+/// mono (1 voice) or duo (2 voice) modular/analogue synths.
+///
+/// Voltages are returned as floating point volts for the caller to scale to
+/// their DAC. Held notes are kept in a stack of up to 16 keys (the oldest key
+/// is forgotten beyond that); the notes played are chosen by
+/// the note priority and assigned to voices in ascending pitch order. When a
+/// key is released the pitch returns to the next held note without a new
+/// trigger, and after the last key is released pitch is held while the gate
+/// closes. A voice whose gate opens is always triggered, including a duo
+/// voice taking over a note that was already held.
+///
+#[derive(Debug, Clone)]
+pub struct MidiToCv {
+    channel: Option<Channel>,
+    priority: NotePriority,
+    scale: PitchScale,
+    bend_range: f32,
+    velocity_range: VoltageRange,
+    trigger_length: Timestamp,
+    legato: bool,
+    held: List<(u7, u7), MAX_HELD>, // Key, Velocity in the order pressed
+    bend: i14,
+    voices: [CvVoice; MAX_CV_VOICES],
+    voice_count: usize,
+    ccs: [CcOutput; MAX_CV_CONTROLLERS],
+    cc_count: usize,
+}
+
+impl MidiToCv {
+    ///
+    /// Converter with `voices` outputs (1 for mono, 2 for duo, up to
+    /// [`MAX_CV_VOICES`])
+    ///
+    pub fn new(voices: usize) -> Self {
+        Self {
+            channel: None,
+            priority: NotePriority::Last,
+            scale: PitchScale::default(),
+            bend_range: DEFAULT_BEND_RANGE,
+            velocity_range: VoltageRange::default(),
+            trigger_length: DEFAULT_TRIGGER_LENGTH,
+            legato: false,
+            held: List::default(),
+            bend: PITCH_BEND_CENTRE,
+            voices: [CvVoice::default(); MAX_CV_VOICES],
+            voice_count: voices.clamp(1, MAX_CV_VOICES),
+            ccs: [CcOutput::default(); MAX_CV_CONTROLLERS],
+            cc_count: 0,
+        }
+    }
+
+    ///
+    /// Only respond to a single channel (default is all channels)
+    ///
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: NotePriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_pitch_scale(mut self, scale: PitchScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    ///
+    /// Pitch bend range in semitones
+    ///
+    pub fn with_bend_range(mut self, bend_range: f32) -> Self {
+        self.bend_range = bend_range;
+        self
+    }
+
+    pub fn with_velocity_range(mut self, range: VoltageRange) -> Self {
+        self.velocity_range = range;
+        self
+    }
+
+    ///
+    /// Trigger length in microseconds
+    ///
+    pub fn with_trigger_length(mut self, length: Timestamp) -> Self {
+        self.trigger_length = length;
+        self
+    }
+
+    ///
+    /// Legato; a new note while the gate is open does not re-trigger
+    ///
+    pub fn with_legato(mut self, legato: bool) -> Self {
+        self.legato = legato;
+        self
+    }
+
+    ///
+    /// Add a controller output; outputs are indexed in the order added and
+    /// any beyond [`MAX_CV_CONTROLLERS`] are ignored
+    ///
+    pub fn with_cc(mut self, control: u7, range: VoltageRange) -> Self {
+        if self.cc_count < MAX_CV_CONTROLLERS {
+            self.ccs[self.cc_count] = CcOutput {
+                control,
+                range,
+                value: 0,
+            };
+            self.cc_count += 1;
+        }
+        self
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voice_count
+    }
+
+    ///
+    /// **Handle an event received at `now`**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, now: Timestamp) {
+        match (self.channel, event.channel()) {
+            (Some(channel), Some(event_channel)) if channel != event_channel => return,
+            _ => (),
+        }
+
+        match *event {
+            MidiEvent::NoteOn(_, key, 0) | MidiEvent::NoteOff(_, key, _) => {
+                self.held.retain(|(held, _)| *held != key);
+                self.assign(now, None);
+            }
+            MidiEvent::NoteOn(_, key, velocity) => {
+                self.held.retain(|(held, _)| *held != key);
+                if self.held.is_full() {
+                    self.held.remove(0);
+                }
+                self.held.push((key, velocity));
+                self.assign(now, Some(key));
+            }
+            MidiEvent::PitchBend(_, amount) => self.bend = amount,
+            MidiEvent::ControllerChange(_, control, value) => self.ccs[..self.cc_count]
+                .iter_mut()
+                .filter(|cc| cc.control == control)
+                .for_each(|cc| cc.value = value),
+            MidiEvent::AllNotesOff(_) | MidiEvent::AllSoundOff(_) => {
+                self.held.clear();
+                self.assign(now, None);
+            }
+            MidiEvent::ResetAllControllers(_) => {
+                self.bend = PITCH_BEND_CENTRE;
+                self.ccs.iter_mut().for_each(|cc| cc.value = 0);
+            }
+            _ => (),
+        }
+    }
+
+    ///
+    /// Pitch CV of a voice in volts, including pitch bend
+    ///
+    pub fn pitch(&self, voice: usize) -> Option<f32> {
+        let bend = pitch_bend::to_semitones(self.bend, self.bend_range);
+        Some(
+            self.scale
+                .volts(self.voice(voice)?.pitch_note as f32 + bend),
+        )
+    }
+
+    pub fn gate(&self, voice: usize) -> Option<bool> {
+        Some(self.voice(voice)?.note.is_some())
+    }
+
+    ///
+    /// Trigger state of a voice at `now`; high for the trigger length after a
+    /// note starts
+    ///
+    pub fn trigger(&self, voice: usize, now: Timestamp) -> Option<bool> {
+        Some(match self.voice(voice)?.trigger_at {
+            Some(trigger_at) => now >= trigger_at && now - trigger_at < self.trigger_length,
+            None => false,
+        })
+    }
+
+    pub fn velocity(&self, voice: usize) -> Option<f32> {
+        Some(self.velocity_range.scale(self.voice(voice)?.velocity))
+    }
+
+    ///
+    /// Voltage of a controller output added with `with_cc`
+    ///
+    pub fn cc(&self, index: usize) -> Option<f32> {
+        let cc = self.ccs[..self.cc_count].get(index)?;
+        Some(cc.range.scale(cc.value))
+    }
+
+    ///
+    /// Note currently gated on a voice
+    ///
+    pub fn note(&self, voice: usize) -> Option<u7> {
+        self.voice(voice)?.note
+    }
+
+    fn voice(&self, voice: usize) -> Option<&CvVoice> {
+        self.voices[..self.voice_count].get(voice)
+    }
+
+    fn assign(&mut self, now: Timestamp, pressed: Option<u7>) {
+        let mut held = self.held;
+        let count = held.len().min(self.voice_count);
+        let selected = &mut held[..];
+        match self.priority {
+            NotePriority::Last => selected.reverse(),
+            NotePriority::Low => selected.sort_unstable_by_key(|(key, _)| *key),
+            NotePriority::High => selected.sort_unstable_by_key(|(key, _)| u7::MAX - *key),
+        }
+        let selected = &mut selected[..count];
+        selected.sort_unstable_by_key(|(key, _)| *key);
+
+        for (index, voice) in self.voices[..self.voice_count].iter_mut().enumerate() {
+            match selected.get(index) {
+                Some(&(key, velocity)) if voice.note != Some(key) => {
+                    // A gate opening always triggers, otherwise only the pressed
+                    // key does; returning to a held note does not
+                    if voice.note.is_none() || (pressed == Some(key) && !self.legato) {
+                        voice.trigger_at = Some(now);
+                    }
+                    voice.velocity = velocity;
+                    voice.note = Some(key);
+                    voice.pitch_note = key;
+                }
+                Some(_) => (),
+                None => voice.note = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::cv::{exp2, MidiToCv, NotePriority, VoltageRange};
+    use crate::MidiEvent;
+    use parameterized::parameterized;
+
+    #[test]
+    fn pitch__volts_per_octave() {
+        let mut target = MidiToCv::new(1);
+
+        target.handle_event(&MidiEvent::NoteOn(0, 48, 100), 0);
+        assert_eq!(target.pitch(0), Some(1.0));
+        assert_eq!(target.gate(0), Some(true));
+        assert_eq!(target.trigger(0, 1_000), Some(true));
+        assert_eq!(target.trigger(0, 2_000), Some(false));
+
+        target.handle_event(&MidiEvent::PitchBend(0, 8191), 0);
+        assert!((target.pitch(0).unwrap() - (1.0 + 2.0 / 12.0)).abs() < 0.0001);
+    }
+
+    #[parameterized(x = {-10.0, -1.3, -0.5, 0.0, 0.25, 0.5, 1.0 / 12.0, 5.7})]
+    fn exp2__matches_powf(x: f32) {
+        let expected = 2f32.powf(x);
+
+        assert!((exp2(x) - expected).abs() <= expected * 1e-6);
+    }
+
+    #[test]
+    fn pitch__where_voice_out_of_range() {
+        let target = MidiToCv::new(2).with_cc(7, VoltageRange::new(0.0, 5.0));
+
+        assert_eq!(target.pitch(2), None);
+        assert_eq!(target.gate(2), None);
+        assert_eq!(target.trigger(2, 0), None);
+        assert_eq!(target.velocity(2), None);
+        assert_eq!(target.note(2), None);
+        assert_eq!(target.cc(1), None);
+    }
+
+    #[test]
+    fn pitch__hz_per_volt() {
+        use crate::cv::PitchScale;
+
+        let mut target = MidiToCv::new(1).with_pitch_scale(PitchScale::HzPerVolt {
+            reference_note: 57,
+            reference_volts: 4.0,
+        });
+
+        target.handle_event(&MidiEvent::NoteOn(0, 69, 100), 0);
+
+        assert!((target.pitch(0).unwrap() - 8.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn handle_event__last_note_priority_returns_without_trigger() {
+        let mut target = MidiToCv::new(1);
+        target.handle_event(&MidiEvent::NoteOn(0, 40, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 45, 100), 10_000);
+        assert_eq!(target.note(0), Some(45));
+        assert_eq!(target.trigger(0, 10_000), Some(true));
+
+        target.handle_event(&MidiEvent::NoteOff(0, 45, 0), 20_000);
+
+        assert_eq!(target.note(0), Some(40));
+        assert_eq!(target.trigger(0, 20_000), Some(false));
+
+        target.handle_event(&MidiEvent::NoteOn(0, 40, 0), 30_000);
+        assert_eq!(target.gate(0), Some(false));
+        // Pitch is held after release
+        assert_eq!(target.pitch(0), Some(4.0 / 12.0));
+    }
+
+    #[test]
+    fn handle_event__low_and_high_priority() {
+        let mut low = MidiToCv::new(1).with_priority(NotePriority::Low);
+        let mut high = MidiToCv::new(1).with_priority(NotePriority::High);
+
+        for key in [50, 40, 60].iter() {
+            low.handle_event(&MidiEvent::NoteOn(0, *key, 100), 0);
+            high.handle_event(&MidiEvent::NoteOn(0, *key, 100), 0);
+        }
+
+        assert_eq!(low.note(0), Some(40));
+        assert_eq!(high.note(0), Some(60));
+    }
+
+    #[test]
+    fn handle_event__duo_assigns_ascending() {
+        let mut target = MidiToCv::new(2).with_priority(NotePriority::Last);
+
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 48, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 55, 100), 0);
+
+        assert_eq!(target.note(0), Some(48));
+        assert_eq!(target.note(1), Some(55));
+    }
+
+    #[test]
+    fn handle_event__duo_voice_taking_over_held_note_triggers() {
+        let mut target = MidiToCv::new(2).with_velocity_range(VoltageRange::new(0.0, 127.0));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 0);
+
+        target.handle_event(&MidiEvent::NoteOn(0, 48, 90), 10_000);
+
+        assert_eq!(target.note(0), Some(48));
+        assert_eq!(target.note(1), Some(60));
+        assert_eq!(target.gate(1), Some(true));
+        assert_eq!(target.trigger(1, 10_000), Some(true));
+        assert_eq!(target.velocity(0), Some(90.0));
+        assert_eq!(target.velocity(1), Some(100.0));
+    }
+
+    #[test]
+    fn handle_event__legato_does_not_retrigger() {
+        let mut target = MidiToCv::new(1).with_legato(true);
+        target.handle_event(&MidiEvent::NoteOn(0, 40, 100), 0);
+
+        target.handle_event(&MidiEvent::NoteOn(0, 42, 100), 10_000);
+
+        assert_eq!(target.note(0), Some(42));
+        assert_eq!(target.trigger(0, 10_000), Some(false));
+    }
+
+    #[test]
+    fn handle_event__velocity_and_cc_scaling() {
+        let mut target = MidiToCv::new(1)
+            .with_channel(2)
+            .with_velocity_range(VoltageRange::new(0.0, 10.0))
+            .with_cc(1, VoltageRange::new(-5.0, 5.0));
+
+        target.handle_event(&MidiEvent::NoteOn(2, 60, 127), 0);
+        target.handle_event(&MidiEvent::ControllerChange(2, 1, 127), 0);
+        target.handle_event(&MidiEvent::ControllerChange(3, 1, 0), 0);
+
+        assert_eq!(target.velocity(0), Some(10.0));
+        assert_eq!(target.cc(0), Some(5.0));
+    }
+
+    #[test]
+    fn handle_event__forgets_oldest_held_key() {
+        let mut target = MidiToCv::new(1);
+        for key in 40..60 {
+            target.handle_event(&MidiEvent::NoteOn(0, key, 100), 0);
+        }
+
+        for key in (44..60).rev() {
+            target.handle_event(&MidiEvent::NoteOff(0, key, 0), 0);
+        }
+
+        assert_eq!(target.gate(0), Some(false));
+    }
+}
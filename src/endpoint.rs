@@ -0,0 +1,762 @@
+use crate::{u14, u7, Form, Group, List, Protocol, StreamMessage, StreamText, UmpEvent, UmpPacket};
+
+///
+/// UMP version implemented (major, minor)
+///
+pub const UMP_VERSION: (u8, u8) = (1, 1);
+
+/// Longest endpoint name (bytes)
+pub const MAX_ENDPOINT_NAME: usize = 98;
+/// Longest product instance ID (bytes)
+pub const MAX_PRODUCT_INSTANCE_ID: usize = 42;
+/// Longest function block name (bytes)
+pub const MAX_FUNCTION_BLOCK_NAME: usize = 91;
+/// Number of function blocks an endpoint may have
+pub const MAX_FUNCTION_BLOCKS: usize = 32;
+
+// Endpoint Discovery filter
+const FILTER_ENDPOINT_INFO: u8 = 0x01;
+const FILTER_DEVICE_IDENTITY: u8 = 0x02;
+const FILTER_ENDPOINT_NAME: u8 = 0x04;
+const FILTER_PRODUCT_INSTANCE_ID: u8 = 0x08;
+const FILTER_STREAM_CONFIGURATION: u8 = 0x10;
+const FILTER_ENDPOINT_ALL: u8 = 0x1F;
+
+// Function Block Discovery filter
+const FILTER_FUNCTION_BLOCK_INFO: u8 = 0x01;
+const FILTER_FUNCTION_BLOCK_NAME: u8 = 0x02;
+const FILTER_FUNCTION_BLOCK_ALL: u8 = 0x03;
+const ALL_FUNCTION_BLOCKS: u8 = 0xFF;
+
+// Bytes of text per packet
+const ENDPOINT_TEXT_SIZE: usize = 14;
+const FUNCTION_BLOCK_TEXT_SIZE: usize = 13;
+
+///
+/// UTF-8 text of up to `N` bytes; longer text is cut at a character boundary
+///
+fn truncate_text<const N: usize>(text: &str) -> List<u8, N> {
+    let mut end = text.len().min(N);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut bytes = List::default();
+    bytes.extend(text.as_bytes()[..end].iter().copied());
+    bytes
+}
+
+///
+/// Device Identity of an endpoint
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeviceIdentity {
+    pub manufacturer: [u7; 3], // SysEx ID (1 byte IDs are followed by two zeros)
+    pub family: u14,
+    pub model: u14,
+    pub revision: [u7; 4],
+}
+
+///
+/// Protocol and Jitter Reduction timestamps in use on a stream
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StreamConfiguration {
+    pub protocol: Protocol,
+    pub receive_jr: bool,
+    pub transmit_jr: bool,
+}
+
+impl Default for StreamConfiguration {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::Midi1,
+            receive_jr: false,
+            transmit_jr: false,
+        }
+    }
+}
+
+///
+/// **Function Block**
+///
+/// A set of groups used by one function of the endpoint. The name is UTF-8.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FunctionBlock {
+    pub block: u7,
+    pub active: bool,
+    pub name: List<u8, MAX_FUNCTION_BLOCK_NAME>,
+    pub ui_hint: u8,   // 0b01 receiver, 0b10 sender, 0b11 both
+    pub midi1: u8,     // 0 not MIDI 1.0, 1 MIDI 1.0, 2 MIDI 1.0 restricted to 31.25kbps
+    pub direction: u8, // 0b01 input, 0b10 output, 0b11 bidirectional
+    pub first_group: Group,
+    pub groups: u8, // Number of groups spanned
+    pub ci_version: u8,
+    pub sysex8_streams: u8,
+}
+
+impl FunctionBlock {
+    ///
+    /// Active bidirectional block spanning groups from `first_group`
+    ///
+    pub fn new(block: u7, first_group: Group, groups: u8) -> Self {
+        Self {
+            block,
+            active: true,
+            name: List::default(),
+            ui_hint: 0b11,
+            midi1: 0,
+            direction: 0b11,
+            first_group,
+            groups,
+            ci_version: 0,
+            sysex8_streams: 0,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = truncate_text(name);
+        self
+    }
+
+    ///
+    /// Block uses the given group
+    ///
+    pub fn contains(&self, group: Group) -> bool {
+        (self.first_group..self.first_group.saturating_add(self.groups)).contains(&group)
+    }
+
+    fn info(&self) -> StreamMessage {
+        StreamMessage::FunctionBlockInfo {
+            active: self.active,
+            block: self.block,
+            ui_hint: self.ui_hint,
+            midi1: self.midi1,
+            direction: self.direction,
+            first_group: self.first_group,
+            groups: self.groups,
+            ci_version: self.ci_version,
+            sysex8_streams: self.sysex8_streams,
+        }
+    }
+
+    fn name_packets(&self) -> impl Iterator<Item = UmpPacket> + '_ {
+        let block = self.block;
+        StreamText::split(&self.name, FUNCTION_BLOCK_TEXT_SIZE)
+            .map(move |text| StreamMessage::FunctionBlockName { block, text }.to_packet())
+    }
+}
+
+///
+/// **UMP Endpoint**
+///
+/// Description of an endpoint exchanged by discovery. The name and product
+/// instance ID are UTF-8.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Endpoint {
+    pub version: (u8, u8),
+    pub name: List<u8, MAX_ENDPOINT_NAME>,
+    pub product_instance_id: List<u8, MAX_PRODUCT_INSTANCE_ID>,
+    pub identity: Option<DeviceIdentity>,
+    pub midi1: bool, // Supported protocols
+    pub midi2: bool,
+    pub receive_jr: bool, // Jitter Reduction timestamp support
+    pub transmit_jr: bool,
+    pub static_function_blocks: bool,
+    pub function_blocks: List<FunctionBlock, MAX_FUNCTION_BLOCKS>,
+    pub configuration: StreamConfiguration,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Endpoint {
+    pub fn new() -> Self {
+        Self {
+            version: UMP_VERSION,
+            name: List::default(),
+            product_instance_id: List::default(),
+            identity: None,
+            midi1: true,
+            midi2: true,
+            receive_jr: false,
+            transmit_jr: false,
+            static_function_blocks: true,
+            function_blocks: List::with_filler(FunctionBlock::new(0, 0, 0)),
+            configuration: StreamConfiguration::default(),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = truncate_text(name);
+        self
+    }
+
+    pub fn with_product_instance_id(mut self, id: &str) -> Self {
+        self.product_instance_id = truncate_text(id);
+        self
+    }
+
+    pub fn with_identity(mut self, identity: DeviceIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    ///
+    /// Add a function block; up to `MAX_FUNCTION_BLOCKS` are kept
+    ///
+    pub fn with_function_block(mut self, block: FunctionBlock) -> Self {
+        self.function_blocks.push(block);
+        self
+    }
+
+    pub fn function_block(&self, block: u7) -> Option<&FunctionBlock> {
+        self.function_blocks
+            .iter()
+            .find(|entry| entry.block == block)
+    }
+
+    fn supports(&self, protocol: Protocol) -> bool {
+        match protocol {
+            Protocol::Midi1 => self.midi1,
+            Protocol::Midi2 => self.midi2,
+        }
+    }
+
+    fn info(&self) -> StreamMessage {
+        StreamMessage::EndpointInfo {
+            version: self.version,
+            static_function_blocks: self.static_function_blocks,
+            function_blocks: self.function_blocks.len() as u7,
+            midi2: self.midi2,
+            midi1: self.midi1,
+            receive_jr: self.receive_jr,
+            transmit_jr: self.transmit_jr,
+        }
+    }
+
+    fn notification(&self) -> StreamMessage {
+        StreamMessage::ConfigurationNotification {
+            protocol: self.configuration.protocol,
+            receive_jr: self.configuration.receive_jr,
+            transmit_jr: self.configuration.transmit_jr,
+        }
+    }
+}
+
+///
+/// **Endpoint Responder**
+///
+/// Answers Endpoint and Function Block discovery for a local endpoint and
+/// applies Stream Configuration Requests the endpoint supports. Replies are
+/// added to a caller supplied buffer, eg a `Vec` or, without std, a [`List`]:
+///
+/// ```
+/// use sc_midi::{Endpoint, EndpointResponder, List, StreamMessage, UmpEvent, UmpPacket};
+///
+/// let mut responder = EndpointResponder::new(Endpoint::new().with_name("Synth"));
+/// let discovery = StreamMessage::EndpointDiscovery {
+///     version: (1, 1),
+///     filter: 0x1F,
+/// };
+/// let mut replies = List::<UmpPacket, 8>::default();
+///
+/// responder.handle_event(&UmpEvent::Stream(discovery), &mut replies);
+///
+/// assert!(!replies.is_empty());
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct EndpointResponder {
+    endpoint: Endpoint,
+}
+
+impl EndpointResponder {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// Adds the packets to send in reply to `replies`.
+    ///
+    pub fn handle_event(&mut self, event: &UmpEvent, replies: &mut impl Extend<UmpPacket>) {
+        let endpoint = &mut self.endpoint;
+
+        match *event {
+            UmpEvent::Stream(StreamMessage::EndpointDiscovery { filter, .. }) => {
+                if filter & FILTER_ENDPOINT_INFO > 0 {
+                    replies.extend(Some(endpoint.info().to_packet()));
+                }
+                if filter & FILTER_DEVICE_IDENTITY > 0 {
+                    replies.extend(endpoint.identity.map(|identity| {
+                        StreamMessage::DeviceIdentity {
+                            manufacturer: identity.manufacturer,
+                            family: identity.family,
+                            model: identity.model,
+                            revision: identity.revision,
+                        }
+                        .to_packet()
+                    }));
+                }
+                if filter & FILTER_ENDPOINT_NAME > 0 && !endpoint.name.is_empty() {
+                    replies.extend(
+                        StreamText::split(&endpoint.name, ENDPOINT_TEXT_SIZE)
+                            .map(|text| StreamMessage::EndpointName(text).to_packet()),
+                    );
+                }
+                if filter & FILTER_PRODUCT_INSTANCE_ID > 0
+                    && !endpoint.product_instance_id.is_empty()
+                {
+                    replies.extend(
+                        StreamText::split(&endpoint.product_instance_id, ENDPOINT_TEXT_SIZE)
+                            .map(|text| StreamMessage::ProductInstanceId(text).to_packet()),
+                    );
+                }
+                if filter & FILTER_STREAM_CONFIGURATION > 0 {
+                    replies.extend(Some(endpoint.notification().to_packet()));
+                }
+            }
+            UmpEvent::Stream(StreamMessage::FunctionBlockDiscovery { block, filter }) => {
+                for function_block in endpoint
+                    .function_blocks
+                    .iter()
+                    .filter(|entry| block == ALL_FUNCTION_BLOCKS || entry.block == block)
+                {
+                    if filter & FILTER_FUNCTION_BLOCK_INFO > 0 {
+                        replies.extend(Some(function_block.info().to_packet()));
+                    }
+                    if filter & FILTER_FUNCTION_BLOCK_NAME > 0 && !function_block.name.is_empty() {
+                        replies.extend(function_block.name_packets());
+                    }
+                }
+            }
+            UmpEvent::Stream(StreamMessage::ConfigurationRequest {
+                protocol,
+                receive_jr,
+                transmit_jr,
+            }) => {
+                if endpoint.supports(protocol) {
+                    endpoint.configuration = StreamConfiguration {
+                        protocol,
+                        receive_jr: receive_jr && endpoint.receive_jr,
+                        transmit_jr: transmit_jr && endpoint.transmit_jr,
+                    };
+                }
+                replies.extend(Some(endpoint.notification().to_packet()));
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// **Activate or deactivate a function block**
+    ///
+    /// Returns the Function Block Info notification to send; None for an
+    /// unknown block.
+    ///
+    pub fn set_active(&mut self, block: u7, active: bool) -> Option<UmpPacket> {
+        let function_block = self
+            .endpoint
+            .function_blocks
+            .iter_mut()
+            .find(|entry| entry.block == block)?;
+        function_block.active = active;
+        Some(function_block.info().to_packet())
+    }
+}
+
+///
+/// Text assembled from a sequence of packets
+///
+#[derive(Debug, Clone, Default)]
+struct TextBuffer<const N: usize> {
+    bytes: List<u8, N>,
+}
+
+impl<const N: usize> TextBuffer<N> {
+    ///
+    /// Append a packet; returns the text once complete
+    ///
+    fn append(&mut self, text: &StreamText) -> Option<List<u8, N>> {
+        if let Form::Complete | Form::Start = text.form {
+            self.bytes.clear();
+        }
+        self.bytes.extend(text.bytes().iter().copied());
+        match text.form {
+            Form::Complete | Form::End => {
+                let result = self.bytes;
+                self.bytes.clear();
+                Some(result)
+            }
+            _ => None,
+        }
+    }
+}
+
+///
+/// **Endpoint Querier**
+///
+/// Discovers a remote endpoint and builds a model of it from the replies.
+/// Function blocks are requested once the Endpoint Info has been received.
+/// Function block names are expected one block at a time.
+///
+#[derive(Debug, Clone, Default)]
+pub struct EndpointQuerier {
+    endpoint: Option<Endpoint>,
+    name: TextBuffer<MAX_ENDPOINT_NAME>,
+    product_instance_id: TextBuffer<MAX_PRODUCT_INSTANCE_ID>,
+    block_name: (u7, TextBuffer<MAX_FUNCTION_BLOCK_NAME>), // Block being named
+}
+
+impl EndpointQuerier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Endpoint Discovery requesting all notifications
+    ///
+    pub fn discover(&self) -> UmpPacket {
+        StreamMessage::EndpointDiscovery {
+            version: UMP_VERSION,
+            filter: FILTER_ENDPOINT_ALL,
+        }
+        .to_packet()
+    }
+
+    ///
+    /// Stream Configuration Request to send to the remote endpoint
+    ///
+    pub fn request_configuration(&self, configuration: StreamConfiguration) -> UmpPacket {
+        StreamMessage::ConfigurationRequest {
+            protocol: configuration.protocol,
+            receive_jr: configuration.receive_jr,
+            transmit_jr: configuration.transmit_jr,
+        }
+        .to_packet()
+    }
+
+    ///
+    /// Remote endpoint; None until Endpoint Info has been received
+    ///
+    pub fn endpoint(&self) -> Option<&Endpoint> {
+        self.endpoint.as_ref()
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// Returns a follow up request to send.
+    ///
+    pub fn handle_event(&mut self, event: &UmpEvent) -> Option<UmpPacket> {
+        let message = match event {
+            UmpEvent::Stream(message) => message,
+            _ => return None,
+        };
+
+        if let StreamMessage::EndpointInfo {
+            version,
+            static_function_blocks,
+            function_blocks,
+            midi2,
+            midi1,
+            receive_jr,
+            transmit_jr,
+        } = *message
+        {
+            let endpoint = self.endpoint.get_or_insert_with(Endpoint::new);
+            endpoint.version = version;
+            endpoint.static_function_blocks = static_function_blocks;
+            endpoint.midi2 = midi2;
+            endpoint.midi1 = midi1;
+            endpoint.receive_jr = receive_jr;
+            endpoint.transmit_jr = transmit_jr;
+            endpoint
+                .function_blocks
+                .retain(|entry| entry.block < function_blocks);
+            return if function_blocks > 0 {
+                Some(
+                    StreamMessage::FunctionBlockDiscovery {
+                        block: ALL_FUNCTION_BLOCKS,
+                        filter: FILTER_FUNCTION_BLOCK_ALL,
+                    }
+                    .to_packet(),
+                )
+            } else {
+                None
+            };
+        }
+
+        let endpoint = self.endpoint.as_mut()?;
+        match *message {
+            StreamMessage::DeviceIdentity {
+                manufacturer,
+                family,
+                model,
+                revision,
+            } => {
+                endpoint.identity = Some(DeviceIdentity {
+                    manufacturer,
+                    family,
+                    model,
+                    revision,
+                })
+            }
+            StreamMessage::EndpointName(text) => {
+                if let Some(name) = self.name.append(&text) {
+                    endpoint.name = name;
+                }
+            }
+            StreamMessage::ProductInstanceId(text) => {
+                if let Some(id) = self.product_instance_id.append(&text) {
+                    endpoint.product_instance_id = id;
+                }
+            }
+            StreamMessage::ConfigurationNotification {
+                protocol,
+                receive_jr,
+                transmit_jr,
+            } => {
+                endpoint.configuration = StreamConfiguration {
+                    protocol,
+                    receive_jr,
+                    transmit_jr,
+                }
+            }
+            StreamMessage::FunctionBlockInfo {
+                active,
+                block,
+                ui_hint,
+                midi1,
+                direction,
+                first_group,
+                groups,
+                ci_version,
+                sysex8_streams,
+            } => {
+                let entry = function_block_mut(endpoint, block)?;
+                entry.active = active;
+                entry.ui_hint = ui_hint;
+                entry.midi1 = midi1;
+                entry.direction = direction;
+                entry.first_group = first_group;
+                entry.groups = groups;
+                entry.ci_version = ci_version;
+                entry.sysex8_streams = sysex8_streams;
+            }
+            StreamMessage::FunctionBlockName { block, text } => {
+                if let Form::Complete | Form::Start = text.form {
+                    self.block_name.0 = block;
+                } else if self.block_name.0 != block {
+                    // Continues a name that was never started
+                    return None;
+                }
+                if let Some(name) = self.block_name.1.append(&text) {
+                    function_block_mut(endpoint, block)?.name = name;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+///
+/// Function block of the endpoint, added if new; None if there is no room
+///
+fn function_block_mut(endpoint: &mut Endpoint, block: u7) -> Option<&mut FunctionBlock> {
+    if endpoint.function_block(block).is_none() {
+        endpoint
+            .function_blocks
+            .push(FunctionBlock::new(block, 0, 1));
+        endpoint
+            .function_blocks
+            .sort_unstable_by_key(|entry| entry.block);
+    }
+    endpoint
+        .function_blocks
+        .iter_mut()
+        .find(|entry| entry.block == block)
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::endpoint::{
+        DeviceIdentity, Endpoint, EndpointQuerier, EndpointResponder, FunctionBlock,
+        StreamConfiguration, MAX_ENDPOINT_NAME,
+    };
+    use crate::{List, Protocol, StreamMessage, UmpEvent, UmpPacket};
+
+    fn device() -> Endpoint {
+        Endpoint::new()
+            .with_name("Embedded Synthesizer")
+            .with_product_instance_id("0001")
+            .with_identity(DeviceIdentity {
+                manufacturer: [0x00, 0x21, 0x09],
+                family: 1,
+                model: 2,
+                revision: [0, 1, 0, 0],
+            })
+            .with_function_block(FunctionBlock::new(0, 0, 1).with_name("Keyboard"))
+            .with_function_block(FunctionBlock::new(1, 1, 2).with_name("Sound Generator Engine"))
+    }
+
+    fn events(packets: &[UmpPacket]) -> Vec<UmpEvent> {
+        packets.iter().map(UmpEvent::decode).collect()
+    }
+
+    fn replies(target: &mut EndpointResponder, request: &UmpPacket) -> Vec<UmpPacket> {
+        let mut replies = Vec::new();
+        target.handle_event(&UmpEvent::decode(request), &mut replies);
+        replies
+    }
+
+    #[test]
+    fn handle_event__endpoint_discovery() {
+        let mut target = EndpointResponder::new(device());
+        let discovery = StreamMessage::EndpointDiscovery {
+            version: (1, 1),
+            filter: 0x05,
+        };
+
+        let actual = replies(&mut target, &discovery.to_packet());
+
+        // Info and a name split over 2 packets
+        assert_eq!(actual.len(), 3);
+        assert_eq!(
+            UmpEvent::decode(&actual[0]),
+            UmpEvent::Stream(StreamMessage::EndpointInfo {
+                version: (1, 1),
+                static_function_blocks: true,
+                function_blocks: 2,
+                midi2: true,
+                midi1: true,
+                receive_jr: false,
+                transmit_jr: false,
+            })
+        );
+    }
+
+    #[test]
+    fn handle_event__function_block_discovery() {
+        let mut target = EndpointResponder::new(device());
+        let discovery = StreamMessage::FunctionBlockDiscovery {
+            block: 1,
+            filter: 0x01,
+        };
+
+        let actual = events(&replies(&mut target, &discovery.to_packet()));
+
+        assert_eq!(
+            actual,
+            vec![UmpEvent::Stream(StreamMessage::FunctionBlockInfo {
+                active: true,
+                block: 1,
+                ui_hint: 3,
+                midi1: 0,
+                direction: 3,
+                first_group: 1,
+                groups: 2,
+                ci_version: 0,
+                sysex8_streams: 0,
+            })]
+        );
+    }
+
+    #[test]
+    fn handle_event__configuration_request() {
+        let mut target = EndpointResponder::new(device());
+        let request = StreamMessage::ConfigurationRequest {
+            protocol: Protocol::Midi2,
+            receive_jr: true,
+            transmit_jr: false,
+        };
+
+        let actual = events(&replies(&mut target, &request.to_packet()));
+
+        // JR timestamps are not supported by the endpoint
+        assert_eq!(
+            actual,
+            vec![UmpEvent::Stream(StreamMessage::ConfigurationNotification {
+                protocol: Protocol::Midi2,
+                receive_jr: false,
+                transmit_jr: false,
+            })]
+        );
+        assert_eq!(target.endpoint().configuration.protocol, Protocol::Midi2);
+    }
+
+    #[test]
+    fn handle_event__where_reply_buffer_is_full() {
+        let mut target = EndpointResponder::new(device());
+        let discovery = StreamMessage::EndpointDiscovery {
+            version: (1, 1),
+            filter: 0x1F,
+        };
+        let mut actual = List::<UmpPacket, 2>::default();
+
+        target.handle_event(&UmpEvent::Stream(discovery), &mut actual);
+
+        assert_eq!(actual.len(), 2);
+        assert!(matches!(
+            UmpEvent::decode(&actual[0]),
+            UmpEvent::Stream(StreamMessage::EndpointInfo { .. })
+        ));
+    }
+
+    #[test]
+    fn with_name__truncates_at_character_boundary() {
+        let name = format!("a{}", "é".repeat(50));
+
+        let actual = Endpoint::new().with_name(&name);
+
+        assert_eq!(*actual.name, name.as_bytes()[..MAX_ENDPOINT_NAME - 1]);
+    }
+
+    #[test]
+    fn querier__discovers_responder() {
+        let mut responder = EndpointResponder::new(device());
+        let mut target = EndpointQuerier::new();
+
+        let mut requests = vec![target.discover()];
+        while let Some(request) = requests.pop() {
+            for reply in replies(&mut responder, &request) {
+                requests.extend(target.handle_event(&UmpEvent::decode(&reply)));
+            }
+        }
+
+        assert_eq!(target.endpoint(), Some(&device()));
+    }
+
+    #[test]
+    fn querier__request_configuration() {
+        let mut responder = EndpointResponder::new(device());
+        let mut target = EndpointQuerier::new();
+        let configuration = StreamConfiguration {
+            protocol: Protocol::Midi2,
+            receive_jr: false,
+            transmit_jr: false,
+        };
+        for reply in replies(&mut responder, &target.discover()) {
+            target.handle_event(&UmpEvent::decode(&reply));
+        }
+
+        let request = target.request_configuration(configuration);
+        for reply in replies(&mut responder, &request) {
+            target.handle_event(&UmpEvent::decode(&reply));
+        }
+
+        assert_eq!(target.endpoint().unwrap().configuration, configuration);
+    }
+}
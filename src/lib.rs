@@ -1,5 +1,41 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+mod ble;
+mod ci;
+#[cfg(feature = "std")]
+mod clip;
+mod clock;
+pub mod controller;
+mod cv;
+mod endpoint;
+mod list;
+#[cfg(feature = "std")]
+mod merge;
+mod mpe;
+mod notes;
+#[cfg(feature = "std")]
+mod osc;
+pub mod parameter;
+mod pedal;
+#[cfg(feature = "std")]
+mod per_note;
+pub mod pitch_bend;
+mod processor;
 mod read;
+mod receiver;
+#[cfg(feature = "std")]
+mod rtp;
+mod sensing;
+mod state;
+mod sync;
+mod tempo;
 mod timecode;
+mod translate;
+mod transport;
+mod ump;
+mod usb;
+mod voice;
+mod write;
 
 #[allow(non_camel_case_types)]
 pub type u3 = u8;
@@ -13,7 +49,76 @@ pub type i14 = i16;
 #[allow(non_camel_case_types)]
 pub type u14 = u16;
 
-pub use read::{MidiEvent, MidiReader, SysExID};
+///
+/// Caller supplied time in microseconds
+///
+/// Components that depend on time never read a clock themselves, this allows
+/// them to be driven from a std thread or an embedded timer.
+///
+pub type Timestamp = u64;
+
+pub use ble::{
+    BleMidiReader, BleMidiWriter, BlePacket, BleTimestamp, BLE_DEFAULT_PACKET_SIZE,
+    BLE_MIN_PACKET_SIZE,
+};
+pub use ci::{
+    CiMessage, CiPayload, CiReader, DeviceInfo, Muid, Nak, PeCapabilities, ProfileId,
+    BROADCAST_MUID, CATEGORY_PROCESS_INQUIRY, CATEGORY_PROFILE_CONFIGURATION,
+    CATEGORY_PROPERTY_EXCHANGE, CI_VERSION, DEVICE_ID_FUNCTION_BLOCK, MAX_CI_MESSAGE_SIZE,
+    MAX_NAK_TEXT, MAX_PROFILES, SUB_ID_MIDI_CI, UNIVERSAL_NON_REALTIME,
+};
+#[cfg(feature = "std")]
+pub use clip::{Clip, ClipEvent};
+pub use clock::{ClockGenerator, START_DELAY};
+pub use cv::{
+    MidiToCv, NotePriority, PitchScale, VoltageRange, DEFAULT_TRIGGER_LENGTH, MAX_CV_CONTROLLERS,
+    MAX_CV_VOICES,
+};
+pub use endpoint::{
+    DeviceIdentity, Endpoint, EndpointQuerier, EndpointResponder, FunctionBlock,
+    StreamConfiguration, MAX_ENDPOINT_NAME, MAX_FUNCTION_BLOCKS, MAX_FUNCTION_BLOCK_NAME,
+    MAX_PRODUCT_INSTANCE_ID, UMP_VERSION,
+};
+pub use list::List;
+#[cfg(feature = "std")]
+pub use merge::{MidiMerger, DEFAULT_MAX_SYSEX};
+pub use mpe::{
+    MpeConfiguration, MpeEvent, MpeEvents, MpeExpression, MpeNote, MpeReceiver, MpeSender, Zone,
+    MAX_MPE_NOTES, MPE_DEFAULT_TIMBRE, MPE_MANAGER_BEND_RANGE, MPE_MEMBER_BEND_RANGE,
+};
+pub use notes::{panic_events, HeldNote, NoteTracker, MAX_HELD_NOTES};
+#[cfg(feature = "std")]
+pub use osc::{OscArgument, OscBridge, OscMapping, OscMessage};
+pub use pedal::{PedalState, SoundingNote, SoundingNotes, MAX_SOUNDING_NOTES, PEDAL_ON_THRESHOLD};
+#[cfg(feature = "std")]
+pub use per_note::{per_note_bend, MpeFallback, NoteState, PerNoteTracker, PITCH_BEND_CENTRE_32};
+pub use processor::{
+    Chain, ChannelFilter, ChannelRemap, ControllerRemap, KeyRange, KeySplit, MessageFilter,
+    Processor, Transpose, VelocityCurve,
+};
+pub use read::{MessageKind, MidiEvent, MidiEvents, MidiReader, SysExID};
+pub use receiver::{ChannelMode, NotesOff, Received, Receiver, VoiceAssignment};
+#[cfg(feature = "std")]
+pub use rtp::{Port, RtpMidiSession, RtpMidiSocket, SessionState};
+pub use sensing::{
+    ActiveSensingGenerator, ActiveSensingMonitor, SensingStatus, ACTIVE_SENSING_INTERVAL,
+    ACTIVE_SENSING_TIMEOUT,
+};
+pub use state::{ChannelState, MidiState};
+pub use sync::{ClockConverter, ClockRatio, PulseWidth, SyncEdge, SyncSignal, DEFAULT_PULSE_WIDTH};
+pub use tempo::{bpm_to_interval, interval_to_bpm, TempoEvent, TempoFollower, CLOCKS_PER_QUARTER};
+pub use timecode::{Rate, TimeCode};
+pub use translate::{scale_down, scale_up, Midi1ToMidi2, Midi2ToMidi1, NOTE_OFF_VELOCITY};
+pub use transport::{
+    BarsBeats, Position, TimeSignature, Transport, TransportEvent, CLOCKS_PER_MIDI_BEAT,
+};
+pub use ump::{
+    packet_words, Form, Group, Midi2Event, NoteAttribute, Protocol, StreamMessage, StreamText,
+    SysEx7, SysEx8, UmpEvent, UmpPacket, UmpReader, Utility,
+};
+pub use usb::{Cable, UsbMidiPacket, UsbMidiPackets, UsbMidiReader, UsbMidiWriter};
+pub use voice::{StealMode, Voice, VoiceAllocator, VoiceEvent, VoiceEvents, MAX_VOICES};
+pub use write::{EventBytes, MidiWriter};
 
 #[cfg(test)]
 mod tests {
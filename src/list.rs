@@ -0,0 +1,183 @@
+use core::fmt;
+use core::iter::FromIterator;
+use core::ops::{Deref, DerefMut};
+
+///
+/// **Fixed capacity list**
+///
+/// Holds up to `N` items without allocating so components can be used
+/// without std. Derefs to a slice of the items held; items pushed beyond the
+/// capacity are dropped.
+///
+#[derive(Copy, Clone)]
+pub struct List<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> List<T, N> {
+    ///
+    /// Empty list; `filler` occupies unused storage and is never visible
+    ///
+    pub fn with_filler(filler: T) -> Self {
+        Self {
+            items: [filler; N],
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    ///
+    /// Add an item, items beyond the capacity are dropped
+    ///
+    pub fn push(&mut self, item: T) {
+        if self.len < N {
+            self.items[self.len] = item;
+            self.len += 1;
+        }
+    }
+
+    ///
+    /// Remove the item at `index`, keeping the order of those after it
+    ///
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        let item = self[index];
+        self.items.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        item
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let mut kept = 0;
+        for index in 0..self.len {
+            if keep(&self.items[index]) {
+                self.items[kept] = self.items[index];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::with_filler(T::default())
+    }
+}
+
+impl<T, const N: usize> Deref for List<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+impl<T, const N: usize> DerefMut for List<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for List<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for List<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for List<T, N> {}
+
+impl<T: Copy, const N: usize> Extend<T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        // Items beyond the capacity are dropped
+        items.into_iter().for_each(|item| self.push(item));
+    }
+}
+
+impl<T: Copy + Default, const N: usize> FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let mut list = Self::default();
+        list.extend(items);
+        list
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for List<T, N> {
+    type Item = T;
+    type IntoIter = core::iter::Take<core::array::IntoIter<T, N>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let len = self.len;
+        IntoIterator::into_iter(self.items).take(len)
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::list::List;
+
+    #[test]
+    fn push__drops_items_beyond_capacity() {
+        let mut target = List::<u8, 2>::default();
+
+        target.push(1);
+        target.push(2);
+        target.push(3);
+
+        assert_eq!(*target, [1, 2]);
+        assert!(target.is_full());
+    }
+
+    #[test]
+    fn remove_and_retain__keep_order() {
+        let mut target = List::<u8, 8>::default();
+        for item in 1..=6 {
+            target.push(item);
+        }
+
+        assert_eq!(target.remove(1), 2);
+        target.retain(|item| item % 3 != 0);
+
+        assert_eq!(*target, [1, 4, 5]);
+        assert_eq!(target.into_iter().collect::<Vec<_>>(), vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn eq__ignores_unused_storage() {
+        let mut target = List::<u8, 4>::default();
+        target.push(1);
+        target.push(2);
+        target.remove(1);
+        let mut other = List::<u8, 4>::default();
+        other.push(1);
+
+        assert_eq!(target, other);
+    }
+}
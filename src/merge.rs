@@ -0,0 +1,217 @@
+use crate::read::MESSAGE_SYS_EX_END;
+use crate::{MessageKind, MidiEvent, MidiReader, MidiWriter};
+
+/// Default size of the largest System Exclusive message that is merged
+pub const DEFAULT_MAX_SYSEX: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Source {
+    reader: MidiReader,
+    sysex: Vec<u8>, // Buffered System Exclusive message (empty if none)
+}
+
+///
+/// **Midi Merger**
+///
+/// Merges the byte streams of several MIDI inputs into a single valid output
+/// stream. Each input is parsed with a [`MidiReader`] and only complete
+/// messages are written to the output so messages from different inputs are
+/// never interleaved:
+///
+/// * System Realtime messages are passed through immediately
+/// * System Exclusive messages are buffered until complete (an End byte or
+///   any other status byte) and then written in one piece; a message that
+///   grows beyond the maximum size is dropped
+/// * Status bytes are re-inserted whenever the output running status differs,
+///   so inputs that use running status are merged correctly
+///
+#[derive(Debug)]
+pub struct MidiMerger {
+    sources: Vec<Source>,
+    writer: MidiWriter,
+    output: Vec<u8>,
+    max_sysex: usize, // Largest buffered System Exclusive message (bytes)
+}
+
+impl MidiMerger {
+    ///
+    /// Merger for `sources` inputs; the output uses running status
+    ///
+    pub fn new(sources: usize) -> Self {
+        Self {
+            sources: (0..sources).map(|_| Source::default()).collect(),
+            writer: MidiWriter::new().with_running_status(),
+            output: Vec::new(),
+            max_sysex: DEFAULT_MAX_SYSEX,
+        }
+    }
+
+    ///
+    /// Set the size of the largest System Exclusive message (including the
+    /// Start byte) that is merged
+    ///
+    pub fn with_max_sysex(mut self, size: usize) -> Self {
+        self.max_sysex = size;
+        self
+    }
+
+    ///
+    /// Enable or disable running status on the output
+    ///
+    pub fn with_running_status(mut self, enabled: bool) -> Self {
+        self.writer = if enabled {
+            MidiWriter::new().with_running_status()
+        } else {
+            MidiWriter::new()
+        };
+        self
+    }
+
+    pub fn sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    ///
+    /// **Handle a byte from an input**
+    ///
+    /// Returns the bytes to send to the output (often none). Bytes from an
+    /// unknown source are ignored.
+    ///
+    pub fn handle_byte(&mut self, source: usize, byte: u8) -> &[u8] {
+        self.output.clear();
+        let source = match self.sources.get_mut(source) {
+            Some(source) => source,
+            None => return &self.output,
+        };
+        let event = match source.reader.handle_byte(byte) {
+            Some(event) => event,
+            None => return &self.output,
+        };
+
+        match event {
+            MidiEvent::SystemExclusiveStart(_) => {
+                source.sysex.clear();
+                source.sysex.extend_from_slice(&event.to_bytes());
+            }
+            MidiEvent::SystemExclusiveData(data) if !source.sysex.is_empty() => {
+                if source.sysex.len() < self.max_sysex {
+                    source.sysex.push(data)
+                } else {
+                    // Too large to buffer; drop the rest of the message
+                    source.sysex.clear();
+                }
+            }
+            MidiEvent::SystemExclusiveData(_) => {}
+            MidiEvent::SystemExclusiveEnd if !source.sysex.is_empty() => {
+                Self::flush_sysex(source, &mut self.writer, &mut self.output);
+            }
+            MidiEvent::SystemExclusiveEnd => {}
+            event if event.kind() == MessageKind::SystemRealtime => {
+                self.output.extend_from_slice(&self.writer.write(&event));
+            }
+            event => {
+                // Any other status byte ends an unterminated System Exclusive
+                if !source.sysex.is_empty() {
+                    Self::flush_sysex(source, &mut self.writer, &mut self.output);
+                }
+                self.output.extend_from_slice(&self.writer.write(&event));
+            }
+        }
+        &self.output
+    }
+
+    fn flush_sysex(source: &mut Source, writer: &mut MidiWriter, output: &mut Vec<u8>) {
+        writer.reset();
+        output.append(&mut source.sysex);
+        output.push(MESSAGE_SYS_EX_END);
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::MidiMerger;
+
+    fn feed(target: &mut MidiMerger, source: usize, bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .flat_map(|byte| target.handle_byte(source, *byte).to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn handle_byte__does_not_interleave_messages() {
+        let mut target = MidiMerger::new(2);
+
+        let mut actual = feed(&mut target, 0, &[0x90, 60]);
+        actual.extend(feed(&mut target, 1, &[0x91, 62, 100]));
+        actual.extend(feed(&mut target, 0, &[100]));
+
+        assert_eq!(actual, vec![0x91, 62, 100, 0x90, 60, 100]);
+    }
+
+    #[test]
+    fn handle_byte__realtime_passes_immediately() {
+        let mut target = MidiMerger::new(2);
+
+        let mut actual = feed(&mut target, 0, &[0x90, 60]);
+        actual.extend(feed(&mut target, 1, &[0xF8]));
+        actual.extend(feed(&mut target, 0, &[0xFE, 100]));
+
+        assert_eq!(actual, vec![0xF8, 0xFE, 0x90, 60, 100]);
+    }
+
+    #[test]
+    fn handle_byte__buffers_sysex() {
+        let mut target = MidiMerger::new(2);
+
+        let mut actual = feed(&mut target, 0, &[0xF0, 0x43, 1, 2]);
+        actual.extend(feed(&mut target, 1, &[0xC0, 5]));
+        actual.extend(feed(&mut target, 0, &[0xF8, 3, 0xF7]));
+
+        assert_eq!(actual, vec![0xC0, 5, 0xF8, 0xF0, 0x43, 1, 2, 3, 0xF7]);
+    }
+
+    #[test]
+    fn handle_byte__drops_sysex_beyond_max_size() {
+        let mut target = MidiMerger::new(1).with_max_sysex(4);
+
+        let mut actual = feed(&mut target, 0, &[0xF0, 0x7D, 1, 2, 3, 4, 0xF7]);
+        actual.extend(feed(&mut target, 0, &[0xF0, 0x7D, 1, 0xF7]));
+
+        assert_eq!(actual, vec![0xF0, 0x7D, 1, 0xF7]);
+    }
+
+    #[test]
+    fn handle_byte__terminates_interrupted_sysex() {
+        let mut target = MidiMerger::new(1);
+
+        let actual = feed(&mut target, 0, &[0xF0, 0x7E, 1, 0x80, 60, 0]);
+
+        assert_eq!(actual, vec![0xF0, 0x7E, 1, 0xF7, 0x80, 60, 0]);
+    }
+
+    #[test]
+    fn handle_byte__reinserts_running_status() {
+        let mut target = MidiMerger::new(2);
+
+        let mut actual = feed(&mut target, 0, &[0x90, 60, 100, 62, 100]);
+        actual.extend(feed(&mut target, 1, &[0x90, 64, 100]));
+        actual.extend(feed(&mut target, 1, &[0xB0, 7, 100]));
+        actual.extend(feed(&mut target, 0, &[60, 0]));
+
+        assert_eq!(
+            actual,
+            vec![0x90, 60, 100, 62, 100, 64, 100, 0xB0, 7, 100, 0x90, 60, 0]
+        );
+    }
+
+    #[test]
+    fn handle_byte__where_running_status_disabled() {
+        let mut target = MidiMerger::new(1).with_running_status(false);
+
+        let actual = feed(&mut target, 0, &[0x90, 60, 100, 62, 100]);
+
+        assert_eq!(actual, vec![0x90, 60, 100, 0x90, 62, 100]);
+    }
+}
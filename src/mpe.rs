@@ -0,0 +1,850 @@
+use crate::controller::BRIGHTNESS;
+use crate::parameter::{
+    ParameterChange, ParameterKind, ParameterTracker, RPN_MPE_CONFIGURATION, RPN_PITCH_BEND_RANGE,
+};
+use crate::pitch_bend::{self, PITCH_BEND_CENTRE};
+use crate::{i14, u7, Channel, List, MidiEvent, MidiEvents};
+
+/// Default pitch bend range of member channels (semitones)
+pub const MPE_MEMBER_BEND_RANGE: u7 = 48;
+/// Default pitch bend range of manager channels (semitones)
+pub const MPE_MANAGER_BEND_RANGE: u7 = 2;
+/// Timbre (CC 74) value before any has been received
+pub const MPE_DEFAULT_TIMBRE: u7 = 64;
+/// Number of notes tracked by an MPE receiver or sender
+pub const MAX_MPE_NOTES: usize = 32;
+
+const LOWER_MANAGER: Channel = 0;
+const UPPER_MANAGER: Channel = 15;
+
+///
+/// MPE zone
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Zone {
+    Lower, // Manager channel 1 (0), members ascending from channel 2 (1)
+    Upper, // Manager channel 16 (15), members descending from channel 15 (14)
+}
+
+impl Zone {
+    pub fn manager_channel(&self) -> Channel {
+        match self {
+            Zone::Lower => LOWER_MANAGER,
+            Zone::Upper => UPPER_MANAGER,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct ZoneConfig {
+    members: u7,
+    member_bend_range: u7,
+    manager_bend_range: u7,
+}
+
+impl ZoneConfig {
+    fn new(members: u7) -> Self {
+        Self {
+            members,
+            member_bend_range: MPE_MEMBER_BEND_RANGE,
+            manager_bend_range: MPE_MANAGER_BEND_RANGE,
+        }
+    }
+}
+
+///
+/// **MPE Configuration**
+///
+/// Zone layout as set by MPE Configuration Messages (RPN 6 sent on a manager
+/// channel) along with the pitch bend range (RPN 0) of each zone.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MpeConfiguration {
+    lower: ZoneConfig,
+    upper: ZoneConfig,
+}
+
+impl MpeConfiguration {
+    ///
+    /// Configuration with no zones (MPE disabled)
+    ///
+    pub fn new() -> Self {
+        Self {
+            lower: ZoneConfig::new(0),
+            upper: ZoneConfig::new(0),
+        }
+    }
+
+    ///
+    /// Set the number of member channels of a zone (0 disables the zone)
+    ///
+    /// As per the spec the other zone is reduced if the two would overlap and
+    /// pitch bend ranges return to their defaults.
+    ///
+    pub fn set_zone(&mut self, zone: Zone, members: u7) {
+        let members = members.min(15);
+        let (this, other) = match zone {
+            Zone::Lower => (&mut self.lower, &mut self.upper),
+            Zone::Upper => (&mut self.upper, &mut self.lower),
+        };
+        *this = ZoneConfig::new(members);
+        if members + other.members > 14 {
+            other.members = 14u8.saturating_sub(members);
+        }
+    }
+
+    ///
+    /// Builder form of `set_zone`
+    ///
+    pub fn with_zone(mut self, zone: Zone, members: u7) -> Self {
+        self.set_zone(zone, members);
+        self
+    }
+
+    pub fn members(&self, zone: Zone) -> u7 {
+        self.zone(zone).members
+    }
+
+    pub fn is_enabled(&self, zone: Zone) -> bool {
+        self.members(zone) > 0
+    }
+
+    ///
+    /// Member channels of a zone in allocation order
+    ///
+    pub fn member_channels(&self, zone: Zone) -> List<Channel, 15> {
+        let mut channels = List::default();
+        for index in 0..self.members(zone) {
+            channels.push(match zone {
+                Zone::Lower => 1 + index,
+                Zone::Upper => 14 - index,
+            });
+        }
+        channels
+    }
+
+    ///
+    /// Zone a channel belongs to and whether it is the manager channel
+    ///
+    pub fn zone_of(&self, channel: Channel) -> Option<(Zone, bool)> {
+        let lower = self.lower.members;
+        let upper = self.upper.members;
+        match channel {
+            LOWER_MANAGER if lower > 0 => Some((Zone::Lower, true)),
+            UPPER_MANAGER if upper > 0 => Some((Zone::Upper, true)),
+            _ if lower > 0 && channel <= lower => Some((Zone::Lower, false)),
+            _ if upper > 0 && channel < UPPER_MANAGER && channel >= UPPER_MANAGER - upper => {
+                Some((Zone::Upper, false))
+            }
+            _ => None,
+        }
+    }
+
+    ///
+    /// Pitch bend range (semitones) of member channels
+    ///
+    pub fn member_bend_range(&self, zone: Zone) -> u7 {
+        self.zone(zone).member_bend_range
+    }
+
+    ///
+    /// Pitch bend range (semitones) of the manager channel
+    ///
+    pub fn manager_bend_range(&self, zone: Zone) -> u7 {
+        self.zone(zone).manager_bend_range
+    }
+
+    ///
+    /// **Apply a parameter change**
+    ///
+    /// Returns true if the configuration was changed.
+    ///
+    pub fn handle_parameter(&mut self, change: &ParameterChange) -> bool {
+        if change.kind != ParameterKind::Registered {
+            return false;
+        }
+        let previous = *self;
+        match (change.parameter, change.channel) {
+            (RPN_MPE_CONFIGURATION, LOWER_MANAGER) => self.set_zone(Zone::Lower, change.msb),
+            (RPN_MPE_CONFIGURATION, UPPER_MANAGER) => self.set_zone(Zone::Upper, change.msb),
+            (RPN_PITCH_BEND_RANGE, channel) => match self.zone_of(channel) {
+                Some((zone, true)) => self.zone_mut(zone).manager_bend_range = change.msb,
+                Some((zone, false)) => self.zone_mut(zone).member_bend_range = change.msb,
+                None => return false,
+            },
+            _ => return false,
+        }
+        *self != previous
+    }
+
+    ///
+    /// **MPE Configuration Message events for a zone**
+    ///
+    pub fn to_events(&self, zone: Zone) -> [MidiEvent; 6] {
+        ParameterChange {
+            channel: zone.manager_channel(),
+            kind: ParameterKind::Registered,
+            parameter: RPN_MPE_CONFIGURATION,
+            msb: self.members(zone),
+            lsb: 0,
+        }
+        .to_events()
+    }
+
+    fn zone(&self, zone: Zone) -> &ZoneConfig {
+        match zone {
+            Zone::Lower => &self.lower,
+            Zone::Upper => &self.upper,
+        }
+    }
+
+    fn zone_mut(&mut self, zone: Zone) -> &mut ZoneConfig {
+        match zone {
+            Zone::Lower => &mut self.lower,
+            Zone::Upper => &mut self.upper,
+        }
+    }
+}
+
+impl Default for MpeConfiguration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Per-note expression dimensions
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MpeExpression {
+    pub bend: i14,    // Pitch bend
+    pub pressure: u7, // Channel pressure
+    pub timbre: u7,   // CC 74
+}
+
+impl Default for MpeExpression {
+    fn default() -> Self {
+        Self {
+            bend: PITCH_BEND_CENTRE,
+            pressure: 0,
+            timbre: MPE_DEFAULT_TIMBRE,
+        }
+    }
+}
+
+///
+/// A note playing on a member channel
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MpeNote {
+    pub zone: Zone,
+    pub channel: Channel,
+    pub key: u7,
+    pub velocity: u7,
+    pub expression: MpeExpression,
+}
+
+impl MpeNote {
+    const NONE: MpeNote = MpeNote {
+        zone: Zone::Lower,
+        channel: 0,
+        key: 0,
+        velocity: 0,
+        expression: MpeExpression {
+            bend: PITCH_BEND_CENTRE,
+            pressure: 0,
+            timbre: MPE_DEFAULT_TIMBRE,
+        },
+    };
+}
+
+///
+/// Event produced by the MPE receiver
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MpeEvent {
+    NoteOn(MpeNote),
+    NoteOff(MpeNote, u7),  // Note, Release velocity
+    Expression(MpeNote),   // Expression of a note changed
+    Zone(Zone, MidiEvent), // Message on a manager channel; applies to the zone
+    Configured(MpeConfiguration),
+    NonMpe(MidiEvent), // Message on a channel outside of any zone
+}
+
+///
+/// MPE events produced from a single message; room for a Note Off for every
+/// note and the message itself
+///
+pub type MpeEvents = List<MpeEvent, { MAX_MPE_NOTES + 1 }>;
+
+///
+/// **MPE Receiver**
+///
+/// Groups pitch bend, channel pressure and timbre (CC 74) received on member
+/// channels with the notes playing on those channels. Expression sent before
+/// a Note On sets the initial expression of that note. Zones are configured
+/// from MPE Configuration Messages in the received stream. Up to
+/// `MAX_MPE_NOTES` notes are tracked; further Note Ons are ignored.
+///
+/// All Notes Off, All Sound Off and mode changes end the notes of a member
+/// channel or, on a manager channel, of the whole zone; a System Reset ends
+/// every note.
+///
+#[derive(Debug, Clone)]
+pub struct MpeReceiver {
+    configuration: MpeConfiguration,
+    parameters: ParameterTracker,
+    channels: [MpeExpression; 16],
+    notes: List<MpeNote, MAX_MPE_NOTES>,
+}
+
+impl MpeReceiver {
+    pub fn new() -> Self {
+        Self {
+            configuration: MpeConfiguration::new(),
+            parameters: ParameterTracker::new(),
+            channels: [MpeExpression::default(); 16],
+            notes: List::with_filler(MpeNote::NONE),
+        }
+    }
+
+    pub fn with_configuration(mut self, configuration: MpeConfiguration) -> Self {
+        self.configuration = configuration;
+        self
+    }
+
+    pub fn configuration(&self) -> &MpeConfiguration {
+        &self.configuration
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = &MpeNote> {
+        self.notes.iter()
+    }
+
+    ///
+    /// Pitch of a note in (fractional) semitones including member and
+    /// manager channel pitch bend
+    ///
+    pub fn pitch(&self, note: &MpeNote) -> f32 {
+        let config = &self.configuration;
+        let manager = self.channels[note.zone.manager_channel() as usize].bend;
+        note.key as f32
+            + pitch_bend::to_semitones(
+                note.expression.bend,
+                config.member_bend_range(note.zone) as f32,
+            )
+            + pitch_bend::to_semitones(manager, config.manager_bend_range(note.zone) as f32)
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> MpeEvents {
+        let mut events = List::with_filler(MpeEvent::NonMpe(MidiEvent::SystemExclusiveEnd));
+        if let Some(change) = self.parameters.handle_event(event) {
+            if self.configuration.handle_parameter(&change) {
+                if change.parameter == RPN_MPE_CONFIGURATION {
+                    self.end_notes(|_| true, &mut events);
+                }
+                events.push(MpeEvent::Configured(self.configuration));
+                return events;
+            }
+        }
+
+        if let MidiEvent::SystemReset = event {
+            self.end_notes(|_| true, &mut events);
+        }
+        let channel = match event.channel() {
+            Some(channel) => channel & 0x0F,
+            None => return events,
+        };
+        let zone = match self.configuration.zone_of(channel) {
+            Some((zone, false)) => zone,
+            Some((zone, true)) => {
+                match *event {
+                    MidiEvent::PitchBend(_, amount) => {
+                        self.channels[channel as usize].bend = amount
+                    }
+                    event if ends_notes(&event) => {
+                        self.end_notes(|note| note.zone == zone, &mut events)
+                    }
+                    _ => (),
+                }
+                events.push(MpeEvent::Zone(zone, *event));
+                return events;
+            }
+            None => {
+                events.push(MpeEvent::NonMpe(*event));
+                return events;
+            }
+        };
+
+        let expression = &mut self.channels[channel as usize];
+        match *event {
+            MidiEvent::NoteOn(_, key, 0) | MidiEvent::NoteOff(_, key, _) => {
+                let velocity = match *event {
+                    MidiEvent::NoteOff(_, _, velocity) => velocity,
+                    _ => 0,
+                };
+                if let Some(index) = self
+                    .notes
+                    .iter()
+                    .position(|note| note.channel == channel && note.key == key)
+                {
+                    events.push(MpeEvent::NoteOff(self.notes.remove(index), velocity));
+                }
+                return events;
+            }
+            MidiEvent::NoteOn(_, key, velocity) => {
+                if self.notes.is_full() {
+                    return events;
+                }
+                let note = MpeNote {
+                    zone,
+                    channel,
+                    key,
+                    velocity,
+                    expression: *expression,
+                };
+                self.notes.push(note);
+                events.push(MpeEvent::NoteOn(note));
+                return events;
+            }
+            MidiEvent::PitchBend(_, amount) => expression.bend = amount,
+            MidiEvent::ChannelAfterTouch(_, pressure) => expression.pressure = pressure,
+            MidiEvent::ControllerChange(_, BRIGHTNESS, value) => expression.timbre = value,
+            _ => {
+                if ends_notes(event) {
+                    self.end_notes(|note| note.channel == channel, &mut events);
+                }
+                events.push(MpeEvent::Zone(zone, *event));
+                return events;
+            }
+        }
+
+        let expression = *expression;
+        for note in self.notes.iter_mut().filter(|note| note.channel == channel) {
+            note.expression = expression;
+            events.push(MpeEvent::Expression(*note));
+        }
+        events
+    }
+}
+
+impl MpeReceiver {
+    ///
+    /// Remove the matching notes with a Note Off for each
+    ///
+    fn end_notes(&mut self, matches: impl Fn(&MpeNote) -> bool, events: &mut MpeEvents) {
+        for note in self.notes.iter().filter(|note| matches(note)) {
+            events.push(MpeEvent::NoteOff(*note, 0));
+        }
+        self.notes.retain(|note| !matches(note));
+    }
+}
+
+///
+/// Message ends the notes of its channel
+///
+fn ends_notes(event: &MidiEvent) -> bool {
+    matches!(
+        event,
+        MidiEvent::AllNotesOff(_)
+            | MidiEvent::AllSoundOff(_)
+            | MidiEvent::OmniMode(_, _)
+            | MidiEvent::MonoMode(_, _)
+            | MidiEvent::PolyphonicMode(_)
+    )
+}
+
+impl Default for MpeReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct MemberChannel {
+    channel: Channel,
+    notes: usize,
+    last_used: u64,
+}
+
+///
+/// **MPE Sender**
+///
+/// Allocates a member channel of a zone to each outgoing note so each note
+/// can be given its own pitch bend, pressure and timbre. A free channel that
+/// was used the longest ago is preferred; when all channels are in use the
+/// channel with the fewest notes (then the oldest) is shared. Up to
+/// `MAX_MPE_NOTES` notes can be playing at once.
+///
+#[derive(Debug, Clone)]
+pub struct MpeSender {
+    zone: Zone,
+    configuration: MpeConfiguration,
+    members: List<MemberChannel, 15>,
+    notes: List<(u7, Channel), MAX_MPE_NOTES>, // Key, Member channel
+    counter: u64,
+}
+
+impl MpeSender {
+    pub fn new(zone: Zone, configuration: MpeConfiguration) -> Self {
+        let mut members = List::default();
+        for channel in configuration.member_channels(zone) {
+            members.push(MemberChannel {
+                channel,
+                notes: 0,
+                last_used: 0,
+            });
+        }
+        Self {
+            zone,
+            configuration,
+            members,
+            notes: List::default(),
+            counter: 0,
+        }
+    }
+
+    ///
+    /// MPE Configuration Message to send to receivers
+    ///
+    pub fn configure(&self) -> [MidiEvent; 6] {
+        self.configuration.to_events(self.zone)
+    }
+
+    ///
+    /// **Start a note**
+    ///
+    /// Returns the expression of the note followed by the Note On, all on the
+    /// allocated member channel. A note already playing on `key` is ended
+    /// first. Returns nothing if the zone has no members or `MAX_MPE_NOTES`
+    /// are already playing.
+    ///
+    pub fn note_on(&mut self, key: u7, velocity: u7, expression: MpeExpression) -> MidiEvents {
+        let mut events = MidiEvents::new();
+        if self.members.is_empty() {
+            return events;
+        }
+        if let Some(event) = self.note_off(key, 0) {
+            events.push(event);
+        }
+        if self.notes.is_full() {
+            return events;
+        }
+        let member = match self
+            .members
+            .iter_mut()
+            .min_by_key(|member| (member.notes, member.last_used))
+        {
+            Some(member) => member,
+            None => return events,
+        };
+        self.counter += 1;
+        member.notes += 1;
+        member.last_used = self.counter;
+        let channel = member.channel;
+        self.notes.push((key, channel));
+
+        for event in &[
+            MidiEvent::PitchBend(channel, expression.bend),
+            MidiEvent::ControllerChange(channel, BRIGHTNESS, expression.timbre),
+            MidiEvent::ChannelAfterTouch(channel, expression.pressure),
+            MidiEvent::NoteOn(channel, key, velocity),
+        ] {
+            events.push(*event);
+        }
+        events
+    }
+
+    ///
+    /// **End a note**
+    ///
+    pub fn note_off(&mut self, key: u7, velocity: u7) -> Option<MidiEvent> {
+        let index = self.notes.iter().position(|(held, _)| *held == key)?;
+        let (_, channel) = self.notes.remove(index);
+        self.counter += 1;
+        if let Some(member) = self.members.iter_mut().find(|m| m.channel == channel) {
+            member.notes = member.notes.saturating_sub(1);
+            member.last_used = self.counter;
+        }
+        Some(MidiEvent::NoteOff(channel, key, velocity))
+    }
+
+    ///
+    /// **Forget all notes**
+    ///
+    /// Frees every member channel, eg after All Notes Off or All Sound Off
+    /// has been sent to the zone.
+    ///
+    pub fn release_all(&mut self) {
+        self.notes.clear();
+        for member in self.members.iter_mut() {
+            member.notes = 0;
+        }
+    }
+
+    pub fn pitch_bend(&self, key: u7, amount: i14) -> Option<MidiEvent> {
+        self.channel_of(key)
+            .map(|channel| MidiEvent::PitchBend(channel, amount))
+    }
+
+    pub fn pressure(&self, key: u7, pressure: u7) -> Option<MidiEvent> {
+        self.channel_of(key)
+            .map(|channel| MidiEvent::ChannelAfterTouch(channel, pressure))
+    }
+
+    pub fn timbre(&self, key: u7, value: u7) -> Option<MidiEvent> {
+        self.channel_of(key)
+            .map(|channel| MidiEvent::ControllerChange(channel, BRIGHTNESS, value))
+    }
+
+    ///
+    /// Member channel a note is playing on
+    ///
+    pub fn channel_of(&self, key: u7) -> Option<Channel> {
+        self.notes
+            .iter()
+            .find(|(held, _)| *held == key)
+            .map(|(_, channel)| *channel)
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::mpe::{
+        MpeConfiguration, MpeEvent, MpeExpression, MpeReceiver, MpeSender, Zone, MAX_MPE_NOTES,
+    };
+    use crate::MidiEvent;
+
+    #[test]
+    fn configuration__zones_do_not_overlap() {
+        let mut target = MpeConfiguration::new().with_zone(Zone::Lower, 10);
+
+        target.set_zone(Zone::Upper, 7);
+
+        assert_eq!(target.members(Zone::Upper), 7);
+        assert_eq!(target.members(Zone::Lower), 7);
+        assert_eq!(
+            *target.member_channels(Zone::Upper),
+            [14, 13, 12, 11, 10, 9, 8]
+        );
+        assert_eq!(target.zone_of(7), Some((Zone::Lower, false)));
+        assert_eq!(target.zone_of(8), Some((Zone::Upper, false)));
+        assert_eq!(target.zone_of(15), Some((Zone::Upper, true)));
+    }
+
+    #[test]
+    fn handle_event__configures_from_mcm() {
+        let mut target = MpeReceiver::new();
+        let config = MpeConfiguration::new().with_zone(Zone::Lower, 5);
+
+        let actual: Vec<MpeEvent> = config
+            .to_events(Zone::Lower)
+            .iter()
+            .flat_map(|event| target.handle_event(event))
+            .filter(|event| matches!(event, MpeEvent::Configured(_)))
+            .collect();
+
+        assert_eq!(actual, vec![MpeEvent::Configured(config)]);
+        assert_eq!(target.configuration().members(Zone::Lower), 5);
+        assert_eq!(target.configuration().member_bend_range(Zone::Lower), 48);
+    }
+
+    #[test]
+    fn handle_event__reconfiguration_ends_notes() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 15));
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100));
+        let config = MpeConfiguration::new().with_zone(Zone::Lower, 5);
+
+        let actual: Vec<MpeEvent> = config
+            .to_events(Zone::Lower)
+            .iter()
+            .flat_map(|event| target.handle_event(event))
+            .filter(|event| !matches!(event, MpeEvent::Zone(..)))
+            .collect();
+
+        assert!(
+            matches!(actual[..], [MpeEvent::NoteOff(note, 0), MpeEvent::Configured(_)] if note.key == 60)
+        );
+        assert_eq!(target.notes().count(), 0);
+    }
+
+    #[test]
+    fn handle_event__groups_expression_by_note() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 15));
+
+        target.handle_event(&MidiEvent::PitchBend(1, 100));
+        let on = target.handle_event(&MidiEvent::NoteOn(1, 60, 90));
+        target.handle_event(&MidiEvent::NoteOn(2, 64, 90));
+        let pressure = target.handle_event(&MidiEvent::ChannelAfterTouch(2, 50));
+        let timbre = target.handle_event(&MidiEvent::ControllerChange(1, 74, 10));
+
+        match (&on[..], &pressure[..], &timbre[..]) {
+            (
+                [MpeEvent::NoteOn(on)],
+                [MpeEvent::Expression(pressure)],
+                [MpeEvent::Expression(timbre)],
+            ) => {
+                assert_eq!(on.expression.bend, 100);
+                assert_eq!((pressure.key, pressure.expression.pressure), (64, 50));
+                assert_eq!((timbre.key, timbre.expression.timbre), (60, 10));
+            }
+            _ => panic!("Unexpected events"),
+        }
+    }
+
+    #[test]
+    fn handle_event__manager_and_non_mpe_channels() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 3));
+
+        assert_eq!(
+            *target.handle_event(&MidiEvent::ControllerChange(0, 64, 127)),
+            [MpeEvent::Zone(
+                Zone::Lower,
+                MidiEvent::ControllerChange(0, 64, 127)
+            )]
+        );
+        assert_eq!(
+            *target.handle_event(&MidiEvent::NoteOn(9, 36, 100)),
+            [MpeEvent::NonMpe(MidiEvent::NoteOn(9, 36, 100))]
+        );
+    }
+
+    #[test]
+    fn handle_event__all_notes_off_on_manager_ends_zone_notes() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 15));
+        for key in 0..MAX_MPE_NOTES as u8 {
+            target.handle_event(&MidiEvent::NoteOn(1 + key % 15, 40 + key, 100));
+        }
+
+        let actual = target.handle_event(&MidiEvent::AllNotesOff(0));
+
+        assert_eq!(actual.len(), MAX_MPE_NOTES + 1);
+        assert!(matches!(actual[0], MpeEvent::NoteOff(note, 0) if note.key == 40));
+        assert_eq!(
+            actual[MAX_MPE_NOTES],
+            MpeEvent::Zone(Zone::Lower, MidiEvent::AllNotesOff(0))
+        );
+        assert_eq!(target.notes().count(), 0);
+        assert_eq!(target.handle_event(&MidiEvent::NoteOn(1, 60, 100)).len(), 1);
+    }
+
+    #[test]
+    fn handle_event__all_sound_off_on_member_ends_channel_notes() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 3));
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(2, 64, 100));
+
+        let actual = target.handle_event(&MidiEvent::AllSoundOff(1));
+
+        assert!(
+            matches!(actual[..], [MpeEvent::NoteOff(note, 0), MpeEvent::Zone(..)] if note.key == 60)
+        );
+        assert_eq!(
+            target.notes().map(|note| note.key).collect::<Vec<_>>(),
+            vec![64]
+        );
+    }
+
+    #[test]
+    fn pitch__combines_member_and_manager_bend() {
+        let mut target = MpeReceiver::new()
+            .with_configuration(MpeConfiguration::new().with_zone(Zone::Lower, 3));
+        target.handle_event(&MidiEvent::PitchBend(0, 8191));
+        target.handle_event(&MidiEvent::PitchBend(1, -8192));
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100));
+
+        let note = *target.notes().next().unwrap();
+
+        assert_eq!(target.pitch(&note), 60.0 - 48.0 + 2.0);
+    }
+
+    #[test]
+    fn sender__allocates_member_channels() {
+        let config = MpeConfiguration::new().with_zone(Zone::Upper, 2);
+        let mut target = MpeSender::new(Zone::Upper, config);
+
+        let first = target.note_on(60, 100, MpeExpression::default());
+        target.note_on(62, 100, MpeExpression::default());
+        // All channels in use; shares the oldest
+        let third = target.note_on(64, 100, MpeExpression::default());
+
+        assert_eq!(first[3], MidiEvent::NoteOn(14, 60, 100));
+        assert_eq!(third[3], MidiEvent::NoteOn(14, 64, 100));
+        assert_eq!(
+            target.pitch_bend(62, 10),
+            Some(MidiEvent::PitchBend(13, 10))
+        );
+        assert_eq!(target.note_off(60, 0), Some(MidiEvent::NoteOff(14, 60, 0)));
+    }
+
+    #[test]
+    fn sender__note_on_ends_note_already_playing() {
+        let config = MpeConfiguration::new().with_zone(Zone::Lower, 2);
+        let mut target = MpeSender::new(Zone::Lower, config);
+        target.note_on(60, 100, MpeExpression::default());
+
+        let actual = target.note_on(60, 90, MpeExpression::default());
+
+        assert_eq!(actual[0], MidiEvent::NoteOff(1, 60, 0));
+        assert_eq!(actual[4], MidiEvent::NoteOn(2, 60, 90));
+        assert_eq!(target.note_off(60, 0), Some(MidiEvent::NoteOff(2, 60, 0)));
+        assert_eq!(target.note_off(60, 0), None);
+    }
+
+    #[test]
+    fn sender__release_all_frees_member_channels() {
+        let config = MpeConfiguration::new().with_zone(Zone::Lower, 2);
+        let mut target = MpeSender::new(Zone::Lower, config);
+        target.note_on(60, 100, MpeExpression::default());
+        target.note_on(62, 100, MpeExpression::default());
+
+        target.release_all();
+
+        assert_eq!(target.channel_of(60), None);
+        assert_eq!(target.note_off(62, 0), None);
+        let actual = target.note_on(64, 100, MpeExpression::default());
+        assert_eq!(actual[3], MidiEvent::NoteOn(1, 64, 100));
+    }
+
+    #[test]
+    fn sender__to_receiver() {
+        let config = MpeConfiguration::new().with_zone(Zone::Lower, 4);
+        let mut sender = MpeSender::new(Zone::Lower, config);
+        let mut target = MpeReceiver::new();
+        let mut events: Vec<MidiEvent> = sender.configure().to_vec();
+        events.extend(sender.note_on(
+            60,
+            100,
+            MpeExpression {
+                bend: -20,
+                pressure: 5,
+                timbre: 99,
+            },
+        ));
+
+        let actual: Vec<MpeEvent> = events
+            .iter()
+            .flat_map(|event| target.handle_event(event))
+            .filter(|event| matches!(event, MpeEvent::NoteOn(_)))
+            .collect();
+
+        match actual[..] {
+            [MpeEvent::NoteOn(note)] => {
+                assert_eq!(note.channel, 1);
+                assert_eq!(note.expression.bend, -20);
+                assert_eq!(note.expression.pressure, 5);
+                assert_eq!(note.expression.timbre, 99);
+            }
+            _ => panic!("Expected a note; got {:?}", actual),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{u7, Channel, List, MidiEvent, Timestamp};
+
+/// Number of held notes whose hold time is tracked across all channels
+pub const MAX_HELD_NOTES: usize = 64;
+
+///
+/// A note that is currently held
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct HeldNote {
+    pub channel: Channel,
+    pub key: u7,
+    pub velocity: u7,
+    pub since: Timestamp, // Time of the Note On
+}
+
+impl HeldNote {
+    ///
+    /// Time the note has been held for
+    ///
+    pub fn held_for(&self, now: Timestamp) -> Timestamp {
+        now.saturating_sub(self.since)
+    }
+
+    ///
+    /// Note Off event that releases this note
+    ///
+    pub fn note_off(&self) -> MidiEvent {
+        MidiEvent::NoteOff(self.channel, self.key, 0)
+    }
+}
+
+///
+/// **Note Tracker**
+///
+/// Tracks held notes across all channels so hanging notes can be detected and
+/// released. A Note On with a velocity of 0 is treated as a Note Off. Every
+/// held key is recorded so a panic releases all of them, but the hold time is
+/// only tracked for the first `MAX_HELD_NOTES`; notes beyond that are not
+/// reported as held or hanging.
+///
+#[derive(Debug, Clone, Default)]
+pub struct NoteTracker {
+    notes: List<HeldNote, MAX_HELD_NOTES>,
+    held: [u128; 16], // Bit per key of each channel
+}
+
+impl NoteTracker {
+    pub fn new() -> Self {
+        Self {
+            notes: List::default(),
+            held: [0; 16],
+        }
+    }
+
+    ///
+    /// **Handle an event received at `now`**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, now: Timestamp) {
+        match *event {
+            MidiEvent::NoteOn(channel, key, 0) | MidiEvent::NoteOff(channel, key, _) => {
+                self.held[channel as usize & 0x0F] &= !(1 << (key & 0x7F));
+                self.notes
+                    .retain(|note| !(note.channel == channel && note.key == key));
+            }
+            MidiEvent::NoteOn(channel, key, velocity) => {
+                self.held[channel as usize & 0x0F] |= 1 << (key & 0x7F);
+                match self
+                    .notes
+                    .iter_mut()
+                    .find(|note| note.channel == channel && note.key == key)
+                {
+                    // Re-triggered note, restart the hold time
+                    Some(note) => {
+                        note.velocity = velocity;
+                        note.since = now;
+                    }
+                    None => self.notes.push(HeldNote {
+                        channel,
+                        key,
+                        velocity,
+                        since: now,
+                    }),
+                }
+            }
+            MidiEvent::AllNotesOff(channel) | MidiEvent::AllSoundOff(channel) => {
+                self.held[channel as usize & 0x0F] = 0;
+                self.notes.retain(|note| note.channel != channel)
+            }
+            MidiEvent::SystemReset => {
+                self.held = [0; 16];
+                self.notes.clear();
+            }
+            _ => (),
+        }
+    }
+
+    ///
+    /// Held notes in the order they were pressed
+    ///
+    pub fn held_notes(&self) -> impl Iterator<Item = &HeldNote> {
+        self.notes.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.iter().all(|keys| *keys == 0)
+    }
+
+    ///
+    /// **Notes held for at least `threshold`**
+    ///
+    pub fn hanging_notes(
+        &self,
+        now: Timestamp,
+        threshold: Timestamp,
+    ) -> impl Iterator<Item = &HeldNote> {
+        self.notes
+            .iter()
+            .filter(move |note| note.held_for(now) >= threshold)
+    }
+
+    ///
+    /// **Release hanging notes**
+    ///
+    /// Removes notes held for at least `threshold` and returns the Note Off
+    /// events required to release them.
+    ///
+    pub fn release_hanging(
+        &mut self,
+        now: Timestamp,
+        threshold: Timestamp,
+    ) -> impl Iterator<Item = MidiEvent> {
+        let mut hanging = List::<HeldNote, MAX_HELD_NOTES>::default();
+        let held = &mut self.held;
+        self.notes.retain(|note| {
+            let is_hanging = note.held_for(now) >= threshold;
+            if is_hanging {
+                held[note.channel as usize & 0x0F] &= !(1 << (note.key & 0x7F));
+                hanging.push(*note);
+            }
+            !is_hanging
+        });
+        hanging.into_iter().map(|note| note.note_off())
+    }
+
+    ///
+    /// **Generate a panic event list**
+    ///
+    /// A Note Off for every held note followed by All Notes Off and All Sound
+    /// Off on each of the 16 channels. Clears the tracker.
+    ///
+    pub fn panic(&mut self) -> impl Iterator<Item = MidiEvent> {
+        let held = self.held;
+        self.held = [0; 16];
+        self.notes.clear();
+        (0..16)
+            .flat_map(move |channel| {
+                (0..128)
+                    .filter(move |key| held[channel as usize] & (1 << key) > 0)
+                    .map(move |key| MidiEvent::NoteOff(channel, key, 0))
+            })
+            .chain(panic_events())
+    }
+}
+
+///
+/// **All Notes Off / All Sound Off for every channel**
+///
+pub fn panic_events() -> impl Iterator<Item = MidiEvent> {
+    (0..16).flat_map(|channel| {
+        [
+            MidiEvent::AllNotesOff(channel),
+            MidiEvent::AllSoundOff(channel),
+        ]
+    })
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::notes::{NoteTracker, MAX_HELD_NOTES};
+    use crate::MidiEvent;
+
+    #[test]
+    fn handle_event__where_velocity_zero_note_on_is_off() {
+        let mut target = NoteTracker::new();
+
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 0), 10);
+
+        let actual: Vec<(u8, u8)> = target
+            .held_notes()
+            .map(|note| (note.channel, note.key))
+            .collect();
+        assert_eq!(actual, vec![(1, 60)]);
+    }
+
+    #[test]
+    fn handle_event__where_all_notes_off() {
+        let mut target = NoteTracker::new();
+        target.handle_event(&MidiEvent::NoteOn(2, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(2, 62, 100), 0);
+
+        target.handle_event(&MidiEvent::AllNotesOff(2), 10);
+
+        assert!(target.is_empty());
+    }
+
+    #[test]
+    fn hanging_notes__where_held_beyond_threshold() {
+        let mut target = NoteTracker::new();
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 100), 500);
+        target.handle_event(&MidiEvent::NoteOn(0, 64, 100), 900);
+        // Re-trigger restarts hold time
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 90), 800);
+
+        let actual: Vec<u8> = target.hanging_notes(1000, 500).map(|n| n.key).collect();
+
+        assert_eq!(actual, vec![62]);
+    }
+
+    #[test]
+    fn release_hanging__returns_note_offs() {
+        let mut target = NoteTracker::new();
+        target.handle_event(&MidiEvent::NoteOn(3, 60, 100), 0);
+        target.handle_event(&MidiEvent::NoteOn(3, 62, 100), 900);
+
+        let actual: Vec<MidiEvent> = target.release_hanging(1000, 500).collect();
+
+        assert_eq!(actual, vec![MidiEvent::NoteOff(3, 60, 0)]);
+        assert_eq!(target.held_notes().count(), 1);
+    }
+
+    #[test]
+    fn panic__releases_everything() {
+        let mut target = NoteTracker::new();
+        target.handle_event(&MidiEvent::NoteOn(5, 60, 100), 0);
+
+        let actual: Vec<MidiEvent> = target.panic().collect();
+
+        assert_eq!(actual.len(), 1 + 16 * 2);
+        assert_eq!(actual[0], MidiEvent::NoteOff(5, 60, 0));
+        assert_eq!(actual[1], MidiEvent::AllNotesOff(0));
+        assert_eq!(actual[2], MidiEvent::AllSoundOff(0));
+        assert_eq!(actual[32], MidiEvent::AllSoundOff(15));
+        assert!(target.is_empty());
+    }
+
+    #[test]
+    fn panic__releases_notes_beyond_tracked_hold_times() {
+        let mut target = NoteTracker::new();
+        for key in 0..MAX_HELD_NOTES as u8 + 10 {
+            target.handle_event(&MidiEvent::NoteOn(key % 2, key, 100), 0);
+        }
+
+        let actual: Vec<MidiEvent> = target.panic().collect();
+
+        assert_eq!(actual.len(), MAX_HELD_NOTES + 10 + 16 * 2);
+        assert!(actual.contains(&MidiEvent::NoteOff(1, MAX_HELD_NOTES as u8 + 9, 0)));
+        assert!(target.is_empty());
+    }
+}
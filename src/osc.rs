@@ -0,0 +1,576 @@
+use crate::{Channel, MessageKind, MidiEvent};
+use std::convert::TryFrom;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// Bundle identifier
+const BUNDLE: &[u8; 8] = b"#bundle\0";
+
+// Placeholders of address patterns
+const CHANNEL: &str = "{channel}"; // Channel 1-16
+const REALTIME: &str = "{realtime}"; // Name of a System Realtime message
+
+const REALTIME_NAMES: [(MidiEvent, &str); 6] = [
+    (MidiEvent::Clock, "clock"),
+    (MidiEvent::Start, "start"),
+    (MidiEvent::Continue, "continue"),
+    (MidiEvent::Stop, "stop"),
+    (MidiEvent::ActiveSensing, "activesensing"),
+    (MidiEvent::SystemReset, "reset"),
+];
+
+///
+/// OSC 1.0 argument
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+impl OscArgument {
+    fn tag(&self) -> u8 {
+        match self {
+            OscArgument::Int(_) => b'i',
+            OscArgument::Float(_) => b'f',
+            OscArgument::String(_) => b's',
+            OscArgument::Blob(_) => b'b',
+        }
+    }
+
+    ///
+    /// Numeric value of the argument, floats are truncated
+    ///
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            OscArgument::Int(value) => Some(value),
+            OscArgument::Float(value) => Some(value as i32),
+            _ => None,
+        }
+    }
+}
+
+fn write_padded(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend_from_slice(data);
+    bytes.resize((bytes.len() + 4) & !3, 0);
+}
+
+fn read_string(bytes: &[u8]) -> Option<(&str, usize)> {
+    let len = bytes.iter().position(|byte| *byte == 0)?;
+    let text = std::str::from_utf8(&bytes[..len]).ok()?;
+    Some((text, (len + 4) & !3))
+}
+
+fn read_i32(bytes: &[u8]) -> Option<i32> {
+    Some(i32::from_be_bytes([
+        *bytes.first()?,
+        *bytes.get(1)?,
+        *bytes.get(2)?,
+        *bytes.get(3)?,
+    ]))
+}
+
+///
+/// Size of a bundle element or blob; None if negative
+///
+fn read_size(bytes: &[u8]) -> Option<usize> {
+    usize::try_from(read_i32(bytes)?).ok()
+}
+
+///
+/// **OSC 1.0 Message**
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+impl OscMessage {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            arguments: Vec::new(),
+        }
+    }
+
+    pub fn with_argument(mut self, argument: OscArgument) -> Self {
+        self.arguments.push(argument);
+        self
+    }
+
+    ///
+    /// **Encode as an OSC packet**
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_padded(&mut bytes, self.address.as_bytes());
+        let mut tags = vec![b','];
+        tags.extend(self.arguments.iter().map(OscArgument::tag));
+        write_padded(&mut bytes, &tags);
+        for argument in &self.arguments {
+            match argument {
+                OscArgument::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArgument::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArgument::String(value) => write_padded(&mut bytes, value.as_bytes()),
+                OscArgument::Blob(value) => {
+                    bytes.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                    bytes.extend_from_slice(value);
+                    bytes.resize((bytes.len() + 3) & !3, 0);
+                }
+            }
+        }
+        bytes
+    }
+
+    ///
+    /// **Decode an OSC packet**
+    ///
+    /// Messages of bundles are returned in order, time tags are ignored.
+    /// Returns None if the packet is malformed or has unsupported arguments.
+    ///
+    pub fn decode_packet(bytes: &[u8]) -> Option<Vec<OscMessage>> {
+        let mut messages = Vec::new();
+        Self::decode_into(bytes, &mut messages)?;
+        Some(messages)
+    }
+
+    fn decode_into(bytes: &[u8], messages: &mut Vec<OscMessage>) -> Option<()> {
+        if let Some(mut elements) = bytes.strip_prefix(&BUNDLE[..]) {
+            elements = elements.get(8..)?; // Time tag
+            while !elements.is_empty() {
+                let end = read_size(elements)?.checked_add(4)?;
+                Self::decode_into(elements.get(4..end)?, messages)?;
+                elements = elements.get(end..)?;
+            }
+            return Some(());
+        }
+
+        let (address, len) = read_string(bytes)?;
+        let mut message = OscMessage::new(address);
+        let mut data = bytes.get(len..)?;
+        // Type tags may be left out by older implementations
+        let tags = match read_string(data) {
+            Some((tags, len)) if tags.starts_with(',') => {
+                data = &data[len..];
+                &tags[1..]
+            }
+            _ => "",
+        };
+        for tag in tags.bytes() {
+            let argument = match tag {
+                b'i' => OscArgument::Int(read_i32(data)?),
+                b'f' => OscArgument::Float(f32::from_bits(read_i32(data)? as u32)),
+                b's' => {
+                    let (value, len) = read_string(data)?;
+                    let argument = OscArgument::String(value.to_string());
+                    data = data.get(len - 4..)?;
+                    argument
+                }
+                b'b' => {
+                    let size = read_size(data)?;
+                    let argument = OscArgument::Blob(data.get(4..size.checked_add(4)?)?.to_vec());
+                    data = data.get((size.checked_add(3)? & !3)..)?;
+                    argument
+                }
+                _ => return None,
+            };
+            data = data.get(4..)?;
+            message.arguments.push(argument);
+        }
+        messages.push(message);
+        Some(())
+    }
+}
+
+///
+/// **Mapping between MIDI events and OSC messages**
+///
+/// Each message kind has an address pattern where `{channel}` is replaced
+/// by the channel (1-16) and `{realtime}` by the name of a System Realtime
+/// message (clock, start, continue, stop, activesensing or reset). The
+/// default patterns are:
+///
+/// * `/midi/ch/{channel}/note` key, velocity
+/// * `/midi/ch/{channel}/noteoff` key, velocity
+/// * `/midi/ch/{channel}/polypressure` key, pressure
+/// * `/midi/ch/{channel}/cc` controller, value
+/// * `/midi/ch/{channel}/program` program
+/// * `/midi/ch/{channel}/pressure` pressure
+/// * `/midi/ch/{channel}/pitchbend` amount (-8192 to 8191)
+/// * `/midi/{realtime}`
+///
+/// Channel mode, System Common and System Exclusive messages are not mapped.
+///
+#[derive(Debug, Clone)]
+pub struct OscMapping {
+    patterns: Vec<(MessageKind, String)>,
+}
+
+impl Default for OscMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OscMapping {
+    pub fn new() -> Self {
+        Self {
+            patterns: vec![
+                (MessageKind::NoteOn, "/midi/ch/{channel}/note".to_string()),
+                (
+                    MessageKind::NoteOff,
+                    "/midi/ch/{channel}/noteoff".to_string(),
+                ),
+                (
+                    MessageKind::PolyphonicAfterTouch,
+                    "/midi/ch/{channel}/polypressure".to_string(),
+                ),
+                (
+                    MessageKind::ControllerChange,
+                    "/midi/ch/{channel}/cc".to_string(),
+                ),
+                (
+                    MessageKind::ProgramChange,
+                    "/midi/ch/{channel}/program".to_string(),
+                ),
+                (
+                    MessageKind::ChannelAfterTouch,
+                    "/midi/ch/{channel}/pressure".to_string(),
+                ),
+                (
+                    MessageKind::PitchBend,
+                    "/midi/ch/{channel}/pitchbend".to_string(),
+                ),
+                (MessageKind::SystemRealtime, "/midi/{realtime}".to_string()),
+            ],
+        }
+    }
+
+    ///
+    /// Set the address pattern of a message kind
+    ///
+    pub fn with_address(mut self, kind: MessageKind, pattern: &str) -> Self {
+        self.patterns.retain(|(entry, _)| *entry != kind);
+        self.patterns.push((kind, pattern.to_string()));
+        self
+    }
+
+    ///
+    /// Stop mapping a message kind
+    ///
+    pub fn without(mut self, kind: MessageKind) -> Self {
+        self.patterns.retain(|(entry, _)| *entry != kind);
+        self
+    }
+
+    ///
+    /// **OSC message for an event**
+    ///
+    pub fn to_osc(&self, event: &MidiEvent) -> Option<OscMessage> {
+        let pattern = self.pattern(event.kind())?;
+        let channel = event.channel().unwrap_or(0);
+        let realtime = REALTIME_NAMES
+            .iter()
+            .find(|(entry, _)| entry == event)
+            .map_or("", |(_, name)| name);
+        let address = pattern
+            .replace(CHANNEL, &(channel as u32 + 1).to_string())
+            .replace(REALTIME, realtime);
+
+        let arguments: Vec<i32> = match *event {
+            MidiEvent::NoteOn(_, key, value)
+            | MidiEvent::NoteOff(_, key, value)
+            | MidiEvent::PolyphonicAfterTouch(_, key, value)
+            | MidiEvent::ControllerChange(_, key, value) => vec![key as i32, value as i32],
+            MidiEvent::ProgramChange(_, value) | MidiEvent::ChannelAfterTouch(_, value) => {
+                vec![value as i32]
+            }
+            MidiEvent::PitchBend(_, amount) => vec![amount as i32],
+            _ => vec![],
+        };
+        Some(OscMessage {
+            address,
+            arguments: arguments.into_iter().map(OscArgument::Int).collect(),
+        })
+    }
+
+    ///
+    /// **Event for an OSC message**
+    ///
+    /// Returns None if the address matches no pattern or the arguments are
+    /// missing; values are clamped to the range of the MIDI message.
+    ///
+    pub fn from_osc(&self, message: &OscMessage) -> Option<MidiEvent> {
+        let value = |index: usize, max: i32| -> Option<u8> {
+            Some(message.arguments.get(index)?.as_int()?.clamp(0, max) as u8)
+        };
+
+        self.patterns.iter().find_map(|(kind, pattern)| {
+            let (channel, realtime) = match_address(pattern, &message.address)?;
+            Some(match kind {
+                MessageKind::NoteOn => MidiEvent::NoteOn(channel, value(0, 127)?, value(1, 127)?),
+                MessageKind::NoteOff => MidiEvent::NoteOff(channel, value(0, 127)?, value(1, 127)?),
+                MessageKind::PolyphonicAfterTouch => {
+                    MidiEvent::PolyphonicAfterTouch(channel, value(0, 127)?, value(1, 127)?)
+                }
+                MessageKind::ControllerChange => {
+                    MidiEvent::ControllerChange(channel, value(0, 127)?, value(1, 127)?)
+                }
+                MessageKind::ProgramChange => MidiEvent::ProgramChange(channel, value(0, 127)?),
+                MessageKind::ChannelAfterTouch => {
+                    MidiEvent::ChannelAfterTouch(channel, value(0, 127)?)
+                }
+                MessageKind::PitchBend => {
+                    let amount = message.arguments.first()?.as_int()?;
+                    MidiEvent::PitchBend(channel, amount.clamp(-8192, 8191) as i16)
+                }
+                MessageKind::SystemRealtime => {
+                    REALTIME_NAMES
+                        .iter()
+                        .find(|(_, name)| Some(*name) == realtime)?
+                        .0
+                }
+                _ => return None,
+            })
+        })
+    }
+
+    fn pattern(&self, kind: MessageKind) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|(entry, _)| *entry == kind)
+            .map(|(_, pattern)| pattern.as_str())
+    }
+}
+
+///
+/// Match an address against a pattern returning the channel (0 if the
+/// pattern has no channel) and realtime message name
+///
+fn match_address<'a>(pattern: &str, address: &'a str) -> Option<(Channel, Option<&'a str>)> {
+    let mut pattern_parts = pattern.split('/');
+    let mut address_parts = address.split('/');
+    let mut channel = 0;
+    let mut realtime = None;
+    loop {
+        match (pattern_parts.next(), address_parts.next()) {
+            (None, None) => return Some((channel, realtime)),
+            (Some(CHANNEL), Some(part)) => match part.parse::<u8>() {
+                Ok(value @ 1..=16) => channel = value - 1,
+                _ => return None,
+            },
+            (Some(REALTIME), Some(part)) => realtime = Some(part),
+            (Some(expected), Some(part)) if expected == part => {}
+            _ => return None,
+        }
+    }
+}
+
+///
+/// **OSC bridge over UDP**
+///
+/// Sends events as OSC messages to a target address and turns received OSC
+/// packets back into events using an [`OscMapping`].
+///
+pub struct OscBridge {
+    socket: UdpSocket,
+    target: SocketAddr,
+    mapping: OscMapping,
+}
+
+impl OscBridge {
+    pub fn bind(address: SocketAddr, target: SocketAddr) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(address)?,
+            target,
+            mapping: OscMapping::new(),
+        })
+    }
+
+    pub fn with_mapping(mut self, mapping: OscMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    ///
+    /// **Send an event**
+    ///
+    /// Returns false if the event is not mapped.
+    ///
+    pub fn send(&self, event: &MidiEvent) -> io::Result<bool> {
+        match self.mapping.to_osc(event) {
+            Some(message) => {
+                self.socket.send_to(&message.encode(), self.target)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    ///
+    /// **Receive events**
+    ///
+    /// Waits for a single packet; returns no events on timeout or when the
+    /// packet has no mapped messages.
+    ///
+    pub fn receive(&self, timeout: Duration) -> io::Result<Vec<MidiEvent>> {
+        let mut buffer = [0; 1500];
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let len = match self.socket.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(OscMessage::decode_packet(&buffer[..len])
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|message| self.mapping.from_osc(message))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::osc::{OscArgument, OscBridge, OscMapping, OscMessage};
+    use crate::{MessageKind, MidiEvent};
+    use parameterized::parameterized;
+    use std::time::Duration;
+
+    #[test]
+    fn encode__layout() {
+        let target = OscMessage::new("/midi/ch/1/note")
+            .with_argument(OscArgument::Int(60))
+            .with_argument(OscArgument::Float(0.5));
+
+        let actual = target.encode();
+
+        let mut expected = b"/midi/ch/1/note\0,if\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 60, 0x3F, 0, 0, 0]);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn decode_packet__round_trips() {
+        let message = OscMessage::new("/test")
+            .with_argument(OscArgument::String("name".to_string()))
+            .with_argument(OscArgument::Blob(vec![1, 2, 3, 4, 5]))
+            .with_argument(OscArgument::Int(-1));
+
+        let actual = OscMessage::decode_packet(&message.encode());
+
+        assert_eq!(actual, Some(vec![message]));
+    }
+
+    #[test]
+    fn decode_packet__bundle() {
+        let first = OscMessage::new("/midi/start");
+        let second = OscMessage::new("/midi/stop");
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for message in &[&first, &second] {
+            let bytes = message.encode();
+            bundle.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&bytes);
+        }
+
+        let actual = OscMessage::decode_packet(&bundle);
+
+        assert_eq!(actual, Some(vec![first, second]));
+    }
+
+    #[parameterized(
+        bytes = {
+            &b"/b\0\0,b\0\0\xFF\xFF\xFF\xFF"[..],
+            &b"/b\0\0,b\0\0\x7F\xFF\xFF\xFF\0\0\0\0"[..],
+            &b"#bundle\0\0\0\0\0\0\0\0\x01\xFF\xFF\xFF\xF8/a\0\0,\0\0\0"[..],
+            &b"#bundle\0\0\0\0\0\0\0\0\x01\x7F\xFF\xFF\xFF/a\0\0,\0\0\0"[..],
+        }
+    )]
+    fn decode_packet__where_size_invalid(bytes: &[u8]) {
+        assert_eq!(OscMessage::decode_packet(bytes), None);
+    }
+
+    #[parameterized(
+        event = {
+            MidiEvent::NoteOn(0, 60, 100),
+            MidiEvent::NoteOff(15, 60, 0),
+            MidiEvent::PolyphonicAfterTouch(1, 60, 10),
+            MidiEvent::ControllerChange(2, 7, 90),
+            MidiEvent::ProgramChange(3, 5),
+            MidiEvent::ChannelAfterTouch(4, 12),
+            MidiEvent::PitchBend(5, -8192),
+            MidiEvent::Start,
+        },
+        address = {
+            "/midi/ch/1/note",
+            "/midi/ch/16/noteoff",
+            "/midi/ch/2/polypressure",
+            "/midi/ch/3/cc",
+            "/midi/ch/4/program",
+            "/midi/ch/5/pressure",
+            "/midi/ch/6/pitchbend",
+            "/midi/start",
+        }
+    )]
+    fn to_osc__round_trips(event: MidiEvent, address: &str) {
+        let target = OscMapping::new();
+
+        let message = target.to_osc(&event).unwrap();
+        let actual = target.from_osc(&message);
+
+        assert_eq!(message.address, address);
+        assert_eq!(actual, Some(event));
+    }
+
+    #[test]
+    fn from_osc__custom_pattern_and_float_arguments() {
+        let target =
+            OscMapping::new().with_address(MessageKind::ControllerChange, "/fader/{channel}/level");
+        let message = OscMessage::new("/fader/10/level")
+            .with_argument(OscArgument::Float(7.0))
+            .with_argument(OscArgument::Float(200.0));
+
+        let actual = target.from_osc(&message);
+
+        assert_eq!(actual, Some(MidiEvent::ControllerChange(9, 7, 127)));
+    }
+
+    #[parameterized(
+        address = { "/midi/ch/0/note", "/midi/ch/17/note", "/midi/ch/1/note/x", "/other" }
+    )]
+    fn from_osc__where_not_mapped(address: &str) {
+        let target = OscMapping::new();
+        let message = OscMessage::new(address)
+            .with_argument(OscArgument::Int(60))
+            .with_argument(OscArgument::Int(100));
+
+        assert_eq!(target.from_osc(&message), None);
+    }
+
+    #[test]
+    fn bridge__localhost() {
+        let address = "127.0.0.1:0".parse().unwrap();
+        let receiver = OscBridge::bind(address, address).unwrap();
+        let sender = OscBridge::bind(address, receiver.local_addr().unwrap()).unwrap();
+
+        assert!(sender.send(&MidiEvent::NoteOn(0, 60, 100)).unwrap());
+        assert!(!sender.send(&MidiEvent::TuneRequest).unwrap());
+        let actual = receiver.receive(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(actual, vec![MidiEvent::NoteOn(0, 60, 100)]);
+    }
+}
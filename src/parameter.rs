@@ -0,0 +1,256 @@
+use crate::controller::{
+    DATA_DECREMENT, DATA_ENTRY, DATA_ENTRY_LSB, DATA_INCREMENT, NRPN_LSB, NRPN_MSB, RPN_LSB,
+    RPN_MSB, RPN_NULL,
+};
+use crate::{u14, u7, Channel, MidiEvent};
+
+/// Pitch bend sensitivity
+pub const RPN_PITCH_BEND_RANGE: u14 = 0x0000;
+/// Fine tuning
+pub const RPN_FINE_TUNING: u14 = 0x0001;
+/// Coarse tuning
+pub const RPN_COARSE_TUNING: u14 = 0x0002;
+/// MPE Configuration Message
+pub const RPN_MPE_CONFIGURATION: u14 = 0x0006;
+
+///
+/// Registered or non-registered parameter
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParameterKind {
+    Registered,
+    NonRegistered,
+}
+
+///
+/// Value written to a RPN/NRPN
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ParameterChange {
+    pub channel: Channel,
+    pub kind: ParameterKind,
+    pub parameter: u14,
+    pub msb: u7,
+    pub lsb: u7,
+}
+
+impl ParameterChange {
+    ///
+    /// 14 bit value
+    ///
+    pub fn value(&self) -> u14 {
+        ((self.msb as u14) << 7) | self.lsb as u14
+    }
+
+    ///
+    /// **Controller events that write this parameter**
+    ///
+    /// Includes the Null RPN so later Data Entry is not misinterpreted.
+    ///
+    pub fn to_events(&self) -> [MidiEvent; 6] {
+        let (select_msb, select_lsb) = match self.kind {
+            ParameterKind::Registered => (RPN_MSB, RPN_LSB),
+            ParameterKind::NonRegistered => (NRPN_MSB, NRPN_LSB),
+        };
+        let channel = self.channel;
+        [
+            MidiEvent::ControllerChange(channel, select_msb, (self.parameter >> 7) as u7 & 0x7F),
+            MidiEvent::ControllerChange(channel, select_lsb, self.parameter as u7 & 0x7F),
+            MidiEvent::ControllerChange(channel, DATA_ENTRY, self.msb),
+            MidiEvent::ControllerChange(channel, DATA_ENTRY_LSB, self.lsb),
+            MidiEvent::ControllerChange(channel, RPN_MSB, RPN_NULL),
+            MidiEvent::ControllerChange(channel, RPN_LSB, RPN_NULL),
+        ]
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Selection {
+    kind: ParameterKind,
+    msb: u7,
+    lsb: u7,
+    value_msb: u7,
+    value_lsb: u7,
+}
+
+impl Selection {
+    fn is_null(&self) -> bool {
+        self.msb == RPN_NULL && self.lsb == RPN_NULL
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            kind: ParameterKind::Registered,
+            msb: RPN_NULL,
+            lsb: RPN_NULL,
+            value_msb: 0,
+            value_lsb: 0,
+        }
+    }
+}
+
+///
+/// **Parameter Tracker**
+///
+/// Follows RPN/NRPN selection and Data Entry controllers on each channel and
+/// reports the resulting parameter changes. A change is reported for each
+/// Data Entry MSB, LSB, Increment or Decrement while a parameter is selected.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ParameterTracker {
+    channels: [Selection; 16],
+}
+
+impl ParameterTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> Option<ParameterChange> {
+        let (channel, control, value) = match *event {
+            MidiEvent::ControllerChange(channel, control, value) => (channel, control, value),
+            MidiEvent::ResetAllControllers(channel) => {
+                self.channels[channel as usize & 0x0F] = Selection::default();
+                return None;
+            }
+            _ => return None,
+        };
+        let selection = &mut self.channels[channel as usize & 0x0F];
+
+        match control {
+            RPN_MSB | RPN_LSB | NRPN_MSB | NRPN_LSB => {
+                let kind = match control {
+                    RPN_MSB | RPN_LSB => ParameterKind::Registered,
+                    _ => ParameterKind::NonRegistered,
+                };
+                if selection.kind != kind {
+                    *selection = Selection {
+                        kind,
+                        ..Selection::default()
+                    };
+                }
+                match control {
+                    RPN_MSB | NRPN_MSB => selection.msb = value,
+                    _ => selection.lsb = value,
+                }
+                selection.value_msb = 0;
+                selection.value_lsb = 0;
+                return None;
+            }
+            DATA_ENTRY => {
+                selection.value_msb = value;
+                selection.value_lsb = 0;
+            }
+            DATA_ENTRY_LSB => selection.value_lsb = value,
+            DATA_INCREMENT | DATA_DECREMENT => {
+                let current = ((selection.value_msb as u14) << 7) | selection.value_lsb as u14;
+                let updated = if control == DATA_INCREMENT {
+                    (current + 1).min(0x3FFF)
+                } else {
+                    current.saturating_sub(1)
+                };
+                selection.value_msb = (updated >> 7) as u7;
+                selection.value_lsb = updated as u7 & 0x7F;
+            }
+            _ => return None,
+        }
+
+        if selection.is_null() {
+            return None;
+        }
+        Some(ParameterChange {
+            channel,
+            kind: selection.kind,
+            parameter: ((selection.msb as u14) << 7) | selection.lsb as u14,
+            msb: selection.value_msb,
+            lsb: selection.value_lsb,
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::parameter::{ParameterChange, ParameterKind, ParameterTracker};
+    use crate::MidiEvent;
+
+    #[test]
+    fn handle_event__registered_parameter() {
+        let mut target = ParameterTracker::new();
+
+        assert_eq!(
+            target.handle_event(&MidiEvent::ControllerChange(1, 101, 0)),
+            None
+        );
+        assert_eq!(
+            target.handle_event(&MidiEvent::ControllerChange(1, 100, 0)),
+            None
+        );
+        let actual = target.handle_event(&MidiEvent::ControllerChange(1, 6, 12));
+
+        assert_eq!(
+            actual,
+            Some(ParameterChange {
+                channel: 1,
+                kind: ParameterKind::Registered,
+                parameter: 0,
+                msb: 12,
+                lsb: 0
+            })
+        );
+    }
+
+    #[test]
+    fn handle_event__non_registered_with_lsb_and_increment() {
+        let mut target = ParameterTracker::new();
+        target.handle_event(&MidiEvent::ControllerChange(0, 99, 1));
+        target.handle_event(&MidiEvent::ControllerChange(0, 98, 2));
+        target.handle_event(&MidiEvent::ControllerChange(0, 6, 1));
+        target.handle_event(&MidiEvent::ControllerChange(0, 38, 127));
+
+        let actual = target
+            .handle_event(&MidiEvent::ControllerChange(0, 96, 0))
+            .unwrap();
+
+        assert_eq!(actual.kind, ParameterKind::NonRegistered);
+        assert_eq!(actual.parameter, 0x82);
+        assert_eq!(actual.value(), 0x100);
+    }
+
+    #[test]
+    fn handle_event__where_null_rpn() {
+        let mut target = ParameterTracker::new();
+        target.handle_event(&MidiEvent::ControllerChange(0, 101, 127));
+        target.handle_event(&MidiEvent::ControllerChange(0, 100, 127));
+
+        assert_eq!(
+            target.handle_event(&MidiEvent::ControllerChange(0, 6, 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn to_events__round_trips() {
+        let change = ParameterChange {
+            channel: 3,
+            kind: ParameterKind::Registered,
+            parameter: 6,
+            msb: 5,
+            lsb: 9,
+        };
+        let mut target = ParameterTracker::new();
+
+        let actual: Vec<ParameterChange> = change
+            .to_events()
+            .iter()
+            .filter_map(|event| target.handle_event(event))
+            .collect();
+
+        assert_eq!(actual.last(), Some(&change));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::{controller, u7, Channel, List, MidiEvent};
+
+/// Default value at or above which a pedal is considered down
+pub const PEDAL_ON_THRESHOLD: u7 = 64;
+/// Number of sounding notes tracked across all channels
+pub const MAX_SOUNDING_NOTES: usize = 128;
+
+///
+/// A note that is sounding; either because the key is held or a pedal is
+/// holding it
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SoundingNote {
+    pub key: u7,
+    pub velocity: u7,
+    pub held: bool,      // Key is still down
+    pub sostenuto: bool, // Captured by the sostenuto pedal
+    pub soft: bool,      // Struck while the soft pedal was down
+}
+
+impl SoundingNote {
+    ///
+    /// Note is only sounding because of the sustain pedal
+    ///
+    pub fn is_sustained(&self) -> bool {
+        !self.held && !self.sostenuto
+    }
+}
+
+///
+/// Pedal positions of a channel
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PedalState {
+    pub sustain: u7,
+    pub sostenuto: u7,
+    pub soft: u7,
+    sustain_down: bool,
+    sostenuto_down: bool,
+    soft_down: bool,
+}
+
+impl PedalState {
+    pub fn is_sustain_down(&self) -> bool {
+        self.sustain_down
+    }
+
+    pub fn is_sostenuto_down(&self) -> bool {
+        self.sostenuto_down
+    }
+
+    pub fn is_soft_down(&self) -> bool {
+        self.soft_down
+    }
+
+    ///
+    /// Sustain depth as 0.0..=1.0 for engines that model half pedalling
+    ///
+    pub fn sustain_level(&self) -> f32 {
+        self.sustain as f32 / 127.0
+    }
+}
+
+///
+/// **Sounding Notes**
+///
+/// Pedal aware note state; tracks which notes are sounding rather than which
+/// keys are held. Note Offs are deferred while the sustain pedal (CC 64) is
+/// down, the sostenuto pedal (CC 66) holds only notes whose keys were down
+/// when it was pressed and the soft pedal (CC 67) is recorded against notes
+/// struck while it is down.
+///
+/// Pedals use a pair of thresholds so half-pedal controllers that hover
+/// around the centre do not chatter; a pedal goes down at or above the on
+/// threshold and comes up below the off threshold.
+///
+/// Up to `MAX_SOUNDING_NOTES` are tracked across all channels; when full the
+/// oldest sounding note is released to make room for a new one.
+///
+#[derive(Debug, Clone)]
+pub struct SoundingNotes {
+    on_threshold: u7,
+    off_threshold: u7,
+    notes: List<(Channel, SoundingNote), MAX_SOUNDING_NOTES>, // In the order struck
+    pedals: [PedalState; 16],
+}
+
+impl SoundingNotes {
+    pub fn new() -> Self {
+        Self {
+            on_threshold: PEDAL_ON_THRESHOLD,
+            off_threshold: PEDAL_ON_THRESHOLD,
+            notes: List::default(),
+            pedals: Default::default(),
+        }
+    }
+
+    ///
+    /// Set the pedal thresholds; `off` is limited to at most `on`
+    ///
+    pub fn with_thresholds(mut self, on: u7, off: u7) -> Self {
+        self.on_threshold = on;
+        self.off_threshold = off.min(on);
+        self
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// Adds the effective Note On/Off events to `events`; Note Offs are
+    /// emitted when a note actually stops sounding, which may be on a pedal
+    /// release.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, events: &mut impl Extend<MidiEvent>) {
+        match *event {
+            MidiEvent::NoteOn(channel, key, 0) | MidiEvent::NoteOff(channel, key, _) => {
+                self.key_up(channel & 0x0F, key, events)
+            }
+            MidiEvent::NoteOn(channel, key, velocity) => {
+                self.key_down(channel & 0x0F, key, velocity, events)
+            }
+            MidiEvent::ControllerChange(channel, control, value) => {
+                self.controller_change(channel & 0x0F, control, value, events)
+            }
+            // All notes off is a key release; pedals still apply
+            MidiEvent::AllNotesOff(channel)
+            | MidiEvent::OmniMode(channel, _)
+            | MidiEvent::MonoMode(channel, _)
+            | MidiEvent::PolyphonicMode(channel) => {
+                let channel = channel & 0x0F;
+                let notes = self.notes;
+                for (_, note) in notes.iter().filter(|(held, _)| *held == channel) {
+                    self.key_up(channel, note.key, events);
+                }
+            }
+            MidiEvent::AllSoundOff(channel) => self.silence(channel & 0x0F, events),
+            MidiEvent::ResetAllControllers(channel) => {
+                for control in [
+                    controller::SUSTAIN,
+                    controller::SOSTENUTO,
+                    controller::SOFT_PEDAL,
+                ]
+                .iter()
+                {
+                    self.controller_change(channel & 0x0F, *control, 0, events);
+                }
+            }
+            MidiEvent::SystemReset => {
+                for channel in 0..16 {
+                    self.silence(channel, events);
+                }
+                self.pedals = Default::default();
+            }
+            _ => (),
+        }
+    }
+
+    ///
+    /// Sounding notes of a channel in the order they were struck
+    ///
+    pub fn sounding_notes(&self, channel: Channel) -> impl Iterator<Item = &SoundingNote> {
+        let channel = channel & 0x0F;
+        self.notes
+            .iter()
+            .filter(move |(sounding, _)| *sounding == channel)
+            .map(|(_, note)| note)
+    }
+
+    pub fn is_sounding(&self, channel: Channel, key: u7) -> bool {
+        self.sounding_notes(channel).any(|note| note.key == key)
+    }
+
+    pub fn pedals(&self, channel: Channel) -> &PedalState {
+        &self.pedals[channel as usize & 0x0F]
+    }
+
+    fn position(&self, channel: Channel, key: u7) -> Option<usize> {
+        self.notes
+            .iter()
+            .position(|(sounding, note)| *sounding == channel && note.key == key)
+    }
+
+    fn key_down(
+        &mut self,
+        channel: Channel,
+        key: u7,
+        velocity: u7,
+        events: &mut impl Extend<MidiEvent>,
+    ) {
+        let soft = self.pedals[channel as usize].soft_down;
+
+        // Re-striking a note held by a pedal ends the previous sound; a key
+        // captured by sostenuto stays captured
+        let mut sostenuto = false;
+        if let Some(index) = self.position(channel, key) {
+            sostenuto = self.notes.remove(index).1.sostenuto;
+            events.extend(Some(MidiEvent::NoteOff(channel, key, 0)));
+        }
+        if self.notes.is_full() {
+            let (oldest_channel, oldest) = self.notes.remove(0);
+            events.extend(Some(MidiEvent::NoteOff(oldest_channel, oldest.key, 0)));
+        }
+        self.notes.push((
+            channel,
+            SoundingNote {
+                key,
+                velocity,
+                held: true,
+                sostenuto,
+                soft,
+            },
+        ));
+        events.extend(Some(MidiEvent::NoteOn(channel, key, velocity)));
+    }
+
+    fn key_up(&mut self, channel: Channel, key: u7, events: &mut impl Extend<MidiEvent>) {
+        let sustain_down = self.pedals[channel as usize].sustain_down;
+
+        if let Some(index) = self
+            .position(channel, key)
+            .filter(|i| self.notes[*i].1.held)
+        {
+            let note = &mut self.notes[index].1;
+            note.held = false;
+            if !note.sostenuto && !sustain_down {
+                self.notes.remove(index);
+                events.extend(Some(MidiEvent::NoteOff(channel, key, 0)));
+            }
+        }
+    }
+
+    fn controller_change(
+        &mut self,
+        channel: Channel,
+        control: u7,
+        value: u7,
+        events: &mut impl Extend<MidiEvent>,
+    ) {
+        let on_threshold = self.on_threshold;
+        let off_threshold = self.off_threshold;
+        let pedals = &mut self.pedals[channel as usize];
+
+        let (position, down) = match control {
+            controller::SUSTAIN => (&mut pedals.sustain, &mut pedals.sustain_down),
+            controller::SOSTENUTO => (&mut pedals.sostenuto, &mut pedals.sostenuto_down),
+            controller::SOFT_PEDAL => (&mut pedals.soft, &mut pedals.soft_down),
+            _ => return,
+        };
+        *position = value;
+        let was_down = *down;
+        if !was_down && value >= on_threshold {
+            *down = true;
+        } else if was_down && value < off_threshold {
+            *down = false;
+        }
+        let is_down = *down;
+        if was_down == is_down {
+            return;
+        }
+
+        let sustain_down = pedals.sustain_down;
+        let on_channel = |sounding: &Channel| *sounding == channel;
+        match (control, is_down) {
+            (controller::SUSTAIN, false) => self.notes.retain(|(sounding, note)| {
+                let release = on_channel(sounding) && note.is_sustained();
+                if release {
+                    events.extend(Some(MidiEvent::NoteOff(channel, note.key, 0)));
+                }
+                !release
+            }),
+            (controller::SOSTENUTO, true) => self
+                .notes
+                .iter_mut()
+                .filter(|(sounding, note)| on_channel(sounding) && note.held)
+                .for_each(|(_, note)| note.sostenuto = true),
+            (controller::SOSTENUTO, false) => {
+                self.notes.retain(|(sounding, note)| {
+                    let release =
+                        on_channel(sounding) && note.sostenuto && !note.held && !sustain_down;
+                    if release {
+                        events.extend(Some(MidiEvent::NoteOff(channel, note.key, 0)));
+                    }
+                    !release
+                });
+                self.notes
+                    .iter_mut()
+                    .filter(|(sounding, _)| on_channel(sounding))
+                    .for_each(|(_, note)| note.sostenuto = false);
+            }
+            _ => (),
+        }
+    }
+
+    fn silence(&mut self, channel: Channel, events: &mut impl Extend<MidiEvent>) {
+        self.notes.retain(|(sounding, note)| {
+            if *sounding == channel {
+                events.extend(Some(MidiEvent::NoteOff(channel, note.key, 0)));
+            }
+            *sounding != channel
+        });
+    }
+}
+
+impl Default for SoundingNotes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::controller::{SOFT_PEDAL, SOSTENUTO, SUSTAIN};
+    use crate::pedal::{SoundingNotes, MAX_SOUNDING_NOTES};
+    use crate::MidiEvent;
+
+    fn handle(target: &mut SoundingNotes, event: &MidiEvent) -> Vec<MidiEvent> {
+        let mut events = Vec::new();
+        target.handle_event(event, &mut events);
+        events
+    }
+
+    fn keys(target: &SoundingNotes) -> Vec<u8> {
+        target.sounding_notes(0).map(|note| note.key).collect()
+    }
+
+    #[test]
+    fn handle_event__without_pedals_passes_notes() {
+        let mut target = SoundingNotes::new();
+
+        assert_eq!(
+            *handle(&mut target, &MidiEvent::NoteOn(0, 60, 100)),
+            [MidiEvent::NoteOn(0, 60, 100)]
+        );
+        assert_eq!(
+            *handle(&mut target, &MidiEvent::NoteOn(0, 60, 0)),
+            [MidiEvent::NoteOff(0, 60, 0)]
+        );
+        assert!(keys(&target).is_empty());
+    }
+
+    #[test]
+    fn handle_event__where_sustain_defers_note_off() {
+        let mut target = SoundingNotes::new();
+        handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 127));
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+        handle(&mut target, &MidiEvent::NoteOn(0, 64, 100));
+
+        assert!(handle(&mut target, &MidiEvent::NoteOff(0, 60, 0)).is_empty());
+        assert_eq!(keys(&target), vec![60, 64]);
+
+        let actual = handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 0));
+
+        assert_eq!(*actual, [MidiEvent::NoteOff(0, 60, 0)]);
+        assert_eq!(keys(&target), vec![64]);
+    }
+
+    #[test]
+    fn handle_event__where_sustained_note_is_restruck() {
+        let mut target = SoundingNotes::new();
+        handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 127));
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+        handle(&mut target, &MidiEvent::NoteOff(0, 60, 0));
+
+        let actual = handle(&mut target, &MidiEvent::NoteOn(0, 60, 80));
+
+        assert_eq!(
+            *actual,
+            [MidiEvent::NoteOff(0, 60, 0), MidiEvent::NoteOn(0, 60, 80)]
+        );
+    }
+
+    #[test]
+    fn handle_event__where_sostenuto_captures_held_keys_only() {
+        let mut target = SoundingNotes::new();
+        handle(&mut target, &MidiEvent::NoteOn(0, 48, 100));
+        handle(&mut target, &MidiEvent::ControllerChange(0, SOSTENUTO, 127));
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+
+        assert!(handle(&mut target, &MidiEvent::NoteOff(0, 48, 0)).is_empty());
+        assert_eq!(
+            *handle(&mut target, &MidiEvent::NoteOff(0, 60, 0)),
+            [MidiEvent::NoteOff(0, 60, 0)]
+        );
+
+        let actual = handle(&mut target, &MidiEvent::ControllerChange(0, SOSTENUTO, 0));
+
+        assert_eq!(*actual, [MidiEvent::NoteOff(0, 48, 0)]);
+    }
+
+    #[test]
+    fn handle_event__where_sostenuto_key_is_restruck() {
+        let mut target = SoundingNotes::new();
+        handle(&mut target, &MidiEvent::NoteOn(0, 48, 100));
+        handle(&mut target, &MidiEvent::ControllerChange(0, SOSTENUTO, 127));
+        handle(&mut target, &MidiEvent::NoteOff(0, 48, 0));
+        handle(&mut target, &MidiEvent::NoteOn(0, 48, 90));
+
+        assert!(handle(&mut target, &MidiEvent::NoteOff(0, 48, 0)).is_empty());
+        assert_eq!(keys(&target), vec![48]);
+
+        let actual = handle(&mut target, &MidiEvent::ControllerChange(0, SOSTENUTO, 0));
+
+        assert_eq!(*actual, [MidiEvent::NoteOff(0, 48, 0)]);
+    }
+
+    #[test]
+    fn handle_event__where_half_pedal_thresholds() {
+        let mut target = SoundingNotes::new().with_thresholds(80, 40);
+        handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 70));
+        assert!(!target.pedals(0).is_sustain_down());
+
+        handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 90));
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+        handle(&mut target, &MidiEvent::NoteOff(0, 60, 0));
+        // Above the off threshold the pedal stays down
+        assert!(handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 50)).is_empty());
+        assert!(target.pedals(0).is_sustain_down());
+
+        let actual = handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 20));
+
+        assert_eq!(*actual, [MidiEvent::NoteOff(0, 60, 0)]);
+    }
+
+    #[test]
+    fn handle_event__where_all_notes_off_with_sustain() {
+        let mut target = SoundingNotes::new();
+        handle(&mut target, &MidiEvent::ControllerChange(0, SUSTAIN, 127));
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+
+        assert!(handle(&mut target, &MidiEvent::AllNotesOff(0)).is_empty());
+        assert_eq!(
+            *handle(&mut target, &MidiEvent::AllSoundOff(0)),
+            [MidiEvent::NoteOff(0, 60, 0)]
+        );
+    }
+
+    #[test]
+    fn handle_event__where_full_releases_oldest() {
+        let mut target = SoundingNotes::new();
+        for key in 0..MAX_SOUNDING_NOTES as u8 {
+            handle(&mut target, &MidiEvent::NoteOn(1, key, 100));
+        }
+
+        let actual = handle(&mut target, &MidiEvent::NoteOn(2, 60, 100));
+
+        assert_eq!(
+            *actual,
+            [MidiEvent::NoteOff(1, 0, 0), MidiEvent::NoteOn(2, 60, 100)]
+        );
+        assert!(!target.is_sounding(1, 0));
+        assert!(target.is_sounding(2, 60));
+    }
+
+    #[test]
+    fn handle_event__where_soft_pedal_marks_notes() {
+        let mut target = SoundingNotes::new();
+        handle(
+            &mut target,
+            &MidiEvent::ControllerChange(0, SOFT_PEDAL, 127),
+        );
+        handle(&mut target, &MidiEvent::NoteOn(0, 60, 100));
+        handle(&mut target, &MidiEvent::ControllerChange(0, SOFT_PEDAL, 0));
+        handle(&mut target, &MidiEvent::NoteOn(0, 62, 100));
+
+        let actual: Vec<bool> = target.sounding_notes(0).map(|note| note.soft).collect();
+
+        assert_eq!(*actual, [true, false]);
+    }
+}
@@ -0,0 +1,632 @@
+use crate::controller::BRIGHTNESS;
+use crate::pitch_bend::{PITCH_BEND_CENTRE, PITCH_BEND_MAX, PITCH_BEND_MIN};
+use crate::{
+    i14, scale_down, scale_up, u7, Channel, Midi2Event, Midi2ToMidi1, MidiEvent, MpeConfiguration,
+    MpeExpression, MpeSender, NoteAttribute, UmpEvent, Zone, MPE_DEFAULT_TIMBRE,
+};
+
+/// Centre value of 32 bit pitch bend
+pub const PITCH_BEND_CENTRE_32: u32 = 0x8000_0000;
+
+// Control Changes with a per-note meaning
+const ALL_SOUND_OFF: u7 = 120;
+const RESET_ALL_CONTROLLERS: u7 = 121;
+const ALL_NOTES_OFF: u7 = 123;
+
+// Registered per-note controller holding the absolute pitch of a note
+const PITCH_7_25: u8 = 3;
+
+///
+/// **Per-note state**
+///
+/// Velocity and per-note controllers of a note number on a channel. Per-note
+/// controllers received before a Note On apply to that note.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NoteState {
+    pub channel: Channel,
+    pub note: u7,
+    pub velocity: u16, // 0 when the note is not held
+    pub attribute: NoteAttribute,
+    pub pitch_bend: u32,
+    pub pressure: u32,
+    registered: Vec<(u8, u32)>, // Index, Value
+    assignable: Vec<(u8, u32)>, // Index, Value
+}
+
+impl NoteState {
+    fn new(channel: Channel, note: u7) -> Self {
+        Self {
+            channel,
+            note,
+            velocity: 0,
+            attribute: NoteAttribute::None,
+            pitch_bend: PITCH_BEND_CENTRE_32,
+            pressure: 0,
+            registered: Vec::new(),
+            assignable: Vec::new(),
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.velocity > 0
+    }
+
+    ///
+    /// Value of a registered per-note controller; None if never received
+    ///
+    pub fn registered(&self, index: u8) -> Option<u32> {
+        find(&self.registered, index)
+    }
+
+    ///
+    /// Value of an assignable per-note controller; None if never received
+    ///
+    pub fn assignable(&self, index: u8) -> Option<u32> {
+        find(&self.assignable, index)
+    }
+
+    fn reset_controllers(&mut self) {
+        self.pitch_bend = PITCH_BEND_CENTRE_32;
+        self.pressure = 0;
+        self.registered.clear();
+        self.assignable.clear();
+    }
+
+    fn is_default(&self) -> bool {
+        !self.is_held()
+            && self.pitch_bend == PITCH_BEND_CENTRE_32
+            && self.pressure == 0
+            && self.registered.is_empty()
+            && self.assignable.is_empty()
+    }
+}
+
+fn find(controllers: &[(u8, u32)], index: u8) -> Option<u32> {
+    controllers
+        .iter()
+        .find(|(control, _)| *control == index)
+        .map(|(_, value)| *value)
+}
+
+fn set(controllers: &mut Vec<(u8, u32)>, index: u8, value: u32) {
+    match controllers
+        .iter_mut()
+        .find(|(control, _)| *control == index)
+    {
+        Some(entry) => entry.1 = value,
+        None => controllers.push((index, value)),
+    }
+}
+
+///
+/// **Per-Note Tracker**
+///
+/// Tracks MIDI 2.0 notes along with their per-note pitch bend, pressure and
+/// registered/assignable per-note controllers, the per-note counterpart of
+/// `ChannelState`.
+///
+/// Per-Note Management is applied as per the spec; Detach leaves sounding
+/// notes with the controller values they had so later per-note controllers
+/// only apply to the next note with that number, Reset returns the per-note
+/// controllers of the note number to their defaults.
+///
+#[derive(Debug, Clone, Default)]
+pub struct PerNoteTracker {
+    notes: Vec<NoteState>,    // Current state of each note number in use
+    detached: Vec<NoteState>, // Sounding notes detached from their note number
+}
+
+impl PerNoteTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &Midi2Event) {
+        let channel = event.channel();
+        match *event {
+            Midi2Event::NoteOn {
+                note,
+                velocity,
+                attribute,
+                ..
+            } => {
+                let state = self.state_mut(channel, note);
+                // Velocity 0 is a valid MIDI 2.0 velocity; the note is still held
+                state.velocity = velocity.max(1);
+                state.attribute = attribute;
+            }
+            Midi2Event::NoteOff { note, .. } => {
+                self.state_mut(channel, note).velocity = 0;
+                self.detached
+                    .retain(|state| (state.channel, state.note) != (channel, note));
+            }
+            Midi2Event::PolyPressure { note, value, .. } => {
+                self.state_mut(channel, note).pressure = value
+            }
+            Midi2Event::PerNotePitchBend { note, value, .. } => {
+                self.state_mut(channel, note).pitch_bend = value
+            }
+            Midi2Event::RegisteredPerNoteController {
+                note, index, value, ..
+            } => set(&mut self.state_mut(channel, note).registered, index, value),
+            Midi2Event::AssignablePerNoteController {
+                note, index, value, ..
+            } => set(&mut self.state_mut(channel, note).assignable, index, value),
+            Midi2Event::PerNoteManagement {
+                note,
+                detach,
+                reset,
+                ..
+            } => {
+                let state = self.state_mut(channel, note);
+                if detach && state.is_held() {
+                    let detached = state.clone();
+                    state.velocity = 0;
+                    self.detached.push(detached);
+                }
+                if reset {
+                    self.state_mut(channel, note).reset_controllers();
+                }
+            }
+            Midi2Event::ControlChange { index, .. } => match index {
+                ALL_SOUND_OFF | ALL_NOTES_OFF => self.release_channel(channel),
+                RESET_ALL_CONTROLLERS => self
+                    .notes
+                    .iter_mut()
+                    .filter(|state| state.channel == channel)
+                    .for_each(|state| state.reset_controllers()),
+                _ => {}
+            },
+            _ => {}
+        }
+        self.notes.retain(|state| !state.is_default());
+    }
+
+    ///
+    /// State of a note number; None if no state has been received
+    ///
+    pub fn note(&self, channel: Channel, note: u7) -> Option<&NoteState> {
+        self.notes
+            .iter()
+            .find(|state| (state.channel, state.note) == (channel, note))
+    }
+
+    ///
+    /// Held notes including those detached by Per-Note Management
+    ///
+    pub fn held_notes(&self) -> impl Iterator<Item = &NoteState> {
+        self.detached
+            .iter()
+            .chain(self.notes.iter().filter(|state| state.is_held()))
+    }
+
+    fn state_mut(&mut self, channel: Channel, note: u7) -> &mut NoteState {
+        let index = match self
+            .notes
+            .iter()
+            .position(|state| (state.channel, state.note) == (channel, note))
+        {
+            Some(index) => index,
+            None => {
+                self.notes.push(NoteState::new(channel, note & 0x7F));
+                self.notes.len() - 1
+            }
+        };
+        &mut self.notes[index]
+    }
+
+    fn release_channel(&mut self, channel: Channel) {
+        self.detached.retain(|state| state.channel != channel);
+        self.notes
+            .iter_mut()
+            .filter(|state| state.channel == channel)
+            .for_each(|state| state.velocity = 0);
+    }
+}
+
+///
+/// **MPE fallback for MIDI 1.0 endpoints**
+///
+/// Sends MIDI 2.0 notes with per-note expression to a MIDI 1.0 receiver as
+/// MPE. Each note is given a member channel of the zone; per-note pitch bend
+/// and poly pressure become pitch bend and channel pressure on that channel.
+/// Registered per-note controllers with a control change equivalent (1, 2, 7,
+/// 8, 10, 11 and 70-79, where 74 is the MPE timbre dimension) become control
+/// changes on that channel and Pitch 7.25 (3) becomes pitch bend relative to
+/// the note. Per-note pitch bend is scaled directly to 14 bits; the default
+/// per-note bend sensitivity of 48 semitones matches the default MPE member
+/// bend range. A Per-Note Management reset centres the pitch bend and clears
+/// the pressure of the note's member channel.
+///
+/// Channel-wide messages are translated to MIDI 1.0 and sent on the manager
+/// channel. Notes from all source channels are merged into the zone.
+/// Other per-note controllers have no MPE equivalent and are dropped.
+///
+pub struct MpeFallback {
+    sender: MpeSender,
+    zone: Zone,
+    bend_range: u7, // Member channel pitch bend range (semitones)
+    tracker: PerNoteTracker,
+    translator: Midi2ToMidi1,
+}
+
+impl MpeFallback {
+    pub fn new(zone: Zone, configuration: MpeConfiguration) -> Self {
+        Self {
+            sender: MpeSender::new(zone, configuration),
+            zone,
+            bend_range: configuration.member_bend_range(zone),
+            tracker: PerNoteTracker::new(),
+            translator: Midi2ToMidi1::new(),
+        }
+    }
+
+    ///
+    /// MPE Configuration Message to send to the receiver
+    ///
+    pub fn configure(&self) -> [MidiEvent; 6] {
+        self.sender.configure()
+    }
+
+    ///
+    /// **Translate an event**
+    ///
+    pub fn handle_event(&mut self, event: &Midi2Event) -> Vec<MidiEvent> {
+        let channel = event.channel();
+        self.tracker.handle_event(event);
+
+        match *event {
+            Midi2Event::NoteOn { note, velocity, .. } => {
+                let expression = self
+                    .tracker
+                    .note(channel, note)
+                    .map(expression)
+                    .unwrap_or_default();
+                let velocity = (scale_down(velocity as u32, 16, 7) as u7).max(1);
+                self.sender.note_on(note, velocity, expression).to_vec()
+            }
+            Midi2Event::NoteOff { note, velocity, .. } => self
+                .sender
+                .note_off(note, scale_down(velocity as u32, 16, 7) as u7)
+                .into_iter()
+                .collect(),
+            Midi2Event::PerNotePitchBend { note, value, .. } => self
+                .sender
+                .pitch_bend(note, bend(value))
+                .into_iter()
+                .collect(),
+            Midi2Event::PolyPressure { note, value, .. } => self
+                .sender
+                .pressure(note, scale_down(value, 32, 7) as u7)
+                .into_iter()
+                .collect(),
+            Midi2Event::RegisteredPerNoteController {
+                note,
+                index: PITCH_7_25,
+                value,
+                ..
+            } => self
+                .sender
+                .pitch_bend(note, pitch_to_bend(value, note, self.bend_range))
+                .into_iter()
+                .collect(),
+            Midi2Event::RegisteredPerNoteController {
+                note, index, value, ..
+            } if matches!(index, 1 | 2 | 7 | 8 | 10 | 11 | 70..=79) => self
+                .sender
+                .channel_of(note)
+                .map(|member| {
+                    MidiEvent::ControllerChange(member, index, scale_down(value, 32, 7) as u7)
+                })
+                .into_iter()
+                .collect(),
+            Midi2Event::PerNoteManagement {
+                note, reset: true, ..
+            } => match self.sender.channel_of(note) {
+                Some(member) => vec![
+                    MidiEvent::PitchBend(member, PITCH_BEND_CENTRE),
+                    MidiEvent::ChannelAfterTouch(member, 0),
+                ],
+                None => Vec::new(),
+            },
+            Midi2Event::RegisteredPerNoteController { .. }
+            | Midi2Event::AssignablePerNoteController { .. }
+            | Midi2Event::PerNoteManagement { .. } => Vec::new(),
+            Midi2Event::ControlChange {
+                index: ALL_SOUND_OFF | ALL_NOTES_OFF,
+                ..
+            } => {
+                self.sender.release_all();
+                self.on_manager(event)
+            }
+            _ => self.on_manager(event),
+        }
+    }
+
+    fn on_manager(&mut self, event: &Midi2Event) -> Vec<MidiEvent> {
+        let manager = self.zone.manager_channel();
+        self.translator
+            .handle_event(&UmpEvent::Midi2(0, *event))
+            .iter()
+            .map(|event| event.with_channel(manager))
+            .collect()
+    }
+}
+
+fn bend(value: u32) -> i16 {
+    scale_down(value, 32, 14) as i16 - 0x2000
+}
+
+///
+/// Pitch bend for a Pitch 7.25 value (note number with a 25 bit fraction)
+/// relative to `note` with a bend range of `bend_range` semitones
+///
+fn pitch_to_bend(pitch: u32, note: u7, bend_range: u7) -> i14 {
+    let offset = pitch as i64 - ((note as i64) << 25);
+    let amount = (offset << 13) / ((bend_range.max(1) as i64) << 25);
+    amount.clamp(PITCH_BEND_MIN as i64, PITCH_BEND_MAX as i64) as i14
+}
+
+fn expression(state: &NoteState) -> MpeExpression {
+    MpeExpression {
+        bend: bend(state.pitch_bend),
+        pressure: scale_down(state.pressure, 32, 7) as u7,
+        timbre: state
+            .registered(BRIGHTNESS)
+            .map_or(MPE_DEFAULT_TIMBRE, |value| scale_down(value, 32, 7) as u7),
+    }
+}
+
+///
+/// Per-note pitch bend for a MIDI 1.0 style 14 bit bend amount
+///
+pub fn per_note_bend(amount: i16) -> u32 {
+    let amount = amount.clamp(PITCH_BEND_MIN, PITCH_BEND_MAX);
+    scale_up((amount - PITCH_BEND_MIN) as u32, 14, 32)
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::per_note::{per_note_bend, MpeFallback, PerNoteTracker, PITCH_BEND_CENTRE_32};
+    use crate::{Midi2Event, MidiEvent, MpeConfiguration, NoteAttribute, Zone};
+    use parameterized::parameterized;
+
+    fn note_on(note: u8) -> Midi2Event {
+        Midi2Event::NoteOn {
+            channel: 0,
+            note,
+            velocity: 0x8000,
+            attribute: NoteAttribute::None,
+        }
+    }
+
+    fn note_off(note: u8) -> Midi2Event {
+        Midi2Event::NoteOff {
+            channel: 0,
+            note,
+            velocity: 0,
+            attribute: NoteAttribute::None,
+        }
+    }
+
+    fn bend(note: u8, value: u32) -> Midi2Event {
+        Midi2Event::PerNotePitchBend {
+            channel: 0,
+            note,
+            value,
+        }
+    }
+
+    #[test]
+    fn handle_event__controllers_before_note_on_apply() {
+        let mut target = PerNoteTracker::new();
+
+        target.handle_event(&Midi2Event::RegisteredPerNoteController {
+            channel: 0,
+            note: 60,
+            index: 74,
+            value: 1234,
+        });
+        target.handle_event(&note_on(60));
+
+        let actual = target.note(0, 60).unwrap();
+        assert!(actual.is_held());
+        assert_eq!(actual.registered(74), Some(1234));
+        assert_eq!(actual.registered(1), None);
+        assert_eq!(target.held_notes().count(), 1);
+    }
+
+    #[test]
+    fn handle_event__detach() {
+        let mut target = PerNoteTracker::new();
+        target.handle_event(&note_on(60));
+        target.handle_event(&bend(60, 100));
+
+        target.handle_event(&Midi2Event::PerNoteManagement {
+            channel: 0,
+            note: 60,
+            detach: true,
+            reset: false,
+        });
+        target.handle_event(&bend(60, 200));
+
+        let held: Vec<u32> = target.held_notes().map(|note| note.pitch_bend).collect();
+        assert_eq!(held, vec![100]);
+        assert_eq!(target.note(0, 60).unwrap().pitch_bend, 200);
+
+        target.handle_event(&note_off(60));
+        assert_eq!(target.held_notes().count(), 0);
+    }
+
+    #[test]
+    fn handle_event__reset() {
+        let mut target = PerNoteTracker::new();
+        target.handle_event(&bend(60, 100));
+
+        target.handle_event(&Midi2Event::PerNoteManagement {
+            channel: 0,
+            note: 60,
+            detach: false,
+            reset: true,
+        });
+
+        assert_eq!(target.note(0, 60), None);
+    }
+
+    #[test]
+    fn handle_event__all_notes_off() {
+        let mut target = PerNoteTracker::new();
+        target.handle_event(&note_on(60));
+        target.handle_event(&note_on(62));
+
+        target.handle_event(&Midi2Event::ControlChange {
+            channel: 0,
+            index: 123,
+            value: 0,
+        });
+
+        assert_eq!(target.held_notes().count(), 0);
+    }
+
+    #[test]
+    fn mpe_fallback__notes_on_member_channels() {
+        let mut target = MpeFallback::new(
+            Zone::Lower,
+            MpeConfiguration::new().with_zone(Zone::Lower, 4),
+        );
+
+        target.handle_event(&bend(60, per_note_bend(1000)));
+        let first = target.handle_event(&note_on(60));
+        let second = target.handle_event(&note_on(64));
+        let bend_events = target.handle_event(&bend(64, PITCH_BEND_CENTRE_32));
+        let off = target.handle_event(&note_off(60));
+
+        assert_eq!(first[0], MidiEvent::PitchBend(1, 1000));
+        assert_eq!(first[3], MidiEvent::NoteOn(1, 60, 64));
+        assert_eq!(second[3], MidiEvent::NoteOn(2, 64, 64));
+        assert_eq!(bend_events, vec![MidiEvent::PitchBend(2, 0)]);
+        assert_eq!(off, vec![MidiEvent::NoteOff(1, 60, 0)]);
+    }
+
+    fn registered(note: u8, index: u8, value: u32) -> Midi2Event {
+        Midi2Event::RegisteredPerNoteController {
+            channel: 0,
+            note,
+            index,
+            value,
+        }
+    }
+
+    #[parameterized(
+        index = {1, 11, 74, 79, 4, 12, 80},
+        expected = {
+            Some(MidiEvent::ControllerChange(1, 1, 127)),
+            Some(MidiEvent::ControllerChange(1, 11, 127)),
+            Some(MidiEvent::ControllerChange(1, 74, 127)),
+            Some(MidiEvent::ControllerChange(1, 79, 127)),
+            None,
+            None,
+            None,
+        }
+    )]
+    fn mpe_fallback__registered_controllers(index: u8, expected: Option<MidiEvent>) {
+        let mut target = MpeFallback::new(
+            Zone::Lower,
+            MpeConfiguration::new().with_zone(Zone::Lower, 4),
+        );
+        target.handle_event(&note_on(60));
+
+        let actual = target.handle_event(&registered(60, index, 0xFFFF_FFFF));
+
+        assert_eq!(actual, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[parameterized(
+        pitch = {61 << 25, 59 << 25, 60 << 25, 127 << 25},
+        expected = {170, -170, 0, 8191}
+    )]
+    fn mpe_fallback__pitch_7_25_is_member_bend(pitch: u32, expected: i16) {
+        let mut target = MpeFallback::new(
+            Zone::Lower,
+            MpeConfiguration::new().with_zone(Zone::Lower, 4),
+        );
+        target.handle_event(&note_on(60));
+
+        let actual = target.handle_event(&registered(60, 3, pitch));
+
+        assert_eq!(actual, vec![MidiEvent::PitchBend(1, expected)]);
+    }
+
+    #[test]
+    fn mpe_fallback__per_note_reset_centres_member_channel() {
+        let mut target = MpeFallback::new(
+            Zone::Lower,
+            MpeConfiguration::new().with_zone(Zone::Lower, 4),
+        );
+        target.handle_event(&note_on(60));
+        target.handle_event(&note_on(62));
+        target.handle_event(&bend(62, per_note_bend(1000)));
+
+        let actual = target.handle_event(&Midi2Event::PerNoteManagement {
+            channel: 0,
+            note: 62,
+            detach: false,
+            reset: true,
+        });
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::PitchBend(2, 0),
+                MidiEvent::ChannelAfterTouch(2, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn mpe_fallback__all_notes_off_releases_member_channels() {
+        let mut target = MpeFallback::new(
+            Zone::Lower,
+            MpeConfiguration::new().with_zone(Zone::Lower, 4),
+        );
+        target.handle_event(&note_on(60));
+
+        let actual = target.handle_event(&Midi2Event::ControlChange {
+            channel: 0,
+            index: 123,
+            value: 0,
+        });
+
+        assert_eq!(actual, vec![MidiEvent::AllNotesOff(0)]);
+        assert!(target.handle_event(&note_off(60)).is_empty());
+    }
+
+    #[parameterized(
+        amount = {i16::MIN, -0x2000, 0, 0x1FFF, 0x7000},
+        expected = {0, 0, 0x8000_0000, 0xFFFF_FFFF, 0xFFFF_FFFF}
+    )]
+    fn per_note_bend__clamps_amount(amount: i16, expected: u32) {
+        assert_eq!(per_note_bend(amount), expected);
+    }
+
+    #[test]
+    fn mpe_fallback__channel_messages_on_manager() {
+        let mut target = MpeFallback::new(
+            Zone::Upper,
+            MpeConfiguration::new().with_zone(Zone::Upper, 4),
+        );
+
+        let actual = target.handle_event(&Midi2Event::ControlChange {
+            channel: 3,
+            index: 64,
+            value: 0xFFFF_FFFF,
+        });
+
+        assert_eq!(actual, vec![MidiEvent::ControllerChange(15, 64, 127)]);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{i14, u14, u7};
+
+/// Most negative pitch bend amount
+pub const PITCH_BEND_MIN: i14 = -0x2000;
+/// Most positive pitch bend amount
+pub const PITCH_BEND_MAX: i14 = 0x1FFF;
+/// Pitch bend amount with no bend applied
+pub const PITCH_BEND_CENTRE: i14 = 0;
+
+/// Default bend range (in semitones) as defined by the General MIDI spec
+pub const DEFAULT_BEND_RANGE: f32 = 2.0;
+
+const RAW_CENTRE: u14 = 0x2000;
+
+///
+/// **Decode a pitch bend from wire data bytes**
+///
+/// The 14 bit value is centred on 0x2000, which decodes as exactly 0, giving
+/// a range of -8192..=8191.
+///
+#[inline]
+pub fn decode(lsb: u7, msb: u7) -> i14 {
+    let word = (((msb & 0x7F) as u14) << 7) | ((lsb & 0x7F) as u14);
+    word as i14 - RAW_CENTRE as i14
+}
+
+///
+/// **Encode a pitch bend amount into wire data bytes**
+///
+/// Inverse of [`decode`]; returns `(lsb, msb)` in transmission order. Amounts
+/// outside of -8192..=8191 are clamped.
+///
+#[inline]
+pub fn encode(amount: i14) -> (u7, u7) {
+    let word = (clamp(amount) + RAW_CENTRE as i14) as u14;
+    ((word & 0x7F) as u7, (word >> 7) as u7)
+}
+
+///
+/// **Convert a pitch bend amount to a normalised value**
+///
+/// Returns a value in the range -1.0..=1.0. The negative and positive halves
+/// are scaled independently so both extremes are reachable and the centre is
+/// exactly 0.0.
+///
+pub fn to_normalised(amount: i14) -> f32 {
+    let amount = clamp(amount);
+    if amount < 0 {
+        amount as f32 / -(PITCH_BEND_MIN as f32)
+    } else {
+        amount as f32 / PITCH_BEND_MAX as f32
+    }
+}
+
+///
+/// **Convert a normalised value to a pitch bend amount**
+///
+/// Inverse of [`to_normalised`]; values outside -1.0..=1.0 are clamped.
+///
+pub fn from_normalised(value: f32) -> i14 {
+    let value = value.clamp(-1.0, 1.0);
+    if value < 0.0 {
+        round(value * -(PITCH_BEND_MIN as f32))
+    } else {
+        round(value * PITCH_BEND_MAX as f32)
+    }
+}
+
+// Round half away from zero; f32::round is not available without std
+fn round(value: f32) -> i14 {
+    if value < 0.0 {
+        (value - 0.5) as i14
+    } else {
+        (value + 0.5) as i14
+    }
+}
+
+///
+/// **Convert a pitch bend amount to semitones**
+///
+/// `range` is the bend sensitivity in semitones (eg 2.0 for the GM default of
+/// +/- 2 semitones).
+///
+pub fn to_semitones(amount: i14, range: f32) -> f32 {
+    to_normalised(amount) * range
+}
+
+///
+/// **Convert a pitch bend amount to cents**
+///
+pub fn to_cents(amount: i14, range: f32) -> f32 {
+    to_semitones(amount, range) * 100.0
+}
+
+///
+/// **Convert an offset in semitones to a pitch bend amount**
+///
+/// Offsets beyond `range` are clamped to the extremes of the bend range.
+///
+pub fn from_semitones(semitones: f32, range: f32) -> i14 {
+    if range <= 0.0 {
+        return PITCH_BEND_CENTRE;
+    }
+    from_normalised(semitones / range)
+}
+
+///
+/// **Convert an offset in cents to a pitch bend amount**
+///
+pub fn from_cents(cents: f32, range: f32) -> i14 {
+    from_semitones(cents / 100.0, range)
+}
+
+#[inline]
+fn clamp(amount: i14) -> i14 {
+    amount.clamp(PITCH_BEND_MIN, PITCH_BEND_MAX)
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::pitch_bend::*;
+    use parameterized::parameterized;
+
+    #[parameterized(
+        bytes = {(0x00, 0x40), (0x00, 0x00), (0x7F, 0x7F), (0x01, 0x40), (0x7F, 0x3F)},
+        expected = {0, -8192, 8191, 1, -1}
+    )]
+    fn decode__wire_bytes(bytes: (u8, u8), expected: i16) {
+        let actual = decode(bytes.0, bytes.1);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn encode__is_inverse_of_decode() {
+        for amount in PITCH_BEND_MIN..=PITCH_BEND_MAX {
+            let (lsb, msb) = encode(amount);
+
+            assert!(lsb < 0x80 && msb < 0x80);
+            assert_eq!(decode(lsb, msb), amount);
+        }
+    }
+
+    #[test]
+    fn encode__where_out_of_range_is_clamped() {
+        assert_eq!(encode(i16::MAX), (0x7F, 0x7F));
+        assert_eq!(encode(i16::MIN), (0x00, 0x00));
+    }
+
+    #[parameterized(
+        amount = {PITCH_BEND_MIN, PITCH_BEND_CENTRE, PITCH_BEND_MAX, -4096},
+        expected = {-1.0, 0.0, 1.0, -0.5}
+    )]
+    fn to_normalised__extremes_and_centre(amount: i16, expected: f32) {
+        assert_eq!(to_normalised(amount), expected);
+    }
+
+    #[test]
+    fn from_normalised__round_trips() {
+        for amount in [PITCH_BEND_MIN, -1234, 0, 1, 4321, PITCH_BEND_MAX].iter() {
+            assert_eq!(from_normalised(to_normalised(*amount)), *amount);
+        }
+    }
+
+    #[test]
+    fn to_semitones__with_range() {
+        assert_eq!(to_semitones(PITCH_BEND_MAX, DEFAULT_BEND_RANGE), 2.0);
+        assert_eq!(to_semitones(PITCH_BEND_MIN, 12.0), -12.0);
+        assert_eq!(to_cents(PITCH_BEND_MIN, DEFAULT_BEND_RANGE), -200.0);
+    }
+
+    #[test]
+    fn from_cents__with_range() {
+        assert_eq!(from_cents(-100.0, DEFAULT_BEND_RANGE), -4096);
+        assert_eq!(from_cents(300.0, DEFAULT_BEND_RANGE), PITCH_BEND_MAX);
+        assert_eq!(from_semitones(1.0, 0.0), PITCH_BEND_CENTRE);
+    }
+}
@@ -0,0 +1,734 @@
+use crate::{u7, Channel, MessageKind, MidiEvent, MidiEvents};
+
+///
+/// **Event processor**
+///
+/// A single stage of a processing pipeline. Each stage receives an event and
+/// returns the (possibly modified) events to pass on; most stages return at
+/// most one event while a [`KeySplit`] may return a copy for each zone.
+///
+/// Stages are chained with [`Processor::then`]; closures taking an event and
+/// returning an optional event are also processors so custom stages can be
+/// written inline:
+///
+/// ```
+/// use sc_midi::{ChannelFilter, MidiEvent, Processor, Transpose};
+///
+/// let mut pipeline = ChannelFilter::only(0)
+///     .then(Transpose::new(12))
+///     .then(|event| match event {
+///         MidiEvent::PitchBend(_, _) => None,
+///         _ => Some(event),
+///     });
+///
+/// assert_eq!(
+///     *pipeline.process(MidiEvent::NoteOn(0, 60, 100)),
+///     [MidiEvent::NoteOn(0, 72, 100)]
+/// );
+/// assert!(pipeline.process(MidiEvent::NoteOn(1, 60, 100)).is_empty());
+/// ```
+///
+/// Stages that return more than one event implement the trait and build their
+/// result with [`MidiEvents::new`] and [`crate::List::push`]:
+///
+/// ```
+/// use sc_midi::{MidiEvent, MidiEvents, Processor};
+///
+/// struct Octaves;
+///
+/// impl Processor for Octaves {
+///     fn process(&mut self, event: MidiEvent) -> MidiEvents {
+///         let mut events = MidiEvents::new();
+///         events.push(event);
+///         if let MidiEvent::NoteOn(channel, key, velocity) = event {
+///             events.push(MidiEvent::NoteOn(channel, key.saturating_add(12).min(127), velocity));
+///         }
+///         events
+///     }
+/// }
+///
+/// assert_eq!(
+///     *Octaves.process(MidiEvent::NoteOn(0, 60, 100)),
+///     [MidiEvent::NoteOn(0, 60, 100), MidiEvent::NoteOn(0, 72, 100)]
+/// );
+/// ```
+///
+pub trait Processor {
+    ///
+    /// Process an event
+    ///
+    fn process(&mut self, event: MidiEvent) -> MidiEvents;
+
+    ///
+    /// Pass events from this stage on to `next`
+    ///
+    fn then<P: Processor>(self, next: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+impl<F> Processor for F
+where
+    F: FnMut(MidiEvent) -> Option<MidiEvent>,
+{
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        self(event).into()
+    }
+}
+
+///
+/// Two chained processors, see [`Processor::then`]
+///
+/// Every event returned by the first stage is passed to the second; events
+/// beyond the capacity of [`MidiEvents`] are dropped.
+///
+#[derive(Debug, Clone)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Processor, B: Processor> Processor for Chain<A, B> {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        let mut events = MidiEvents::new();
+        for event in self.first.process(event).iter() {
+            for event in self.second.process(*event).iter() {
+                events.push(*event);
+            }
+        }
+        events
+    }
+}
+
+///
+/// **Pass events on selected channels**
+///
+/// System events are not channel specific and always pass.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ChannelFilter {
+    channels: u16, // Bit per channel
+}
+
+impl ChannelFilter {
+    ///
+    /// Filter passing no channels
+    ///
+    pub fn new() -> Self {
+        Self { channels: 0 }
+    }
+
+    ///
+    /// Filter passing a single channel
+    ///
+    pub fn only(channel: Channel) -> Self {
+        Self::new().with_channel(channel)
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channels |= 1 << (channel & 0x0F);
+        self
+    }
+
+    pub fn without_channel(mut self, channel: Channel) -> Self {
+        self.channels &= !(1 << (channel & 0x0F));
+        self
+    }
+
+    pub fn passes(&self, channel: Channel) -> bool {
+        self.channels & (1 << (channel & 0x0F)) != 0
+    }
+}
+
+impl Default for ChannelFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor for ChannelFilter {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        match event.channel() {
+            Some(channel) if !self.passes(channel) => None,
+            _ => Some(event),
+        }
+        .into()
+    }
+}
+
+impl MessageKind {
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+///
+/// **Pass or block events by message type**
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MessageFilter {
+    kinds: u16, // Bit per MessageKind that passes
+}
+
+impl MessageFilter {
+    ///
+    /// Filter passing all messages
+    ///
+    pub fn new() -> Self {
+        Self { kinds: 0xFFFF }
+    }
+
+    ///
+    /// Filter passing only the listed message types
+    ///
+    pub fn allow(kinds: &[MessageKind]) -> Self {
+        Self {
+            kinds: kinds.iter().fold(0, |bits, kind| bits | kind.bit()),
+        }
+    }
+
+    ///
+    /// Filter passing everything except the listed message types
+    ///
+    pub fn block(kinds: &[MessageKind]) -> Self {
+        Self {
+            kinds: kinds.iter().fold(0xFFFF, |bits, kind| bits & !kind.bit()),
+        }
+    }
+
+    pub fn passes(&self, kind: MessageKind) -> bool {
+        self.kinds & kind.bit() != 0
+    }
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor for MessageFilter {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        Some(event).filter(|event| self.passes(event.kind())).into()
+    }
+}
+
+///
+/// **Move channel events between channels**
+///
+/// Each channel maps to itself until remapped.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ChannelRemap {
+    map: [Channel; 16],
+}
+
+impl ChannelRemap {
+    pub fn new() -> Self {
+        let mut map = [0; 16];
+        for (channel, target) in map.iter_mut().enumerate() {
+            *target = channel as Channel;
+        }
+        Self { map }
+    }
+
+    ///
+    /// Remap every channel to a single channel
+    ///
+    pub fn all_to(channel: Channel) -> Self {
+        Self {
+            map: [channel & 0x0F; 16],
+        }
+    }
+
+    pub fn with_mapping(mut self, from: Channel, to: Channel) -> Self {
+        self.map[from as usize & 0x0F] = to & 0x0F;
+        self
+    }
+}
+
+impl Default for ChannelRemap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor for ChannelRemap {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        Some(match event.channel() {
+            Some(channel) => event.with_channel(self.map[channel as usize & 0x0F]),
+            None => event,
+        })
+        .into()
+    }
+}
+
+///
+/// **Transpose notes**
+///
+/// Applies to Note On/Off and Polyphonic After-Touch. Notes transposed outside
+/// of 0-127 are dropped.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Transpose {
+    semitones: i8,
+}
+
+impl Transpose {
+    pub fn new(semitones: i8) -> Self {
+        Self { semitones }
+    }
+
+    fn key(&self, key: u7) -> Option<u7> {
+        let key = key as i16 + self.semitones as i16;
+        if (0..=127).contains(&key) {
+            Some(key as u7)
+        } else {
+            None
+        }
+    }
+}
+
+impl Processor for Transpose {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        match event {
+            MidiEvent::NoteOff(channel, key, velocity) => self
+                .key(key)
+                .map(|key| MidiEvent::NoteOff(channel, key, velocity)),
+            MidiEvent::NoteOn(channel, key, velocity) => self
+                .key(key)
+                .map(|key| MidiEvent::NoteOn(channel, key, velocity)),
+            MidiEvent::PolyphonicAfterTouch(channel, key, pressure) => self
+                .key(key)
+                .map(|key| MidiEvent::PolyphonicAfterTouch(channel, key, pressure)),
+            event => Some(event),
+        }
+        .into()
+    }
+}
+
+///
+/// **Note On velocity curve**
+///
+/// A lookup table applied to Note On velocities. A velocity of 0 (Note Off)
+/// is left unchanged and other velocities are never mapped to 0 so a curve
+/// cannot turn a Note On into a Note Off.
+///
+#[derive(Clone)]
+pub struct VelocityCurve {
+    table: [u7; 128],
+}
+
+impl VelocityCurve {
+    ///
+    /// Linear curve; velocities pass unchanged
+    ///
+    pub fn new() -> Self {
+        Self::from_fn(|velocity| velocity)
+    }
+
+    ///
+    /// Curve from a function mapping velocity to velocity
+    ///
+    pub fn from_fn<F: Fn(u7) -> u7>(curve: F) -> Self {
+        let mut table = [0; 128];
+        for (velocity, value) in table.iter_mut().enumerate().skip(1) {
+            *value = curve(velocity as u7).clamp(1, 127);
+        }
+        Self { table }
+    }
+
+    ///
+    /// Scale velocities by `factor`
+    ///
+    pub fn scale(factor: f32) -> Self {
+        Self::from_fn(|velocity| (velocity as f32 * factor + 0.5).clamp(0.0, 127.0) as u7)
+    }
+
+    ///
+    /// Fixed velocity for all notes
+    ///
+    pub fn fixed(velocity: u7) -> Self {
+        Self::from_fn(|_| velocity)
+    }
+
+    ///
+    /// **Exponential curve**
+    ///
+    /// A `gamma` above 1.0 softens the response, below 1.0 hardens it.
+    ///
+    #[cfg(feature = "std")]
+    pub fn gamma(gamma: f32) -> Self {
+        Self::from_fn(|velocity| {
+            (127.0 * (velocity as f32 / 127.0).powf(gamma) + 0.5).clamp(0.0, 127.0) as u7
+        })
+    }
+
+    pub fn apply(&self, velocity: u7) -> u7 {
+        self.table[velocity as usize & 0x7F]
+    }
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for VelocityCurve {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VelocityCurve").finish_non_exhaustive()
+    }
+}
+
+impl Processor for VelocityCurve {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        Some(match event {
+            MidiEvent::NoteOn(channel, key, velocity) => {
+                MidiEvent::NoteOn(channel, key, self.apply(velocity))
+            }
+            event => event,
+        })
+        .into()
+    }
+}
+
+///
+/// **Remap controller numbers**
+///
+/// Each controller maps to itself until remapped. Channel mode messages
+/// (controllers 120-127) are separate events and are not affected.
+///
+#[derive(Clone)]
+pub struct ControllerRemap {
+    map: [u7; 128],
+}
+
+impl ControllerRemap {
+    pub fn new() -> Self {
+        let mut map = [0; 128];
+        for (control, target) in map.iter_mut().enumerate() {
+            *target = control as u7;
+        }
+        Self { map }
+    }
+
+    pub fn with_mapping(mut self, from: u7, to: u7) -> Self {
+        self.map[from as usize & 0x7F] = to & 0x7F;
+        self
+    }
+}
+
+impl Default for ControllerRemap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for ControllerRemap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ControllerRemap").finish_non_exhaustive()
+    }
+}
+
+impl Processor for ControllerRemap {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        Some(match event {
+            MidiEvent::ControllerChange(channel, control, value) => {
+                MidiEvent::ControllerChange(channel, self.map[control as usize & 0x7F], value)
+            }
+            event => event,
+        })
+        .into()
+    }
+}
+
+fn event_key(event: &MidiEvent) -> Option<u7> {
+    match *event {
+        MidiEvent::NoteOff(_, key, _)
+        | MidiEvent::NoteOn(_, key, _)
+        | MidiEvent::PolyphonicAfterTouch(_, key, _) => Some(key),
+        _ => None,
+    }
+}
+
+///
+/// **Pass notes within a key range**
+///
+/// Applies to Note On/Off and Polyphonic After-Touch; other events pass.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyRange {
+    low: u7,
+    high: u7,
+}
+
+impl KeyRange {
+    ///
+    /// Range from `low` to `high` inclusive
+    ///
+    pub fn new(low: u7, high: u7) -> Self {
+        Self { low, high }
+    }
+
+    pub fn contains(&self, key: u7) -> bool {
+        (self.low..=self.high).contains(&key)
+    }
+}
+
+impl Processor for KeyRange {
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        match event_key(&event) {
+            Some(key) if !self.contains(key) => None,
+            _ => Some(event),
+        }
+        .into()
+    }
+}
+
+///
+/// **Keyboard split**
+///
+/// Routes notes below the split key to the lower channel and notes from the
+/// split key upwards to the upper channel. Other channel events (eg the
+/// sustain pedal) are sent to both zones, lower zone first.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeySplit {
+    split: u7,
+    lower: Channel,
+    upper: Channel,
+}
+
+impl KeySplit {
+    pub fn new(split: u7, lower: Channel, upper: Channel) -> Self {
+        Self {
+            split,
+            lower: lower & 0x0F,
+            upper: upper & 0x0F,
+        }
+    }
+
+    ///
+    /// **Route an event**
+    ///
+    /// Returns the events for the lower and upper zones; system events are
+    /// returned unchanged as the lower event only.
+    ///
+    pub fn route(&self, event: MidiEvent) -> (Option<MidiEvent>, Option<MidiEvent>) {
+        match (event_key(&event), event.channel()) {
+            (Some(key), _) if key < self.split => (Some(event.with_channel(self.lower)), None),
+            (Some(_), _) => (None, Some(event.with_channel(self.upper))),
+            (None, Some(_)) => (
+                Some(event.with_channel(self.lower)),
+                Some(event.with_channel(self.upper)),
+            ),
+            (None, None) => (Some(event), None),
+        }
+    }
+}
+
+impl Processor for KeySplit {
+    ///
+    /// Notes are routed by key; other channel events go to both zones
+    ///
+    fn process(&mut self, event: MidiEvent) -> MidiEvents {
+        let mut events = MidiEvents::new();
+        let (lower, upper) = self.route(event);
+        for event in lower.iter().chain(upper.iter()) {
+            events.push(*event);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::processor::{
+        ChannelFilter, ChannelRemap, ControllerRemap, KeyRange, KeySplit, MessageFilter,
+        MessageKind, Processor, Transpose, VelocityCurve,
+    };
+    use crate::{MidiEvent, MidiEvents};
+    use parameterized::parameterized;
+
+    #[test]
+    fn channel_filter__passes_system_events() {
+        let mut target = ChannelFilter::only(3);
+
+        assert!(target.process(MidiEvent::NoteOn(2, 60, 1)).is_empty());
+        assert_eq!(
+            *target.process(MidiEvent::NoteOn(3, 60, 1)),
+            [MidiEvent::NoteOn(3, 60, 1)]
+        );
+        assert_eq!(*target.process(MidiEvent::Clock), [MidiEvent::Clock]);
+    }
+
+    #[test]
+    fn message_filter__block() {
+        let mut target =
+            MessageFilter::block(&[MessageKind::PitchBend, MessageKind::SystemRealtime]);
+
+        assert!(target.process(MidiEvent::PitchBend(0, 10)).is_empty());
+        assert!(target.process(MidiEvent::ActiveSensing).is_empty());
+        assert_eq!(
+            *target.process(MidiEvent::AllNotesOff(0)),
+            [MidiEvent::AllNotesOff(0)]
+        );
+    }
+
+    #[test]
+    fn message_filter__allow() {
+        let mut target = MessageFilter::allow(&[MessageKind::NoteOn, MessageKind::NoteOff]);
+
+        assert!(target
+            .process(MidiEvent::ControllerChange(0, 1, 2))
+            .is_empty());
+        assert_eq!(
+            *target.process(MidiEvent::NoteOff(0, 1, 2)),
+            [MidiEvent::NoteOff(0, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn channel_remap__moves_channel_events() {
+        let mut target = ChannelRemap::new().with_mapping(0, 9);
+
+        assert_eq!(
+            *target.process(MidiEvent::ProgramChange(0, 5)),
+            [MidiEvent::ProgramChange(9, 5)]
+        );
+        assert_eq!(
+            *target.process(MidiEvent::ProgramChange(1, 5)),
+            [MidiEvent::ProgramChange(1, 5)]
+        );
+    }
+
+    #[parameterized(
+        semitones = {12, -12, 12, -61},
+        event = {
+            MidiEvent::NoteOn(0, 60, 100),
+            MidiEvent::NoteOff(0, 60, 0),
+            MidiEvent::NoteOn(0, 120, 100),
+            MidiEvent::PolyphonicAfterTouch(0, 60, 10),
+        },
+        expected = {
+            Some(MidiEvent::NoteOn(0, 72, 100)),
+            Some(MidiEvent::NoteOff(0, 48, 0)),
+            None,
+            None,
+        }
+    )]
+    fn transpose__process(semitones: i8, event: MidiEvent, expected: Option<MidiEvent>) {
+        let mut target = Transpose::new(semitones);
+
+        assert_eq!(target.process(event), MidiEvents::from(expected));
+    }
+
+    #[parameterized(
+        velocity = {0, 1, 64, 127},
+        expected = {0, 1, 32, 64}
+    )]
+    fn velocity_curve__scale(velocity: u8, expected: u8) {
+        let mut target = VelocityCurve::scale(0.5);
+
+        assert_eq!(
+            *target.process(MidiEvent::NoteOn(0, 60, velocity)),
+            [MidiEvent::NoteOn(0, 60, expected)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn velocity_curve__gamma() {
+        let target = VelocityCurve::gamma(2.0);
+
+        assert_eq!(target.apply(127), 127);
+        assert_eq!(target.apply(64), 32);
+        assert_eq!(target.apply(1), 1);
+    }
+
+    #[test]
+    fn controller_remap__process() {
+        let mut target = ControllerRemap::new().with_mapping(1, 74);
+
+        assert_eq!(
+            *target.process(MidiEvent::ControllerChange(2, 1, 99)),
+            [MidiEvent::ControllerChange(2, 74, 99)]
+        );
+    }
+
+    #[test]
+    fn key_split__routes_by_key() {
+        let mut target = KeySplit::new(60, 0, 1);
+
+        assert_eq!(
+            *target.process(MidiEvent::NoteOn(5, 59, 1)),
+            [MidiEvent::NoteOn(0, 59, 1)]
+        );
+        assert_eq!(
+            *target.process(MidiEvent::NoteOn(5, 60, 1)),
+            [MidiEvent::NoteOn(1, 60, 1)]
+        );
+        assert_eq!(
+            target.route(MidiEvent::ControllerChange(5, 64, 127)),
+            (
+                Some(MidiEvent::ControllerChange(0, 64, 127)),
+                Some(MidiEvent::ControllerChange(1, 64, 127))
+            )
+        );
+    }
+
+    #[parameterized(
+        event = {
+            MidiEvent::ControllerChange(5, 64, 127),
+            MidiEvent::PitchBend(5, 100),
+            MidiEvent::ProgramChange(5, 3),
+        }
+    )]
+    fn key_split__process_sends_channel_events_to_both_zones(event: MidiEvent) {
+        let mut target = KeySplit::new(60, 0, 1);
+
+        let actual = target.process(event);
+
+        assert_eq!(*actual, [event.with_channel(0), event.with_channel(1)]);
+    }
+
+    #[test]
+    fn then__passes_each_split_event_on() {
+        let mut target = KeySplit::new(60, 0, 1).then(ChannelRemap::new().with_mapping(1, 9));
+
+        let actual = target.process(MidiEvent::ControllerChange(5, 64, 127));
+
+        assert_eq!(
+            *actual,
+            [
+                MidiEvent::ControllerChange(0, 64, 127),
+                MidiEvent::ControllerChange(9, 64, 127)
+            ]
+        );
+    }
+
+    #[test]
+    fn then__chains_stages() {
+        let mut target = KeyRange::new(36, 72)
+            .then(Transpose::new(-12))
+            .then(ChannelRemap::all_to(4))
+            .then(|event| match event {
+                MidiEvent::NoteOn(channel, key, _) => Some(MidiEvent::NoteOn(channel, key, 100)),
+                event => Some(event),
+            });
+
+        assert!(target.process(MidiEvent::NoteOn(0, 80, 1)).is_empty());
+        assert_eq!(
+            *target.process(MidiEvent::NoteOn(0, 60, 1)),
+            [MidiEvent::NoteOn(4, 48, 100)]
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use crate::{pitch_bend, List};
+use core::fmt;
+
 #[allow(non_camel_case_types)]
 pub type u3 = u8;
 #[allow(non_camel_case_types)]
@@ -10,49 +13,49 @@ pub type i14 = i16;
 #[allow(non_camel_case_types)]
 pub type u14 = u16;
 
-const STATUS_MASK: u8 = 0b1000_0000;
-const CHANNEL_MASK: u8 = 0b0000_1111;
-const SYSTEM_MASK: u8 = 0b1111_0000;
-const SYSTEM_RT_MASK: u8 = 0b1111_1000;
+pub(crate) const STATUS_MASK: u8 = 0b1000_0000;
+pub(crate) const CHANNEL_MASK: u8 = 0b0000_1111;
+pub(crate) const SYSTEM_MASK: u8 = 0b1111_0000;
+pub(crate) const SYSTEM_RT_MASK: u8 = 0b1111_1000;
 
-const MESSAGE_NONE: u8 = 0x00;
+pub(crate) const MESSAGE_NONE: u8 = 0x00;
 // Channel Voice Messages
-const MESSAGE_NOTE_OFF: u8 = 0x80;
-const MESSAGE_NOTE_ON: u8 = 0x90;
-const MESSAGE_POLY_AFTER_TOUCH: u8 = 0xA0; // Polyphonic AfterTouch
-const MESSAGE_CONTROLLER_CHANGE: u8 = 0xB0; // Controller Change / Channel Mode
-const MESSAGE_PROGRAM_CHANGE: u8 = 0xC0;
-const MESSAGE_CHANNEL_AFTER_TOUCH: u8 = 0xD0;
-const MESSAGE_PITCH_BEND: u8 = 0xE0;
+pub(crate) const MESSAGE_NOTE_OFF: u8 = 0x80;
+pub(crate) const MESSAGE_NOTE_ON: u8 = 0x90;
+pub(crate) const MESSAGE_POLY_AFTER_TOUCH: u8 = 0xA0; // Polyphonic AfterTouch
+pub(crate) const MESSAGE_CONTROLLER_CHANGE: u8 = 0xB0; // Controller Change / Channel Mode
+pub(crate) const MESSAGE_PROGRAM_CHANGE: u8 = 0xC0;
+pub(crate) const MESSAGE_CHANNEL_AFTER_TOUCH: u8 = 0xD0;
+pub(crate) const MESSAGE_PITCH_BEND: u8 = 0xE0;
 // System Common Messages
-const MESSAGE_SYS_EX_START: u8 = 0xF0; // System Exclusive Start
-const MESSAGE_MTC_QUARTER_FRAME: u8 = 0xF1; // Time Code Quarter Frame
-const MESSAGE_SONG_POSITION_PTR: u8 = 0xF2; // Song Position Pointer
-const MESSAGE_SONG_SELECT: u8 = 0xF3;
-const MESSAGE_TUNE_REQUEST: u8 = 0xF6;
-const MESSAGE_SYS_EX_END: u8 = 0xF7; // System Exclusive End
-                                     // System Realtime Messages
-const MESSAGE_CLOCK: u8 = 0xF8;
-const MESSAGE_START: u8 = 0xFA;
-const MESSAGE_CONTINUE: u8 = 0xFB;
-const MESSAGE_STOP: u8 = 0xFC;
-const MESSAGE_ACTIVE_SENSING: u8 = 0xFE;
-const MESSAGE_SYSTEM_RESET: u8 = 0xFF;
+pub(crate) const MESSAGE_SYS_EX_START: u8 = 0xF0; // System Exclusive Start
+pub(crate) const MESSAGE_MTC_QUARTER_FRAME: u8 = 0xF1; // Time Code Quarter Frame
+pub(crate) const MESSAGE_SONG_POSITION_PTR: u8 = 0xF2; // Song Position Pointer
+pub(crate) const MESSAGE_SONG_SELECT: u8 = 0xF3;
+pub(crate) const MESSAGE_TUNE_REQUEST: u8 = 0xF6;
+pub(crate) const MESSAGE_SYS_EX_END: u8 = 0xF7; // System Exclusive End
+                                                // System Realtime Messages
+pub(crate) const MESSAGE_CLOCK: u8 = 0xF8;
+pub(crate) const MESSAGE_START: u8 = 0xFA;
+pub(crate) const MESSAGE_CONTINUE: u8 = 0xFB;
+pub(crate) const MESSAGE_STOP: u8 = 0xFC;
+pub(crate) const MESSAGE_ACTIVE_SENSING: u8 = 0xFE;
+pub(crate) const MESSAGE_SYSTEM_RESET: u8 = 0xFF;
 
 // Channel Mode messages
-const CHANNEL_MODE_ALL_SOUND_OFF: u7 = 0x78;
-const CHANNEL_MODE_RESET_ALL: u7 = 0x79;
-const CHANNEL_MODE_LOCAL_CONTROL: u7 = 0x7A;
-const CHANNEL_MODE_ALL_NOTES_OFF: u7 = 0x7B;
-const CHANNEL_MODE_OMNI_ON: u7 = 0x7C;
-const CHANNEL_MODE_OMNI_OFF: u7 = 0x7D;
-const CHANNEL_MODE_MONO_ON: u7 = 0x7E;
-const CHANNEL_MODE_POLYPHONIC_ON: u7 = 0x7F;
+pub(crate) const CHANNEL_MODE_ALL_SOUND_OFF: u7 = 0x78;
+pub(crate) const CHANNEL_MODE_RESET_ALL: u7 = 0x79;
+pub(crate) const CHANNEL_MODE_LOCAL_CONTROL: u7 = 0x7A;
+pub(crate) const CHANNEL_MODE_ALL_NOTES_OFF: u7 = 0x7B;
+pub(crate) const CHANNEL_MODE_OMNI_ON: u7 = 0x7C;
+pub(crate) const CHANNEL_MODE_OMNI_OFF: u7 = 0x7D;
+pub(crate) const CHANNEL_MODE_MONO_ON: u7 = 0x7E;
+pub(crate) const CHANNEL_MODE_POLYPHONIC_ON: u7 = 0x7F;
 
 ///
 /// Size of System Exclusive ID
 ///
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SysExID {
     Byte(u7),
     Word(u14),
@@ -61,7 +64,7 @@ pub enum SysExID {
 ///
 /// Generated Midi events
 ///
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MidiEvent {
     // Channel events
     NoteOff(Channel, u7, u7),              // Channel, Key, Velocity
@@ -69,15 +72,15 @@ pub enum MidiEvent {
     NoteOn(Channel, u7, u7),               // Channel, Key, Velocity
     PolyphonicAfterTouch(Channel, u7, u7), // Channel, Key, Pressure
     ControllerChange(Channel, u7, u7),     // Channel, Control, Value
-    AllSoundOff,
-    ResetAllControllers,
-    LocalControl(Channel, bool),    // Channel, On
-    OmniMode(Channel, bool),        // Channel, On
-    MonoMode(Channel, u7),          // Channel, Num Channels
-    PolyphonicMode(Channel),        // Channel
-    ProgramChange(Channel, u7),     // Channel, Program Num
-    ChannelAfterTouch(Channel, u7), // Channel, Pressure
-    PitchBend(Channel, i14),        // Channel, Amount
+    AllSoundOff(Channel),                  // Channel
+    ResetAllControllers(Channel),          // Channel
+    LocalControl(Channel, bool),           // Channel, On
+    OmniMode(Channel, bool),               // Channel, On
+    MonoMode(Channel, u7),                 // Channel, Num Channels
+    PolyphonicMode(Channel),               // Channel
+    ProgramChange(Channel, u7),            // Channel, Program Num
+    ChannelAfterTouch(Channel, u7),        // Channel, Pressure
+    PitchBend(Channel, i14),               // Channel, Amount
     // System Common events
     MTCQuarterFrame(u3, u4),  // Type, Value
     SongPositionPointer(u14), // MIDI beats (1 beat = 6 MIDI clocks)
@@ -96,89 +99,215 @@ pub enum MidiEvent {
     SystemExclusiveEnd,
 }
 
-impl std::fmt::Display for MidiEvent {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                MidiEvent::NoteOff(channel, key, velocity) => {
-                    format!("Note Off: {}; {}; {}", channel, key, velocity)
-                }
-                MidiEvent::NoteOn(channel, key, velocity) => {
-                    format!("Note On: {}; {}; {}", channel, key, velocity)
-                }
-                MidiEvent::PolyphonicAfterTouch(channel, key, pressure) => {
-                    format!("Polyphonic After-Touch: {}; {}; {}", channel, key, pressure)
-                }
-                MidiEvent::ControllerChange(channel, control, value) => {
-                    format!(
-                        "Controller Change: {}; 0x{:02X}; {}",
-                        channel, control, value
-                    )
-                }
-                MidiEvent::AllSoundOff => String::from("All Sound Off"),
-                MidiEvent::ResetAllControllers => String::from("Reset All Controllers"),
-                MidiEvent::AllNotesOff(channel) => {
-                    format!("All Notes Off: {}", channel)
-                }
-                MidiEvent::LocalControl(channel, on) => {
-                    format!("Local Control: {}; {}", channel, on)
-                }
-                MidiEvent::OmniMode(channel, on) => {
-                    format!("Omni Model: {}; {}", channel, on)
-                }
-                MidiEvent::MonoMode(channel, num_channels) => {
-                    format!("MonoMode: {}; {}", channel, num_channels)
-                }
-                MidiEvent::PolyphonicMode(channel) => {
-                    format!("PolyphonicMode: {}", channel)
-                }
-                MidiEvent::ProgramChange(channel, program_num) => {
-                    format!("Program Change: {}; {}", channel, program_num)
-                }
-                MidiEvent::ChannelAfterTouch(channel, pressure) => {
-                    format!("Channel After-Touch: {}; {}", channel, pressure)
-                }
-                MidiEvent::PitchBend(channel, amount) => {
-                    format!("Pitch-Bend: {}; {}", channel, amount)
-                }
-                MidiEvent::MTCQuarterFrame(_, _) => String::from("MIDI Time Code Quarter Frame"),
-                MidiEvent::SongPositionPointer(position) => {
-                    format!("Song Position Pointer: {}", position)
-                }
-                MidiEvent::SongSelect(song_num) => {
-                    format!("Song Select: {}", song_num)
-                }
-                MidiEvent::TuneRequest => String::from("Tune Request"),
-                MidiEvent::Clock => String::from("Timing Clock"),
-                MidiEvent::Start => String::from("Start"),
-                MidiEvent::Continue => String::from("Continue"),
-                MidiEvent::Stop => String::from("Stop"),
-                MidiEvent::ActiveSensing => String::from("Active Sensing"),
-                MidiEvent::SystemReset => String::from("System Reset"),
-                MidiEvent::SystemExclusiveStart(_) => String::from("System Exclusive Start"),
-                MidiEvent::SystemExclusiveData(_) => String::from("System Exclusive Data"),
-                MidiEvent::SystemExclusiveEnd => String::from("System Exclusive End"),
-            }
-        )
+///
+/// Message type of an event
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MessageKind {
+    NoteOff,
+    NoteOn,
+    PolyphonicAfterTouch,
+    ControllerChange,
+    ChannelMode, // All Sound Off through Poly Mode On
+    ProgramChange,
+    ChannelAfterTouch,
+    PitchBend,
+    SystemCommon,
+    SystemRealtime,
+    SystemExclusive,
+}
+
+impl MidiEvent {
+    ///
+    /// **Channel of a channel voice/mode event**
+    ///
+    /// Returns None for system events
+    ///
+    pub fn channel(&self) -> Option<Channel> {
+        match *self {
+            MidiEvent::NoteOff(channel, _, _)
+            | MidiEvent::AllNotesOff(channel)
+            | MidiEvent::NoteOn(channel, _, _)
+            | MidiEvent::PolyphonicAfterTouch(channel, _, _)
+            | MidiEvent::ControllerChange(channel, _, _)
+            | MidiEvent::AllSoundOff(channel)
+            | MidiEvent::ResetAllControllers(channel)
+            | MidiEvent::LocalControl(channel, _)
+            | MidiEvent::OmniMode(channel, _)
+            | MidiEvent::MonoMode(channel, _)
+            | MidiEvent::PolyphonicMode(channel)
+            | MidiEvent::ProgramChange(channel, _)
+            | MidiEvent::ChannelAfterTouch(channel, _)
+            | MidiEvent::PitchBend(channel, _) => Some(channel),
+            _ => None,
+        }
+    }
+
+    ///
+    /// **Message type of the event**
+    ///
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            MidiEvent::NoteOff(_, _, _) => MessageKind::NoteOff,
+            MidiEvent::NoteOn(_, _, _) => MessageKind::NoteOn,
+            MidiEvent::PolyphonicAfterTouch(_, _, _) => MessageKind::PolyphonicAfterTouch,
+            MidiEvent::ControllerChange(_, _, _) => MessageKind::ControllerChange,
+            MidiEvent::AllSoundOff(_)
+            | MidiEvent::ResetAllControllers(_)
+            | MidiEvent::LocalControl(_, _)
+            | MidiEvent::AllNotesOff(_)
+            | MidiEvent::OmniMode(_, _)
+            | MidiEvent::MonoMode(_, _)
+            | MidiEvent::PolyphonicMode(_) => MessageKind::ChannelMode,
+            MidiEvent::ProgramChange(_, _) => MessageKind::ProgramChange,
+            MidiEvent::ChannelAfterTouch(_, _) => MessageKind::ChannelAfterTouch,
+            MidiEvent::PitchBend(_, _) => MessageKind::PitchBend,
+            MidiEvent::MTCQuarterFrame(_, _)
+            | MidiEvent::SongPositionPointer(_)
+            | MidiEvent::SongSelect(_)
+            | MidiEvent::TuneRequest => MessageKind::SystemCommon,
+            MidiEvent::Clock
+            | MidiEvent::Start
+            | MidiEvent::Continue
+            | MidiEvent::Stop
+            | MidiEvent::ActiveSensing
+            | MidiEvent::SystemReset => MessageKind::SystemRealtime,
+            MidiEvent::SystemExclusiveStart(_)
+            | MidiEvent::SystemExclusiveData(_)
+            | MidiEvent::SystemExclusiveEnd => MessageKind::SystemExclusive,
+        }
+    }
+
+    ///
+    /// **Copy of a channel event moved to another channel**
+    ///
+    /// System events are returned unchanged.
+    ///
+    pub fn with_channel(self, channel: Channel) -> Self {
+        let channel = channel & 0x0F;
+        match self {
+            MidiEvent::NoteOff(_, key, velocity) => MidiEvent::NoteOff(channel, key, velocity),
+            MidiEvent::AllNotesOff(_) => MidiEvent::AllNotesOff(channel),
+            MidiEvent::NoteOn(_, key, velocity) => MidiEvent::NoteOn(channel, key, velocity),
+            MidiEvent::PolyphonicAfterTouch(_, key, pressure) => {
+                MidiEvent::PolyphonicAfterTouch(channel, key, pressure)
+            }
+            MidiEvent::ControllerChange(_, control, value) => {
+                MidiEvent::ControllerChange(channel, control, value)
+            }
+            MidiEvent::AllSoundOff(_) => MidiEvent::AllSoundOff(channel),
+            MidiEvent::ResetAllControllers(_) => MidiEvent::ResetAllControllers(channel),
+            MidiEvent::LocalControl(_, on) => MidiEvent::LocalControl(channel, on),
+            MidiEvent::OmniMode(_, on) => MidiEvent::OmniMode(channel, on),
+            MidiEvent::MonoMode(_, count) => MidiEvent::MonoMode(channel, count),
+            MidiEvent::PolyphonicMode(_) => MidiEvent::PolyphonicMode(channel),
+            MidiEvent::ProgramChange(_, program) => MidiEvent::ProgramChange(channel, program),
+            MidiEvent::ChannelAfterTouch(_, pressure) => {
+                MidiEvent::ChannelAfterTouch(channel, pressure)
+            }
+            MidiEvent::PitchBend(_, amount) => MidiEvent::PitchBend(channel, amount),
+            event => event,
+        }
     }
 }
 
-#[inline]
-fn to_u14(msb: u7, lsb: u7) -> u14 {
-    ((msb as u14) << 7) | (lsb as u14)
+///
+/// **MIDI 1.0 events**
+///
+/// Events produced from a single message, eg by translating a MIDI 2.0
+/// message or by a [`crate::Processor`]. Holds up to 8 events unless a
+/// component needs more.
+///
+pub type MidiEvents<const N: usize = 8> = List<MidiEvent, N>;
+
+impl<const N: usize> List<MidiEvent, N> {
+    pub fn new() -> Self {
+        Self::with_filler(MidiEvent::SystemExclusiveEnd)
+    }
+}
+
+impl<const N: usize> From<Option<MidiEvent>> for List<MidiEvent, N> {
+    fn from(event: Option<MidiEvent>) -> Self {
+        let mut events = Self::new();
+        if let Some(event) = event {
+            events.push(event);
+        }
+        events
+    }
+}
+
+impl fmt::Display for MidiEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiEvent::NoteOff(channel, key, velocity) => {
+                write!(f, "Note Off: {}; {}; {}", channel, key, velocity)
+            }
+            MidiEvent::NoteOn(channel, key, velocity) => {
+                write!(f, "Note On: {}; {}; {}", channel, key, velocity)
+            }
+            MidiEvent::PolyphonicAfterTouch(channel, key, pressure) => {
+                write!(
+                    f,
+                    "Polyphonic After-Touch: {}; {}; {}",
+                    channel, key, pressure
+                )
+            }
+            MidiEvent::ControllerChange(channel, control, value) => {
+                write!(
+                    f,
+                    "Controller Change: {}; 0x{:02X}; {}",
+                    channel, control, value
+                )
+            }
+            MidiEvent::AllSoundOff(channel) => write!(f, "All Sound Off: {}", channel),
+            MidiEvent::ResetAllControllers(channel) => {
+                write!(f, "Reset All Controllers: {}", channel)
+            }
+            MidiEvent::AllNotesOff(channel) => write!(f, "All Notes Off: {}", channel),
+            MidiEvent::LocalControl(channel, on) => {
+                write!(f, "Local Control: {}; {}", channel, on)
+            }
+            MidiEvent::OmniMode(channel, on) => write!(f, "Omni Model: {}; {}", channel, on),
+            MidiEvent::MonoMode(channel, num_channels) => {
+                write!(f, "MonoMode: {}; {}", channel, num_channels)
+            }
+            MidiEvent::PolyphonicMode(channel) => write!(f, "PolyphonicMode: {}", channel),
+            MidiEvent::ProgramChange(channel, program_num) => {
+                write!(f, "Program Change: {}; {}", channel, program_num)
+            }
+            MidiEvent::ChannelAfterTouch(channel, pressure) => {
+                write!(f, "Channel After-Touch: {}; {}", channel, pressure)
+            }
+            MidiEvent::PitchBend(channel, amount) => {
+                write!(f, "Pitch-Bend: {}; {}", channel, amount)
+            }
+            MidiEvent::MTCQuarterFrame(_, _) => write!(f, "MIDI Time Code Quarter Frame"),
+            MidiEvent::SongPositionPointer(position) => {
+                write!(f, "Song Position Pointer: {}", position)
+            }
+            MidiEvent::SongSelect(song_num) => write!(f, "Song Select: {}", song_num),
+            MidiEvent::TuneRequest => write!(f, "Tune Request"),
+            MidiEvent::Clock => write!(f, "Timing Clock"),
+            MidiEvent::Start => write!(f, "Start"),
+            MidiEvent::Continue => write!(f, "Continue"),
+            MidiEvent::Stop => write!(f, "Stop"),
+            MidiEvent::ActiveSensing => write!(f, "Active Sensing"),
+            MidiEvent::SystemReset => write!(f, "System Reset"),
+            MidiEvent::SystemExclusiveStart(_) => write!(f, "System Exclusive Start"),
+            MidiEvent::SystemExclusiveData(_) => write!(f, "System Exclusive Data"),
+            MidiEvent::SystemExclusiveEnd => write!(f, "System Exclusive End"),
+        }
+    }
 }
 
-#[inline]
-fn to_i14(msb: u7, lsb: u7) -> i14 {
-    let word = ((msb as u14) << 7) | (lsb as u14);
-    word as i16 - 0x1FFF
+fn to_u14(msb: u7, lsb: u7) -> u14 {
+    ((msb as u14) << 7) | (lsb as u14)
 }
 
 ///
 /// State-machine message phase
 ///
+#[derive(Debug)]
 enum Phase {
     Start,
     ByteTwo,
@@ -192,6 +321,7 @@ enum Phase {
 ///
 /// Handles an inbound MIDI byte stream and returns events
 ///
+#[derive(Debug)]
 pub struct MidiReader {
     phase: Phase,
     message: u8,   // Current message
@@ -199,6 +329,12 @@ pub struct MidiReader {
     data_byte: u7, // Data byte
 }
 
+impl Default for MidiReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MidiReader {
     pub fn new() -> Self {
         Self {
@@ -360,8 +496,8 @@ impl MidiReader {
                 self.phase = Phase::ByteTwo;
                 // Split out mode messages
                 match self.data_byte {
-                    CHANNEL_MODE_ALL_SOUND_OFF => Some(MidiEvent::AllSoundOff),
-                    CHANNEL_MODE_RESET_ALL => Some(MidiEvent::ResetAllControllers),
+                    CHANNEL_MODE_ALL_SOUND_OFF => Some(MidiEvent::AllSoundOff(self.channel)),
+                    CHANNEL_MODE_RESET_ALL => Some(MidiEvent::ResetAllControllers(self.channel)),
                     CHANNEL_MODE_LOCAL_CONTROL => {
                         Some(MidiEvent::LocalControl(self.channel, byte != 0))
                     }
@@ -381,7 +517,7 @@ impl MidiReader {
                 self.phase = Phase::ByteTwo;
                 Some(MidiEvent::PitchBend(
                     self.channel,
-                    to_i14(byte, self.data_byte),
+                    pitch_bend::decode(self.data_byte, byte),
                 ))
             }
             MESSAGE_SYS_EX_START => {
@@ -403,6 +539,7 @@ impl MidiReader {
 }
 
 #[cfg(test)]
+#[allow(non_snake_case)]
 mod tests {
     use crate::{MidiEvent, MidiReader};
     use parameterized::parameterized;
@@ -419,6 +556,14 @@ mod tests {
 
             // Channel messages
             &[0x82, 0x40, 127],
+            &[0xE0, 0x00, 0x40],
+            &[0xE1, 0x00, 0x00],
+            &[0xE2, 0x7F, 0x7F],
+
+            // Channel mode messages
+            &[0xB3, 0x78, 0x00],
+            &[0xB4, 0x79, 0x00],
+            &[0xB5, 0x7B, 0x00],
         },
         expected = {
             // Real time
@@ -431,6 +576,14 @@ mod tests {
 
             // Channel messages
             vec![MidiEvent::NoteOff(2, 0x40, 127)],
+            vec![MidiEvent::PitchBend(0, 0)],
+            vec![MidiEvent::PitchBend(1, -8192)],
+            vec![MidiEvent::PitchBend(2, 8191)],
+
+            // Channel mode messages
+            vec![MidiEvent::AllSoundOff(3)],
+            vec![MidiEvent::ResetAllControllers(4)],
+            vec![MidiEvent::AllNotesOff(5)],
         }
     )]
     fn handle_byte__single_event(bytes: &[u8], expected: Vec<MidiEvent>) {
@@ -0,0 +1,323 @@
+use crate::{u7, Channel, MidiEvent};
+
+///
+/// Receiver channel mode
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChannelMode {
+    OmniOnPoly,  // Mode 1
+    OmniOnMono,  // Mode 2
+    OmniOffPoly, // Mode 3
+    OmniOffMono, // Mode 4
+}
+
+impl ChannelMode {
+    fn new(omni: bool, mono: bool) -> Self {
+        match (omni, mono) {
+            (true, false) => ChannelMode::OmniOnPoly,
+            (true, true) => ChannelMode::OmniOnMono,
+            (false, false) => ChannelMode::OmniOffPoly,
+            (false, true) => ChannelMode::OmniOffMono,
+        }
+    }
+
+    ///
+    /// Mode number as defined by the MIDI spec (1-4)
+    ///
+    pub fn number(&self) -> u8 {
+        match self {
+            ChannelMode::OmniOnPoly => 1,
+            ChannelMode::OmniOnMono => 2,
+            ChannelMode::OmniOffPoly => 3,
+            ChannelMode::OmniOffMono => 4,
+        }
+    }
+
+    pub fn is_omni(&self) -> bool {
+        matches!(self, ChannelMode::OmniOnPoly | ChannelMode::OmniOnMono)
+    }
+
+    pub fn is_mono(&self) -> bool {
+        matches!(self, ChannelMode::OmniOnMono | ChannelMode::OmniOffMono)
+    }
+}
+
+///
+/// How an accepted message maps to voices
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VoiceAssignment {
+    Poly,        // Assigned polyphonically (eg by a `VoiceAllocator`)
+    Mono(usize), // Controls a single monophonic voice
+}
+
+///
+/// Channels whose notes are released by a mode change
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NotesOff {
+    channels: u16, // Bit per channel
+}
+
+impl NotesOff {
+    pub fn contains(&self, channel: Channel) -> bool {
+        self.channels & (1 << (channel & 0x0F)) != 0
+    }
+
+    ///
+    /// All Notes Off for each channel
+    ///
+    pub fn events(&self) -> impl Iterator<Item = MidiEvent> {
+        let notes_off = *self;
+        (0..16)
+            .filter(move |channel| notes_off.contains(*channel))
+            .map(MidiEvent::AllNotesOff)
+    }
+}
+
+///
+/// Result of a message received by the receiver
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Received {
+    /// Message accepted for the given voice assignment
+    Accepted(MidiEvent, VoiceAssignment),
+    /// Mode changed; this implies All Notes Off for every voice, given for
+    /// each channel received in the previous mode
+    ModeChanged(ChannelMode, NotesOff),
+}
+
+///
+/// **Receiver**
+///
+/// Applies the channel mode rules of the MIDI spec for a receiver with a
+/// basic channel:
+///
+/// * Mode 1 (Omni On/Poly); voice messages on all channels, polyphonic
+/// * Mode 2 (Omni On/Mono); voice messages on all channels, one mono voice
+/// * Mode 3 (Omni Off/Poly); voice messages on the basic channel, polyphonic
+/// * Mode 4 (Omni Off/Mono); voice messages on the basic channel through
+///   basic + M - 1 each control mono voice 0 through M - 1
+///
+/// Omni, Mono and Poly mode messages are only recognised on the basic channel
+/// and each implies All Notes Off, which is returned with the new mode so
+/// held notes can be released. Receivers power up in mode 1.
+///
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    basic_channel: Channel,
+    voices: usize,
+    omni: bool,
+    mono: bool,
+    mono_channels: u7, // M from the Mono Mode message (0 = all voices)
+}
+
+impl Receiver {
+    ///
+    /// Receiver on `basic_channel` with `voices` voices
+    ///
+    pub fn new(basic_channel: Channel, voices: usize) -> Self {
+        Self {
+            basic_channel: basic_channel & 0x0F,
+            voices: voices.max(1),
+            omni: true,
+            mono: false,
+            mono_channels: 0,
+        }
+    }
+
+    ///
+    /// Set the initial mode
+    ///
+    pub fn with_mode(mut self, mode: ChannelMode) -> Self {
+        self.omni = mode.is_omni();
+        self.mono = mode.is_mono();
+        self
+    }
+
+    pub fn mode(&self) -> ChannelMode {
+        ChannelMode::new(self.omni, self.mono)
+    }
+
+    pub fn basic_channel(&self) -> Channel {
+        self.basic_channel
+    }
+
+    ///
+    /// Number of mono voices (and channels) used in mode 4
+    ///
+    pub fn mono_voice_count(&self) -> usize {
+        let available = (16 - self.basic_channel as usize).min(self.voices);
+        match self.mono_channels as usize {
+            0 => available,
+            count => count.min(available),
+        }
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// Returns None when the message is not for this receiver.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> Option<Received> {
+        let channel = event.channel()?;
+
+        let (omni, mono, mono_channels) = match *event {
+            MidiEvent::OmniMode(_, on) => (on, self.mono, self.mono_channels),
+            MidiEvent::MonoMode(_, count) => (self.omni, true, count),
+            MidiEvent::PolyphonicMode(_) => (self.omni, false, self.mono_channels),
+            _ => {
+                return self
+                    .assignment(channel)
+                    .map(|assignment| Received::Accepted(*event, assignment))
+            }
+        };
+
+        if channel != self.basic_channel {
+            return None;
+        }
+        let notes_off = NotesOff {
+            channels: (0..16)
+                .filter(|channel| self.assignment(*channel).is_some())
+                .fold(0, |channels, channel| channels | 1 << channel),
+        };
+        self.omni = omni;
+        self.mono = mono;
+        self.mono_channels = mono_channels;
+        Some(Received::ModeChanged(self.mode(), notes_off))
+    }
+
+    ///
+    /// Voice assignment for messages on `channel`; None if not received
+    ///
+    pub fn assignment(&self, channel: Channel) -> Option<VoiceAssignment> {
+        match self.mode() {
+            ChannelMode::OmniOnPoly => Some(VoiceAssignment::Poly),
+            ChannelMode::OmniOnMono => Some(VoiceAssignment::Mono(0)),
+            ChannelMode::OmniOffPoly if channel == self.basic_channel => {
+                Some(VoiceAssignment::Poly)
+            }
+            ChannelMode::OmniOffPoly => None,
+            ChannelMode::OmniOffMono => {
+                let voice = channel.checked_sub(self.basic_channel)? as usize;
+                if voice < self.mono_voice_count() {
+                    Some(VoiceAssignment::Mono(voice))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::receiver::{ChannelMode, Received, Receiver, VoiceAssignment};
+    use crate::MidiEvent;
+    use crate::VoiceAllocator;
+    use parameterized::parameterized;
+
+    #[test]
+    fn handle_event__powers_up_in_mode_1() {
+        let mut target = Receiver::new(0, 8);
+
+        assert_eq!(target.mode().number(), 1);
+        assert_eq!(
+            target.handle_event(&MidiEvent::NoteOn(9, 60, 100)),
+            Some(Received::Accepted(
+                MidiEvent::NoteOn(9, 60, 100),
+                VoiceAssignment::Poly
+            ))
+        );
+        assert_eq!(target.handle_event(&MidiEvent::Clock), None);
+    }
+
+    fn mode_change(received: Option<Received>) -> Option<(ChannelMode, Vec<MidiEvent>)> {
+        match received? {
+            Received::ModeChanged(mode, notes_off) => Some((mode, notes_off.events().collect())),
+            Received::Accepted(_, _) => None,
+        }
+    }
+
+    #[test]
+    fn handle_event__mode_messages_only_on_basic_channel() {
+        let mut target = Receiver::new(2, 8);
+
+        assert_eq!(target.handle_event(&MidiEvent::OmniMode(3, false)), None);
+        let (mode, notes_off) =
+            mode_change(target.handle_event(&MidiEvent::OmniMode(2, false))).unwrap();
+        assert_eq!(mode, ChannelMode::OmniOffPoly);
+        assert_eq!(notes_off.len(), 16);
+        assert_eq!(
+            mode_change(target.handle_event(&MidiEvent::MonoMode(2, 4))),
+            Some((ChannelMode::OmniOffMono, vec![MidiEvent::AllNotesOff(2)]))
+        );
+        assert_eq!(
+            mode_change(target.handle_event(&MidiEvent::PolyphonicMode(2))),
+            Some((
+                ChannelMode::OmniOffPoly,
+                (2..6).map(MidiEvent::AllNotesOff).collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn handle_event__mode_change_releases_held_notes() {
+        let mut target = Receiver::new(0, 4);
+        let mut voices = VoiceAllocator::new(4);
+        for event in &[MidiEvent::NoteOn(0, 60, 100), MidiEvent::NoteOn(9, 64, 100)] {
+            if let Some(Received::Accepted(event, _)) = target.handle_event(event) {
+                voices.handle_event(&event);
+            }
+        }
+
+        if let Some(Received::ModeChanged(_, notes_off)) =
+            target.handle_event(&MidiEvent::OmniMode(0, false))
+        {
+            for event in notes_off.events() {
+                voices.handle_event(&event);
+            }
+        }
+
+        assert!(voices.voices().iter().all(|voice| !voice.is_held()));
+    }
+
+    #[parameterized(
+        mode = {
+            ChannelMode::OmniOnMono, ChannelMode::OmniOffPoly, ChannelMode::OmniOffPoly,
+            ChannelMode::OmniOffMono, ChannelMode::OmniOffMono, ChannelMode::OmniOffMono,
+        },
+        channel = {7, 2, 3, 2, 5, 6},
+        expected = {
+            Some(VoiceAssignment::Mono(0)),
+            Some(VoiceAssignment::Poly),
+            None,
+            Some(VoiceAssignment::Mono(0)),
+            Some(VoiceAssignment::Mono(3)),
+            None,
+        }
+    )]
+    fn assignment__by_mode(mode: ChannelMode, channel: u8, expected: Option<VoiceAssignment>) {
+        let mut target = Receiver::new(2, 8).with_mode(ChannelMode::OmniOffPoly);
+        if mode.is_mono() {
+            target.handle_event(&MidiEvent::MonoMode(2, 4));
+        }
+        if mode.is_omni() {
+            target.handle_event(&MidiEvent::OmniMode(2, true));
+        }
+
+        assert_eq!(target.mode(), mode);
+        assert_eq!(target.assignment(channel), expected);
+    }
+
+    #[test]
+    fn mono_voice_count__where_m_is_zero() {
+        let mut target = Receiver::new(12, 8);
+
+        target.handle_event(&MidiEvent::MonoMode(12, 0));
+
+        // Limited by the channels remaining above the basic channel
+        assert_eq!(target.mono_voice_count(), 4);
+    }
+}
@@ -0,0 +1,1695 @@
+use crate::controller::{BANK_SELECT, BANK_SELECT_LSB};
+use crate::{u7, Channel, ChannelState, MidiEvent, MidiReader, MidiState, MidiWriter, Timestamp};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+// AppleMIDI exchange packets
+const SIGNATURE: [u8; 2] = [0xFF, 0xFF];
+const PROTOCOL_VERSION: u32 = 2;
+const COMMAND_INVITATION: [u8; 2] = *b"IN";
+const COMMAND_ACCEPTED: [u8; 2] = *b"OK";
+const COMMAND_REJECTED: [u8; 2] = *b"NO";
+const COMMAND_END: [u8; 2] = *b"BY";
+const COMMAND_SYNC: [u8; 2] = *b"CK";
+const COMMAND_FEEDBACK: [u8; 2] = *b"RS";
+
+// RTP header
+const RTP_VERSION: u8 = 0x80;
+const RTP_VERSION_MASK: u8 = 0xC0;
+const PAYLOAD_TYPE: u8 = 0x61;
+const RTP_HEADER_LEN: usize = 12;
+
+// MIDI command section header
+const FLAG_LONG: u8 = 0x80; // 12 bit length
+const FLAG_JOURNAL: u8 = 0x40;
+const FLAG_FIRST_DELTA: u8 = 0x20; // Delta time before the first command
+const MAX_SHORT_LEN: usize = 0x0F;
+
+// Recovery journal
+const JOURNAL_SYSTEM: u8 = 0x40; // Y flag
+const JOURNAL_CHANNELS: u8 = 0x20; // A flag
+const CHAPTER_PROGRAM: u8 = 0x80; // P
+const CHAPTER_CONTROLLERS: u8 = 0x40; // C
+const CHAPTER_NOTES: u8 = 0x08; // N
+const NO_OFF_BITS: u8 = 0xF0; // Low 15, High 0
+
+// Session timestamps are in units of 100 microseconds
+const CLOCK_UNIT: Timestamp = 100;
+
+///
+/// UDP port of an AppleMIDI session; the data port is the control port + 1
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Port {
+    Control,
+    Data,
+}
+
+///
+/// State of an RTP-MIDI session
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SessionState {
+    Idle,
+    Inviting(Port), // Invitation sent on the port
+    Connected,
+    Rejected,
+    Ended,
+}
+
+///
+/// AppleMIDI exchange packet
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Exchange {
+    Invitation {
+        token: u32,
+        ssrc: u32,
+        name: String,
+    },
+    Accepted {
+        token: u32,
+        ssrc: u32,
+        name: String,
+    },
+    Rejected {
+        token: u32,
+        ssrc: u32,
+    },
+    End {
+        token: u32,
+        ssrc: u32,
+    },
+    Sync {
+        ssrc: u32,
+        count: u8,
+        timestamps: [u64; 3],
+    },
+    Feedback {
+        ssrc: u32,
+        sequence: u16,
+    },
+}
+
+///
+/// Sequence number `a` comes after `b`, allowing for wrapping
+///
+fn is_after(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) > 0
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl Exchange {
+    fn ssrc(&self) -> u32 {
+        match *self {
+            Exchange::Invitation { ssrc, .. }
+            | Exchange::Accepted { ssrc, .. }
+            | Exchange::Rejected { ssrc, .. }
+            | Exchange::End { ssrc, .. }
+            | Exchange::Sync { ssrc, .. }
+            | Exchange::Feedback { ssrc, .. } => ssrc,
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 || bytes[..2] != SIGNATURE {
+            return None;
+        }
+        let command = [bytes[2], bytes[3]];
+        match command {
+            COMMAND_SYNC => {
+                if bytes.len() < 36 {
+                    return None;
+                }
+                let timestamp = |offset: usize| {
+                    let mut value = [0; 8];
+                    value.copy_from_slice(&bytes[offset..offset + 8]);
+                    u64::from_be_bytes(value)
+                };
+                Some(Exchange::Sync {
+                    ssrc: be_u32(&bytes[4..]),
+                    count: bytes[8],
+                    timestamps: [timestamp(12), timestamp(20), timestamp(28)],
+                })
+            }
+            COMMAND_FEEDBACK => Some(Exchange::Feedback {
+                ssrc: be_u32(&bytes[4..]),
+                sequence: u16::from_be_bytes([*bytes.get(8)?, *bytes.get(9)?]),
+            }),
+            _ => {
+                if bytes.len() < 16 {
+                    return None;
+                }
+                let token = be_u32(&bytes[8..]);
+                let ssrc = be_u32(&bytes[12..]);
+                let name = bytes[16..].split(|byte| *byte == 0).next().unwrap_or(&[]);
+                let name = String::from_utf8_lossy(name).into_owned();
+                match command {
+                    COMMAND_INVITATION => Some(Exchange::Invitation { token, ssrc, name }),
+                    COMMAND_ACCEPTED => Some(Exchange::Accepted { token, ssrc, name }),
+                    COMMAND_REJECTED => Some(Exchange::Rejected { token, ssrc }),
+                    COMMAND_END => Some(Exchange::End { token, ssrc }),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        let mut session = |command: [u8; 2], token: u32, ssrc: u32, name: Option<&str>| {
+            bytes.extend_from_slice(&command);
+            bytes.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+            bytes.extend_from_slice(&token.to_be_bytes());
+            bytes.extend_from_slice(&ssrc.to_be_bytes());
+            if let Some(name) = name {
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(0);
+            }
+        };
+        match self {
+            Exchange::Invitation { token, ssrc, name } => {
+                session(COMMAND_INVITATION, *token, *ssrc, Some(name))
+            }
+            Exchange::Accepted { token, ssrc, name } => {
+                session(COMMAND_ACCEPTED, *token, *ssrc, Some(name))
+            }
+            Exchange::Rejected { token, ssrc } => session(COMMAND_REJECTED, *token, *ssrc, None),
+            Exchange::End { token, ssrc } => session(COMMAND_END, *token, *ssrc, None),
+            Exchange::Sync {
+                ssrc,
+                count,
+                timestamps,
+            } => {
+                bytes.extend_from_slice(&COMMAND_SYNC);
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+                bytes.extend_from_slice(&[*count, 0, 0, 0]);
+                for timestamp in timestamps {
+                    bytes.extend_from_slice(&timestamp.to_be_bytes());
+                }
+            }
+            Exchange::Feedback { ssrc, sequence } => {
+                bytes.extend_from_slice(&COMMAND_FEEDBACK);
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+                bytes.extend_from_slice(&sequence.to_be_bytes());
+                bytes.extend_from_slice(&[0, 0]);
+            }
+        }
+        bytes
+    }
+}
+
+fn write_delta(delta: u32, bytes: &mut Vec<u8>) {
+    let delta = delta & 0x0FFF_FFFF;
+    for shift in [21, 14, 7].iter() {
+        if delta >> shift > 0 {
+            bytes.push(0x80 | (delta >> shift) as u8 & 0x7F);
+        }
+    }
+    bytes.push(delta as u8 & 0x7F);
+}
+
+fn read_delta(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut delta = 0;
+    for (index, byte) in bytes.iter().take(4).enumerate() {
+        delta = (delta << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Some((delta, index + 1));
+        }
+    }
+    None
+}
+
+///
+/// **Encode a MIDI list**
+///
+/// Running status is used between channel messages. System Exclusive is
+/// split into segments (RFC 6295): a segment still open at the end of the
+/// list or interrupted by another command ends with F0 and is continued by a
+/// segment starting with F7. `sys_ex` tracks a System Exclusive message left
+/// open by a previous list. System Realtime commands inside a segment are not
+/// preceded by delta times.
+///
+fn encode_list(commands: &[(u32, MidiEvent)], sys_ex: &mut bool) -> Vec<u8> {
+    let mut writer = MidiWriter::new().with_running_status();
+    let mut bytes = Vec::new();
+    let mut segment = false; // A System Exclusive segment is open
+    for (delta, event) in commands {
+        let start_command = |bytes: &mut Vec<u8>| {
+            if !bytes.is_empty() {
+                write_delta(*delta, bytes);
+            }
+        };
+        match event {
+            MidiEvent::SystemExclusiveData(_) | MidiEvent::SystemExclusiveEnd if !*sys_ex => {
+                // Not part of a System Exclusive message
+                continue;
+            }
+            MidiEvent::SystemExclusiveData(_) | MidiEvent::SystemExclusiveEnd if !segment => {
+                start_command(&mut bytes);
+                bytes.push(0xF7);
+                segment = true;
+            }
+            MidiEvent::SystemExclusiveData(_) | MidiEvent::SystemExclusiveEnd => {}
+            _ if segment && event.to_bytes()[0] >= 0xF8 => {}
+            _ => {
+                if segment {
+                    bytes.push(0xF0);
+                    segment = false;
+                }
+                start_command(&mut bytes);
+            }
+        }
+        match event {
+            MidiEvent::SystemExclusiveStart(_) => {
+                *sys_ex = true;
+                segment = true;
+            }
+            MidiEvent::SystemExclusiveEnd => {
+                *sys_ex = false;
+                segment = false;
+            }
+            _ => {}
+        }
+        bytes.extend_from_slice(&writer.write(event));
+    }
+    if segment {
+        bytes.push(0xF0);
+    }
+    bytes
+}
+
+///
+/// Length of the command at the start of a MIDI list
+///
+/// A System Exclusive segment runs to its F0, F4 or F7 terminator, or to the
+/// end of the list if unterminated.
+///
+fn command_len(list: &[u8], status: &mut Option<u8>) -> Option<usize> {
+    let channel_len = |status: u8| match status & 0xF0 {
+        0xC0 | 0xD0 => 2,
+        _ => 3,
+    };
+    let first = *list.first()?;
+    let len = match first {
+        0xF0 | 0xF7 => list[1..]
+            .iter()
+            .position(|byte| matches!(byte, 0xF0 | 0xF4 | 0xF7))
+            .map_or(list.len(), |end| end + 2),
+        0xF1 | 0xF3 => 2,
+        0xF2 => 3,
+        0xF4..=0xFF => 1,
+        0x80..=0xEF => {
+            *status = Some(first);
+            channel_len(first)
+        }
+        _ => channel_len((*status)?) - 1,
+    };
+    if let 0xF0..=0xF7 = first {
+        *status = None;
+    }
+    Some(len.min(list.len()))
+}
+
+///
+/// Channel journals of a recovery journal; None if truncated
+///
+fn decode_journal(journal: &[u8]) -> Option<Vec<ChannelJournal>> {
+    let header = journal.get(..3)?;
+    let (flags, channels) = (header[0], (header[0] & 0x0F) as usize + 1);
+    if flags & JOURNAL_CHANNELS == 0 {
+        return Some(Vec::new());
+    }
+    let mut data = &journal[3..];
+    if flags & JOURNAL_SYSTEM > 0 {
+        // The system journal is skipped
+        let header = data.get(..2)?;
+        let len = (((header[0] & 0x03) as usize) << 8) | header[1] as usize;
+        data = data.get(len..)?;
+    }
+    let mut journals = Vec::new();
+    for _ in 0..channels {
+        let (channel, len) = ChannelJournal::decode(data)?;
+        journals.push(channel);
+        data = &data[len..];
+    }
+    Some(journals)
+}
+
+///
+/// Channel journal of the recovery journal
+///
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct ChannelJournal {
+    channel: Channel,
+    program: Option<(u7, Option<(u7, u7)>)>, // Program, Bank MSB/LSB
+    controllers: Vec<(u7, u7)>,
+    notes: Vec<(u7, u7)>, // Held notes and velocity
+    off: Vec<u7>,         // Notes released since the checkpoint
+}
+
+impl ChannelJournal {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        bytes.extend_from_slice(&[(self.channel & 0x0F) << 3, 0, 0]);
+        let mut chapters = 0;
+
+        if let Some((program, bank)) = self.program {
+            chapters |= CHAPTER_PROGRAM;
+            let (msb, lsb) = bank.unwrap_or((0, 0));
+            let bank_flag = if bank.is_some() { 0x80 } else { 0 };
+            bytes.extend_from_slice(&[program & 0x7F, bank_flag | (msb & 0x7F), lsb & 0x7F]);
+        }
+        if !self.controllers.is_empty() {
+            chapters |= CHAPTER_CONTROLLERS;
+            bytes.push((self.controllers.len() - 1) as u8 & 0x7F);
+            for (control, value) in &self.controllers {
+                bytes.extend_from_slice(&[control & 0x7F, value & 0x7F]);
+            }
+        }
+        if !self.notes.is_empty() || !self.off.is_empty() {
+            chapters |= CHAPTER_NOTES;
+            let notes = &self.notes[..self.notes.len().min(127)];
+            let low = self.off.iter().min().map(|note| note / 8);
+            let high = self.off.iter().max().map(|note| note / 8);
+            bytes.push(notes.len() as u8);
+            bytes.push(match (low, high) {
+                (Some(low), Some(high)) => (low << 4) | high,
+                _ => NO_OFF_BITS,
+            });
+            for (note, velocity) in notes {
+                bytes.extend_from_slice(&[note & 0x7F, velocity & 0x7F]);
+            }
+            if let (Some(low), Some(high)) = (low, high) {
+                for octet in low..=high {
+                    bytes.push(
+                        self.off
+                            .iter()
+                            .filter(|note| *note / 8 == octet)
+                            .fold(0, |bits, note| bits | (0x80 >> (note % 8))),
+                    );
+                }
+            }
+        }
+
+        let len = bytes.len() - start;
+        bytes[start] |= (len >> 8) as u8 & 0x03;
+        bytes[start + 1] = len as u8;
+        bytes[start + 2] = chapters;
+    }
+
+    fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        let header = bytes.get(..3)?;
+        let len = (((header[0] & 0x03) as usize) << 8) | header[1] as usize;
+        let chapters = header[2];
+        let mut data = bytes.get(3..len)?;
+        let mut journal = ChannelJournal {
+            channel: (header[0] >> 3) & 0x0F,
+            ..Default::default()
+        };
+
+        if chapters & CHAPTER_PROGRAM > 0 {
+            let chapter = data.get(..3)?;
+            let bank = if chapter[1] & 0x80 > 0 {
+                Some((chapter[1] & 0x7F, chapter[2] & 0x7F))
+            } else {
+                None
+            };
+            journal.program = Some((chapter[0] & 0x7F, bank));
+            data = &data[3..];
+        }
+        if chapters & CHAPTER_CONTROLLERS > 0 {
+            let count = (*data.first()? & 0x7F) as usize + 1;
+            let end = count.checked_mul(2)?.checked_add(1)?;
+            let entries = data.get(1..end)?;
+            journal.controllers = entries
+                .chunks(2)
+                .map(|entry| (entry[0] & 0x7F, entry[1] & 0x7F))
+                .collect();
+            data = &data[end..];
+        }
+        if chapters & CHAPTER_NOTES > 0 {
+            let count = (*data.first()? & 0x7F) as usize;
+            let range = *data.get(1)?;
+            let logs_end = count.checked_mul(2)?.checked_add(2)?;
+            let logs = data.get(2..logs_end)?;
+            journal.notes = logs
+                .chunks(2)
+                .map(|log| (log[0] & 0x7F, log[1] & 0x7F))
+                .collect();
+            let (low, high) = (range >> 4, range & 0x0F);
+            // Low above high (including Low 15, High 0) has no off bits
+            if low <= high {
+                let octets_end = logs_end.checked_add((high - low) as usize + 1)?;
+                let octets = data.get(logs_end..octets_end)?;
+                for (octet, bits) in (low..=high).zip(octets) {
+                    journal.off.extend(
+                        (0..8)
+                            .filter(|bit| bits & (0x80 >> bit) > 0)
+                            .map(|bit| octet * 8 + bit),
+                    );
+                }
+            }
+        }
+        Some((journal, len))
+    }
+
+    ///
+    /// Events bringing the receiver state in line with the journal
+    ///
+    /// Each event is applied to a copy of the state before the next is
+    /// compared, so a Bank Select restored with the program is not repeated
+    /// by the controllers.
+    ///
+    fn recover(&self, state: &ChannelState, events: &mut Vec<MidiEvent>) {
+        let channel = self.channel;
+        let mut state = state.clone();
+        let mut push = |state: &mut ChannelState, event: MidiEvent| {
+            state.handle_event(&event);
+            events.push(event);
+        };
+        if let Some((program, bank)) = self.program {
+            if state.program() != program {
+                if let Some((msb, lsb)) = bank {
+                    for (control, value) in [(BANK_SELECT, msb), (BANK_SELECT_LSB, lsb)] {
+                        if state.controller(control) != value {
+                            let event = MidiEvent::ControllerChange(channel, control, value);
+                            push(&mut state, event);
+                        }
+                    }
+                }
+                push(&mut state, MidiEvent::ProgramChange(channel, program));
+            }
+        }
+        for (control, value) in &self.controllers {
+            if state.controller(*control) != *value {
+                let event = MidiEvent::ControllerChange(channel, *control, *value);
+                push(&mut state, event);
+            }
+        }
+        for note in &self.off {
+            if state.is_note_held(*note) {
+                push(&mut state, MidiEvent::NoteOff(channel, *note, 0));
+            }
+        }
+        for (note, velocity) in &self.notes {
+            if !state.is_note_held(*note) {
+                push(&mut state, MidiEvent::NoteOn(channel, *note, *velocity));
+            }
+        }
+    }
+}
+
+///
+/// **RTP-MIDI Session**
+///
+/// AppleMIDI session protocol and RTP-MIDI payloads independent of the
+/// network. Received packets are passed to `handle_packet` along with the
+/// port they arrived on and packets to send are collected with `transmit`.
+///
+/// The initiator invites the remote on the control and then the data port
+/// and starts a clock synchronisation (CK) once accepted. Events are sent
+/// with running status and a recovery journal holding the program,
+/// controllers and held notes of each channel used, along with notes
+/// released since the last sequence number acknowledged by the receiver. On
+/// packet loss the receiver uses the journal to restore its state.
+///
+pub struct RtpMidiSession {
+    name: String,
+    ssrc: u32,
+    state: SessionState,
+    token: u32,
+    peer: Option<(u32, String)>, // SSRC and name of the remote
+    outgoing: Vec<(Port, Vec<u8>)>,
+    sequence: u16, // Next sequence number to send
+    sent: MidiState,
+    sys_ex: bool,                      // System Exclusive sent is unfinished
+    programs: u16,                     // Channels with a Program Change sent
+    banks: u16,                        // Channels with a Bank Select sent
+    channels: u16,                     // Channels used
+    released: Vec<(Channel, u7, u16)>, // Released notes and the sequence sending them
+    acknowledged: u16,                 // Last sequence number acknowledged by the peer
+    expected: Option<u16>,             // Next sequence number to receive
+    received: MidiState,
+    reader: MidiReader,
+    status: Option<u8>,    // Running status of received MIDI lists
+    sys_ex_received: bool, // System Exclusive received is unfinished
+    latency: Option<Timestamp>,
+    offset: Option<i64>,
+}
+
+impl RtpMidiSession {
+    pub fn new(name: &str, ssrc: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            ssrc,
+            state: SessionState::Idle,
+            token: 0,
+            peer: None,
+            outgoing: Vec::new(),
+            sequence: 0,
+            sent: MidiState::new(),
+            sys_ex: false,
+            programs: 0,
+            banks: 0,
+            channels: 0,
+            released: Vec::new(),
+            acknowledged: u16::MAX,
+            expected: None,
+            received: MidiState::new(),
+            reader: MidiReader::new(),
+            status: None,
+            sys_ex_received: false,
+            latency: None,
+            offset: None,
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == SessionState::Connected
+    }
+
+    ///
+    /// Name of the remote session
+    ///
+    pub fn peer_name(&self) -> Option<&str> {
+        self.peer.as_ref().map(|(_, name)| name.as_str())
+    }
+
+    ///
+    /// One way latency measured by clock synchronisation
+    ///
+    pub fn latency(&self) -> Option<Timestamp> {
+        self.latency
+    }
+
+    ///
+    /// Remote clock less the local clock in microseconds
+    ///
+    pub fn offset(&self) -> Option<i64> {
+        self.offset
+    }
+
+    ///
+    /// Packets to send
+    ///
+    pub fn transmit(&mut self) -> Vec<(Port, Vec<u8>)> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    ///
+    /// **Invite the remote session**
+    ///
+    pub fn invite(&mut self, now: Timestamp) {
+        self.token = (now as u32) ^ self.ssrc;
+        self.state = SessionState::Inviting(Port::Control);
+        self.queue(
+            Port::Control,
+            Exchange::Invitation {
+                token: self.token,
+                ssrc: self.ssrc,
+                name: self.name.clone(),
+            },
+        );
+    }
+
+    ///
+    /// **Start a clock synchronisation**
+    ///
+    pub fn synchronise(&mut self, now: Timestamp) {
+        self.queue(
+            Port::Data,
+            Exchange::Sync {
+                ssrc: self.ssrc,
+                count: 0,
+                timestamps: [now / CLOCK_UNIT, 0, 0],
+            },
+        );
+    }
+
+    ///
+    /// **End the session**
+    ///
+    pub fn end(&mut self) {
+        if self.peer.is_some() {
+            self.queue(
+                Port::Control,
+                Exchange::End {
+                    token: self.token,
+                    ssrc: self.ssrc,
+                },
+            );
+        }
+        self.state = SessionState::Ended;
+        self.peer = None;
+    }
+
+    ///
+    /// **Send events**
+    ///
+    /// Ignored unless connected.
+    ///
+    pub fn send(&mut self, events: &[MidiEvent], now: Timestamp) {
+        if !self.is_connected() || events.is_empty() {
+            return;
+        }
+        let commands: Vec<(u32, MidiEvent)> = events.iter().map(|event| (0, *event)).collect();
+        let list = encode_list(&commands, &mut self.sys_ex);
+        let journal = self.journal();
+
+        let mut bytes = vec![RTP_VERSION, PAYLOAD_TYPE];
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        bytes.extend_from_slice(&((now / CLOCK_UNIT) as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.ssrc.to_be_bytes());
+        let journal_flag = if journal.is_empty() { 0 } else { FLAG_JOURNAL };
+        if list.len() > MAX_SHORT_LEN {
+            bytes.push(FLAG_LONG | journal_flag | (list.len() >> 8) as u8 & 0x0F);
+            bytes.push(list.len() as u8);
+        } else {
+            bytes.push(journal_flag | list.len() as u8);
+        }
+        bytes.extend_from_slice(&list);
+        bytes.extend_from_slice(&journal);
+        self.outgoing.push((Port::Data, bytes));
+
+        for event in events {
+            self.track_sent(event, self.sequence);
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+    }
+
+    ///
+    /// **Handle a received packet**
+    ///
+    /// Returns received events preceded by any recovered from the journal.
+    ///
+    pub fn handle_packet(&mut self, port: Port, packet: &[u8], now: Timestamp) -> Vec<MidiEvent> {
+        if let Some(exchange) = Exchange::decode(packet) {
+            self.handle_exchange(port, exchange, now);
+            Vec::new()
+        } else if port == Port::Data && self.is_connected() {
+            self.handle_data(packet).unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn queue(&mut self, port: Port, exchange: Exchange) {
+        self.outgoing.push((port, exchange.encode()));
+    }
+
+    fn handle_exchange(&mut self, port: Port, exchange: Exchange, now: Timestamp) {
+        match exchange {
+            Exchange::Invitation { token, ssrc, name } => {
+                let busy = self.peer.as_ref().is_some_and(|(peer, _)| *peer != ssrc)
+                    || matches!(self.state, SessionState::Inviting(_));
+                if busy {
+                    self.queue(
+                        port,
+                        Exchange::Rejected {
+                            token,
+                            ssrc: self.ssrc,
+                        },
+                    );
+                    return;
+                }
+                self.token = token;
+                self.peer = Some((ssrc, name));
+                if port == Port::Data {
+                    self.connected();
+                }
+                self.queue(
+                    port,
+                    Exchange::Accepted {
+                        token,
+                        ssrc: self.ssrc,
+                        name: self.name.clone(),
+                    },
+                );
+            }
+            Exchange::Accepted { token, ssrc, name } if token == self.token => match self.state {
+                SessionState::Inviting(Port::Control) if port == Port::Control => {
+                    self.peer = Some((ssrc, name));
+                    self.state = SessionState::Inviting(Port::Data);
+                    self.queue(
+                        Port::Data,
+                        Exchange::Invitation {
+                            token,
+                            ssrc: self.ssrc,
+                            name: self.name.clone(),
+                        },
+                    );
+                }
+                SessionState::Inviting(Port::Data) if port == Port::Data => {
+                    self.connected();
+                    self.synchronise(now);
+                }
+                _ => {}
+            },
+            Exchange::Rejected { token, .. } if token == self.token => {
+                self.state = SessionState::Rejected;
+                self.peer = None;
+            }
+            Exchange::End { ssrc, .. } if self.is_peer(ssrc) => {
+                self.state = SessionState::Ended;
+                self.peer = None;
+            }
+            Exchange::Sync {
+                ssrc,
+                count,
+                timestamps,
+            } if self.is_peer(ssrc) => {
+                let now = now / CLOCK_UNIT;
+                let [sent, replied, _] = timestamps;
+                match count {
+                    0 => self.queue(
+                        Port::Data,
+                        Exchange::Sync {
+                            ssrc: self.ssrc,
+                            count: 1,
+                            timestamps: [sent, now, 0],
+                        },
+                    ),
+                    1 => {
+                        self.queue(
+                            Port::Data,
+                            Exchange::Sync {
+                                ssrc: self.ssrc,
+                                count: 2,
+                                timestamps: [sent, replied, now],
+                            },
+                        );
+                        self.synchronised(sent, now, replied as i64 - (sent + now) as i64 / 2);
+                    }
+                    2 => {
+                        let [sent, replied, completed] = timestamps;
+                        self.synchronised(
+                            sent,
+                            completed,
+                            (sent + completed) as i64 / 2 - replied as i64,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            Exchange::Feedback { ssrc, sequence }
+                if self.is_peer(ssrc) && is_after(sequence, self.acknowledged) =>
+            {
+                // Notes released up to the sequence are acknowledged
+                self.acknowledged = sequence;
+                self.released
+                    .retain(|(_, _, released)| is_after(*released, sequence));
+            }
+            _ => {}
+        }
+    }
+
+    fn is_peer(&self, ssrc: u32) -> bool {
+        self.peer.as_ref().is_some_and(|(peer, _)| *peer == ssrc)
+    }
+
+    ///
+    /// Packet was sent by the session peer
+    ///
+    fn is_from_peer(&self, packet: &[u8]) -> bool {
+        let ssrc = match Exchange::decode(packet) {
+            Some(exchange) => exchange.ssrc(),
+            None if packet.len() >= RTP_HEADER_LEN => be_u32(&packet[8..]),
+            None => return false,
+        };
+        self.is_peer(ssrc)
+    }
+
+    fn connected(&mut self) {
+        self.state = SessionState::Connected;
+        self.expected = None;
+    }
+
+    fn synchronised(&mut self, sent: u64, completed: u64, offset: i64) {
+        self.latency = Some(completed.saturating_sub(sent) * CLOCK_UNIT / 2);
+        self.offset = Some(offset * CLOCK_UNIT as i64);
+    }
+
+    fn handle_data(&mut self, packet: &[u8]) -> Option<Vec<MidiEvent>> {
+        if packet.len() <= RTP_HEADER_LEN || packet[0] & RTP_VERSION_MASK != RTP_VERSION {
+            return None;
+        }
+        let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+        let loss = match self.expected {
+            // Late or duplicate packets are dropped
+            Some(expected) if (sequence.wrapping_sub(expected) as i16) < 0 => return None,
+            Some(expected) => sequence != expected,
+            None => false,
+        };
+        self.expected = Some(sequence.wrapping_add(1));
+        self.queue(
+            Port::Control,
+            Exchange::Feedback {
+                ssrc: self.ssrc,
+                sequence,
+            },
+        );
+
+        let header = packet[RTP_HEADER_LEN];
+        let (len, start) = if header & FLAG_LONG > 0 {
+            let low = *packet.get(RTP_HEADER_LEN + 1)?;
+            (
+                (((header & 0x0F) as usize) << 8) | low as usize,
+                RTP_HEADER_LEN + 2,
+            )
+        } else {
+            ((header & 0x0F) as usize, RTP_HEADER_LEN + 1)
+        };
+        let list = packet.get(start..start + len)?;
+
+        let mut events = Vec::new();
+        if loss && header & FLAG_JOURNAL > 0 {
+            self.recover(&packet[start + len..], &mut events);
+        }
+
+        // Commands up to a malformed one are kept
+        let mut index = 0;
+        let mut first = true;
+        while index < list.len() {
+            if !first || header & FLAG_FIRST_DELTA > 0 {
+                match read_delta(&list[index..]) {
+                    Some((_, len)) => index += len,
+                    None => break,
+                }
+            }
+            first = false;
+            let len = match list
+                .get(index..)
+                .and_then(|list| command_len(list, &mut self.status))
+            {
+                Some(len) => len,
+                None => break,
+            };
+            let command = &list[index..index + len];
+            let received = &mut self.received;
+            let mut emit = |event: MidiEvent| {
+                received.handle_event(&event);
+                events.push(event);
+            };
+            match command {
+                [0xF0, segment @ ..] => {
+                    // First System Exclusive segment; an F0 ending it or a
+                    // missing terminator leaves the message open
+                    let terminator = segment.last().copied();
+                    self.sys_ex_received = !matches!(terminator, Some(0xF4 | 0xF7));
+                    let command = match terminator {
+                        Some(0xF0) => &command[..command.len() - 1],
+                        _ => command,
+                    };
+                    for byte in command {
+                        if let Some(event) = self.reader.handle_byte(*byte) {
+                            emit(event);
+                        }
+                    }
+                }
+                [0xF7, segment @ ..] => {
+                    // Continuation segment; decoded here as the reader may
+                    // have moved on to commands sent between segments. F4
+                    // cancels the message, and continuations of a message
+                    // never started are dropped
+                    for byte in segment {
+                        match byte {
+                            0x00..=0x7F if self.sys_ex_received => {
+                                emit(MidiEvent::SystemExclusiveData(*byte))
+                            }
+                            0xF7 if self.sys_ex_received => {
+                                self.sys_ex_received = false;
+                                emit(MidiEvent::SystemExclusiveEnd);
+                            }
+                            0xF4 => self.sys_ex_received = false,
+                            0xF8..=0xFF => {
+                                if let Some(event) = self.reader.handle_byte(*byte) {
+                                    emit(event);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {
+                    for byte in command {
+                        if let Some(event) = self.reader.handle_byte(*byte) {
+                            emit(event);
+                        }
+                    }
+                }
+            }
+            index += len;
+        }
+        Some(events)
+    }
+
+    fn recover(&mut self, journal: &[u8], events: &mut Vec<MidiEvent>) {
+        // Nothing is recovered from a journal that does not fit the packet
+        let journals = match decode_journal(journal) {
+            Some(journals) => journals,
+            None => return,
+        };
+        let mut recovered = Vec::new();
+        for channel in &journals {
+            channel.recover(self.received.channel(channel.channel), &mut recovered);
+        }
+        for event in recovered {
+            self.received.handle_event(&event);
+            events.push(event);
+        }
+    }
+
+    fn track_sent(&mut self, event: &MidiEvent, sequence: u16) {
+        if let Some(channel) = event.channel() {
+            let bit = 1 << (channel & 0x0F);
+            self.channels |= bit;
+            match *event {
+                MidiEvent::ProgramChange(_, _) => self.programs |= bit,
+                MidiEvent::ControllerChange(_, BANK_SELECT | BANK_SELECT_LSB, _) => {
+                    self.banks |= bit
+                }
+                MidiEvent::NoteOn(_, key, 0) | MidiEvent::NoteOff(_, key, _) => {
+                    self.forget_release(channel, key);
+                    self.released.push((channel, key, sequence));
+                }
+                MidiEvent::NoteOn(_, key, _) => self.forget_release(channel, key),
+                _ => {}
+            }
+        }
+        self.sent.handle_event(event);
+    }
+
+    fn forget_release(&mut self, channel: Channel, key: u7) {
+        self.released
+            .retain(|(released, note, _)| !(*released == channel && *note == key));
+    }
+
+    ///
+    /// Recovery journal for the state before the next packet
+    ///
+    fn journal(&self) -> Vec<u8> {
+        let defaults = ChannelState::new();
+        let journals: Vec<ChannelJournal> = self
+            .sent
+            .channels()
+            .filter(|(channel, _)| self.channels & (1 << channel) > 0)
+            .map(|(channel, state)| ChannelJournal {
+                channel,
+                program: if self.programs & (1 << channel) > 0 {
+                    // Bank Select is only recovered if it was sent
+                    let bank = (self.banks & (1 << channel) > 0).then(|| {
+                        (
+                            state.controller(BANK_SELECT),
+                            state.controller(BANK_SELECT_LSB),
+                        )
+                    });
+                    Some((state.program(), bank))
+                } else {
+                    None
+                },
+                controllers: (0..128)
+                    .filter(|control| state.controller(*control) != defaults.controller(*control))
+                    .map(|control| (control, state.controller(control)))
+                    .collect(),
+                notes: state.held_notes().collect(),
+                off: self
+                    .released
+                    .iter()
+                    .filter(|(released, _, _)| *released == channel)
+                    .map(|(_, note, _)| *note)
+                    .collect(),
+            })
+            .collect();
+        if journals.is_empty() {
+            return Vec::new();
+        }
+
+        // The journal covers the packets since the last acknowledged
+        let checkpoint = self.acknowledged.to_be_bytes();
+        let mut bytes = vec![
+            JOURNAL_CHANNELS | (journals.len() - 1) as u8,
+            checkpoint[0],
+            checkpoint[1],
+        ];
+        for journal in &journals {
+            journal.encode(&mut bytes);
+        }
+        bytes
+    }
+}
+
+///
+/// **RTP-MIDI over UDP**
+///
+/// Drives an [`RtpMidiSession`] with a pair of UDP sockets, the data port
+/// being the control port + 1. Session time is measured from when the
+/// sockets were bound.
+///
+pub struct RtpMidiSocket {
+    session: RtpMidiSession,
+    control: UdpSocket,
+    data: UdpSocket,
+    peer: Option<(SocketAddr, SocketAddr)>, // Remote control and data addresses
+    start: Instant,
+}
+
+impl RtpMidiSocket {
+    ///
+    /// **Bind the control and data ports**
+    ///
+    /// With port 0 a free pair of ports is chosen.
+    ///
+    pub fn bind(session: RtpMidiSession, address: SocketAddr) -> io::Result<Self> {
+        let mut error = None;
+        for _ in 0..16 {
+            let control = UdpSocket::bind(address)?;
+            let mut data_address = control.local_addr()?;
+            data_address.set_port(data_address.port().wrapping_add(1));
+            match UdpSocket::bind(data_address) {
+                Ok(data) => {
+                    control.set_nonblocking(true)?;
+                    data.set_nonblocking(true)?;
+                    return Ok(Self {
+                        session,
+                        control,
+                        data,
+                        peer: None,
+                        start: Instant::now(),
+                    });
+                }
+                Err(err) if address.port() == 0 => error = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(error.unwrap_or_else(|| io::Error::from(io::ErrorKind::AddrInUse)))
+    }
+
+    ///
+    /// Address of the control port
+    ///
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.control.local_addr()
+    }
+
+    pub fn session(&self) -> &RtpMidiSession {
+        &self.session
+    }
+
+    ///
+    /// **Invite a remote session at its control port**
+    ///
+    pub fn invite(&mut self, address: SocketAddr, timeout: Duration) -> io::Result<()> {
+        let mut data = address;
+        data.set_port(address.port().wrapping_add(1));
+        self.peer = Some((address, data));
+        self.session.invite(self.now());
+        self.wait(timeout, |session| {
+            !matches!(session.state(), SessionState::Inviting(_))
+        })?;
+        match self.session.state() {
+            SessionState::Connected => Ok(()),
+            _ => Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+        }
+    }
+
+    ///
+    /// **Wait for an invitation**
+    ///
+    pub fn accept(&mut self, timeout: Duration) -> io::Result<()> {
+        self.wait(timeout, RtpMidiSession::is_connected)
+    }
+
+    ///
+    /// **Send events**
+    ///
+    pub fn send(&mut self, events: &[MidiEvent]) -> io::Result<()> {
+        let now = self.now();
+        self.session.send(events, now);
+        self.flush()
+    }
+
+    ///
+    /// **Receive events**
+    ///
+    /// Handles session packets until events are received; returns no events
+    /// if none arrive within the timeout.
+    ///
+    pub fn receive(&mut self, timeout: Duration) -> io::Result<Vec<MidiEvent>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let events = self.poll()?;
+            if !events.is_empty() || Instant::now() >= deadline {
+                return Ok(events);
+            }
+        }
+    }
+
+    ///
+    /// **End the session**
+    ///
+    pub fn end(&mut self) -> io::Result<()> {
+        self.session.end();
+        self.flush()
+    }
+
+    fn now(&self) -> Timestamp {
+        self.start.elapsed().as_micros() as Timestamp
+    }
+
+    fn wait(
+        &mut self,
+        timeout: Duration,
+        done: impl Fn(&RtpMidiSession) -> bool,
+    ) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.flush()?;
+        while !done(&self.session) {
+            if Instant::now() >= deadline {
+                return Err(io::Error::from(io::ErrorKind::TimedOut));
+            }
+            self.poll()?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Handle a received packet if any and send replies
+    ///
+    fn poll(&mut self) -> io::Result<Vec<MidiEvent>> {
+        let mut buffer = [0; 1500];
+        let mut events = Vec::new();
+        let mut received = false;
+        for port in [Port::Control, Port::Data].iter() {
+            match self.socket(*port).recv_from(&mut buffer) {
+                Ok((len, source)) => {
+                    received = true;
+                    let now = self.now();
+                    let packet = &buffer[..len];
+                    events.extend(self.session.handle_packet(*port, packet, now));
+                    // Addresses are learnt from accepted invitations and the
+                    // peer's packets only
+                    if self.session.is_from_peer(packet) {
+                        let (control, data) = self.peer.get_or_insert((source, source));
+                        match port {
+                            Port::Control => *control = source,
+                            Port::Data => *data = source,
+                        }
+                    } else {
+                        // Replies to anyone else (eg a rejection) return to the sender
+                        for (port, reply) in self.session.transmit() {
+                            self.socket(port).send_to(&reply, source)?;
+                        }
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.flush()?;
+        if !received {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(events)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (port, packet) in self.session.transmit() {
+            if let Some((control, data)) = self.peer {
+                match port {
+                    Port::Control => self.control.send_to(&packet, control)?,
+                    Port::Data => self.data.send_to(&packet, data)?,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn socket(&self, port: Port) -> &UdpSocket {
+        match port {
+            Port::Control => &self.control,
+            Port::Data => &self.data,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::rtp::{
+        decode_journal, encode_list, ChannelJournal, Exchange, Port, RtpMidiSession, RtpMidiSocket,
+        SessionState,
+    };
+    use crate::{MidiEvent, SysExID};
+    use std::time::Duration;
+
+    ///
+    /// Deliver packets between sessions until quiet, dropping data packets
+    /// for which `drop` returns true
+    ///
+    fn exchange(
+        a: &mut RtpMidiSession,
+        b: &mut RtpMidiSession,
+        drop: impl Fn(&[u8]) -> bool,
+    ) -> Vec<MidiEvent> {
+        let mut events = Vec::new();
+        loop {
+            let to_b = a.transmit();
+            let to_a = b.transmit();
+            if to_a.is_empty() && to_b.is_empty() {
+                return events;
+            }
+            for (port, packet) in to_b {
+                if !(port == Port::Data && drop(&packet)) {
+                    events.extend(b.handle_packet(port, &packet, 2000));
+                }
+            }
+            for (port, packet) in to_a {
+                a.handle_packet(port, &packet, 3000);
+            }
+        }
+    }
+
+    fn connect() -> (RtpMidiSession, RtpMidiSession) {
+        let mut initiator = RtpMidiSession::new("Initiator", 0x1111);
+        let mut responder = RtpMidiSession::new("Responder", 0x2222);
+        initiator.invite(1000);
+        exchange(&mut initiator, &mut responder, |_| false);
+        (initiator, responder)
+    }
+
+    #[test]
+    fn exchange__round_trips() {
+        let packets = [
+            Exchange::Invitation {
+                token: 1,
+                ssrc: 2,
+                name: "Session".to_string(),
+            },
+            Exchange::End { token: 1, ssrc: 2 },
+            Exchange::Sync {
+                ssrc: 2,
+                count: 1,
+                timestamps: [1, 2, 0],
+            },
+            Exchange::Feedback {
+                ssrc: 2,
+                sequence: 300,
+            },
+        ];
+
+        for packet in packets.iter() {
+            assert_eq!(Exchange::decode(&packet.encode()).as_ref(), Some(packet));
+        }
+    }
+
+    #[test]
+    fn invite__connects_and_synchronises() {
+        let (initiator, responder) = connect();
+
+        assert_eq!(initiator.state(), SessionState::Connected);
+        assert_eq!(responder.state(), SessionState::Connected);
+        assert_eq!(responder.peer_name(), Some("Initiator"));
+        // Clock synchronisation completed on both sides
+        assert_eq!(initiator.latency(), Some(0));
+        assert_eq!(initiator.offset(), Some(-1000));
+        assert_eq!(responder.offset(), Some(1000));
+    }
+
+    #[test]
+    fn invite__where_responder_busy() {
+        let (_, mut responder) = connect();
+        let mut target = RtpMidiSession::new("Other", 0x3333);
+
+        target.invite(0);
+        exchange(&mut target, &mut responder, |_| false);
+
+        assert_eq!(target.state(), SessionState::Rejected);
+    }
+
+    #[test]
+    fn encode_list__running_status_and_sys_ex() {
+        let mut sys_ex = false;
+        let actual = encode_list(
+            &[
+                (0, MidiEvent::NoteOn(0, 60, 100)),
+                (0x81, MidiEvent::NoteOn(0, 62, 100)),
+                (0, MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D))),
+                (0, MidiEvent::SystemExclusiveData(1)),
+                (0, MidiEvent::SystemExclusiveEnd),
+            ],
+            &mut sys_ex,
+        );
+
+        assert_eq!(
+            actual,
+            vec![0x90, 60, 100, 0x81, 0x01, 62, 100, 0, 0xF0, 0x7D, 1, 0xF7]
+        );
+        assert!(!sys_ex);
+    }
+
+    #[test]
+    fn encode_list__sys_ex_segments() {
+        let mut sys_ex = false;
+
+        let start = encode_list(
+            &[
+                (0, MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D))),
+                (0, MidiEvent::SystemExclusiveData(1)),
+            ],
+            &mut sys_ex,
+        );
+        assert_eq!(start, vec![0xF0, 0x7D, 1, 0xF0]);
+        assert!(sys_ex);
+
+        let middle = encode_list(
+            &[
+                (0, MidiEvent::SystemExclusiveData(2)),
+                (0, MidiEvent::NoteOn(0, 60, 100)),
+                (0, MidiEvent::SystemExclusiveData(3)),
+            ],
+            &mut sys_ex,
+        );
+        assert_eq!(
+            middle,
+            vec![0xF7, 2, 0xF0, 0, 0x90, 60, 100, 0, 0xF7, 3, 0xF0]
+        );
+
+        let end = encode_list(&[(0, MidiEvent::SystemExclusiveEnd)], &mut sys_ex);
+        assert_eq!(end, vec![0xF7, 0xF7]);
+        assert!(!sys_ex);
+    }
+
+    #[test]
+    fn encode_list__realtime_inside_sys_ex() {
+        let actual = encode_list(
+            &[
+                (0, MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D))),
+                (0, MidiEvent::SystemExclusiveData(1)),
+                (0, MidiEvent::Clock),
+                (0, MidiEvent::SystemExclusiveData(2)),
+                (0, MidiEvent::SystemExclusiveEnd),
+                (0, MidiEvent::Clock),
+            ],
+            &mut false,
+        );
+
+        assert_eq!(actual, vec![0xF0, 0x7D, 1, 0xF8, 2, 0xF7, 0, 0xF8]);
+    }
+
+    #[test]
+    fn channel_journal__round_trips() {
+        let journal = ChannelJournal {
+            channel: 3,
+            program: Some((5, Some((1, 2)))),
+            controllers: vec![(7, 90), (64, 127)],
+            notes: vec![(60, 100), (64, 90)],
+            off: vec![62, 70, 71],
+        };
+        let mut bytes = Vec::new();
+
+        journal.encode(&mut bytes);
+
+        assert_eq!(ChannelJournal::decode(&bytes), Some((journal, bytes.len())));
+    }
+
+    #[test]
+    fn channel_journal__where_off_range_reversed() {
+        // Notes chapter with Low 2 and High 1 but no bitfield
+        let bytes = [0x18, 7, 0x08, 1, 0x21, 60, 100];
+
+        let actual = ChannelJournal::decode(&bytes);
+
+        assert_eq!(
+            actual,
+            Some((
+                ChannelJournal {
+                    channel: 3,
+                    notes: vec![(60, 100)],
+                    ..Default::default()
+                },
+                7
+            ))
+        );
+    }
+
+    #[test]
+    fn decode_journal__skips_system_journal() {
+        let journal = ChannelJournal {
+            channel: 1,
+            program: Some((5, None)),
+            ..Default::default()
+        };
+        let mut bytes = vec![0x60, 0, 0, 0x00, 4, 0x00, 0x00];
+        journal.encode(&mut bytes);
+
+        assert_eq!(decode_journal(&bytes), Some(vec![journal]));
+        // Channel journal beyond the end of the packet
+        assert_eq!(decode_journal(&bytes[..bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn send__received_by_peer() {
+        let (mut initiator, mut responder) = connect();
+        let events = vec![
+            MidiEvent::NoteOn(0, 60, 100),
+            MidiEvent::NoteOn(0, 64, 100),
+            MidiEvent::ControllerChange(1, 7, 90),
+        ];
+
+        initiator.send(&events, 5000);
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(actual, events);
+    }
+
+    #[test]
+    fn send__sys_ex_split_across_packets() {
+        let (mut initiator, mut responder) = connect();
+        let events = vec![
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D)),
+            MidiEvent::SystemExclusiveData(1),
+            MidiEvent::Clock,
+            MidiEvent::SystemExclusiveData(2),
+            MidiEvent::SystemExclusiveEnd,
+        ];
+
+        for event in &events {
+            initiator.send(&[*event], 5000);
+        }
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(actual, events);
+    }
+
+    #[test]
+    fn send__realtime_inside_sys_ex() {
+        let (mut initiator, mut responder) = connect();
+        let events = vec![
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D)),
+            MidiEvent::SystemExclusiveData(1),
+            MidiEvent::Clock,
+            MidiEvent::SystemExclusiveData(2),
+            MidiEvent::SystemExclusiveEnd,
+            MidiEvent::NoteOn(0, 60, 100),
+        ];
+
+        initiator.send(&events, 5000);
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(actual, events);
+    }
+
+    #[test]
+    fn send__channel_message_inside_sys_ex() {
+        let (mut initiator, mut responder) = connect();
+        let events = vec![
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D)),
+            MidiEvent::SystemExclusiveData(1),
+            MidiEvent::NoteOn(0, 60, 100),
+            MidiEvent::SystemExclusiveData(3),
+            MidiEvent::SystemExclusiveData(4),
+            MidiEvent::SystemExclusiveEnd,
+        ];
+
+        initiator.send(&events, 5000);
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(actual, events);
+    }
+
+    #[test]
+    fn handle_packet__unterminated_sys_ex_segment() {
+        let (_, mut target) = connect();
+        let mut packet = vec![0x80, 0x61, 0, 0, 0, 0, 0, 0, 0, 0, 0x11, 0x11];
+        // Ends with a System Exclusive segment missing its terminator
+        packet.extend_from_slice(&[7, 0x90, 60, 100, 0, 0xF0, 0x7D, 1]);
+
+        let actual = target.handle_packet(Port::Data, &packet, 5000);
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::NoteOn(0, 60, 100),
+                MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7D)),
+                MidiEvent::SystemExclusiveData(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn send__recovers_lost_packet() {
+        let (mut initiator, mut responder) = connect();
+        initiator.send(&[MidiEvent::NoteOn(0, 60, 100)], 5000);
+        exchange(&mut initiator, &mut responder, |_| false);
+
+        initiator.send(
+            &[
+                MidiEvent::NoteOff(0, 60, 0),
+                MidiEvent::NoteOn(0, 62, 80),
+                MidiEvent::ProgramChange(2, 9),
+            ],
+            6000,
+        );
+        exchange(&mut initiator, &mut responder, |_| true);
+        initiator.send(&[MidiEvent::Clock], 7000);
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::NoteOff(0, 60, 0),
+                MidiEvent::NoteOn(0, 62, 80),
+                MidiEvent::ProgramChange(2, 9),
+                MidiEvent::Clock,
+            ]
+        );
+    }
+
+    #[test]
+    fn send__recovers_bank_select_when_sent() {
+        let (mut initiator, mut responder) = connect();
+        initiator.send(&[MidiEvent::Clock], 5000);
+        exchange(&mut initiator, &mut responder, |_| false);
+
+        initiator.send(
+            &[
+                MidiEvent::ControllerChange(2, 0, 1),
+                MidiEvent::ProgramChange(2, 9),
+            ],
+            6000,
+        );
+        exchange(&mut initiator, &mut responder, |_| true);
+        initiator.send(&[MidiEvent::Clock], 7000);
+        let actual = exchange(&mut initiator, &mut responder, |_| false);
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::ControllerChange(2, 0, 1),
+                MidiEvent::ProgramChange(2, 9),
+                MidiEvent::Clock,
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_packet__feedback_acknowledges_earlier_releases() {
+        let (mut initiator, _) = connect();
+        initiator.send(&[MidiEvent::NoteOff(0, 60, 0)], 5000);
+        initiator.send(&[MidiEvent::NoteOff(0, 62, 0)], 6000);
+        initiator.transmit();
+
+        let feedback = Exchange::Feedback {
+            ssrc: 0x2222,
+            sequence: 0,
+        };
+        initiator.handle_packet(Port::Control, &feedback.encode(), 7000);
+
+        assert_eq!(initiator.released, vec![(0, 62, 1)]);
+        assert_eq!(initiator.journal()[1..3], [0, 0]);
+    }
+
+    #[test]
+    fn is_from_peer__only_for_session_peer() {
+        let (mut initiator, mut responder) = connect();
+        let mut other = RtpMidiSession::new("Other", 0x3333);
+        other.invite(0);
+        let invitation = other.transmit().remove(0).1;
+        initiator.send(&[MidiEvent::Clock], 5000);
+        let data = initiator.transmit().remove(0).1;
+
+        responder.handle_packet(Port::Control, &invitation, 6000);
+
+        assert!(!responder.is_from_peer(&invitation));
+        assert!(responder.is_from_peer(&data));
+    }
+
+    #[test]
+    fn socket__sessions_on_localhost() {
+        let address = "127.0.0.1:0".parse().unwrap();
+        let mut responder =
+            RtpMidiSocket::bind(RtpMidiSession::new("Responder", 0x2222), address).unwrap();
+        let responder_address = responder.local_addr().unwrap();
+        let events = vec![MidiEvent::NoteOn(9, 36, 127), MidiEvent::NoteOff(9, 36, 0)];
+
+        let receiver = std::thread::spawn(move || {
+            responder.accept(Duration::from_secs(5)).unwrap();
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                let events = responder.receive(Duration::from_secs(5)).unwrap();
+                assert!(!events.is_empty());
+                received.extend(events);
+            }
+            received
+        });
+        let mut initiator =
+            RtpMidiSocket::bind(RtpMidiSession::new("Initiator", 0x1111), address).unwrap();
+        initiator
+            .invite(responder_address, Duration::from_secs(5))
+            .unwrap();
+        initiator.send(&events).unwrap();
+
+        assert_eq!(receiver.join().unwrap(), events);
+        initiator.end().unwrap();
+    }
+
+    #[test]
+    fn socket__rejects_third_initiator() {
+        let address = "127.0.0.1:0".parse().unwrap();
+        let mut responder =
+            RtpMidiSocket::bind(RtpMidiSession::new("Responder", 0x2222), address).unwrap();
+        let responder_address = responder.local_addr().unwrap();
+        let events = vec![MidiEvent::Start];
+
+        let receiver = std::thread::spawn(move || {
+            responder.accept(Duration::from_secs(5)).unwrap();
+            responder.receive(Duration::from_secs(5)).unwrap()
+        });
+        let mut initiator =
+            RtpMidiSocket::bind(RtpMidiSession::new("Initiator", 0x1111), address).unwrap();
+        initiator
+            .invite(responder_address, Duration::from_secs(5))
+            .unwrap();
+        let mut other = RtpMidiSocket::bind(RtpMidiSession::new("Other", 0x3333), address).unwrap();
+
+        let actual = other.invite(responder_address, Duration::from_secs(5));
+
+        assert_eq!(
+            actual.map_err(|err| err.kind()),
+            Err(std::io::ErrorKind::ConnectionRefused)
+        );
+        assert_eq!(other.session().state(), SessionState::Rejected);
+        initiator.send(&events).unwrap();
+        assert_eq!(receiver.join().unwrap(), events);
+        assert!(initiator.session().is_connected());
+    }
+}
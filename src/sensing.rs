@@ -0,0 +1,223 @@
+use crate::{MidiEvent, NoteTracker, Timestamp};
+
+/// Time without any message before the connection is considered lost (300ms)
+pub const ACTIVE_SENSING_TIMEOUT: Timestamp = 300_000;
+/// Interval used when transmitting Active Sensing; well inside the timeout
+pub const ACTIVE_SENSING_INTERVAL: Timestamp = 250_000;
+
+///
+/// State of Active Sensing on an input
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SensingStatus {
+    /// No Active Sensing has been received; timeouts are not applied
+    Inactive,
+    /// Active Sensing received; messages are expected within the timeout
+    Active,
+    /// Timed out; returns to Inactive (or Active) on the next message
+    ConnectionLost,
+}
+
+///
+/// **Active Sensing Monitor**
+///
+/// Applies the receiver behaviour from the MIDI spec; once an Active Sensing
+/// message has been received, if no message arrives within the timeout all
+/// held notes are turned off and sensing stops until it is received again.
+///
+#[derive(Debug, Clone)]
+pub struct ActiveSensingMonitor {
+    timeout: Timestamp,
+    last_received: Timestamp,
+    status: SensingStatus,
+    notes: NoteTracker,
+}
+
+impl ActiveSensingMonitor {
+    pub fn new() -> Self {
+        Self {
+            timeout: ACTIVE_SENSING_TIMEOUT,
+            last_received: 0,
+            status: SensingStatus::Inactive,
+            notes: NoteTracker::new(),
+        }
+    }
+
+    ///
+    /// Set the timeout (in microseconds)
+    ///
+    pub fn with_timeout(mut self, timeout: Timestamp) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn status(&self) -> SensingStatus {
+        self.status
+    }
+
+    pub fn is_sensing(&self) -> bool {
+        self.status == SensingStatus::Active
+    }
+
+    ///
+    /// **Handle an event received at `now`**
+    ///
+    /// Every received event is fed to the monitor, not just Active Sensing.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, now: Timestamp) {
+        self.last_received = now;
+        self.notes.handle_event(event, now);
+
+        match event {
+            MidiEvent::ActiveSensing => self.status = SensingStatus::Active,
+            _ if self.status == SensingStatus::ConnectionLost => {
+                self.status = SensingStatus::Inactive
+            }
+            _ => (),
+        }
+    }
+
+    ///
+    /// **Check for a timeout at `now`**
+    ///
+    /// Returns the events required to silence the receiver when the
+    /// connection is first detected as lost; Note Offs for every held note
+    /// followed by All Notes Off / All Sound Off on every channel.
+    ///
+    pub fn poll(&mut self, now: Timestamp) -> Option<impl Iterator<Item = MidiEvent>> {
+        if self.status == SensingStatus::Active
+            && now.saturating_sub(self.last_received) > self.timeout
+        {
+            self.status = SensingStatus::ConnectionLost;
+            Some(self.notes.panic())
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ActiveSensingMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// **Active Sensing Generator**
+///
+/// Produces Active Sensing for an output whenever no other message has been
+/// sent within the interval.
+///
+#[derive(Debug, Clone)]
+pub struct ActiveSensingGenerator {
+    interval: Timestamp,
+    last_sent: Option<Timestamp>,
+}
+
+impl ActiveSensingGenerator {
+    pub fn new() -> Self {
+        Self {
+            interval: ACTIVE_SENSING_INTERVAL,
+            last_sent: None,
+        }
+    }
+
+    ///
+    /// Set the transmit interval (in microseconds)
+    ///
+    pub fn with_interval(mut self, interval: Timestamp) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    ///
+    /// Notify the generator any other message was sent at `now`
+    ///
+    pub fn message_sent(&mut self, now: Timestamp) {
+        self.last_sent = Some(now);
+    }
+
+    ///
+    /// **Returns Active Sensing if one is due at `now`**
+    ///
+    pub fn poll(&mut self, now: Timestamp) -> Option<MidiEvent> {
+        match self.last_sent {
+            Some(last_sent) if now.saturating_sub(last_sent) < self.interval => None,
+            _ => {
+                self.last_sent = Some(now);
+                Some(MidiEvent::ActiveSensing)
+            }
+        }
+    }
+}
+
+impl Default for ActiveSensingGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::sensing::{ActiveSensingGenerator, ActiveSensingMonitor, SensingStatus};
+    use crate::MidiEvent;
+
+    #[test]
+    fn poll__where_sensing_never_received() {
+        let mut target = ActiveSensingMonitor::new();
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 0);
+
+        assert!(target.poll(10_000_000).is_none());
+        assert_eq!(target.status(), SensingStatus::Inactive);
+    }
+
+    #[test]
+    fn poll__where_messages_arrive_within_timeout() {
+        let mut target = ActiveSensingMonitor::new();
+        target.handle_event(&MidiEvent::ActiveSensing, 0);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100), 200_000);
+        target.handle_event(&MidiEvent::ActiveSensing, 450_000);
+
+        assert!(target.poll(700_000).is_none());
+        assert!(target.is_sensing());
+    }
+
+    #[test]
+    fn poll__where_connection_lost() {
+        let mut target = ActiveSensingMonitor::new();
+        target.handle_event(&MidiEvent::ActiveSensing, 0);
+        target.handle_event(&MidiEvent::NoteOn(4, 60, 100), 100_000);
+
+        let actual: Vec<MidiEvent> = target.poll(400_001).unwrap().collect();
+
+        assert_eq!(actual[0], MidiEvent::NoteOff(4, 60, 0));
+        assert_eq!(actual.len(), 1 + 16 * 2);
+        assert_eq!(target.status(), SensingStatus::ConnectionLost);
+        // Only reported once
+        assert!(target.poll(500_000).is_none());
+    }
+
+    #[test]
+    fn handle_event__where_sensing_resumes_after_loss() {
+        let mut target = ActiveSensingMonitor::new();
+        target.handle_event(&MidiEvent::ActiveSensing, 0);
+        target.poll(400_000);
+
+        target.handle_event(&MidiEvent::Clock, 500_000);
+        assert_eq!(target.status(), SensingStatus::Inactive);
+        target.handle_event(&MidiEvent::ActiveSensing, 600_000);
+        assert_eq!(target.status(), SensingStatus::Active);
+    }
+
+    #[test]
+    fn generator__sends_only_when_idle() {
+        let mut target = ActiveSensingGenerator::new().with_interval(100);
+
+        assert_eq!(target.poll(0), Some(MidiEvent::ActiveSensing));
+        assert_eq!(target.poll(50), None);
+        target.message_sent(90);
+        assert_eq!(target.poll(150), None);
+        assert_eq!(target.poll(190), Some(MidiEvent::ActiveSensing));
+    }
+}
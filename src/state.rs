@@ -0,0 +1,291 @@
+use crate::pitch_bend::PITCH_BEND_CENTRE;
+use crate::{controller, i14, u7, Channel, MidiEvent};
+
+const NUM_CHANNELS: usize = 16;
+const NUM_KEYS: usize = 128;
+
+///
+/// **Channel State**
+///
+/// Current state of a single MIDI channel, built up from channel voice and
+/// channel mode events.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChannelState {
+    notes: [u7; NUM_KEYS], // Velocity of held notes (0 when not held)
+    poly_pressure: [u7; NUM_KEYS],
+    controllers: [u7; NUM_KEYS],
+    program: u7,
+    pitch_bend: i14,
+    channel_pressure: u7,
+}
+
+impl ChannelState {
+    pub fn new() -> Self {
+        let mut state = Self {
+            notes: [0; NUM_KEYS],
+            poly_pressure: [0; NUM_KEYS],
+            controllers: [0; NUM_KEYS],
+            program: 0,
+            pitch_bend: PITCH_BEND_CENTRE,
+            channel_pressure: 0,
+        };
+        state.controllers[controller::VOLUME as usize] = 100;
+        state.controllers[controller::PAN as usize] = 64;
+        state.reset_controllers();
+        state
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    /// The channel of the event is not checked, any channel event is applied
+    /// to this state. System events are ignored.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        match *event {
+            MidiEvent::NoteOn(_, key, 0) | MidiEvent::NoteOff(_, key, _) => {
+                self.notes[key as usize & 0x7F] = 0;
+                self.poly_pressure[key as usize & 0x7F] = 0;
+            }
+            MidiEvent::NoteOn(_, key, velocity) => self.notes[key as usize & 0x7F] = velocity,
+            MidiEvent::PolyphonicAfterTouch(_, key, pressure) => {
+                self.poly_pressure[key as usize & 0x7F] = pressure
+            }
+            MidiEvent::ControllerChange(_, control, value) => {
+                self.controllers[control as usize & 0x7F] = value
+            }
+            MidiEvent::ProgramChange(_, program) => self.program = program,
+            MidiEvent::ChannelAfterTouch(_, pressure) => self.channel_pressure = pressure,
+            MidiEvent::PitchBend(_, amount) => self.pitch_bend = amount,
+            MidiEvent::ResetAllControllers(_) => self.reset_controllers(),
+            // Mode changes imply all notes off
+            MidiEvent::AllNotesOff(_)
+            | MidiEvent::AllSoundOff(_)
+            | MidiEvent::OmniMode(_, _)
+            | MidiEvent::MonoMode(_, _)
+            | MidiEvent::PolyphonicMode(_) => self.release_all(),
+            _ => (),
+        }
+    }
+
+    ///
+    /// Velocity of a held note; None if the note is not held
+    ///
+    pub fn note(&self, key: u7) -> Option<u7> {
+        match self.notes[key as usize & 0x7F] {
+            0 => None,
+            velocity => Some(velocity),
+        }
+    }
+
+    pub fn is_note_held(&self, key: u7) -> bool {
+        self.note(key).is_some()
+    }
+
+    ///
+    /// Iterator of held notes as (key, velocity) in ascending key order
+    ///
+    pub fn held_notes(&self) -> impl Iterator<Item = (u7, u7)> + '_ {
+        self.notes
+            .iter()
+            .enumerate()
+            .filter(|(_, velocity)| **velocity > 0)
+            .map(|(key, velocity)| (key as u7, *velocity))
+    }
+
+    pub fn held_note_count(&self) -> usize {
+        self.notes.iter().filter(|velocity| **velocity > 0).count()
+    }
+
+    pub fn controller(&self, control: u7) -> u7 {
+        self.controllers[control as usize & 0x7F]
+    }
+
+    pub fn controllers(&self) -> &[u7; 128] {
+        &self.controllers
+    }
+
+    pub fn program(&self) -> u7 {
+        self.program
+    }
+
+    pub fn pitch_bend(&self) -> i14 {
+        self.pitch_bend
+    }
+
+    pub fn channel_pressure(&self) -> u7 {
+        self.channel_pressure
+    }
+
+    pub fn poly_pressure(&self, key: u7) -> u7 {
+        self.poly_pressure[key as usize & 0x7F]
+    }
+
+    ///
+    /// Reset controllers as described by RP-015; volume, pan, bank and
+    /// program are left unchanged.
+    ///
+    fn reset_controllers(&mut self) {
+        for (control, value) in controller::RESET_VALUES.iter() {
+            self.controllers[*control as usize] = *value;
+        }
+        self.pitch_bend = PITCH_BEND_CENTRE;
+        self.channel_pressure = 0;
+        self.poly_pressure = [0; NUM_KEYS];
+    }
+
+    fn release_all(&mut self) {
+        self.notes = [0; NUM_KEYS];
+        self.poly_pressure = [0; NUM_KEYS];
+    }
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// **MIDI State**
+///
+/// State of all 16 channels; channel events are routed to the matching
+/// `ChannelState` and System Reset returns every channel to defaults.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MidiState {
+    channels: [ChannelState; NUM_CHANNELS],
+}
+
+impl MidiState {
+    pub fn new() -> Self {
+        Self {
+            channels: Default::default(),
+        }
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        if let MidiEvent::SystemReset = event {
+            self.channels = Default::default();
+        } else if let Some(channel) = event.channel() {
+            self.channels[channel as usize & 0x0F].handle_event(event);
+        }
+    }
+
+    pub fn channel(&self, channel: Channel) -> &ChannelState {
+        &self.channels[channel as usize & 0x0F]
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = (Channel, &ChannelState)> {
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(channel, state)| (channel as Channel, state))
+    }
+}
+
+impl Default for MidiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::controller;
+    use crate::state::{ChannelState, MidiState};
+    use crate::MidiEvent;
+
+    #[test]
+    fn handle_event__tracks_held_notes() {
+        let mut target = ChannelState::new();
+
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 64, 90));
+        target.handle_event(&MidiEvent::NoteOn(0, 67, 80));
+        target.handle_event(&MidiEvent::NoteOff(0, 64, 0));
+        target.handle_event(&MidiEvent::NoteOn(0, 67, 0));
+
+        let actual: Vec<(u8, u8)> = target.held_notes().collect();
+        assert_eq!(actual, vec![(60, 100)]);
+        assert_eq!(target.note(67), None);
+    }
+
+    #[test]
+    fn handle_event__tracks_values() {
+        let mut target = ChannelState::new();
+
+        target.handle_event(&MidiEvent::ControllerChange(0, controller::MODULATION, 33));
+        target.handle_event(&MidiEvent::ProgramChange(0, 12));
+        target.handle_event(&MidiEvent::PitchBend(0, -100));
+        target.handle_event(&MidiEvent::ChannelAfterTouch(0, 7));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::PolyphonicAfterTouch(0, 60, 44));
+
+        assert_eq!(target.controller(controller::MODULATION), 33);
+        assert_eq!(target.program(), 12);
+        assert_eq!(target.pitch_bend(), -100);
+        assert_eq!(target.channel_pressure(), 7);
+        assert_eq!(target.poly_pressure(60), 44);
+    }
+
+    #[test]
+    fn handle_event__where_reset_all_controllers() {
+        let mut target = ChannelState::new();
+        target.handle_event(&MidiEvent::ControllerChange(0, controller::VOLUME, 20));
+        target.handle_event(&MidiEvent::ControllerChange(0, controller::SUSTAIN, 127));
+        target.handle_event(&MidiEvent::ControllerChange(0, controller::EXPRESSION, 3));
+        target.handle_event(&MidiEvent::ProgramChange(0, 5));
+        target.handle_event(&MidiEvent::PitchBend(0, 1000));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+
+        target.handle_event(&MidiEvent::ResetAllControllers(0));
+
+        assert_eq!(target.controller(controller::VOLUME), 20);
+        assert_eq!(target.controller(controller::SUSTAIN), 0);
+        assert_eq!(target.controller(controller::EXPRESSION), 127);
+        assert_eq!(target.program(), 5);
+        assert_eq!(target.pitch_bend(), 0);
+        assert!(target.is_note_held(60));
+    }
+
+    #[test]
+    fn handle_event__where_all_notes_off_or_all_sound_off() {
+        let mut target = ChannelState::new();
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::AllNotesOff(0));
+        assert_eq!(target.held_note_count(), 0);
+
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 100));
+        target.handle_event(&MidiEvent::AllSoundOff(0));
+        assert_eq!(target.held_note_count(), 0);
+    }
+
+    #[test]
+    fn midi_state__routes_by_channel() {
+        let mut target = MidiState::new();
+
+        target.handle_event(&MidiEvent::NoteOn(3, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(4, 61, 100));
+        target.handle_event(&MidiEvent::AllNotesOff(4));
+        target.handle_event(&MidiEvent::Clock);
+
+        assert!(target.channel(3).is_note_held(60));
+        assert_eq!(target.channel(4).held_note_count(), 0);
+    }
+
+    #[test]
+    fn midi_state__where_system_reset() {
+        let mut target = MidiState::new();
+        target.handle_event(&MidiEvent::ProgramChange(9, 10));
+
+        target.handle_event(&MidiEvent::SystemReset);
+
+        assert_eq!(target, MidiState::new());
+    }
+}
@@ -0,0 +1,521 @@
+use crate::{MidiEvent, Timestamp, CLOCKS_PER_MIDI_BEAT, CLOCKS_PER_QUARTER};
+
+/// Default trigger pulse width (5ms)
+pub const DEFAULT_PULSE_WIDTH: Timestamp = 5_000;
+
+// Number of scheduled edges held by a converter
+const EDGE_CAPACITY: usize = 32;
+
+///
+/// Ratio of output pulses to incoming MIDI clocks
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ClockRatio {
+    multiply: u32,
+    divide: u32,
+}
+
+impl ClockRatio {
+    ///
+    /// Output `multiply` pulses for every `divide` MIDI clocks
+    ///
+    pub fn new(multiply: u32, divide: u32) -> Self {
+        let (multiply, divide) = (multiply.max(1), divide.max(1));
+        let divisor = gcd(multiply, divide);
+        Self {
+            multiply: multiply / divisor,
+            divide: divide / divisor,
+        }
+    }
+
+    ///
+    /// Output pulses per quarter note (eg 24 or 48 for DIN sync, 1, 2 or 4
+    /// for analogue clock)
+    ///
+    pub fn ppqn(pulses: u32) -> Self {
+        Self::new(pulses, CLOCKS_PER_QUARTER)
+    }
+
+    pub fn multiply(&self) -> u32 {
+        self.multiply
+    }
+
+    pub fn divide(&self) -> u32 {
+        self.divide
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+///
+/// Length of an output pulse
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PulseWidth {
+    Fixed(Timestamp), // Microseconds
+    Duty(f32),        // Fraction of the output period (0..1)
+}
+
+///
+/// Output line of the converter
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SyncSignal {
+    Clock,
+    Run,
+}
+
+///
+/// Level change of an output line at a point in time
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SyncEdge {
+    pub time: Timestamp,
+    pub signal: SyncSignal,
+    pub high: bool,
+}
+
+///
+/// Scheduled edges ordered by time, held in a ring buffer
+///
+#[derive(Debug, Clone)]
+struct EdgeQueue {
+    edges: [SyncEdge; EDGE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl EdgeQueue {
+    fn new() -> Self {
+        Self {
+            edges: [SyncEdge {
+                time: 0,
+                signal: SyncSignal::Clock,
+                high: false,
+            }; EDGE_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn get(&self, index: usize) -> SyncEdge {
+        self.edges[(self.head + index) % EDGE_CAPACITY]
+    }
+
+    fn set(&mut self, index: usize, edge: SyncEdge) {
+        self.edges[(self.head + index) % EDGE_CAPACITY] = edge;
+    }
+
+    fn space(&self) -> usize {
+        EDGE_CAPACITY - self.len
+    }
+
+    fn first(&self) -> Option<SyncEdge> {
+        if self.len > 0 {
+            Some(self.get(0))
+        } else {
+            None
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<SyncEdge> {
+        let edge = self.first()?;
+        self.head = (self.head + 1) % EDGE_CAPACITY;
+        self.len -= 1;
+        Some(edge)
+    }
+
+    ///
+    /// Insert after any edges at the same time; dropped if full
+    ///
+    fn insert(&mut self, edge: SyncEdge) {
+        if self.space() == 0 {
+            return;
+        }
+        let mut index = self.len;
+        while index > 0 && self.get(index - 1).time > edge.time {
+            self.set(index, self.get(index - 1));
+            index -= 1;
+        }
+        self.set(index, edge);
+        self.len += 1;
+    }
+
+    fn retain<F: Fn(&SyncEdge) -> bool>(&mut self, keep: F) {
+        let mut kept = 0;
+        for index in 0..self.len {
+            let edge = self.get(index);
+            if keep(&edge) {
+                self.set(kept, edge);
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = SyncEdge> + '_ {
+        (0..self.len).map(move |index| self.get(index))
+    }
+}
+
+///
+/// **Clock Converter**
+///
+/// Converts incoming MIDI Clock into analogue clock/run edges (eg DIN sync or
+/// Eurorack clock) with any ratio of output pulses to MIDI clocks.
+///
+/// Multiplied pulses between MIDI clocks are interpolated from the previous
+/// clock interval, so are scheduled in the future; call `poll` with the
+/// current time to collect edges as they become due. The pulse phase is
+/// aligned to the song position, so divided clocks land on the beat after
+/// Start or a Song Position Pointer; clocks output while stopped keep the
+/// phase running from there.
+///
+/// Scheduled edges are held in a fixed size queue; pulses that do not fit
+/// (only possible with very high multipliers) are dropped.
+///
+#[derive(Debug, Clone)]
+pub struct ClockConverter {
+    ratio: ClockRatio,
+    width: PulseWidth,
+    clock_while_stopped: bool,
+    running: bool,
+    clocks: u64,       // Song position in MIDI clocks of the next clock
+    phase: u64,        // Position of the next clock for pulse phase, runs while stopped
+    late: Option<u64>, // First pulse not scheduled for lack of an interval
+    last_clock: Option<Timestamp>,
+    interval: Option<Timestamp>,
+    edges: EdgeQueue,
+}
+
+impl ClockConverter {
+    pub fn new(ratio: ClockRatio) -> Self {
+        Self {
+            ratio,
+            width: PulseWidth::Fixed(DEFAULT_PULSE_WIDTH),
+            clock_while_stopped: false,
+            running: false,
+            clocks: 0,
+            phase: 0,
+            late: None,
+            last_clock: None,
+            interval: None,
+            edges: EdgeQueue::new(),
+        }
+    }
+
+    ///
+    /// DIN sync at 24 or 48 PPQN with a 50% duty cycle clock
+    ///
+    pub fn din_sync(ppqn: u32) -> Self {
+        Self::new(ClockRatio::ppqn(ppqn)).with_pulse_width(PulseWidth::Duty(0.5))
+    }
+
+    pub fn with_pulse_width(mut self, width: PulseWidth) -> Self {
+        self.width = width;
+        self
+    }
+
+    ///
+    /// Output clock pulses while stopped (the run line remains low)
+    ///
+    pub fn with_clock_while_stopped(mut self, enabled: bool) -> Self {
+        self.clock_while_stopped = enabled;
+        self
+    }
+
+    pub fn set_ratio(&mut self, ratio: ClockRatio) {
+        self.ratio = ratio;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    ///
+    /// **Handle an event received at `now`**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, now: Timestamp) {
+        match *event {
+            MidiEvent::Start => {
+                self.clocks = 0;
+                self.phase = 0;
+                self.set_running(true, now);
+            }
+            MidiEvent::Continue => {
+                self.phase = self.clocks;
+                self.set_running(true, now);
+            }
+            MidiEvent::Stop => self.set_running(false, now),
+            MidiEvent::SongPositionPointer(midi_beats) => {
+                self.clocks = midi_beats as u64 * CLOCKS_PER_MIDI_BEAT as u64;
+                self.phase = self.clocks;
+                self.late = None;
+            }
+            MidiEvent::Clock => self.handle_clock(now),
+            MidiEvent::SystemReset => {
+                self.set_running(false, now);
+                self.clocks = 0;
+                self.phase = 0;
+                self.interval = None;
+                self.last_clock = None;
+            }
+            _ => (),
+        }
+    }
+
+    ///
+    /// **Returns the next edge due at `now`**
+    ///
+    pub fn poll(&mut self, now: Timestamp) -> Option<SyncEdge> {
+        if self.edges.first()?.time <= now {
+            self.edges.pop_front()
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Time of the next scheduled edge
+    ///
+    pub fn next_edge_time(&self) -> Option<Timestamp> {
+        self.edges.first().map(|edge| edge.time)
+    }
+
+    fn set_running(&mut self, running: bool, now: Timestamp) {
+        if self.running == running {
+            return;
+        }
+        self.running = running;
+        self.late = None;
+        if !running && !self.clock_while_stopped {
+            // Drop pulses yet to start; a pulse in progress still ends
+            let next_pulse = self
+                .edges
+                .iter()
+                .find(|edge| edge.signal == SyncSignal::Clock && edge.high)
+                .map(|edge| edge.time);
+            if let Some(next_pulse) = next_pulse {
+                self.edges
+                    .retain(|edge| edge.signal != SyncSignal::Clock || edge.time < next_pulse);
+            }
+        }
+        self.schedule(now, SyncSignal::Run, running);
+    }
+
+    fn handle_clock(&mut self, now: Timestamp) {
+        if let Some(last_clock) = self.last_clock.replace(now) {
+            self.interval = Some(now.saturating_sub(last_clock));
+        }
+        if !self.running && !self.clock_while_stopped {
+            return;
+        }
+
+        // Song position of pulses in units of 1/multiply of a MIDI clock
+        let multiply = self.ratio.multiply as u64;
+        let divide = self.ratio.divide as u64;
+        let start = self.phase * multiply;
+        let end = start + multiply;
+        // Pulses missed before the interval was known are sent late
+        let mut pulse = match self.late.take() {
+            Some(late) if self.interval.is_some() => late,
+            _ => start.div_ceil(divide) * divide,
+        };
+
+        // Room is kept for a Run edge
+        while pulse < end && self.edges.space() > 2 {
+            let time = match self.interval {
+                Some(interval) if pulse < start => {
+                    now.saturating_sub(interval * (start - pulse) / multiply)
+                }
+                Some(interval) => now + interval * (pulse - start) / multiply,
+                // Cannot interpolate without an interval
+                None if pulse == start => now,
+                None => {
+                    self.late = Some(pulse);
+                    break;
+                }
+            };
+            self.schedule(time, SyncSignal::Clock, true);
+            self.schedule(time + self.pulse_width(), SyncSignal::Clock, false);
+            pulse += divide;
+        }
+        self.phase += 1;
+        if self.running {
+            self.clocks += 1;
+        }
+    }
+
+    fn pulse_width(&self) -> Timestamp {
+        let period = self
+            .interval
+            .map(|interval| interval * self.ratio.divide as u64 / self.ratio.multiply as u64);
+        match (self.width, period) {
+            (PulseWidth::Fixed(width), Some(period)) => width.min(period / 2).max(1),
+            (PulseWidth::Fixed(width), None) => width,
+            (PulseWidth::Duty(duty), Some(period)) => ((period as f32 * duty) as Timestamp).max(1),
+            (PulseWidth::Duty(_), None) => DEFAULT_PULSE_WIDTH,
+        }
+    }
+
+    fn schedule(&mut self, time: Timestamp, signal: SyncSignal, high: bool) {
+        self.edges.insert(SyncEdge { time, signal, high });
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::sync::{ClockConverter, ClockRatio, PulseWidth, SyncEdge, SyncSignal};
+    use crate::{MidiEvent, Timestamp};
+
+    const INTERVAL: Timestamp = 20_000;
+
+    fn run(target: &mut ClockConverter, clocks: u64) -> Vec<SyncEdge> {
+        let mut edges = Vec::new();
+        target.handle_event(&MidiEvent::Start, 0);
+        for index in 0..clocks {
+            let now = 1_000 + index * INTERVAL;
+            target.handle_event(&MidiEvent::Clock, now);
+            for time in now..now + INTERVAL {
+                while let Some(edge) = target.poll(time) {
+                    edges.push(edge);
+                }
+            }
+        }
+        edges
+    }
+
+    fn rising_clock_times(edges: &[SyncEdge]) -> Vec<Timestamp> {
+        edges
+            .iter()
+            .filter(|edge| edge.signal == SyncSignal::Clock && edge.high)
+            .map(|edge| edge.time)
+            .collect()
+    }
+
+    #[test]
+    fn clock_ratio__is_reduced() {
+        assert_eq!(ClockRatio::ppqn(4), ClockRatio::new(1, 6));
+        assert_eq!(ClockRatio::ppqn(48).multiply(), 2);
+    }
+
+    #[test]
+    fn handle_event__divides_clock() {
+        let mut target = ClockConverter::new(ClockRatio::ppqn(4));
+
+        let actual = run(&mut target, 13);
+
+        assert_eq!(
+            actual[0],
+            SyncEdge {
+                time: 0,
+                signal: SyncSignal::Run,
+                high: true
+            }
+        );
+        assert_eq!(rising_clock_times(&actual), vec![1_000, 121_000, 241_000]);
+    }
+
+    #[test]
+    fn handle_event__multiplies_clock() {
+        let mut target = ClockConverter::din_sync(48);
+
+        let actual = run(&mut target, 3);
+
+        // No interval to interpolate until the second clock, the pulse missed
+        // after the first clock is sent late
+        assert_eq!(
+            rising_clock_times(&actual),
+            vec![1_000, 11_000, 21_000, 31_000, 41_000, 51_000]
+        );
+        assert_eq!(
+            actual[2],
+            SyncEdge {
+                time: 6_000,
+                signal: SyncSignal::Clock,
+                high: false
+            }
+        );
+    }
+
+    #[test]
+    fn handle_event__where_stopped() {
+        let mut target =
+            ClockConverter::new(ClockRatio::ppqn(24)).with_pulse_width(PulseWidth::Fixed(1_000));
+        run(&mut target, 2);
+
+        target.handle_event(&MidiEvent::Stop, 50_000);
+        target.handle_event(&MidiEvent::Clock, 61_000);
+
+        assert_eq!(
+            target.poll(100_000),
+            Some(SyncEdge {
+                time: 50_000,
+                signal: SyncSignal::Run,
+                high: false
+            })
+        );
+        assert_eq!(target.poll(100_000), None);
+        assert!(!target.is_running());
+    }
+
+    #[test]
+    fn handle_event__phase_follows_song_position() {
+        let mut target = ClockConverter::new(ClockRatio::ppqn(1));
+        target.handle_event(&MidiEvent::SongPositionPointer(2), 0);
+        target.handle_event(&MidiEvent::Continue, 0);
+
+        let mut pulses = Vec::new();
+        for index in 0..24 {
+            let now = index * INTERVAL;
+            target.handle_event(&MidiEvent::Clock, now);
+            while let Some(edge) = target.poll(now) {
+                if edge.signal == SyncSignal::Clock && edge.high {
+                    pulses.push(index);
+                }
+            }
+        }
+
+        // Song position is 12 clocks into the beat
+        assert_eq!(pulses, vec![12]);
+    }
+
+    #[test]
+    fn handle_event__phase_runs_while_stopped() {
+        let mut target = ClockConverter::new(ClockRatio::ppqn(1)).with_clock_while_stopped(true);
+        target.handle_event(&MidiEvent::SongPositionPointer(2), 0);
+
+        let mut pulses = Vec::new();
+        for index in 0..48 {
+            let now = index * INTERVAL;
+            target.handle_event(&MidiEvent::Clock, now);
+            while let Some(edge) = target.poll(now) {
+                if edge.signal == SyncSignal::Clock && edge.high {
+                    pulses.push(index);
+                }
+            }
+        }
+
+        assert_eq!(pulses, vec![12, 36]);
+        assert!(!target.is_running());
+    }
+
+    #[test]
+    fn handle_event__many_pulses_per_clock() {
+        let mut target = ClockConverter::new(ClockRatio::new(64, 1));
+
+        let actual = run(&mut target, 3);
+
+        // Pulses beyond the edge queue are dropped, each kept pulse ends
+        let rising = rising_clock_times(&actual).len();
+        assert!(rising > 0 && rising < 128);
+        assert_eq!(actual.len(), 1 + 2 * rising);
+    }
+}
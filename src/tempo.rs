@@ -0,0 +1,276 @@
+use crate::{MidiEvent, Timestamp};
+
+/// MIDI clocks per quarter note
+pub const CLOCKS_PER_QUARTER: u32 = 24;
+
+/// Microseconds per minute
+const MINUTE: f32 = 60_000_000.0;
+
+/// Consecutive out of tolerance intervals required to accept a tempo change
+const CHANGE_COUNT: u8 = 3;
+
+///
+/// Convert a clock interval (in microseconds) into BPM
+///
+pub fn interval_to_bpm(interval: f32) -> f32 {
+    MINUTE / (interval * CLOCKS_PER_QUARTER as f32)
+}
+
+///
+/// Convert BPM into a clock interval (in microseconds)
+///
+pub fn bpm_to_interval(bpm: f32) -> f32 {
+    MINUTE / (bpm * CLOCKS_PER_QUARTER as f32)
+}
+
+///
+/// Notification from the tempo follower
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TempoEvent {
+    Locked(f32),  // First tempo estimate (BPM) after clock starts arriving
+    Changed(f32), // Tempo change detected (BPM)
+    Stopped,      // Clock is no longer arriving
+}
+
+///
+/// **Tempo Follower**
+///
+/// Estimates BPM from timestamped MIDI Clock events (24 PPQN).
+///
+/// Clock intervals are smoothed with an exponential moving average. Intervals
+/// that differ from the average by more than the tolerance are treated as
+/// jitter and ignored, unless several arrive consecutively in which case the
+/// average is reset to the new tempo.
+///
+#[derive(Debug, Clone)]
+pub struct TempoFollower {
+    smoothing: f32, // Weight of a new interval in the average (0..=1)
+    tolerance: f32, // Relative deviation treated as jitter
+    timeout: Timestamp,
+    interval: Option<f32>,
+    last_clock: Option<Timestamp>,
+    pending: f32, // Sum of out of tolerance intervals
+    pending_count: u8,
+}
+
+impl TempoFollower {
+    pub fn new() -> Self {
+        Self {
+            smoothing: 0.0625,
+            tolerance: 0.1,
+            timeout: 300_000,
+            interval: None,
+            last_clock: None,
+            pending: 0.0,
+            pending_count: 0,
+        }
+    }
+
+    ///
+    /// Set the weight of each new interval in the average (0..=1)
+    ///
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    ///
+    /// Set the relative deviation (eg 0.1 for 10%) beyond which an interval
+    /// is considered either jitter or a tempo change
+    ///
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    ///
+    /// Set the minimum time without a clock (in microseconds) before the
+    /// clock is considered stopped
+    ///
+    pub fn with_timeout(mut self, timeout: Timestamp) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    ///
+    /// Current tempo estimate in BPM
+    ///
+    pub fn bpm(&self) -> Option<f32> {
+        self.interval.map(interval_to_bpm)
+    }
+
+    ///
+    /// Current smoothed clock interval in microseconds
+    ///
+    pub fn interval(&self) -> Option<f32> {
+        self.interval
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.last_clock.is_some()
+    }
+
+    ///
+    /// **Handle an event received at `now`**
+    ///
+    /// Only Clock events are used, all others are ignored.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent, now: Timestamp) -> Option<TempoEvent> {
+        if let MidiEvent::Clock = event {
+            self.handle_clock(now)
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// **Handle a clock received at `now`**
+    ///
+    pub fn handle_clock(&mut self, now: Timestamp) -> Option<TempoEvent> {
+        let last_clock = self.last_clock.replace(now)?;
+        let sample = now.saturating_sub(last_clock) as f32;
+        if sample <= 0.0 {
+            return None;
+        }
+
+        let interval = match self.interval {
+            None => {
+                self.interval = Some(sample);
+                return Some(TempoEvent::Locked(interval_to_bpm(sample)));
+            }
+            Some(interval) => interval,
+        };
+
+        if (sample - interval).abs() <= interval * self.tolerance {
+            self.interval = Some(interval + (sample - interval) * self.smoothing);
+            self.pending = 0.0;
+            self.pending_count = 0;
+            None
+        } else {
+            self.pending += sample;
+            self.pending_count += 1;
+            if self.pending_count < CHANGE_COUNT {
+                return None;
+            }
+            let average = self.pending / self.pending_count as f32;
+            self.pending = 0.0;
+            self.pending_count = 0;
+            // Alternating early/late clocks average back to the current tempo
+            if (average - interval).abs() <= interval * self.tolerance {
+                return None;
+            }
+            self.interval = Some(average);
+            Some(TempoEvent::Changed(interval_to_bpm(average)))
+        }
+    }
+
+    ///
+    /// **Check whether the clock has stopped at `now`**
+    ///
+    /// The clock is considered stopped when nothing has arrived within the
+    /// timeout or three clock intervals, whichever is longer.
+    ///
+    pub fn poll(&mut self, now: Timestamp) -> Option<TempoEvent> {
+        let last_clock = self.last_clock?;
+        let timeout = self
+            .interval
+            .map(|interval| (interval * 3.0) as Timestamp)
+            .unwrap_or(0)
+            .max(self.timeout);
+
+        if now.saturating_sub(last_clock) > timeout {
+            self.reset();
+            Some(TempoEvent::Stopped)
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.interval = None;
+        self.last_clock = None;
+        self.pending = 0.0;
+        self.pending_count = 0;
+    }
+}
+
+impl Default for TempoFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::tempo::{bpm_to_interval, TempoEvent, TempoFollower};
+    use crate::{MidiEvent, Timestamp};
+
+    fn feed(target: &mut TempoFollower, start: Timestamp, intervals: &[Timestamp]) -> Timestamp {
+        let mut now = start;
+        for interval in intervals {
+            now += interval;
+            target.handle_event(&MidiEvent::Clock, now);
+        }
+        now
+    }
+
+    #[test]
+    fn handle_event__locks_to_first_interval() {
+        let mut target = TempoFollower::new();
+
+        assert_eq!(target.handle_event(&MidiEvent::Clock, 0), None);
+        let actual = target.handle_event(&MidiEvent::Clock, 20_833);
+
+        match actual {
+            Some(TempoEvent::Locked(bpm)) => assert!((bpm - 120.0).abs() < 0.01),
+            _ => panic!("Expected lock; got {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn handle_event__filters_jitter() {
+        let mut target = TempoFollower::new();
+        let interval = bpm_to_interval(120.0) as Timestamp;
+        let mut now = feed(&mut target, 0, &[0, interval]);
+
+        for index in 0..96 {
+            // +/- 2ms of jitter and a single late clock
+            let jitter = if index % 2 == 0 { 2_000 } else { 0 };
+            let late = if index == 50 { 8_000 } else { 0 };
+            now += interval + jitter + late - 1_000;
+            assert_eq!(target.handle_event(&MidiEvent::Clock, now), None);
+        }
+
+        assert!((target.bpm().unwrap() - 120.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn handle_event__detects_tempo_change() {
+        let mut target = TempoFollower::new();
+        let now = feed(&mut target, 0, &[0; 1]);
+        let now = feed(&mut target, now, &[bpm_to_interval(120.0) as Timestamp; 24]);
+
+        let interval = bpm_to_interval(140.0) as Timestamp;
+        target.handle_event(&MidiEvent::Clock, now + interval);
+        target.handle_event(&MidiEvent::Clock, now + interval * 2);
+        let actual = target.handle_event(&MidiEvent::Clock, now + interval * 3);
+
+        match actual {
+            Some(TempoEvent::Changed(bpm)) => assert!((bpm - 140.0).abs() < 0.1),
+            _ => panic!("Expected change; got {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn poll__where_clock_stops() {
+        let mut target = TempoFollower::new();
+        let now = feed(&mut target, 0, &[20_000; 10]);
+
+        assert_eq!(target.poll(now + 100_000), None);
+        assert_eq!(target.poll(now + 300_001), Some(TempoEvent::Stopped));
+        assert!(!target.is_running());
+        assert_eq!(target.bpm(), None);
+    }
+}
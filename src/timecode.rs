@@ -1,5 +1,5 @@
-use crate::{u3, u4, MidiEvent};
-use std::fmt::{Display, Formatter};
+use crate::{u3, u4};
+use core::fmt::{Display, Formatter};
 
 const MSN: u8 = 0b1111_0000;
 const LSN: u8 = 0b0000_1111;
@@ -7,6 +7,7 @@ const LSN: u8 = 0b0000_1111;
 ///
 /// Time-code framerate
 ///
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Rate {
     Film,
@@ -99,7 +100,7 @@ impl Default for TimeCode {
 }
 
 impl Display for TimeCode {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{:02}:{:02}:{:02}{}{:02}",
@@ -116,9 +117,9 @@ impl Display for TimeCode {
 }
 
 #[cfg(test)]
+#[allow(non_snake_case)]
 mod tests {
     use crate::timecode::{Rate, TimeCode};
-    use std::sync::mpsc::RecvTimeoutError::Timeout;
 
     #[test]
     fn test_display__where_default_is_created() {
@@ -0,0 +1,716 @@
+use crate::controller::{
+    BANK_SELECT, BANK_SELECT_LSB, DATA_DECREMENT, DATA_ENTRY, DATA_ENTRY_LSB, DATA_INCREMENT,
+    NRPN_LSB, NRPN_MSB, RPN_LSB, RPN_MSB,
+};
+use crate::parameter::{ParameterChange, ParameterKind, ParameterTracker};
+use crate::ump::midi1_event;
+use crate::{
+    pitch_bend, u7, Form, Group, Midi2Event, MidiEvent, MidiEvents, MidiReader, NoteAttribute,
+    SysEx7, UmpEvent,
+};
+
+///
+/// **Scale a value up to a higher resolution**
+///
+/// Uses the min-centre-max algorithm from the MIDI 2.0 spec; the minimum,
+/// centre and maximum values of the source map to the minimum, centre and
+/// maximum of the destination.
+///
+/// # Panics
+///
+/// Unless `1 <= from_bits <= to_bits <= 32`.
+///
+pub fn scale_up(value: u32, from_bits: u8, to_bits: u8) -> u32 {
+    assert!(
+        1 <= from_bits && from_bits <= to_bits && to_bits <= 32,
+        "invalid scale from {} to {} bits",
+        from_bits,
+        to_bits
+    );
+    let scale_bits = to_bits - from_bits;
+    let shifted = value << scale_bits;
+    let centre = 1 << (from_bits - 1);
+    if value <= centre {
+        return shifted;
+    }
+
+    // Fill the lower bits by repeating the bits below the MSB
+    let repeat_bits = from_bits - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    if scale_bits > repeat_bits {
+        repeat <<= scale_bits - repeat_bits;
+    } else {
+        repeat >>= repeat_bits - scale_bits;
+    }
+    let mut result = shifted;
+    while repeat != 0 {
+        result |= repeat;
+        repeat >>= repeat_bits;
+    }
+    result
+}
+
+///
+/// **Scale a value down to a lower resolution**
+///
+/// # Panics
+///
+/// Unless `1 <= to_bits <= from_bits <= 32`.
+///
+pub fn scale_down(value: u32, from_bits: u8, to_bits: u8) -> u32 {
+    assert!(
+        1 <= to_bits && to_bits <= from_bits && from_bits <= 32,
+        "invalid scale from {} to {} bits",
+        from_bits,
+        to_bits
+    );
+    value >> (from_bits - to_bits)
+}
+
+///
+/// MIDI 1.0 Note On with velocity 0 is sent as a Note Off with this velocity
+///
+pub const NOTE_OFF_VELOCITY: u16 = 0x8000;
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Bank {
+    msb: Option<u7>,
+    lsb: u7,
+}
+
+///
+/// **MIDI 1.0 to MIDI 2.0 Translator**
+///
+/// Translates MIDI 1.0 events into MIDI 2.0 Channel Voice, System and SysEx7
+/// packets on a single group:
+///
+/// * Values are scaled up with [`scale_up`]
+/// * RPN/NRPN Data Entry becomes Registered/Assignable Controller messages
+/// * Bank Select is held and sent with the following Program Change
+/// * System Exclusive is split into SysEx7 packets of up to 6 bytes
+///
+pub struct Midi1ToMidi2 {
+    group: Group,
+    parameters: ParameterTracker,
+    banks: [Bank; 16],
+    sysex: [u7; 6], // Pending SysEx bytes
+    sysex_len: usize,
+    sysex_started: bool, // A Start packet has been sent
+    in_sysex: bool,
+}
+
+impl Midi1ToMidi2 {
+    pub fn new(group: Group) -> Self {
+        Self {
+            group: group & 0x0F,
+            parameters: ParameterTracker::new(),
+            banks: [Bank::default(); 16],
+            sysex: [0; 6],
+            sysex_len: 0,
+            sysex_started: false,
+            in_sysex: false,
+        }
+    }
+
+    ///
+    /// **Translate an event**
+    ///
+    /// Returns None for events that are held (Bank Select, parameter
+    /// selection, buffered SysEx) or have no MIDI 2.0 equivalent.
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> Option<UmpEvent> {
+        let group = self.group;
+        let midi2 = |event| Some(UmpEvent::Midi2(group, event));
+
+        match *event {
+            MidiEvent::NoteOn(channel, note, 0) => midi2(Midi2Event::NoteOff {
+                channel,
+                note,
+                velocity: NOTE_OFF_VELOCITY,
+                attribute: NoteAttribute::None,
+            }),
+            MidiEvent::NoteOn(channel, note, velocity) => midi2(Midi2Event::NoteOn {
+                channel,
+                note,
+                velocity: scale_up(velocity as u32, 7, 16) as u16,
+                attribute: NoteAttribute::None,
+            }),
+            MidiEvent::NoteOff(channel, note, velocity) => midi2(Midi2Event::NoteOff {
+                channel,
+                note,
+                velocity: scale_up(velocity as u32, 7, 16) as u16,
+                attribute: NoteAttribute::None,
+            }),
+            MidiEvent::PolyphonicAfterTouch(channel, note, pressure) => {
+                midi2(Midi2Event::PolyPressure {
+                    channel,
+                    note,
+                    value: scale_up(pressure as u32, 7, 32),
+                })
+            }
+            MidiEvent::ControllerChange(channel, control, value) => match control {
+                BANK_SELECT => {
+                    self.banks[channel as usize & 0x0F].msb = Some(value);
+                    None
+                }
+                BANK_SELECT_LSB => {
+                    self.banks[channel as usize & 0x0F].lsb = value;
+                    None
+                }
+                DATA_ENTRY | DATA_ENTRY_LSB | DATA_INCREMENT | DATA_DECREMENT | NRPN_LSB
+                | NRPN_MSB | RPN_LSB | RPN_MSB => self
+                    .parameters
+                    .handle_event(event)
+                    .and_then(|change| midi2(parameter_event(&change))),
+                _ => midi2(Midi2Event::ControlChange {
+                    channel,
+                    index: control,
+                    value: scale_up(value as u32, 7, 32),
+                }),
+            },
+            MidiEvent::AllSoundOff(channel)
+            | MidiEvent::ResetAllControllers(channel)
+            | MidiEvent::LocalControl(channel, _)
+            | MidiEvent::AllNotesOff(channel)
+            | MidiEvent::OmniMode(channel, _)
+            | MidiEvent::MonoMode(channel, _)
+            | MidiEvent::PolyphonicMode(channel) => {
+                self.parameters.handle_event(event);
+                // Channel mode messages are Control Changes 120-127
+                let bytes = event.to_bytes();
+                midi2(Midi2Event::ControlChange {
+                    channel,
+                    index: bytes[1],
+                    value: scale_up(bytes[2] as u32, 7, 32),
+                })
+            }
+            MidiEvent::ProgramChange(channel, program) => {
+                let bank = self.banks[channel as usize & 0x0F];
+                midi2(Midi2Event::ProgramChange {
+                    channel,
+                    program,
+                    bank: bank.msb.map(|msb| (msb, bank.lsb)),
+                })
+            }
+            MidiEvent::ChannelAfterTouch(channel, pressure) => midi2(Midi2Event::ChannelPressure {
+                channel,
+                value: scale_up(pressure as u32, 7, 32),
+            }),
+            MidiEvent::PitchBend(channel, amount) => {
+                let amount = amount.clamp(pitch_bend::PITCH_BEND_MIN, pitch_bend::PITCH_BEND_MAX);
+                midi2(Midi2Event::PitchBend {
+                    channel,
+                    value: scale_up((amount - pitch_bend::PITCH_BEND_MIN) as u32, 14, 32),
+                })
+            }
+            MidiEvent::SystemExclusiveStart(_) => {
+                self.in_sysex = true;
+                self.sysex_started = false;
+                self.sysex_len = 0;
+                // The ID is the first data of the message
+                event.to_bytes()[1..]
+                    .iter()
+                    .filter_map(|byte| self.sysex_byte(*byte))
+                    .last()
+            }
+            MidiEvent::SystemExclusiveData(byte) if self.in_sysex => self.sysex_byte(byte),
+            MidiEvent::SystemExclusiveData(_) => None,
+            MidiEvent::SystemExclusiveEnd if self.in_sysex => {
+                self.in_sysex = false;
+                let form = if self.sysex_started {
+                    Form::End
+                } else {
+                    Form::Complete
+                };
+                Some(self.flush_sysex(form))
+            }
+            MidiEvent::SystemExclusiveEnd => None,
+            event => Some(UmpEvent::System(group, event)),
+        }
+    }
+
+    fn sysex_byte(&mut self, byte: u7) -> Option<UmpEvent> {
+        let mut result = None;
+        if self.sysex_len == self.sysex.len() {
+            let form = if self.sysex_started {
+                Form::Continue
+            } else {
+                Form::Start
+            };
+            result = Some(self.flush_sysex(form));
+            self.sysex_started = true;
+        }
+        self.sysex[self.sysex_len] = byte;
+        self.sysex_len += 1;
+        result
+    }
+
+    fn flush_sysex(&mut self, form: Form) -> UmpEvent {
+        let sysex = SysEx7::new(form, &self.sysex[..self.sysex_len]);
+        self.sysex_len = 0;
+        UmpEvent::SysEx7(self.group, sysex)
+    }
+}
+
+fn parameter_event(change: &ParameterChange) -> Midi2Event {
+    let channel = change.channel;
+    let bank = (change.parameter >> 7) as u7 & 0x7F;
+    let index = change.parameter as u7 & 0x7F;
+    let value = scale_up(change.value() as u32, 14, 32);
+    match change.kind {
+        ParameterKind::Registered => Midi2Event::RegisteredController {
+            channel,
+            bank,
+            index,
+            value,
+        },
+        ParameterKind::NonRegistered => Midi2Event::AssignableController {
+            channel,
+            bank,
+            index,
+            value,
+        },
+    }
+}
+
+///
+/// **MIDI 2.0 to MIDI 1.0 Translator**
+///
+/// Translates MIDI 2.0 Channel Voice, System, MIDI 1.0 Channel Voice and
+/// SysEx7 packets into MIDI 1.0 events. Values are scaled down, Registered
+/// and Assignable Controllers become RPN/NRPN sequences and a Program Change
+/// with a bank is preceded by Bank Select. A MIDI 2.0 Note On with a velocity
+/// that scales to 0 is sent with velocity 1.
+///
+/// Per-note controllers, per-note pitch bend, per-note management, relative
+/// controllers and packets of other types have no MIDI 1.0 equivalent and are
+/// dropped. Groups are not distinguished other than to keep SysEx separate.
+///
+pub struct Midi2ToMidi1 {
+    sysex: [MidiReader; 16], // SysEx reassembly per group
+}
+
+impl Default for Midi2ToMidi1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Midi2ToMidi1 {
+    pub fn new() -> Self {
+        Self {
+            sysex: [(); 16].map(|_| MidiReader::new()),
+        }
+    }
+
+    ///
+    /// **Translate an event**
+    ///
+    pub fn handle_event(&mut self, event: &UmpEvent) -> MidiEvents {
+        let mut events = MidiEvents::new();
+        match *event {
+            UmpEvent::System(_, event) | UmpEvent::Midi1(_, event) => events.push(event),
+            UmpEvent::Midi2(_, event) => translate_midi2(&event, &mut events),
+            UmpEvent::SysEx7(group, sysex) => {
+                let reader = &mut self.sysex[group as usize & 0x0F];
+                let start: &[u8] = match sysex.form {
+                    Form::Complete | Form::Start => &[0xF0],
+                    Form::Continue | Form::End => &[],
+                };
+                let end: &[u8] = match sysex.form {
+                    Form::Complete | Form::End => &[0xF7],
+                    Form::Start | Form::Continue => &[],
+                };
+                start
+                    .iter()
+                    .chain(sysex.data())
+                    .chain(end)
+                    .filter_map(|byte| reader.handle_byte(*byte))
+                    .for_each(|event| events.push(event));
+            }
+            _ => {}
+        }
+        events
+    }
+}
+
+fn translate_midi2(event: &Midi2Event, events: &mut MidiEvents) {
+    let velocity = |velocity: u16| scale_down(velocity as u32, 16, 7) as u7;
+    let value = |value: u32| scale_down(value, 32, 7) as u7;
+
+    match *event {
+        Midi2Event::NoteOn {
+            channel,
+            note,
+            velocity: note_velocity,
+            ..
+        } => events.push(MidiEvent::NoteOn(
+            channel,
+            note,
+            velocity(note_velocity).max(1),
+        )),
+        Midi2Event::NoteOff {
+            channel,
+            note,
+            velocity: note_velocity,
+            ..
+        } => events.push(MidiEvent::NoteOff(channel, note, velocity(note_velocity))),
+        Midi2Event::PolyPressure {
+            channel,
+            note,
+            value: pressure,
+        } => events.push(MidiEvent::PolyphonicAfterTouch(
+            channel,
+            note,
+            value(pressure),
+        )),
+        Midi2Event::ControlChange {
+            channel,
+            index,
+            value: control_value,
+        } => {
+            let status = 0xB0 | (channel & 0x0F);
+            let word = u32::from_be_bytes([0, status, index & 0x7F, value(control_value)]);
+            // Decode Control Changes 120-127 as channel mode messages
+            if let Some(event) = midi1_event(word) {
+                events.push(event);
+            }
+        }
+        Midi2Event::RegisteredController {
+            channel,
+            bank,
+            index,
+            value,
+        }
+        | Midi2Event::AssignableController {
+            channel,
+            bank,
+            index,
+            value,
+        } => {
+            let kind = match event {
+                Midi2Event::RegisteredController { .. } => ParameterKind::Registered,
+                _ => ParameterKind::NonRegistered,
+            };
+            let value = scale_down(value, 32, 14);
+            let change = ParameterChange {
+                channel,
+                kind,
+                parameter: ((bank as u16 & 0x7F) << 7) | (index as u16 & 0x7F),
+                msb: (value >> 7) as u7,
+                lsb: value as u7 & 0x7F,
+            };
+            change
+                .to_events()
+                .iter()
+                .for_each(|event| events.push(*event));
+        }
+        Midi2Event::ProgramChange {
+            channel,
+            program,
+            bank,
+        } => {
+            if let Some((msb, lsb)) = bank {
+                events.push(MidiEvent::ControllerChange(channel, BANK_SELECT, msb));
+                events.push(MidiEvent::ControllerChange(channel, BANK_SELECT_LSB, lsb));
+            }
+            events.push(MidiEvent::ProgramChange(channel, program));
+        }
+        Midi2Event::ChannelPressure {
+            channel,
+            value: pressure,
+        } => events.push(MidiEvent::ChannelAfterTouch(channel, value(pressure))),
+        Midi2Event::PitchBend { channel, value } => {
+            let amount = scale_down(value, 32, 14) as i16 + pitch_bend::PITCH_BEND_MIN;
+            events.push(MidiEvent::PitchBend(channel, amount));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::translate::{scale_down, scale_up, Midi1ToMidi2, Midi2ToMidi1};
+    use crate::{Form, Midi2Event, MidiEvent, NoteAttribute, SysEx7, SysExID, UmpEvent, UmpPacket};
+    use parameterized::parameterized;
+
+    #[parameterized(
+        value = {0, 64, 127, 0, 0x2000, 0x3FFF, 1, 100},
+        from_bits = {7, 7, 7, 14, 14, 14, 7, 7},
+        to_bits = {16, 16, 16, 32, 32, 32, 32, 32},
+        expected = {0, 0x8000, 0xFFFF, 0, 0x8000_0000, 0xFFFF_FFFF, 0x0200_0000, 0xC924_9249}
+    )]
+    fn scale_up__min_centre_max(value: u32, from_bits: u8, to_bits: u8, expected: u32) {
+        assert_eq!(scale_up(value, from_bits, to_bits), expected);
+    }
+
+    #[test]
+    fn scale_down__round_trips() {
+        for value in 0..128 {
+            assert_eq!(scale_down(scale_up(value, 7, 32), 32, 7), value);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid scale")]
+    fn scale_up__where_narrowing() {
+        scale_up(1, 16, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid scale")]
+    fn scale_down__where_to_zero_bits() {
+        scale_down(1, 32, 0);
+    }
+
+    #[test]
+    fn midi1_to_midi2__note_on_velocity_zero() {
+        let mut target = Midi1ToMidi2::new(1);
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(2, 60, 0));
+
+        assert_eq!(
+            actual,
+            Some(UmpEvent::Midi2(
+                1,
+                Midi2Event::NoteOff {
+                    channel: 2,
+                    note: 60,
+                    velocity: 0x8000,
+                    attribute: NoteAttribute::None
+                }
+            ))
+        );
+    }
+
+    #[parameterized(
+        amount = {i16::MIN, -0x2001, 0x2000, 0x7000, i16::MAX},
+        expected = {0, 0, 0xFFFF_FFFF, 0xFFFF_FFFF, 0xFFFF_FFFF}
+    )]
+    fn midi1_to_midi2__pitch_bend_out_of_range_is_clamped(amount: i16, expected: u32) {
+        let mut target = Midi1ToMidi2::new(0);
+
+        let actual = target.handle_event(&MidiEvent::PitchBend(3, amount));
+
+        assert_eq!(
+            actual,
+            Some(UmpEvent::Midi2(
+                0,
+                Midi2Event::PitchBend {
+                    channel: 3,
+                    value: expected
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn midi1_to_midi2__registered_parameter() {
+        let mut target = Midi1ToMidi2::new(0);
+
+        let actual: Vec<UmpEvent> = [
+            MidiEvent::ControllerChange(0, 101, 0),
+            MidiEvent::ControllerChange(0, 100, 0),
+            MidiEvent::ControllerChange(0, 6, 0x40),
+        ]
+        .iter()
+        .filter_map(|event| target.handle_event(event))
+        .collect();
+
+        assert_eq!(
+            actual,
+            vec![UmpEvent::Midi2(
+                0,
+                Midi2Event::RegisteredController {
+                    channel: 0,
+                    bank: 0,
+                    index: 0,
+                    value: 0x8000_0000
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn midi1_to_midi2__folds_bank_into_program_change() {
+        let mut target = Midi1ToMidi2::new(0);
+
+        assert_eq!(
+            target.handle_event(&MidiEvent::ControllerChange(3, 0, 1)),
+            None
+        );
+        assert_eq!(
+            target.handle_event(&MidiEvent::ControllerChange(3, 32, 2)),
+            None
+        );
+        let actual = target.handle_event(&MidiEvent::ProgramChange(3, 10));
+
+        assert_eq!(
+            actual,
+            Some(UmpEvent::Midi2(
+                0,
+                Midi2Event::ProgramChange {
+                    channel: 3,
+                    program: 10,
+                    bank: Some((1, 2))
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn midi1_to_midi2__packetises_sysex() {
+        let mut target = Midi1ToMidi2::new(0);
+        let mut events = vec![MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7E))];
+        events.extend((1..=8).map(MidiEvent::SystemExclusiveData));
+        events.push(MidiEvent::Clock);
+        events.push(MidiEvent::SystemExclusiveEnd);
+
+        let actual: Vec<UmpEvent> = events
+            .iter()
+            .filter_map(|event| target.handle_event(event))
+            .collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                UmpEvent::SysEx7(0, SysEx7::new(Form::Start, &[0x7E, 1, 2, 3, 4, 5])),
+                UmpEvent::System(0, MidiEvent::Clock),
+                UmpEvent::SysEx7(0, SysEx7::new(Form::End, &[6, 7, 8])),
+            ]
+        );
+    }
+
+    #[parameterized(
+        event = {
+            MidiEvent::NoteOn(0, 60, 100),
+            MidiEvent::NoteOff(1, 60, 64),
+            MidiEvent::PolyphonicAfterTouch(2, 60, 127),
+            MidiEvent::ControllerChange(3, 7, 99),
+            MidiEvent::AllNotesOff(4),
+            MidiEvent::MonoMode(5, 2),
+            MidiEvent::ProgramChange(6, 1),
+            MidiEvent::ChannelAfterTouch(7, 1),
+            MidiEvent::PitchBend(8, -8192),
+            MidiEvent::PitchBend(8, 0),
+            MidiEvent::PitchBend(8, 8191),
+            MidiEvent::SongPositionPointer(100),
+        }
+    )]
+    fn round_trip(event: MidiEvent) {
+        let mut up = Midi1ToMidi2::new(0);
+        let mut down = Midi2ToMidi1::new();
+
+        let midi2 = up.handle_event(&event).unwrap();
+        let actual = down.handle_event(&midi2);
+
+        assert_eq!(&*actual, &[event]);
+    }
+
+    #[test]
+    fn midi2_to_midi1__registered_controller() {
+        let mut target = Midi2ToMidi1::new();
+
+        let actual = target.handle_event(&UmpEvent::Midi2(
+            0,
+            Midi2Event::RegisteredController {
+                channel: 1,
+                bank: 0,
+                index: 0,
+                value: 0x0C00_0000,
+            },
+        ));
+
+        assert_eq!(
+            &actual[..4],
+            &[
+                MidiEvent::ControllerChange(1, 101, 0),
+                MidiEvent::ControllerChange(1, 100, 0),
+                MidiEvent::ControllerChange(1, 6, 6),
+                MidiEvent::ControllerChange(1, 38, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn midi2_to_midi1__bank_and_program() {
+        let mut target = Midi2ToMidi1::new();
+
+        let actual = target.handle_event(&UmpEvent::Midi2(
+            0,
+            Midi2Event::ProgramChange {
+                channel: 0,
+                program: 5,
+                bank: Some((1, 2)),
+            },
+        ));
+
+        assert_eq!(
+            &*actual,
+            &[
+                MidiEvent::ControllerChange(0, 0, 1),
+                MidiEvent::ControllerChange(0, 32, 2),
+                MidiEvent::ProgramChange(0, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn midi2_to_midi1__note_on_minimum_velocity() {
+        let mut target = Midi2ToMidi1::new();
+
+        let actual = target.handle_event(&UmpEvent::Midi2(
+            0,
+            Midi2Event::NoteOn {
+                channel: 0,
+                note: 60,
+                velocity: 0x0100,
+                attribute: NoteAttribute::None,
+            },
+        ));
+
+        assert_eq!(&*actual, &[MidiEvent::NoteOn(0, 60, 1)]);
+    }
+
+    #[test]
+    fn midi2_to_midi1__reassembles_sysex() {
+        let mut target = Midi2ToMidi1::new();
+
+        let mut actual = Vec::new();
+        actual.extend_from_slice(&target.handle_event(&UmpEvent::SysEx7(
+            0,
+            SysEx7::new(Form::Start, &[0x00, 0x20, 0x29, 1]),
+        )));
+        actual.extend_from_slice(
+            &target.handle_event(&UmpEvent::SysEx7(0, SysEx7::new(Form::End, &[2]))),
+        );
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::SystemExclusiveStart(SysExID::Word(0x20 << 7 | 0x29)),
+                MidiEvent::SystemExclusiveData(1),
+                MidiEvent::SystemExclusiveData(2),
+                MidiEvent::SystemExclusiveEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn to_packet__round_trips() {
+        let event = Midi2Event::ProgramChange {
+            channel: 9,
+            program: 5,
+            bank: Some((1, 2)),
+        };
+
+        let actual = UmpEvent::decode(&event.to_packet(4));
+
+        assert_eq!(actual, UmpEvent::Midi2(4, event));
+        assert_eq!(
+            UmpEvent::decode(&UmpPacket::from_midi1(2, &MidiEvent::NoteOn(1, 2, 3)).unwrap()),
+            UmpEvent::Midi1(2, MidiEvent::NoteOn(1, 2, 3))
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{u14, MidiEvent, CLOCKS_PER_QUARTER};
+use core::fmt::{Display, Formatter};
+
+/// MIDI clocks per MIDI beat (a sixteenth note); the unit of Song Position Pointer
+pub const CLOCKS_PER_MIDI_BEAT: u32 = 6;
+
+///
+/// Time signature
+///
+/// The denominator is the note value of a beat (eg 4 for quarter notes) and
+/// is rounded up to a power of two no greater than 32.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TimeSignature {
+    numerator: u8,
+    denominator: u8,
+}
+
+impl TimeSignature {
+    pub fn new(numerator: u8, denominator: u8) -> Self {
+        Self {
+            numerator: numerator.max(1),
+            denominator: denominator.clamp(1, 32).next_power_of_two(),
+        }
+    }
+
+    pub fn numerator(&self) -> u8 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> u8 {
+        self.denominator
+    }
+
+    ///
+    /// MIDI clocks in one beat of this time signature
+    ///
+    pub fn clocks_per_beat(&self) -> u32 {
+        CLOCKS_PER_QUARTER * 4 / self.denominator as u32
+    }
+
+    ///
+    /// MIDI clocks in one bar of this time signature
+    ///
+    pub fn clocks_per_bar(&self) -> u32 {
+        self.clocks_per_beat() * self.numerator as u32
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::new(4, 4)
+    }
+}
+
+///
+/// Musical position expressed as bars and beats
+///
+/// Bars and beats are numbered from 1 as they are displayed to musicians,
+/// `clock` is the number of clocks into the beat.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BarsBeats {
+    pub bar: u32,
+    pub beat: u32,
+    pub clock: u32,
+}
+
+impl Display for BarsBeats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{:02}", self.bar, self.beat, self.clock)
+    }
+}
+
+///
+/// Song position in MIDI clocks from the start of the song
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Position {
+    clocks: u32,
+}
+
+impl Position {
+    pub fn from_clocks(clocks: u32) -> Self {
+        Self { clocks }
+    }
+
+    ///
+    /// Position from a Song Position Pointer value
+    ///
+    pub fn from_midi_beats(midi_beats: u14) -> Self {
+        Self::from_clocks(midi_beats as u32 * CLOCKS_PER_MIDI_BEAT)
+    }
+
+    pub fn clocks(&self) -> u32 {
+        self.clocks
+    }
+
+    ///
+    /// Whole MIDI beats (sixteenth notes); the Song Position Pointer value
+    ///
+    pub fn midi_beats(&self) -> u32 {
+        self.clocks / CLOCKS_PER_MIDI_BEAT
+    }
+
+    ///
+    /// Whole sixteenth notes; a MIDI beat is a sixteenth note
+    ///
+    pub fn sixteenths(&self) -> u32 {
+        self.midi_beats()
+    }
+
+    pub fn quarter_notes(&self) -> u32 {
+        self.clocks / CLOCKS_PER_QUARTER
+    }
+
+    ///
+    /// Position as bars and beats for the given time signature
+    ///
+    pub fn bars_beats(&self, time_signature: TimeSignature) -> BarsBeats {
+        let clocks_per_bar = time_signature.clocks_per_bar();
+        let clocks_per_beat = time_signature.clocks_per_beat();
+        let in_bar = self.clocks % clocks_per_bar;
+
+        BarsBeats {
+            bar: self.clocks / clocks_per_bar + 1,
+            beat: in_bar / clocks_per_beat + 1,
+            clock: in_bar % clocks_per_beat,
+        }
+    }
+}
+
+///
+/// Notification from the transport
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TransportEvent {
+    Started,
+    Stopped,
+    Continued,
+    Located(Position), // Song Position Pointer received
+    Tick(Position),    // Clock received while running; position being played
+}
+
+///
+/// **Transport**
+///
+/// Clock slave transport model built from Start, Stop, Continue, Song
+/// Position Pointer and Clock.
+///
+/// As described by the spec, playback starts on the first Clock after Start
+/// or Continue; that clock plays the current position and every subsequent
+/// clock advances it.
+///
+#[derive(Debug, Clone)]
+pub struct Transport {
+    running: bool,
+    position: Position,
+    time_signature: TimeSignature,
+    awaiting_first_clock: bool,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            running: false,
+            position: Position::default(),
+            time_signature: TimeSignature::default(),
+            awaiting_first_clock: true,
+        }
+    }
+
+    pub fn with_time_signature(mut self, time_signature: TimeSignature) -> Self {
+        self.time_signature = time_signature;
+        self
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn bars_beats(&self) -> BarsBeats {
+        self.position.bars_beats(self.time_signature)
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> Option<TransportEvent> {
+        match *event {
+            MidiEvent::Start => {
+                self.running = true;
+                self.position = Position::default();
+                self.awaiting_first_clock = true;
+                Some(TransportEvent::Started)
+            }
+            MidiEvent::Continue => {
+                self.running = true;
+                Some(TransportEvent::Continued)
+            }
+            MidiEvent::Stop => {
+                self.running = false;
+                Some(TransportEvent::Stopped)
+            }
+            MidiEvent::SongPositionPointer(midi_beats) => {
+                self.position = Position::from_midi_beats(midi_beats);
+                self.awaiting_first_clock = true;
+                Some(TransportEvent::Located(self.position))
+            }
+            MidiEvent::Clock if self.running => {
+                if self.awaiting_first_clock {
+                    self.awaiting_first_clock = false;
+                } else {
+                    self.position.clocks += 1;
+                }
+                Some(TransportEvent::Tick(self.position))
+            }
+            MidiEvent::SystemReset => {
+                *self = Self::new().with_time_signature(self.time_signature);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::transport::{BarsBeats, Position, TimeSignature, Transport, TransportEvent};
+    use crate::MidiEvent;
+
+    #[test]
+    fn time_signature__new_limits_values() {
+        let actual = TimeSignature::new(0, 0);
+
+        assert_eq!((actual.numerator(), actual.denominator()), (1, 1));
+        assert_eq!(TimeSignature::new(7, 6).denominator(), 8);
+        assert_eq!(TimeSignature::new(7, 64).denominator(), 32);
+    }
+
+    #[test]
+    fn handle_event__where_started() {
+        let mut target = Transport::new();
+
+        assert_eq!(target.handle_event(&MidiEvent::Clock), None);
+        assert_eq!(
+            target.handle_event(&MidiEvent::Start),
+            Some(TransportEvent::Started)
+        );
+        assert_eq!(
+            target.handle_event(&MidiEvent::Clock),
+            Some(TransportEvent::Tick(Position::from_clocks(0)))
+        );
+        assert_eq!(
+            target.handle_event(&MidiEvent::Clock),
+            Some(TransportEvent::Tick(Position::from_clocks(1)))
+        );
+    }
+
+    #[test]
+    fn handle_event__where_stopped_and_continued() {
+        let mut target = Transport::new();
+        target.handle_event(&MidiEvent::Start);
+        for _ in 0..10 {
+            target.handle_event(&MidiEvent::Clock);
+        }
+
+        target.handle_event(&MidiEvent::Stop);
+        assert_eq!(target.handle_event(&MidiEvent::Clock), None);
+        assert!(!target.is_running());
+
+        target.handle_event(&MidiEvent::Continue);
+        assert_eq!(
+            target.handle_event(&MidiEvent::Clock),
+            Some(TransportEvent::Tick(Position::from_clocks(10)))
+        );
+    }
+
+    #[test]
+    fn handle_event__where_song_position_pointer() {
+        let mut target = Transport::new();
+        target.handle_event(&MidiEvent::Stop);
+
+        target.handle_event(&MidiEvent::SongPositionPointer(16));
+        target.handle_event(&MidiEvent::Continue);
+
+        assert_eq!(
+            target.handle_event(&MidiEvent::Clock),
+            Some(TransportEvent::Tick(Position::from_clocks(96)))
+        );
+        assert_eq!(
+            target.bars_beats(),
+            BarsBeats {
+                bar: 2,
+                beat: 1,
+                clock: 0
+            }
+        );
+    }
+
+    #[test]
+    fn position__units() {
+        let target = Position::from_clocks(24 * 7 + 13);
+
+        assert_eq!(target.midi_beats(), 30);
+        assert_eq!(target.sixteenths(), 30);
+        assert_eq!(target.quarter_notes(), 7);
+        assert_eq!(
+            format!("{}", target.bars_beats(TimeSignature::default())),
+            "2.4.13"
+        );
+        assert_eq!(
+            format!("{}", target.bars_beats(TimeSignature::new(6, 8))),
+            "3.4.01"
+        );
+    }
+}
@@ -0,0 +1,1415 @@
+use crate::{u4, u7, Channel, MidiEvent, MidiReader};
+use core::ops::Deref;
+
+///
+/// UMP Group (0-15)
+///
+pub type Group = u4;
+
+// Message Types
+const MT_UTILITY: u8 = 0x0;
+const MT_SYSTEM: u8 = 0x1;
+const MT_MIDI1_CHANNEL_VOICE: u8 = 0x2;
+const MT_DATA_64: u8 = 0x3; // SysEx7
+const MT_MIDI2_CHANNEL_VOICE: u8 = 0x4;
+const MT_DATA_128: u8 = 0x5; // SysEx8 and Mixed Data Set
+const MT_STREAM: u8 = 0xF;
+
+// Utility
+const UTILITY_NOOP: u32 = 0x0;
+const UTILITY_JR_CLOCK: u32 = 0x1;
+const UTILITY_JR_TIMESTAMP: u32 = 0x2;
+const UTILITY_DELTA_CLOCKSTAMP_TPQ: u32 = 0x3; // Delta Clockstamp Ticks Per Quarter Note
+const UTILITY_DELTA_CLOCKSTAMP: u32 = 0x4;
+
+// MIDI 2.0 Channel Voice
+const MIDI2_REGISTERED_PER_NOTE_CONTROLLER: u8 = 0x0;
+const MIDI2_ASSIGNABLE_PER_NOTE_CONTROLLER: u8 = 0x1;
+const MIDI2_REGISTERED_CONTROLLER: u8 = 0x2; // RPN
+const MIDI2_ASSIGNABLE_CONTROLLER: u8 = 0x3; // NRPN
+const MIDI2_RELATIVE_REGISTERED_CONTROLLER: u8 = 0x4;
+const MIDI2_RELATIVE_ASSIGNABLE_CONTROLLER: u8 = 0x5;
+const MIDI2_PER_NOTE_PITCH_BEND: u8 = 0x6;
+const MIDI2_NOTE_OFF: u8 = 0x8;
+const MIDI2_NOTE_ON: u8 = 0x9;
+const MIDI2_POLY_PRESSURE: u8 = 0xA;
+const MIDI2_CONTROL_CHANGE: u8 = 0xB;
+const MIDI2_PROGRAM_CHANGE: u8 = 0xC;
+const MIDI2_CHANNEL_PRESSURE: u8 = 0xD;
+const MIDI2_PITCH_BEND: u8 = 0xE;
+const MIDI2_PER_NOTE_MANAGEMENT: u8 = 0xF;
+
+// UMP Stream
+const STREAM_ENDPOINT_DISCOVERY: u32 = 0x00;
+const STREAM_ENDPOINT_INFO: u32 = 0x01;
+const STREAM_DEVICE_IDENTITY: u32 = 0x02;
+const STREAM_ENDPOINT_NAME: u32 = 0x03;
+const STREAM_PRODUCT_INSTANCE_ID: u32 = 0x04;
+const STREAM_CONFIGURATION_REQUEST: u32 = 0x05;
+const STREAM_CONFIGURATION_NOTIFICATION: u32 = 0x06;
+const STREAM_FUNCTION_BLOCK_DISCOVERY: u32 = 0x10;
+const STREAM_FUNCTION_BLOCK_INFO: u32 = 0x11;
+const STREAM_FUNCTION_BLOCK_NAME: u32 = 0x12;
+const STREAM_START_OF_CLIP: u32 = 0x20;
+const STREAM_END_OF_CLIP: u32 = 0x21;
+
+///
+/// **Number of 32-bit words in a packet of the given message type**
+///
+pub fn packet_words(message_type: u4) -> usize {
+    match message_type & 0x0F {
+        0x0..=0x2 | 0x6 | 0x7 => 1,
+        0x3 | 0x4 | 0x8..=0xA => 2,
+        0xB | 0xC => 3,
+        _ => 4,
+    }
+}
+
+///
+/// **Universal MIDI Packet**
+///
+/// One to four 32-bit words.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UmpPacket {
+    words: [u32; 4],
+    len: usize,
+}
+
+impl UmpPacket {
+    ///
+    /// Packet from words; None if the length does not match the message type
+    ///
+    pub fn new(words: &[u32]) -> Option<Self> {
+        let len = packet_words((*words.first()? >> 28) as u4);
+        if words.len() != len {
+            return None;
+        }
+        let mut packet = Self { words: [0; 4], len };
+        packet.words[..len].copy_from_slice(words);
+        Some(packet)
+    }
+
+    ///
+    /// **Packet for a MIDI 1.0 event**
+    ///
+    /// System Common/Realtime and MIDI 1.0 Channel Voice messages fit in a
+    /// single 32-bit packet; returns None for System Exclusive events which
+    /// are carried by [`SysEx7`] packets.
+    ///
+    pub fn from_midi1(group: Group, event: &MidiEvent) -> Option<Self> {
+        let bytes = event.to_bytes();
+        let message_type = match bytes[0] {
+            0x80..=0xEF => MT_MIDI1_CHANNEL_VOICE,
+            0xF0 | 0xF7 => return None,
+            0xF1..=0xFF => MT_SYSTEM,
+            _ => return None,
+        };
+        let mut word = [0; 4];
+        word[0] = (message_type << 4) | (group & 0x0F);
+        word[1..=bytes.len()].copy_from_slice(&bytes);
+        Self::new(&[u32::from_be_bytes(word)])
+    }
+
+    fn words_2(word_1: u32, word_2: u32) -> Self {
+        Self {
+            words: [word_1, word_2, 0, 0],
+            len: 2,
+        }
+    }
+
+    pub fn message_type(&self) -> u4 {
+        (self.words[0] >> 28) as u4
+    }
+
+    ///
+    /// Group of the packet (not used by Utility and Stream messages)
+    ///
+    pub fn group(&self) -> Group {
+        (self.words[0] >> 24) as Group & 0x0F
+    }
+}
+
+///
+/// Utility NOOP packet, allows packets to be held in a [`crate::List`]
+///
+impl Default for UmpPacket {
+    fn default() -> Self {
+        Utility::NoOp.to_packet()
+    }
+}
+
+impl Deref for UmpPacket {
+    type Target = [u32];
+
+    fn deref(&self) -> &[u32] {
+        &self.words[..self.len]
+    }
+}
+
+///
+/// Utility messages
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Utility {
+    NoOp,
+    JrClock(u16),            // Sender clock time (1/31250 seconds)
+    JrTimestamp(u16),        // Sender clock time (1/31250 seconds)
+    DeltaClockstampTpq(u16), // Ticks per quarter note
+    DeltaClockstamp(u32),    // Ticks since last event (20 bits)
+}
+
+impl Utility {
+    ///
+    /// **Encode as a Utility packet**
+    ///
+    pub fn to_packet(&self) -> UmpPacket {
+        let (status, data) = match *self {
+            Utility::NoOp => (UTILITY_NOOP, 0),
+            Utility::JrClock(time) => (UTILITY_JR_CLOCK, time as u32),
+            Utility::JrTimestamp(time) => (UTILITY_JR_TIMESTAMP, time as u32),
+            Utility::DeltaClockstampTpq(ticks) => (UTILITY_DELTA_CLOCKSTAMP_TPQ, ticks as u32),
+            Utility::DeltaClockstamp(ticks) => (UTILITY_DELTA_CLOCKSTAMP, ticks & 0x000F_FFFF),
+        };
+        UmpPacket {
+            words: [((MT_UTILITY as u32) << 28) | (status << 20) | data, 0, 0, 0],
+            len: 1,
+        }
+    }
+}
+
+///
+/// **Position of a packet in a multi-packet message**
+///
+/// Used by System Exclusive data and Stream text messages.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Form {
+    Complete,
+    Start,
+    Continue,
+    End,
+}
+
+impl Form {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(Form::Complete),
+            1 => Some(Form::Start),
+            2 => Some(Form::Continue),
+            3 => Some(Form::End),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self as u32
+    }
+}
+
+///
+/// Attribute of a MIDI 2.0 Note On/Off
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NoteAttribute {
+    None,
+    ManufacturerSpecific(u16),
+    ProfileSpecific(u16),
+    Pitch(u16), // Pitch 7.9 (7 bits semitone, 9 bits fraction)
+    Unknown(u8, u16),
+}
+
+impl NoteAttribute {
+    fn new(kind: u8, data: u16) -> Self {
+        match kind {
+            0x00 => NoteAttribute::None,
+            0x01 => NoteAttribute::ManufacturerSpecific(data),
+            0x02 => NoteAttribute::ProfileSpecific(data),
+            0x03 => NoteAttribute::Pitch(data),
+            kind => NoteAttribute::Unknown(kind, data),
+        }
+    }
+
+    fn parts(&self) -> (u8, u16) {
+        match *self {
+            NoteAttribute::None => (0x00, 0),
+            NoteAttribute::ManufacturerSpecific(data) => (0x01, data),
+            NoteAttribute::ProfileSpecific(data) => (0x02, data),
+            NoteAttribute::Pitch(data) => (0x03, data),
+            NoteAttribute::Unknown(kind, data) => (kind, data),
+        }
+    }
+}
+
+///
+/// **MIDI 2.0 Channel Voice messages**
+///
+/// Velocities are 16 bit, controllers, pressure and pitch bend are 32 bit
+/// (pitch bend is centred on 0x8000_0000).
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Midi2Event {
+    NoteOff {
+        channel: Channel,
+        note: u7,
+        velocity: u16,
+        attribute: NoteAttribute,
+    },
+    NoteOn {
+        channel: Channel,
+        note: u7,
+        velocity: u16,
+        attribute: NoteAttribute,
+    },
+    PolyPressure {
+        channel: Channel,
+        note: u7,
+        value: u32,
+    },
+    RegisteredPerNoteController {
+        channel: Channel,
+        note: u7,
+        index: u8,
+        value: u32,
+    },
+    AssignablePerNoteController {
+        channel: Channel,
+        note: u7,
+        index: u8,
+        value: u32,
+    },
+    PerNoteManagement {
+        channel: Channel,
+        note: u7,
+        detach: bool, // Detach per-note controllers from previously received notes
+        reset: bool,  // Reset per-note controllers to default values
+    },
+    ControlChange {
+        channel: Channel,
+        index: u7,
+        value: u32,
+    },
+    RegisteredController {
+        channel: Channel,
+        bank: u7,
+        index: u7,
+        value: u32,
+    },
+    AssignableController {
+        channel: Channel,
+        bank: u7,
+        index: u7,
+        value: u32,
+    },
+    RelativeRegisteredController {
+        channel: Channel,
+        bank: u7,
+        index: u7,
+        value: i32,
+    },
+    RelativeAssignableController {
+        channel: Channel,
+        bank: u7,
+        index: u7,
+        value: i32,
+    },
+    ProgramChange {
+        channel: Channel,
+        program: u7,
+        bank: Option<(u7, u7)>, // Bank MSB, LSB when the bank valid flag is set
+    },
+    ChannelPressure {
+        channel: Channel,
+        value: u32,
+    },
+    PitchBend {
+        channel: Channel,
+        value: u32,
+    },
+    PerNotePitchBend {
+        channel: Channel,
+        note: u7,
+        value: u32,
+    },
+}
+
+impl Midi2Event {
+    ///
+    /// **Encode as a MIDI 2.0 Channel Voice packet**
+    ///
+    pub fn to_packet(&self, group: Group) -> UmpPacket {
+        let (opcode, byte_3, byte_4, value) = match *self {
+            Midi2Event::NoteOff {
+                note,
+                velocity,
+                attribute,
+                ..
+            } => {
+                let (kind, data) = attribute.parts();
+                let value = ((velocity as u32) << 16) | data as u32;
+                (MIDI2_NOTE_OFF, note, kind, value)
+            }
+            Midi2Event::NoteOn {
+                note,
+                velocity,
+                attribute,
+                ..
+            } => {
+                let (kind, data) = attribute.parts();
+                let value = ((velocity as u32) << 16) | data as u32;
+                (MIDI2_NOTE_ON, note, kind, value)
+            }
+            Midi2Event::PolyPressure { note, value, .. } => (MIDI2_POLY_PRESSURE, note, 0, value),
+            Midi2Event::RegisteredPerNoteController {
+                note, index, value, ..
+            } => (MIDI2_REGISTERED_PER_NOTE_CONTROLLER, note, index, value),
+            Midi2Event::AssignablePerNoteController {
+                note, index, value, ..
+            } => (MIDI2_ASSIGNABLE_PER_NOTE_CONTROLLER, note, index, value),
+            Midi2Event::PerNoteManagement {
+                note,
+                detach,
+                reset,
+                ..
+            } => {
+                let flags = ((detach as u8) << 1) | reset as u8;
+                (MIDI2_PER_NOTE_MANAGEMENT, note, flags, 0)
+            }
+            Midi2Event::ControlChange { index, value, .. } => {
+                (MIDI2_CONTROL_CHANGE, index, 0, value)
+            }
+            Midi2Event::RegisteredController {
+                bank, index, value, ..
+            } => (MIDI2_REGISTERED_CONTROLLER, bank, index, value),
+            Midi2Event::AssignableController {
+                bank, index, value, ..
+            } => (MIDI2_ASSIGNABLE_CONTROLLER, bank, index, value),
+            Midi2Event::RelativeRegisteredController {
+                bank, index, value, ..
+            } => (
+                MIDI2_RELATIVE_REGISTERED_CONTROLLER,
+                bank,
+                index,
+                value as u32,
+            ),
+            Midi2Event::RelativeAssignableController {
+                bank, index, value, ..
+            } => (
+                MIDI2_RELATIVE_ASSIGNABLE_CONTROLLER,
+                bank,
+                index,
+                value as u32,
+            ),
+            Midi2Event::ProgramChange { program, bank, .. } => {
+                let (flags, msb, lsb) = match bank {
+                    Some((msb, lsb)) => (0x01, msb, lsb),
+                    None => (0x00, 0, 0),
+                };
+                let value = ((program as u32 & 0x7F) << 24)
+                    | ((msb as u32 & 0x7F) << 8)
+                    | (lsb as u32 & 0x7F);
+                (MIDI2_PROGRAM_CHANGE, 0, flags, value)
+            }
+            Midi2Event::ChannelPressure { value, .. } => (MIDI2_CHANNEL_PRESSURE, 0, 0, value),
+            Midi2Event::PitchBend { value, .. } => (MIDI2_PITCH_BEND, 0, 0, value),
+            Midi2Event::PerNotePitchBend { note, value, .. } => {
+                (MIDI2_PER_NOTE_PITCH_BEND, note, 0, value)
+            }
+        };
+
+        UmpPacket::words_2(
+            ((MT_MIDI2_CHANNEL_VOICE as u32) << 28)
+                | ((group as u32 & 0x0F) << 24)
+                | ((opcode as u32) << 20)
+                | ((self.channel() as u32 & 0x0F) << 16)
+                | ((byte_3 as u32 & 0x7F) << 8)
+                | byte_4 as u32,
+            value,
+        )
+    }
+
+    pub fn channel(&self) -> Channel {
+        match *self {
+            Midi2Event::NoteOff { channel, .. }
+            | Midi2Event::NoteOn { channel, .. }
+            | Midi2Event::PolyPressure { channel, .. }
+            | Midi2Event::RegisteredPerNoteController { channel, .. }
+            | Midi2Event::AssignablePerNoteController { channel, .. }
+            | Midi2Event::PerNoteManagement { channel, .. }
+            | Midi2Event::ControlChange { channel, .. }
+            | Midi2Event::RegisteredController { channel, .. }
+            | Midi2Event::AssignableController { channel, .. }
+            | Midi2Event::RelativeRegisteredController { channel, .. }
+            | Midi2Event::RelativeAssignableController { channel, .. }
+            | Midi2Event::ProgramChange { channel, .. }
+            | Midi2Event::ChannelPressure { channel, .. }
+            | Midi2Event::PitchBend { channel, .. }
+            | Midi2Event::PerNotePitchBend { channel, .. } => channel,
+        }
+    }
+
+    fn decode(words: &[u32]) -> Option<Self> {
+        let channel = (words[0] >> 16) as Channel & 0x0F;
+        let byte_3 = (words[0] >> 8) as u8;
+        let byte_4 = words[0] as u8;
+        let note = byte_3 & 0x7F;
+        let value = words[1];
+
+        Some(match (words[0] >> 20) as u8 & 0x0F {
+            MIDI2_REGISTERED_PER_NOTE_CONTROLLER => Midi2Event::RegisteredPerNoteController {
+                channel,
+                note,
+                index: byte_4,
+                value,
+            },
+            MIDI2_ASSIGNABLE_PER_NOTE_CONTROLLER => Midi2Event::AssignablePerNoteController {
+                channel,
+                note,
+                index: byte_4,
+                value,
+            },
+            MIDI2_REGISTERED_CONTROLLER => Midi2Event::RegisteredController {
+                channel,
+                bank: byte_3 & 0x7F,
+                index: byte_4 & 0x7F,
+                value,
+            },
+            MIDI2_ASSIGNABLE_CONTROLLER => Midi2Event::AssignableController {
+                channel,
+                bank: byte_3 & 0x7F,
+                index: byte_4 & 0x7F,
+                value,
+            },
+            MIDI2_RELATIVE_REGISTERED_CONTROLLER => Midi2Event::RelativeRegisteredController {
+                channel,
+                bank: byte_3 & 0x7F,
+                index: byte_4 & 0x7F,
+                value: value as i32,
+            },
+            MIDI2_RELATIVE_ASSIGNABLE_CONTROLLER => Midi2Event::RelativeAssignableController {
+                channel,
+                bank: byte_3 & 0x7F,
+                index: byte_4 & 0x7F,
+                value: value as i32,
+            },
+            MIDI2_PER_NOTE_PITCH_BEND => Midi2Event::PerNotePitchBend {
+                channel,
+                note,
+                value,
+            },
+            MIDI2_NOTE_OFF => Midi2Event::NoteOff {
+                channel,
+                note,
+                velocity: (value >> 16) as u16,
+                attribute: NoteAttribute::new(byte_4, value as u16),
+            },
+            MIDI2_NOTE_ON => Midi2Event::NoteOn {
+                channel,
+                note,
+                velocity: (value >> 16) as u16,
+                attribute: NoteAttribute::new(byte_4, value as u16),
+            },
+            MIDI2_POLY_PRESSURE => Midi2Event::PolyPressure {
+                channel,
+                note,
+                value,
+            },
+            MIDI2_CONTROL_CHANGE => Midi2Event::ControlChange {
+                channel,
+                index: byte_3 & 0x7F,
+                value,
+            },
+            MIDI2_PROGRAM_CHANGE => Midi2Event::ProgramChange {
+                channel,
+                program: (value >> 24) as u7 & 0x7F,
+                bank: if byte_4 & 0x01 != 0 {
+                    Some(((value >> 8) as u7 & 0x7F, value as u7 & 0x7F))
+                } else {
+                    None
+                },
+            },
+            MIDI2_CHANNEL_PRESSURE => Midi2Event::ChannelPressure { channel, value },
+            MIDI2_PITCH_BEND => Midi2Event::PitchBend { channel, value },
+            MIDI2_PER_NOTE_MANAGEMENT => Midi2Event::PerNoteManagement {
+                channel,
+                note,
+                detach: byte_4 & 0x02 != 0,
+                reset: byte_4 & 0x01 != 0,
+            },
+            _ => return None,
+        })
+    }
+}
+
+///
+/// **System Exclusive (7-bit) data packet**
+///
+/// Up to 6 data bytes per packet; the Start byte (0xF0) and End byte (0xF7)
+/// are not included.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SysEx7 {
+    pub form: Form,
+    bytes: [u7; 6],
+    len: usize,
+}
+
+impl SysEx7 {
+    ///
+    /// Packet with up to 6 data bytes, further bytes are dropped
+    ///
+    pub fn new(form: Form, data: &[u7]) -> Self {
+        let data = &data[..data.len().min(6)];
+        let mut bytes = [0; 6];
+        bytes[..data.len()].copy_from_slice(data);
+        Self {
+            form,
+            bytes,
+            len: data.len(),
+        }
+    }
+
+    pub fn data(&self) -> &[u7] {
+        &self.bytes[..self.len]
+    }
+
+    ///
+    /// **Encode as a Data (64-bit) packet**
+    ///
+    pub fn to_packet(&self, group: Group) -> UmpPacket {
+        let [b1, b2, b3, b4, b5, b6] = self.bytes;
+        UmpPacket::words_2(
+            ((MT_DATA_64 as u32) << 28)
+                | ((group as u32 & 0x0F) << 24)
+                | (self.form.bits() << 20)
+                | ((self.len as u32) << 16)
+                | u32::from_be_bytes([0, 0, b1 & 0x7F, b2 & 0x7F]),
+            u32::from_be_bytes([b3 & 0x7F, b4 & 0x7F, b5 & 0x7F, b6 & 0x7F]),
+        )
+    }
+
+    fn decode(words: &[u32]) -> Option<Self> {
+        let len = (words[0] >> 16) as usize & 0x0F;
+        if len > 6 {
+            return None;
+        }
+        let [_, _, b1, b2] = words[0].to_be_bytes();
+        let [b3, b4, b5, b6] = words[1].to_be_bytes();
+        let mut bytes = [b1, b2, b3, b4, b5, b6];
+        bytes.iter_mut().for_each(|byte| *byte &= 0x7F);
+        Some(Self {
+            form: Form::from_bits((words[0] >> 20) & 0x0F)?,
+            bytes,
+            len,
+        })
+    }
+}
+
+///
+/// **System Exclusive (8-bit) data packet**
+///
+/// Up to 13 data bytes per packet on the given stream.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SysEx8 {
+    pub form: Form,
+    pub stream_id: u8,
+    bytes: [u8; 13],
+    len: usize,
+}
+
+impl SysEx8 {
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    fn decode(words: &[u32]) -> Option<Self> {
+        // Number of bytes includes the stream ID
+        let len = ((words[0] >> 16) as usize & 0x0F).checked_sub(1)?;
+        if len > 13 {
+            return None;
+        }
+        let mut bytes = [0; 13];
+        bytes[0] = words[0] as u8;
+        for (chunk, word) in bytes[1..].chunks_mut(4).zip(&words[1..]) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        Some(Self {
+            form: Form::from_bits((words[0] >> 20) & 0x0F)?,
+            stream_id: (words[0] >> 8) as u8,
+            bytes,
+            len,
+        })
+    }
+}
+
+///
+/// MIDI protocol used on a UMP stream
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Protocol {
+    Midi1,
+    Midi2,
+}
+
+impl Protocol {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(Protocol::Midi1),
+            0x02 => Some(Protocol::Midi2),
+            _ => None,
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Protocol::Midi1 => 0x01,
+            Protocol::Midi2 => 0x02,
+        }
+    }
+}
+
+///
+/// **Text carried by Stream messages**
+///
+/// Names and product instance IDs are UTF-8 split over multiple packets;
+/// trailing zero padding is removed.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StreamText {
+    pub form: Form,
+    bytes: [u8; 14],
+    len: usize,
+}
+
+impl StreamText {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    ///
+    /// **Split text into packets**
+    ///
+    /// Endpoint names and product instance IDs carry 14 bytes per packet,
+    /// function block names 13. `size` is limited to 1 to 14 bytes.
+    ///
+    pub fn split(text: &[u8], size: usize) -> impl Iterator<Item = StreamText> + '_ {
+        let size = size.clamp(1, 14);
+        let count = text.len().div_ceil(size).max(1);
+        (0..count).map(move |index| {
+            let form = match (index, count) {
+                (_, 1) => Form::Complete,
+                (0, _) => Form::Start,
+                (index, count) if index + 1 == count => Form::End,
+                _ => Form::Continue,
+            };
+            let end = core::cmp::min(text.len(), (index + 1) * size);
+            StreamText::new(form, &text[index * size..end])
+        })
+    }
+
+    ///
+    /// Text for a single packet of up to 14 bytes, further bytes are dropped
+    ///
+    pub fn new(form: Form, text: &[u8]) -> Self {
+        let text = &text[..text.len().min(14)];
+        let mut bytes = [0; 14];
+        bytes[..text.len()].copy_from_slice(text);
+        let len = text
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |i| i + 1);
+        Self { form, bytes, len }
+    }
+}
+
+///
+/// **UMP Stream messages**
+///
+/// Endpoint and Function Block discovery and the stream configuration.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StreamMessage {
+    EndpointDiscovery {
+        version: (u8, u8), // UMP version major, minor
+        filter: u8,        // Bitmap of requested notifications
+    },
+    EndpointInfo {
+        version: (u8, u8),
+        static_function_blocks: bool,
+        function_blocks: u7,
+        midi2: bool,
+        midi1: bool,
+        receive_jr: bool,
+        transmit_jr: bool,
+    },
+    DeviceIdentity {
+        manufacturer: [u7; 3],
+        family: u16,
+        model: u16,
+        revision: [u7; 4],
+    },
+    EndpointName(StreamText),
+    ProductInstanceId(StreamText),
+    ConfigurationRequest {
+        protocol: Protocol,
+        receive_jr: bool,
+        transmit_jr: bool,
+    },
+    ConfigurationNotification {
+        protocol: Protocol,
+        receive_jr: bool,
+        transmit_jr: bool,
+    },
+    FunctionBlockDiscovery {
+        block: u8,  // Function block number (0xFF for all)
+        filter: u8, // Bitmap of requested notifications
+    },
+    FunctionBlockInfo {
+        active: bool,
+        block: u7,
+        ui_hint: u8,   // 2 bits
+        midi1: u8,     // 2 bits
+        direction: u8, // 2 bits
+        first_group: Group,
+        groups: u8, // Number of groups spanned
+        ci_version: u8,
+        sysex8_streams: u8,
+    },
+    FunctionBlockName {
+        block: u7,
+        text: StreamText,
+    },
+    StartOfClip,
+    EndOfClip,
+}
+
+impl StreamMessage {
+    ///
+    /// **Encode as a UMP Stream packet**
+    ///
+    /// Function block names carry at most 13 bytes of text per packet; longer
+    /// text is truncated.
+    ///
+    pub fn to_packet(&self) -> UmpPacket {
+        let flag = |value: bool, bit: u8| if value { 1 << bit } else { 0 };
+        let mut form = Form::Complete;
+        let mut payload = [0; 14]; // Bytes following the status in the packet
+        let status = match *self {
+            StreamMessage::EndpointDiscovery { version, filter } => {
+                payload[0] = version.0;
+                payload[1] = version.1;
+                payload[5] = filter;
+                STREAM_ENDPOINT_DISCOVERY
+            }
+            StreamMessage::EndpointInfo {
+                version,
+                static_function_blocks,
+                function_blocks,
+                midi2,
+                midi1,
+                receive_jr,
+                transmit_jr,
+            } => {
+                payload[0] = version.0;
+                payload[1] = version.1;
+                payload[2] = flag(static_function_blocks, 7) | (function_blocks & 0x7F);
+                payload[4] = flag(midi2, 1) | flag(midi1, 0);
+                payload[5] = flag(receive_jr, 1) | flag(transmit_jr, 0);
+                STREAM_ENDPOINT_INFO
+            }
+            StreamMessage::DeviceIdentity {
+                manufacturer,
+                family,
+                model,
+                revision,
+            } => {
+                payload[3..6].copy_from_slice(&manufacturer);
+                payload[6] = family as u8 & 0x7F;
+                payload[7] = (family >> 7) as u8 & 0x7F;
+                payload[8] = model as u8 & 0x7F;
+                payload[9] = (model >> 7) as u8 & 0x7F;
+                payload[10..14].copy_from_slice(&revision);
+                payload.iter_mut().for_each(|byte| *byte &= 0x7F);
+                STREAM_DEVICE_IDENTITY
+            }
+            StreamMessage::EndpointName(text) => {
+                form = text.form;
+                payload[..text.len].copy_from_slice(text.bytes());
+                STREAM_ENDPOINT_NAME
+            }
+            StreamMessage::ProductInstanceId(text) => {
+                form = text.form;
+                payload[..text.len].copy_from_slice(text.bytes());
+                STREAM_PRODUCT_INSTANCE_ID
+            }
+            StreamMessage::ConfigurationRequest {
+                protocol,
+                receive_jr,
+                transmit_jr,
+            } => {
+                payload[0] = protocol.byte();
+                payload[1] = flag(receive_jr, 1) | flag(transmit_jr, 0);
+                STREAM_CONFIGURATION_REQUEST
+            }
+            StreamMessage::ConfigurationNotification {
+                protocol,
+                receive_jr,
+                transmit_jr,
+            } => {
+                payload[0] = protocol.byte();
+                payload[1] = flag(receive_jr, 1) | flag(transmit_jr, 0);
+                STREAM_CONFIGURATION_NOTIFICATION
+            }
+            StreamMessage::FunctionBlockDiscovery { block, filter } => {
+                payload[0] = block;
+                payload[1] = filter;
+                STREAM_FUNCTION_BLOCK_DISCOVERY
+            }
+            StreamMessage::FunctionBlockInfo {
+                active,
+                block,
+                ui_hint,
+                midi1,
+                direction,
+                first_group,
+                groups,
+                ci_version,
+                sysex8_streams,
+            } => {
+                payload[0] = flag(active, 7) | (block & 0x7F);
+                payload[1] = ((ui_hint & 0x03) << 4) | ((midi1 & 0x03) << 2) | (direction & 0x03);
+                payload[2..6].copy_from_slice(&[
+                    first_group & 0x0F,
+                    groups,
+                    ci_version,
+                    sysex8_streams,
+                ]);
+                STREAM_FUNCTION_BLOCK_INFO
+            }
+            StreamMessage::FunctionBlockName { block, text } => {
+                form = text.form;
+                payload[0] = block & 0x7F;
+                let len = text.len.min(13);
+                payload[1..=len].copy_from_slice(&text.bytes()[..len]);
+                STREAM_FUNCTION_BLOCK_NAME
+            }
+            StreamMessage::StartOfClip => STREAM_START_OF_CLIP,
+            StreamMessage::EndOfClip => STREAM_END_OF_CLIP,
+        };
+
+        let word = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        UmpPacket {
+            words: [
+                ((MT_STREAM as u32) << 28)
+                    | (form.bits() << 26)
+                    | (status << 16)
+                    | ((payload[0] as u32) << 8)
+                    | payload[1] as u32,
+                word(&payload[2..6]),
+                word(&payload[6..10]),
+                word(&payload[10..14]),
+            ],
+            len: 4,
+        }
+    }
+
+    fn decode(words: &[u32]) -> Option<Self> {
+        let form = Form::from_bits((words[0] >> 26) & 0x03)?;
+        let [_, _, byte_3, byte_4] = words[0].to_be_bytes();
+        let mut text = [0; 14];
+        text[0] = byte_3;
+        text[1] = byte_4;
+        for (chunk, word) in text[2..].chunks_mut(4).zip(&words[1..]) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+
+        Some(match (words[0] >> 16) & 0x3FF {
+            STREAM_ENDPOINT_DISCOVERY => StreamMessage::EndpointDiscovery {
+                version: (byte_3, byte_4),
+                filter: words[1] as u8,
+            },
+            STREAM_ENDPOINT_INFO => StreamMessage::EndpointInfo {
+                version: (byte_3, byte_4),
+                static_function_blocks: words[1] & 0x8000_0000 != 0,
+                function_blocks: (words[1] >> 24) as u7 & 0x7F,
+                midi2: words[1] & 0x0200 != 0,
+                midi1: words[1] & 0x0100 != 0,
+                receive_jr: words[1] & 0x02 != 0,
+                transmit_jr: words[1] & 0x01 != 0,
+            },
+            STREAM_DEVICE_IDENTITY => {
+                let [_, m1, m2, m3] = words[1].to_be_bytes();
+                let [family_lsb, family_msb, model_lsb, model_msb] = words[2].to_be_bytes();
+                let revision = words[3].to_be_bytes();
+                StreamMessage::DeviceIdentity {
+                    manufacturer: [m1 & 0x7F, m2 & 0x7F, m3 & 0x7F],
+                    family: (u16::from(family_msb & 0x7F) << 7) | u16::from(family_lsb & 0x7F),
+                    model: (u16::from(model_msb & 0x7F) << 7) | u16::from(model_lsb & 0x7F),
+                    revision: [
+                        revision[0] & 0x7F,
+                        revision[1] & 0x7F,
+                        revision[2] & 0x7F,
+                        revision[3] & 0x7F,
+                    ],
+                }
+            }
+            STREAM_ENDPOINT_NAME => StreamMessage::EndpointName(StreamText::new(form, &text)),
+            STREAM_PRODUCT_INSTANCE_ID => {
+                StreamMessage::ProductInstanceId(StreamText::new(form, &text))
+            }
+            STREAM_CONFIGURATION_REQUEST => StreamMessage::ConfigurationRequest {
+                protocol: Protocol::from_byte(byte_3)?,
+                receive_jr: byte_4 & 0x02 != 0,
+                transmit_jr: byte_4 & 0x01 != 0,
+            },
+            STREAM_CONFIGURATION_NOTIFICATION => StreamMessage::ConfigurationNotification {
+                protocol: Protocol::from_byte(byte_3)?,
+                receive_jr: byte_4 & 0x02 != 0,
+                transmit_jr: byte_4 & 0x01 != 0,
+            },
+            STREAM_FUNCTION_BLOCK_DISCOVERY => StreamMessage::FunctionBlockDiscovery {
+                block: byte_3,
+                filter: byte_4,
+            },
+            STREAM_FUNCTION_BLOCK_INFO => {
+                let [first_group, groups, ci_version, sysex8_streams] = words[1].to_be_bytes();
+                StreamMessage::FunctionBlockInfo {
+                    active: byte_3 & 0x80 != 0,
+                    block: byte_3 & 0x7F,
+                    ui_hint: (byte_4 >> 4) & 0x03,
+                    midi1: (byte_4 >> 2) & 0x03,
+                    direction: byte_4 & 0x03,
+                    first_group: first_group & 0x0F,
+                    groups,
+                    ci_version,
+                    sysex8_streams,
+                }
+            }
+            STREAM_FUNCTION_BLOCK_NAME => StreamMessage::FunctionBlockName {
+                block: byte_3 & 0x7F,
+                text: StreamText::new(form, &text[1..]),
+            },
+            STREAM_START_OF_CLIP => StreamMessage::StartOfClip,
+            STREAM_END_OF_CLIP => StreamMessage::EndOfClip,
+            _ => return None,
+        })
+    }
+}
+
+///
+/// **Decoded Universal MIDI Packet**
+///
+/// System and MIDI 1.0 Channel Voice messages are decoded to the same
+/// [`MidiEvent`] used for MIDI 1.0 byte streams. Packets of unsupported or
+/// reserved types are returned as `Unknown`.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UmpEvent {
+    Utility(Utility),
+    System(Group, MidiEvent),
+    Midi1(Group, MidiEvent),
+    Midi2(Group, Midi2Event),
+    SysEx7(Group, SysEx7),
+    SysEx8(Group, SysEx8),
+    Stream(StreamMessage),
+    Unknown(UmpPacket),
+}
+
+impl UmpEvent {
+    ///
+    /// **Decode a packet**
+    ///
+    pub fn decode(packet: &UmpPacket) -> Self {
+        Self::decode_known(packet).unwrap_or(UmpEvent::Unknown(*packet))
+    }
+
+    fn decode_known(packet: &UmpPacket) -> Option<Self> {
+        let group = packet.group();
+        let word = packet[0];
+
+        match packet.message_type() {
+            MT_UTILITY => Some(UmpEvent::Utility(match (word >> 20) & 0x0F {
+                UTILITY_NOOP => Utility::NoOp,
+                UTILITY_JR_CLOCK => Utility::JrClock(word as u16),
+                UTILITY_JR_TIMESTAMP => Utility::JrTimestamp(word as u16),
+                UTILITY_DELTA_CLOCKSTAMP_TPQ => Utility::DeltaClockstampTpq(word as u16),
+                UTILITY_DELTA_CLOCKSTAMP => Utility::DeltaClockstamp(word & 0x000F_FFFF),
+                _ => return None,
+            })),
+            MT_SYSTEM => match (word >> 16) as u8 {
+                0xF0 | 0xF7 => None,
+                0xF1..=0xFF => midi1_event(word).map(|event| UmpEvent::System(group, event)),
+                _ => None,
+            },
+            MT_MIDI1_CHANNEL_VOICE => match (word >> 16) as u8 {
+                0x80..=0xEF => midi1_event(word).map(|event| UmpEvent::Midi1(group, event)),
+                _ => None,
+            },
+            MT_DATA_64 => SysEx7::decode(packet).map(|sysex| UmpEvent::SysEx7(group, sysex)),
+            MT_MIDI2_CHANNEL_VOICE => {
+                Midi2Event::decode(packet).map(|event| UmpEvent::Midi2(group, event))
+            }
+            MT_DATA_128 => match (word >> 20) & 0x0F {
+                0x0..=0x3 => SysEx8::decode(packet).map(|sysex| UmpEvent::SysEx8(group, sysex)),
+                _ => None, // Mixed Data Set
+            },
+            MT_STREAM => StreamMessage::decode(packet).map(UmpEvent::Stream),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Decode the MIDI 1.0 message in the lower 3 bytes of a 32-bit packet
+///
+pub(crate) fn midi1_event(word: u32) -> Option<MidiEvent> {
+    let [_, status, data_1, data_2] = word.to_be_bytes();
+    let mut reader = MidiReader::new();
+    [status, data_1 & 0x7F, data_2 & 0x7F]
+        .iter()
+        .find_map(|byte| reader.handle_byte(*byte))
+}
+
+///
+/// **UMP Reader**
+///
+/// Handles an inbound stream of 32-bit UMP words and returns events
+///
+#[derive(Debug, Clone)]
+pub struct UmpReader {
+    words: [u32; 4],
+    count: usize,
+}
+
+impl Default for UmpReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UmpReader {
+    pub fn new() -> Self {
+        Self {
+            words: [0; 4],
+            count: 0,
+        }
+    }
+
+    ///
+    /// **Handle a word**
+    ///
+    /// Returns an UmpEvent if the packet is complete
+    ///
+    pub fn handle_word(&mut self, word: u32) -> Option<UmpEvent> {
+        self.words[self.count] = word;
+        self.count += 1;
+
+        if self.count < packet_words((self.words[0] >> 28) as u4) {
+            return None;
+        }
+        let packet = UmpPacket::new(&self.words[..self.count]);
+        self.count = 0;
+        packet.map(|packet| UmpEvent::decode(&packet))
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::ump::{
+        Form, Midi2Event, NoteAttribute, Protocol, StreamMessage, StreamText, SysEx7, UmpEvent,
+        UmpPacket, UmpReader, Utility,
+    };
+    use crate::MidiEvent;
+    use parameterized::parameterized;
+
+    fn decode(words: &[u32]) -> UmpEvent {
+        UmpEvent::decode(&UmpPacket::new(words).unwrap())
+    }
+
+    #[parameterized(
+        words = {
+            &[0x0000_0000],
+            &[0x0010_1234],
+            &[0x0030_0060],
+            &[0x0048_0001],
+            &[0x13F8_0000],
+            &[0x10F2_0102],
+            &[0x25F1_3300],
+            &[0x2392_3C64],
+            &[0x2CB0_7B00],
+            &[0x22E0_0040],
+        },
+        expected = {
+            UmpEvent::Utility(Utility::NoOp),
+            UmpEvent::Utility(Utility::JrClock(0x1234)),
+            UmpEvent::Utility(Utility::DeltaClockstampTpq(96)),
+            UmpEvent::Utility(Utility::DeltaClockstamp(0x80001)),
+            UmpEvent::System(3, MidiEvent::Clock),
+            UmpEvent::System(0, MidiEvent::SongPositionPointer(0x0101)),
+            UmpEvent::Unknown(UmpPacket::new(&[0x25F1_3300]).unwrap()),
+            UmpEvent::Midi1(3, MidiEvent::NoteOn(2, 60, 100)),
+            UmpEvent::Midi1(12, MidiEvent::AllNotesOff(0)),
+            UmpEvent::Midi1(2, MidiEvent::PitchBend(0, 0)),
+        }
+    )]
+    fn decode__32_bit_packets(words: &[u32], expected: UmpEvent) {
+        assert_eq!(decode(words), expected);
+    }
+
+    #[parameterized(
+        words = {
+            &[0x4093_3C03, 0xFFFF_3C80],
+            &[0x4081_4000, 0x8000_0000],
+            &[0x40B5_0700, 0xFFFF_FFFF],
+            &[0x4020_0000, 0x1000_0000],
+            &[0x4050_0102, 0xFFFF_FFFF],
+            &[0x40C0_0001, 0x0500_0102],
+            &[0x40C0_0000, 0x0500_0102],
+            &[0x40E1_0000, 0x8000_0000],
+            &[0x40F1_3C03, 0x0000_0000],
+        },
+        expected = {
+            Midi2Event::NoteOn {
+                channel: 3,
+                note: 60,
+                velocity: 0xFFFF,
+                attribute: NoteAttribute::Pitch(0x3C80),
+            },
+            Midi2Event::NoteOff {
+                channel: 1,
+                note: 64,
+                velocity: 0x8000,
+                attribute: NoteAttribute::None,
+            },
+            Midi2Event::ControlChange { channel: 5, index: 7, value: 0xFFFF_FFFF },
+            Midi2Event::RegisteredController { channel: 0, bank: 0, index: 0, value: 0x1000_0000 },
+            Midi2Event::RelativeAssignableController { channel: 0, bank: 1, index: 2, value: -1 },
+            Midi2Event::ProgramChange { channel: 0, program: 5, bank: Some((1, 2)) },
+            Midi2Event::ProgramChange { channel: 0, program: 5, bank: None },
+            Midi2Event::PitchBend { channel: 1, value: 0x8000_0000 },
+            Midi2Event::PerNoteManagement { channel: 1, note: 60, detach: true, reset: true },
+        }
+    )]
+    fn decode__midi2_channel_voice(words: &[u32], expected: Midi2Event) {
+        assert_eq!(decode(words), UmpEvent::Midi2(0, expected));
+    }
+
+    #[test]
+    fn decode__sysex7() {
+        let actual = decode(&[0x3216_7E7F, 0x0601_0203]);
+
+        match actual {
+            UmpEvent::SysEx7(2, sysex) => {
+                assert_eq!(sysex.form, Form::Start);
+                assert_eq!(sysex.data(), &[0x7E, 0x7F, 0x06, 0x01, 0x02, 0x03]);
+            }
+            _ => panic!("unexpected {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn decode__sysex8() {
+        let actual = decode(&[0x5034_07AA, 0xBB00_0000, 0, 0]);
+
+        match actual {
+            UmpEvent::SysEx8(0, sysex) => {
+                assert_eq!(sysex.form, Form::End);
+                assert_eq!(sysex.stream_id, 7);
+                assert_eq!(sysex.data(), &[0xAA, 0xBB, 0x00]);
+            }
+            _ => panic!("unexpected {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn decode__stream_messages() {
+        assert_eq!(
+            decode(&[0xF000_0101, 0x0000_001F, 0, 0]),
+            UmpEvent::Stream(StreamMessage::EndpointDiscovery {
+                version: (1, 1),
+                filter: 0x1F
+            })
+        );
+        assert_eq!(
+            decode(&[0xF001_0101, 0x8200_0301, 0, 0]),
+            UmpEvent::Stream(StreamMessage::EndpointInfo {
+                version: (1, 1),
+                static_function_blocks: true,
+                function_blocks: 2,
+                midi2: true,
+                midi1: true,
+                receive_jr: false,
+                transmit_jr: true,
+            })
+        );
+        assert_eq!(
+            decode(&[0xF005_0200, 0, 0, 0]),
+            UmpEvent::Stream(StreamMessage::ConfigurationRequest {
+                protocol: Protocol::Midi2,
+                receive_jr: false,
+                transmit_jr: false,
+            })
+        );
+        assert_eq!(
+            decode(&[0xF011_8125, 0x0002_0100, 0, 0]),
+            UmpEvent::Stream(StreamMessage::FunctionBlockInfo {
+                active: true,
+                block: 1,
+                ui_hint: 2,
+                midi1: 1,
+                direction: 1,
+                first_group: 0,
+                groups: 2,
+                ci_version: 1,
+                sysex8_streams: 0,
+            })
+        );
+        assert_eq!(
+            decode(&[0xF020_0000, 0, 0, 0]),
+            UmpEvent::Stream(StreamMessage::StartOfClip)
+        );
+    }
+
+    #[parameterized(
+        message = {
+            StreamMessage::EndpointDiscovery { version: (1, 1), filter: 0x1F },
+            StreamMessage::EndpointInfo {
+                version: (1, 1),
+                static_function_blocks: true,
+                function_blocks: 2,
+                midi2: true,
+                midi1: false,
+                receive_jr: true,
+                transmit_jr: false,
+            },
+            StreamMessage::DeviceIdentity {
+                manufacturer: [0x00, 0x21, 0x09],
+                family: 0x1234,
+                model: 0x0102,
+                revision: [1, 2, 3, 4],
+            },
+            StreamMessage::EndpointName(StreamText::new(Form::Complete, b"Synthesizer 12")),
+            StreamMessage::ProductInstanceId(StreamText::new(Form::End, b"42")),
+            StreamMessage::ConfigurationRequest {
+                protocol: Protocol::Midi1,
+                receive_jr: true,
+                transmit_jr: true,
+            },
+            StreamMessage::ConfigurationNotification {
+                protocol: Protocol::Midi2,
+                receive_jr: false,
+                transmit_jr: true,
+            },
+            StreamMessage::FunctionBlockDiscovery { block: 0xFF, filter: 0x03 },
+            StreamMessage::FunctionBlockInfo {
+                active: true,
+                block: 3,
+                ui_hint: 3,
+                midi1: 2,
+                direction: 1,
+                first_group: 4,
+                groups: 2,
+                ci_version: 2,
+                sysex8_streams: 0,
+            },
+            StreamMessage::FunctionBlockName {
+                block: 3,
+                text: StreamText::new(Form::Start, b"Keyboard Zone"),
+            },
+            StreamMessage::EndOfClip,
+        }
+    )]
+    fn to_packet__round_trips(message: StreamMessage) {
+        let actual = UmpEvent::decode(&message.to_packet());
+
+        assert_eq!(actual, UmpEvent::Stream(message));
+    }
+
+    #[test]
+    fn to_packet__truncates_function_block_name() {
+        let message = StreamMessage::FunctionBlockName {
+            block: 1,
+            text: StreamText::new(Form::Complete, b"Keyboard Zones"),
+        };
+
+        let actual = UmpEvent::decode(&message.to_packet());
+
+        assert_eq!(
+            actual,
+            UmpEvent::Stream(StreamMessage::FunctionBlockName {
+                block: 1,
+                text: StreamText::new(Form::Complete, b"Keyboard Zone"),
+            })
+        );
+    }
+
+    #[test]
+    fn new__truncates_long_input() {
+        let sysex = SysEx7::new(Form::Complete, &[1, 2, 3, 4, 5, 6, 7]);
+        let text = StreamText::new(Form::Complete, b"Synthesizer 1234");
+
+        assert_eq!(sysex.data(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(text.bytes(), b"Synthesizer 12");
+    }
+
+    #[test]
+    fn split__stream_text() {
+        let actual: Vec<StreamText> = StreamText::split(b"Function Block Name", 13).collect();
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].form, Form::Start);
+        assert_eq!(actual[0].bytes(), b"Function Bloc");
+        assert_eq!(actual[1].form, Form::End);
+        assert_eq!(actual[1].bytes(), b"k Name");
+    }
+
+    #[test]
+    fn decode__stream_text() {
+        let actual = decode(&[0xF403_5379, 0x6E74_6800, 0, 0]);
+
+        match actual {
+            UmpEvent::Stream(StreamMessage::EndpointName(text)) => {
+                assert_eq!(text.form, Form::Start);
+                assert_eq!(text.bytes(), b"Synth");
+            }
+            _ => panic!("unexpected {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn handle_word__multi_word_packets() {
+        let mut target = UmpReader::new();
+
+        let actual: Vec<UmpEvent> = [0x2090_3C64, 0x4090_3C00, 0xC000_0000, 0x1FFA_0000]
+            .iter()
+            .filter_map(|word| target.handle_word(*word))
+            .collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                UmpEvent::Midi1(0, MidiEvent::NoteOn(0, 60, 100)),
+                UmpEvent::Midi2(
+                    0,
+                    Midi2Event::NoteOn {
+                        channel: 0,
+                        note: 60,
+                        velocity: 0xC000,
+                        attribute: NoteAttribute::None
+                    }
+                ),
+                UmpEvent::System(15, MidiEvent::Start),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode__where_sysex_in_system_packet() {
+        // SysEx is carried by Data packets
+        let actual = decode(&[0x10F0_4300]);
+
+        assert!(matches!(actual, UmpEvent::Unknown(_)));
+    }
+}
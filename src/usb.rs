@@ -0,0 +1,392 @@
+use crate::read::{MESSAGE_SYS_EX_END, SYSTEM_RT_MASK};
+use crate::{u4, MidiEvent, MidiEvents, MidiReader};
+use core::ops::Deref;
+
+///
+/// USB-MIDI virtual cable number (0-15)
+///
+pub type Cable = u4;
+
+// Code Index Numbers
+const CIN_SYSTEM_COMMON_2: u8 = 0x2;
+const CIN_SYSTEM_COMMON_3: u8 = 0x3;
+const CIN_SYS_EX_CONTINUE: u8 = 0x4; // SysEx starts or continues
+const CIN_SINGLE_BYTE_COMMON: u8 = 0x5; // Single byte System Common or SysEx ends with 1 byte
+const CIN_SINGLE_BYTE: u8 = 0xF;
+
+///
+/// **USB-MIDI 1.0 Event Packet**
+///
+/// Cable number and Code Index Number followed by up to 3 MIDI bytes.
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UsbMidiPacket(pub [u8; 4]);
+
+impl UsbMidiPacket {
+    ///
+    /// Packet from a Code Index Number and up to 3 bytes, further bytes are dropped
+    ///
+    pub fn new(cable: Cable, code_index: u4, bytes: &[u8]) -> Self {
+        let bytes = &bytes[..bytes.len().min(3)];
+        let mut packet = [((cable & 0x0F) << 4) | (code_index & 0x0F), 0, 0, 0];
+        packet[1..=bytes.len()].copy_from_slice(bytes);
+        Self(packet)
+    }
+
+    pub fn cable(&self) -> Cable {
+        self.0[0] >> 4
+    }
+
+    pub fn code_index(&self) -> u4 {
+        self.0[0] & 0x0F
+    }
+
+    ///
+    /// MIDI bytes of the packet; empty for reserved Code Index Numbers
+    ///
+    pub fn bytes(&self) -> &[u8] {
+        let len = match self.code_index() {
+            0x5 | 0xF => 1,
+            0x2 | 0x6 | 0xC | 0xD => 2,
+            0x3 | 0x4 | 0x7..=0xB | 0xE => 3,
+            _ => 0,
+        };
+        &self.0[1..=len]
+    }
+}
+
+///
+/// **Packets for a single event**
+///
+/// An event encodes to at most 2 packets (a System Exclusive Start with a
+/// word ID following buffered data).
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UsbMidiPackets {
+    packets: [UsbMidiPacket; 2],
+    len: usize,
+}
+
+impl UsbMidiPackets {
+    fn new() -> Self {
+        Self {
+            packets: [UsbMidiPacket([0; 4]); 2],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, packet: UsbMidiPacket) {
+        self.packets[self.len] = packet;
+        self.len += 1;
+    }
+}
+
+impl Deref for UsbMidiPackets {
+    type Target = [UsbMidiPacket];
+
+    fn deref(&self) -> &[UsbMidiPacket] {
+        &self.packets[..self.len]
+    }
+}
+
+///
+/// **USB-MIDI Writer**
+///
+/// Encodes the events of a single cable into USB-MIDI event packets. System
+/// Exclusive data is gathered into 3 byte packets (CIN 0x4) and completed by
+/// a packet of 1 to 3 bytes ending with End of Exclusive (CIN 0x5-0x7). A
+/// System Exclusive message interrupted by any message other than System
+/// Realtime is ended.
+///
+pub struct UsbMidiWriter {
+    cable: Cable,
+    reader: MidiReader, // Parses byte stream input
+    in_sys_ex: bool,
+    sys_ex: [u8; 3], // Pending System Exclusive bytes
+    len: usize,
+}
+
+impl UsbMidiWriter {
+    pub fn new(cable: Cable) -> Self {
+        Self {
+            cable,
+            reader: MidiReader::new(),
+            in_sys_ex: false,
+            sys_ex: [0; 3],
+            len: 0,
+        }
+    }
+
+    ///
+    /// **Encode a byte of a MIDI byte stream**
+    ///
+    pub fn handle_byte(&mut self, byte: u8) -> UsbMidiPackets {
+        match self.reader.handle_byte(byte) {
+            Some(event) => self.handle_event(&event),
+            None => UsbMidiPackets::new(),
+        }
+    }
+
+    ///
+    /// **Encode an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> UsbMidiPackets {
+        let mut packets = UsbMidiPackets::new();
+        let bytes = event.to_bytes();
+
+        match event {
+            MidiEvent::SystemExclusiveStart(_) => {
+                self.end_sys_ex(&mut packets);
+                self.in_sys_ex = true;
+                for byte in bytes.iter() {
+                    self.sys_ex_byte(*byte, &mut packets);
+                }
+            }
+            MidiEvent::SystemExclusiveData(byte) => {
+                if self.in_sys_ex {
+                    self.sys_ex_byte(*byte, &mut packets);
+                }
+            }
+            MidiEvent::SystemExclusiveEnd => self.end_sys_ex(&mut packets),
+            _ => {
+                let status = bytes[0];
+                let code_index = match status {
+                    0x80..=0xEF => status >> 4,
+                    0xF1 | 0xF3 => CIN_SYSTEM_COMMON_2,
+                    0xF2 => CIN_SYSTEM_COMMON_3,
+                    _ if status & SYSTEM_RT_MASK == SYSTEM_RT_MASK => CIN_SINGLE_BYTE,
+                    _ => CIN_SINGLE_BYTE_COMMON,
+                };
+                if code_index != CIN_SINGLE_BYTE {
+                    self.end_sys_ex(&mut packets);
+                }
+                packets.push(UsbMidiPacket::new(self.cable, code_index, &bytes));
+            }
+        }
+        packets
+    }
+
+    fn sys_ex_byte(&mut self, byte: u8, packets: &mut UsbMidiPackets) {
+        self.sys_ex[self.len] = byte;
+        self.len += 1;
+        if self.len == self.sys_ex.len() {
+            packets.push(UsbMidiPacket::new(
+                self.cable,
+                CIN_SYS_EX_CONTINUE,
+                &self.sys_ex,
+            ));
+            self.len = 0;
+        }
+    }
+
+    fn end_sys_ex(&mut self, packets: &mut UsbMidiPackets) {
+        if !self.in_sys_ex {
+            return;
+        }
+        self.sys_ex[self.len] = MESSAGE_SYS_EX_END;
+        self.len += 1;
+        // CIN 0x5, 0x6 or 0x7 for 1, 2 or 3 bytes
+        packets.push(UsbMidiPacket::new(
+            self.cable,
+            CIN_SYS_EX_CONTINUE + self.len as u8,
+            &self.sys_ex[..self.len],
+        ));
+        self.in_sys_ex = false;
+        self.len = 0;
+    }
+}
+
+///
+/// **USB-MIDI Reader**
+///
+/// Decodes USB-MIDI event packets with a [`MidiReader`] for each cable so
+/// System Exclusive messages on different cables are kept apart.
+///
+#[derive(Default)]
+pub struct UsbMidiReader {
+    readers: [MidiReader; 16],
+}
+
+impl UsbMidiReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// **Handle a packet**
+    ///
+    /// Returns the events completed by the packet.
+    ///
+    pub fn handle_packet(&mut self, packet: &UsbMidiPacket) -> MidiEvents {
+        let mut events = MidiEvents::new();
+        let reader = &mut self.readers[packet.cable() as usize];
+        for byte in packet.bytes() {
+            if let Some(event) = reader.handle_byte(*byte) {
+                events.push(event);
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::usb::{UsbMidiPacket, UsbMidiReader, UsbMidiWriter};
+    use crate::{MidiEvent, SysExID};
+    use parameterized::parameterized;
+
+    fn write(target: &mut UsbMidiWriter, events: &[MidiEvent]) -> Vec<[u8; 4]> {
+        events
+            .iter()
+            .flat_map(|event| target.handle_event(event).to_vec())
+            .map(|packet| packet.0)
+            .collect()
+    }
+
+    #[parameterized(
+        event = {
+            MidiEvent::NoteOn(2, 60, 100),
+            MidiEvent::ProgramChange(0, 5),
+            MidiEvent::PitchBend(15, -1),
+            MidiEvent::MTCQuarterFrame(1, 2),
+            MidiEvent::SongPositionPointer(0x0201),
+            MidiEvent::TuneRequest,
+            MidiEvent::Clock,
+        },
+        expected = {
+            [0x39, 0x92, 60, 100],
+            [0x3C, 0xC0, 5, 0],
+            [0x3E, 0xEF, 0x7F, 0x3F],
+            [0x32, 0xF1, 0x12, 0],
+            [0x33, 0xF2, 0x01, 0x04],
+            [0x35, 0xF6, 0, 0],
+            [0x3F, 0xF8, 0, 0],
+        }
+    )]
+    fn handle_event__single_packet(event: MidiEvent, expected: [u8; 4]) {
+        let mut target = UsbMidiWriter::new(3);
+
+        let actual = write(&mut target, &[event]);
+
+        assert_eq!(actual, vec![expected]);
+    }
+
+    #[parameterized(
+        data = { &[], &[1], &[1, 2], &[1, 2, 3] },
+        expected = {
+            &[[0x07, 0xF0, 0x43, 0xF7]],
+            &[[0x04, 0xF0, 0x43, 0x01], [0x05, 0xF7, 0, 0]],
+            &[[0x04, 0xF0, 0x43, 0x01], [0x06, 0x02, 0xF7, 0]],
+            &[[0x04, 0xF0, 0x43, 0x01], [0x07, 0x02, 0x03, 0xF7]],
+        }
+    )]
+    fn handle_event__sys_ex(data: &[u8], expected: &[[u8; 4]]) {
+        let mut target = UsbMidiWriter::new(0);
+        let mut events = vec![MidiEvent::SystemExclusiveStart(SysExID::Byte(0x43))];
+        events.extend(
+            data.iter()
+                .map(|byte| MidiEvent::SystemExclusiveData(*byte)),
+        );
+        events.push(MidiEvent::SystemExclusiveEnd);
+
+        let actual = write(&mut target, &events);
+
+        assert_eq!(actual, expected.to_vec());
+    }
+
+    #[test]
+    fn new__truncates_long_input() {
+        let packet = UsbMidiPacket::new(1, 0x4, &[0xF0, 0x7D, 0x01, 0x02]);
+
+        assert_eq!(packet.0, [0x14, 0xF0, 0x7D, 0x01]);
+    }
+
+    #[test]
+    fn handle_event__realtime_within_sys_ex() {
+        let mut target = UsbMidiWriter::new(0);
+
+        let actual = write(
+            &mut target,
+            &[
+                MidiEvent::SystemExclusiveStart(SysExID::Word(0x0221)),
+                MidiEvent::Clock,
+                MidiEvent::SystemExclusiveData(0x10),
+                MidiEvent::NoteOn(0, 60, 1),
+            ],
+        );
+
+        assert_eq!(
+            actual,
+            vec![
+                [0x04, 0xF0, 0x00, 0x04],
+                [0x0F, 0xF8, 0, 0],
+                [0x07, 0x21, 0x10, 0xF7],
+                [0x09, 0x90, 60, 1],
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_byte__running_status() {
+        let mut target = UsbMidiWriter::new(1);
+
+        let actual: Vec<[u8; 4]> = [0x90, 60, 100, 62, 100]
+            .iter()
+            .flat_map(|byte| target.handle_byte(*byte).to_vec())
+            .map(|packet| packet.0)
+            .collect();
+
+        assert_eq!(actual, vec![[0x19, 0x90, 60, 100], [0x19, 0x90, 62, 100]]);
+    }
+
+    #[test]
+    fn handle_packet__cables_are_independent() {
+        let mut target = UsbMidiReader::new();
+
+        let actual: Vec<MidiEvent> = [
+            [0x04, 0xF0, 0x43, 0x01],
+            [0x19, 0x90, 60, 100],
+            [0x06, 0x02, 0xF7, 0],
+        ]
+        .iter()
+        .flat_map(|packet| target.handle_packet(&UsbMidiPacket(*packet)).to_vec())
+        .collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                MidiEvent::SystemExclusiveStart(SysExID::Byte(0x43)),
+                MidiEvent::SystemExclusiveData(0x01),
+                MidiEvent::NoteOn(0, 60, 100),
+                MidiEvent::SystemExclusiveData(0x02),
+                MidiEvent::SystemExclusiveEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_packet__round_trips() {
+        let events = [
+            MidiEvent::ControllerChange(4, 7, 100),
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(0x7E)),
+            MidiEvent::SystemExclusiveData(0x01),
+            MidiEvent::Start,
+            MidiEvent::SystemExclusiveData(0x02),
+            MidiEvent::SystemExclusiveData(0x03),
+            MidiEvent::SystemExclusiveData(0x04),
+            MidiEvent::SystemExclusiveEnd,
+            MidiEvent::SongSelect(3),
+        ];
+        let mut writer = UsbMidiWriter::new(5);
+        let mut target = UsbMidiReader::new();
+
+        let actual: Vec<MidiEvent> = events
+            .iter()
+            .flat_map(|event| writer.handle_event(event).to_vec())
+            .flat_map(|packet| target.handle_packet(&packet).to_vec())
+            .collect();
+
+        assert_eq!(actual, events.to_vec());
+    }
+}
@@ -0,0 +1,594 @@
+use crate::{controller, u7, Channel, List, MidiEvent};
+
+/// Number of voices a `VoiceAllocator` can have
+pub const MAX_VOICES: usize = 32;
+
+/// Number of held keys remembered in mono mode
+const MAX_MONO_KEYS: usize = 16;
+
+///
+/// Voice chosen when a note arrives and no voice is free
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StealMode {
+    None, // Drop the new note
+    Oldest,
+    Quietest, // Lowest velocity; oldest when equal
+}
+
+///
+/// Instruction for the sound engine from the voice allocator
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VoiceEvent {
+    /// Start a note (trigger envelopes); `glide_from` is set when portamento
+    /// applies
+    Start {
+        voice: usize,
+        channel: Channel,
+        key: u7,
+        velocity: u7,
+        glide_from: Option<u7>,
+    },
+    /// Change pitch without re-triggering (mono legato)
+    Legato {
+        voice: usize,
+        key: u7,
+        glide_from: Option<u7>,
+    },
+    /// Release a note (gate off)
+    Release { voice: usize },
+    /// Silence a voice immediately (stolen or All Sound Off)
+    Stop { voice: usize },
+}
+
+///
+/// Voice events produced from a single message
+///
+pub type VoiceEvents = List<VoiceEvent, MAX_VOICES>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum VoiceState {
+    Idle,
+    Held,
+    Released,
+}
+
+///
+/// State of a single voice
+///
+#[derive(Debug, Copy, Clone)]
+pub struct Voice {
+    pub channel: Channel,
+    pub key: u7,
+    pub velocity: u7,
+    state: VoiceState,
+    changed: u64, // Allocation counter value when last started/released
+}
+
+impl Voice {
+    const IDLE: Voice = Voice {
+        channel: 0,
+        key: 0,
+        velocity: 0,
+        state: VoiceState::Idle,
+        changed: 0,
+    };
+
+    ///
+    /// Voice has a key held down
+    ///
+    pub fn is_held(&self) -> bool {
+        self.state == VoiceState::Held
+    }
+
+    ///
+    /// Voice is not playing or in its release phase
+    ///
+    pub fn is_free(&self) -> bool {
+        self.state != VoiceState::Held
+    }
+}
+
+///
+/// **Voice Allocator**
+///
+/// Assigns incoming notes to a fixed number of voices (up to `MAX_VOICES`).
+///
+/// In polyphonic mode a new note is given to the voice that was released the
+/// longest ago, or when all voices are held, a voice is stolen according to
+/// the steal mode. With same-note retrigger a note already sounding on a voice
+/// reuses that voice. `MonoMode` switches to a single voice with last note
+/// priority where overlapping notes are played legato (when enabled) and
+/// portamento (CC 65) glides between notes; `PolyphonicMode` switches back.
+///
+#[derive(Debug, Clone)]
+pub struct VoiceAllocator {
+    voices: List<Voice, MAX_VOICES>,
+    steal_mode: StealMode,
+    same_note_retrigger: bool,
+    legato: bool,
+    mono: bool,
+    portamento: [bool; 16],
+    mono_stack: List<(Channel, u7, u7), MAX_MONO_KEYS>, // Held notes in mono mode
+    counter: u64,
+}
+
+impl VoiceAllocator {
+    pub fn new(voices: usize) -> Self {
+        let mut idle = List::with_filler(Voice::IDLE);
+        for _ in 0..voices.clamp(1, MAX_VOICES) {
+            idle.push(Voice::IDLE);
+        }
+        Self {
+            voices: idle,
+            steal_mode: StealMode::Oldest,
+            same_note_retrigger: true,
+            legato: true,
+            mono: false,
+            portamento: [false; 16],
+            mono_stack: List::default(),
+            counter: 0,
+        }
+    }
+
+    pub fn with_steal_mode(mut self, steal_mode: StealMode) -> Self {
+        self.steal_mode = steal_mode;
+        self
+    }
+
+    pub fn with_same_note_retrigger(mut self, enabled: bool) -> Self {
+        self.same_note_retrigger = enabled;
+        self
+    }
+
+    ///
+    /// Legato in mono mode; overlapping notes change pitch without a retrigger
+    ///
+    pub fn with_legato(mut self, legato: bool) -> Self {
+        self.legato = legato;
+        self
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono
+    }
+
+    pub fn voices(&self) -> &[Voice] {
+        &self.voices
+    }
+
+    ///
+    /// **Handle an event**
+    ///
+    pub fn handle_event(&mut self, event: &MidiEvent) -> VoiceEvents {
+        let mut events = List::with_filler(VoiceEvent::Stop { voice: 0 });
+        match *event {
+            MidiEvent::NoteOn(channel, key, 0) | MidiEvent::NoteOff(channel, key, _) => {
+                if self.mono {
+                    self.mono_note_off(channel, key, &mut events)
+                } else {
+                    self.note_off(channel, key, &mut events)
+                }
+            }
+            MidiEvent::NoteOn(channel, key, velocity) => {
+                if self.mono {
+                    self.mono_note_on(channel, key, velocity, &mut events)
+                } else {
+                    self.note_on(channel, key, velocity, &mut events)
+                }
+            }
+            MidiEvent::ControllerChange(channel, controller::PORTAMENTO, value) => {
+                self.portamento[channel as usize & 0x0F] = value >= 64
+            }
+            MidiEvent::ResetAllControllers(channel) => {
+                self.portamento[channel as usize & 0x0F] = false
+            }
+            MidiEvent::AllNotesOff(channel) => self.release_channel(channel, &mut events),
+            MidiEvent::AllSoundOff(channel) => self.stop_channel(channel, &mut events),
+            MidiEvent::MonoMode(_, _) | MidiEvent::PolyphonicMode(_) => {
+                // Notes held on any channel are released before switching
+                for channel in 0..16 {
+                    self.release_channel(channel, &mut events);
+                }
+                self.mono_stack.clear();
+                self.mono = matches!(event, MidiEvent::MonoMode(_, _));
+            }
+            MidiEvent::OmniMode(channel, _) => self.release_channel(channel, &mut events),
+            MidiEvent::SystemReset => {
+                for channel in 0..16 {
+                    self.stop_channel(channel, &mut events);
+                }
+                self.mono = false;
+                self.portamento = [false; 16];
+            }
+            _ => (),
+        }
+        events
+    }
+
+    fn note_on(&mut self, channel: Channel, key: u7, velocity: u7, events: &mut VoiceEvents) {
+        let index = match self.find_voice(channel, key) {
+            Some(index) => index,
+            None => return,
+        };
+        let voice = &self.voices[index];
+        if voice.state == VoiceState::Held && !(voice.channel == channel && voice.key == key) {
+            events.push(VoiceEvent::Stop { voice: index });
+        }
+        let glide_from = match (self.portamento[channel as usize & 0x0F], voice.state) {
+            (true, VoiceState::Idle) | (false, _) => None,
+            (true, _) => Some(voice.key),
+        };
+        self.start(index, channel, key, velocity);
+        events.push(VoiceEvent::Start {
+            voice: index,
+            channel,
+            key,
+            velocity,
+            glide_from,
+        });
+    }
+
+    fn note_off(&mut self, channel: Channel, key: u7, events: &mut VoiceEvents) {
+        self.counter += 1;
+        let counter = self.counter;
+        for (index, voice) in self.voices.iter_mut().enumerate() {
+            if voice.is_held() && voice.channel == channel && voice.key == key {
+                voice.state = VoiceState::Released;
+                voice.changed = counter;
+                events.push(VoiceEvent::Release { voice: index });
+            }
+        }
+    }
+
+    fn mono_note_on(&mut self, channel: Channel, key: u7, velocity: u7, events: &mut VoiceEvents) {
+        self.mono_stack
+            .retain(|(held_channel, held, _)| !(*held_channel == channel && *held == key));
+        if self.mono_stack.is_full() {
+            self.mono_stack.remove(0);
+        }
+        self.mono_stack.push((channel, key, velocity));
+
+        let voice = self.voices[0];
+        let glide_from = match voice.state {
+            VoiceState::Idle => None,
+            _ => Some(voice.key).filter(|_| self.portamento[channel as usize & 0x0F]),
+        };
+        if voice.is_held() && self.legato {
+            self.voices[0].channel = channel;
+            self.voices[0].key = key;
+            events.push(VoiceEvent::Legato {
+                voice: 0,
+                key,
+                glide_from,
+            });
+        } else {
+            self.start(0, channel, key, velocity);
+            events.push(VoiceEvent::Start {
+                voice: 0,
+                channel,
+                key,
+                velocity,
+                glide_from,
+            });
+        }
+    }
+
+    fn mono_note_off(&mut self, channel: Channel, key: u7, events: &mut VoiceEvents) {
+        self.mono_stack
+            .retain(|(held_channel, held, _)| !(*held_channel == channel && *held == key));
+        let voice = self.voices[0];
+        if !voice.is_held() || voice.channel != channel || voice.key != key {
+            return;
+        }
+        match self.mono_stack.last() {
+            // Return to the previous held note
+            Some(&(previous_channel, previous, velocity)) => {
+                // Glides with the portamento of the released channel
+                let glide_from = Some(key).filter(|_| self.portamento[channel as usize & 0x0F]);
+                if self.legato {
+                    self.voices[0].channel = previous_channel;
+                    self.voices[0].key = previous;
+                    events.push(VoiceEvent::Legato {
+                        voice: 0,
+                        key: previous,
+                        glide_from,
+                    });
+                } else {
+                    self.start(0, previous_channel, previous, velocity);
+                    events.push(VoiceEvent::Start {
+                        voice: 0,
+                        channel: previous_channel,
+                        key: previous,
+                        velocity,
+                        glide_from,
+                    });
+                }
+            }
+            None => self.note_off(channel, key, events),
+        }
+    }
+
+    fn find_voice(&self, channel: Channel, key: u7) -> Option<usize> {
+        let candidates = self.voices.iter().enumerate();
+        if self.same_note_retrigger {
+            let same = candidates
+                .clone()
+                .find(|(_, v)| v.state != VoiceState::Idle && v.channel == channel && v.key == key);
+            if let Some((index, _)) = same {
+                return Some(index);
+            }
+        }
+        if let Some((index, _)) = candidates
+            .clone()
+            .filter(|(_, v)| v.is_free())
+            .min_by_key(|(_, v)| (v.state != VoiceState::Idle, v.changed))
+        {
+            return Some(index);
+        }
+        match self.steal_mode {
+            StealMode::None => None,
+            StealMode::Oldest => candidates.min_by_key(|(_, v)| v.changed).map(|(i, _)| i),
+            StealMode::Quietest => candidates
+                .min_by_key(|(_, v)| (v.velocity, v.changed))
+                .map(|(i, _)| i),
+        }
+    }
+
+    fn start(&mut self, index: usize, channel: Channel, key: u7, velocity: u7) {
+        self.counter += 1;
+        self.voices[index] = Voice {
+            channel,
+            key,
+            velocity,
+            state: VoiceState::Held,
+            changed: self.counter,
+        };
+    }
+
+    fn release_channel(&mut self, channel: Channel, events: &mut VoiceEvents) {
+        self.mono_stack.retain(|(held, _, _)| *held != channel);
+        for index in 0..self.voices.len() {
+            let voice = self.voices[index];
+            if voice.is_held() && voice.channel == channel {
+                self.note_off(channel, voice.key, events);
+            }
+        }
+    }
+
+    fn stop_channel(&mut self, channel: Channel, events: &mut VoiceEvents) {
+        self.mono_stack.retain(|(held, _, _)| *held != channel);
+        for (index, voice) in self.voices.iter_mut().enumerate() {
+            if voice.state != VoiceState::Idle && voice.channel == channel {
+                voice.state = VoiceState::Idle;
+                events.push(VoiceEvent::Stop { voice: index });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::voice::{StealMode, VoiceAllocator, VoiceEvent};
+    use crate::MidiEvent;
+
+    fn start(voice: usize, key: u8, velocity: u8) -> VoiceEvent {
+        VoiceEvent::Start {
+            voice,
+            channel: 0,
+            key,
+            velocity,
+            glide_from: None,
+        }
+    }
+
+    #[test]
+    fn handle_event__allocates_free_voices() {
+        let mut target = VoiceAllocator::new(2);
+
+        assert_eq!(
+            *target.handle_event(&MidiEvent::NoteOn(0, 60, 100)),
+            [start(0, 60, 100)]
+        );
+        assert_eq!(
+            *target.handle_event(&MidiEvent::NoteOn(0, 64, 100)),
+            [start(1, 64, 100)]
+        );
+        assert_eq!(
+            *target.handle_event(&MidiEvent::NoteOn(0, 60, 0)),
+            [VoiceEvent::Release { voice: 0 }]
+        );
+    }
+
+    #[test]
+    fn handle_event__reuses_longest_released_voice() {
+        let mut target = VoiceAllocator::new(3);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 64, 100));
+        target.handle_event(&MidiEvent::NoteOff(0, 62, 0));
+        target.handle_event(&MidiEvent::NoteOff(0, 60, 0));
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 67, 100));
+
+        assert_eq!(*actual, [start(1, 67, 100)]);
+    }
+
+    #[test]
+    fn handle_event__steals_oldest() {
+        let mut target = VoiceAllocator::new(2);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 100));
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 64, 100));
+
+        assert_eq!(*actual, [VoiceEvent::Stop { voice: 0 }, start(0, 64, 100)]);
+    }
+
+    #[test]
+    fn handle_event__steals_quietest() {
+        let mut target = VoiceAllocator::new(2).with_steal_mode(StealMode::Quietest);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 20));
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 64, 100));
+
+        assert_eq!(*actual, [VoiceEvent::Stop { voice: 1 }, start(1, 64, 100)]);
+    }
+
+    #[test]
+    fn handle_event__where_no_stealing() {
+        let mut target = VoiceAllocator::new(1).with_steal_mode(StealMode::None);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+
+        assert!(target
+            .handle_event(&MidiEvent::NoteOn(0, 62, 100))
+            .is_empty());
+    }
+
+    #[test]
+    fn handle_event__same_note_retrigger() {
+        let mut target = VoiceAllocator::new(4);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(0, 62, 100));
+        target.handle_event(&MidiEvent::NoteOff(0, 60, 0));
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 60, 90));
+
+        assert_eq!(*actual, [start(0, 60, 90)]);
+    }
+
+    #[test]
+    fn handle_event__mono_legato() {
+        let mut target = VoiceAllocator::new(4);
+        target.handle_event(&MidiEvent::MonoMode(0, 1));
+        target.handle_event(&MidiEvent::ControllerChange(0, 65, 127));
+        target.handle_event(&MidiEvent::ControllerChange(0, 65, 0));
+        assert!(target.is_mono());
+
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 64, 100));
+        assert_eq!(
+            *actual,
+            [VoiceEvent::Legato {
+                voice: 0,
+                key: 64,
+                glide_from: None
+            }]
+        );
+
+        let actual = target.handle_event(&MidiEvent::NoteOff(0, 64, 0));
+        assert_eq!(
+            *actual,
+            [VoiceEvent::Legato {
+                voice: 0,
+                key: 60,
+                glide_from: None
+            }]
+        );
+
+        let actual = target.handle_event(&MidiEvent::NoteOff(0, 60, 0));
+        assert_eq!(*actual, [VoiceEvent::Release { voice: 0 }]);
+    }
+
+    #[test]
+    fn handle_event__mono_same_key_on_two_channels() {
+        let mut target = VoiceAllocator::new(1);
+        target.handle_event(&MidiEvent::MonoMode(0, 1));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100));
+
+        assert!(target
+            .handle_event(&MidiEvent::NoteOff(0, 60, 0))
+            .is_empty());
+        assert!(target.voices()[0].is_held());
+
+        let actual = target.handle_event(&MidiEvent::NoteOff(1, 60, 0));
+
+        assert_eq!(*actual, [VoiceEvent::Release { voice: 0 }]);
+    }
+
+    #[test]
+    fn handle_event__mono_portamento_without_legato() {
+        let mut target = VoiceAllocator::new(1).with_legato(false);
+        target.handle_event(&MidiEvent::MonoMode(0, 1));
+        target.handle_event(&MidiEvent::ControllerChange(0, 65, 127));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+
+        let actual = target.handle_event(&MidiEvent::NoteOn(0, 67, 100));
+
+        assert_eq!(
+            *actual,
+            [VoiceEvent::Start {
+                voice: 0,
+                channel: 0,
+                key: 67,
+                velocity: 100,
+                glide_from: Some(60)
+            }]
+        );
+    }
+
+    #[test]
+    fn handle_event__where_all_notes_off_and_all_sound_off() {
+        let mut target = VoiceAllocator::new(2);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(1, 60, 100));
+
+        assert_eq!(
+            *target.handle_event(&MidiEvent::AllNotesOff(0)),
+            [VoiceEvent::Release { voice: 0 }]
+        );
+        assert_eq!(
+            *target.handle_event(&MidiEvent::AllSoundOff(1)),
+            [VoiceEvent::Stop { voice: 1 }]
+        );
+    }
+
+    #[test]
+    fn handle_event__mono_mode_releases_all_channels() {
+        let mut target = VoiceAllocator::new(4);
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(3, 64, 100));
+        target.handle_event(&MidiEvent::NoteOn(5, 67, 100));
+
+        let actual = target.handle_event(&MidiEvent::MonoMode(0, 1));
+
+        assert_eq!(
+            *actual,
+            [
+                VoiceEvent::Release { voice: 0 },
+                VoiceEvent::Release { voice: 1 },
+                VoiceEvent::Release { voice: 2 },
+            ]
+        );
+        assert!(target.voices().iter().all(|voice| !voice.is_held()));
+    }
+
+    #[test]
+    fn handle_event__polyphonic_mode_restores_poly() {
+        let mut target = VoiceAllocator::new(2);
+        target.handle_event(&MidiEvent::MonoMode(0, 1));
+        target.handle_event(&MidiEvent::NoteOn(0, 60, 100));
+
+        let actual = target.handle_event(&MidiEvent::PolyphonicMode(0));
+
+        assert_eq!(*actual, [VoiceEvent::Release { voice: 0 }]);
+        assert!(!target.is_mono());
+    }
+
+    #[test]
+    fn handle_event__polyphonic_mode_releases_all_channels() {
+        let mut target = VoiceAllocator::new(2);
+        target.handle_event(&MidiEvent::MonoMode(0, 1));
+        target.handle_event(&MidiEvent::NoteOn(2, 60, 100));
+        target.handle_event(&MidiEvent::NoteOn(2, 62, 100));
+
+        let actual = target.handle_event(&MidiEvent::PolyphonicMode(0));
+
+        assert_eq!(*actual, [VoiceEvent::Release { voice: 0 }]);
+        assert!(target.voices().iter().all(|voice| !voice.is_held()));
+    }
+}
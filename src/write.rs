@@ -0,0 +1,293 @@
+use crate::read::{
+    CHANNEL_MASK, CHANNEL_MODE_ALL_NOTES_OFF, CHANNEL_MODE_ALL_SOUND_OFF,
+    CHANNEL_MODE_LOCAL_CONTROL, CHANNEL_MODE_MONO_ON, CHANNEL_MODE_OMNI_OFF, CHANNEL_MODE_OMNI_ON,
+    CHANNEL_MODE_POLYPHONIC_ON, CHANNEL_MODE_RESET_ALL, MESSAGE_ACTIVE_SENSING,
+    MESSAGE_CHANNEL_AFTER_TOUCH, MESSAGE_CLOCK, MESSAGE_CONTINUE, MESSAGE_CONTROLLER_CHANGE,
+    MESSAGE_MTC_QUARTER_FRAME, MESSAGE_NOTE_OFF, MESSAGE_NOTE_ON, MESSAGE_PITCH_BEND,
+    MESSAGE_POLY_AFTER_TOUCH, MESSAGE_PROGRAM_CHANGE, MESSAGE_SONG_POSITION_PTR,
+    MESSAGE_SONG_SELECT, MESSAGE_START, MESSAGE_STOP, MESSAGE_SYSTEM_RESET, MESSAGE_SYS_EX_END,
+    MESSAGE_SYS_EX_START, MESSAGE_TUNE_REQUEST, STATUS_MASK, SYSTEM_RT_MASK,
+};
+use crate::{pitch_bend, u7, Channel, MidiEvent, SysExID};
+use core::ops::Deref;
+
+///
+/// **Encoded bytes of a single event**
+///
+/// At most 4 bytes (a System Exclusive Start with a word ID).
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EventBytes {
+    bytes: [u8; 4],
+    len: usize,
+}
+
+impl EventBytes {
+    fn new(bytes: &[u8]) -> Self {
+        let mut result = Self {
+            bytes: [0; 4],
+            len: bytes.len(),
+        };
+        result.bytes[..bytes.len()].copy_from_slice(bytes);
+        result
+    }
+
+    ///
+    /// Status byte of the event (if not removed by running status)
+    ///
+    pub fn status(&self) -> Option<u8> {
+        self.first().copied().filter(|byte| byte & STATUS_MASK > 0)
+    }
+}
+
+impl Deref for EventBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for EventBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+fn channel_status(message: u8, channel: Channel) -> u8 {
+    message | (channel & CHANNEL_MASK)
+}
+
+impl MidiEvent {
+    ///
+    /// **Encode the event as MIDI bytes**
+    ///
+    /// Always includes the status byte, see [`MidiWriter`] for running status.
+    ///
+    pub fn to_bytes(&self) -> EventBytes {
+        let mode = |channel: Channel, control: u7, value: u7| {
+            EventBytes::new(&[
+                channel_status(MESSAGE_CONTROLLER_CHANGE, channel),
+                control,
+                value & 0x7F,
+            ])
+        };
+
+        match *self {
+            MidiEvent::NoteOff(channel, key, velocity) => EventBytes::new(&[
+                channel_status(MESSAGE_NOTE_OFF, channel),
+                key & 0x7F,
+                velocity & 0x7F,
+            ]),
+            MidiEvent::NoteOn(channel, key, velocity) => EventBytes::new(&[
+                channel_status(MESSAGE_NOTE_ON, channel),
+                key & 0x7F,
+                velocity & 0x7F,
+            ]),
+            MidiEvent::PolyphonicAfterTouch(channel, key, pressure) => EventBytes::new(&[
+                channel_status(MESSAGE_POLY_AFTER_TOUCH, channel),
+                key & 0x7F,
+                pressure & 0x7F,
+            ]),
+            MidiEvent::ControllerChange(channel, control, value) => {
+                mode(channel, control & 0x7F, value)
+            }
+            MidiEvent::AllSoundOff(channel) => mode(channel, CHANNEL_MODE_ALL_SOUND_OFF, 0),
+            MidiEvent::ResetAllControllers(channel) => mode(channel, CHANNEL_MODE_RESET_ALL, 0),
+            MidiEvent::LocalControl(channel, on) => mode(
+                channel,
+                CHANNEL_MODE_LOCAL_CONTROL,
+                if on { 127 } else { 0 },
+            ),
+            MidiEvent::AllNotesOff(channel) => mode(channel, CHANNEL_MODE_ALL_NOTES_OFF, 0),
+            MidiEvent::OmniMode(channel, true) => mode(channel, CHANNEL_MODE_OMNI_ON, 0),
+            MidiEvent::OmniMode(channel, false) => mode(channel, CHANNEL_MODE_OMNI_OFF, 0),
+            MidiEvent::MonoMode(channel, count) => mode(channel, CHANNEL_MODE_MONO_ON, count),
+            MidiEvent::PolyphonicMode(channel) => mode(channel, CHANNEL_MODE_POLYPHONIC_ON, 0),
+            MidiEvent::ProgramChange(channel, program) => EventBytes::new(&[
+                channel_status(MESSAGE_PROGRAM_CHANGE, channel),
+                program & 0x7F,
+            ]),
+            MidiEvent::ChannelAfterTouch(channel, pressure) => EventBytes::new(&[
+                channel_status(MESSAGE_CHANNEL_AFTER_TOUCH, channel),
+                pressure & 0x7F,
+            ]),
+            MidiEvent::PitchBend(channel, amount) => {
+                let (lsb, msb) = pitch_bend::encode(amount);
+                EventBytes::new(&[channel_status(MESSAGE_PITCH_BEND, channel), lsb, msb])
+            }
+            MidiEvent::MTCQuarterFrame(kind, value) => EventBytes::new(&[
+                MESSAGE_MTC_QUARTER_FRAME,
+                ((kind & 0x07) << 4) | (value & 0x0F),
+            ]),
+            MidiEvent::SongPositionPointer(position) => EventBytes::new(&[
+                MESSAGE_SONG_POSITION_PTR,
+                position as u7 & 0x7F,
+                (position >> 7) as u7 & 0x7F,
+            ]),
+            MidiEvent::SongSelect(song) => EventBytes::new(&[MESSAGE_SONG_SELECT, song & 0x7F]),
+            MidiEvent::TuneRequest => EventBytes::new(&[MESSAGE_TUNE_REQUEST]),
+            MidiEvent::Clock => EventBytes::new(&[MESSAGE_CLOCK]),
+            MidiEvent::Start => EventBytes::new(&[MESSAGE_START]),
+            MidiEvent::Continue => EventBytes::new(&[MESSAGE_CONTINUE]),
+            MidiEvent::Stop => EventBytes::new(&[MESSAGE_STOP]),
+            MidiEvent::ActiveSensing => EventBytes::new(&[MESSAGE_ACTIVE_SENSING]),
+            MidiEvent::SystemReset => EventBytes::new(&[MESSAGE_SYSTEM_RESET]),
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(id)) => {
+                EventBytes::new(&[MESSAGE_SYS_EX_START, id & 0x7F])
+            }
+            MidiEvent::SystemExclusiveStart(SysExID::Word(id)) => EventBytes::new(&[
+                MESSAGE_SYS_EX_START,
+                0,
+                (id >> 7) as u7 & 0x7F,
+                id as u7 & 0x7F,
+            ]),
+            MidiEvent::SystemExclusiveData(byte) => EventBytes::new(&[byte & 0x7F]),
+            MidiEvent::SystemExclusiveEnd => EventBytes::new(&[MESSAGE_SYS_EX_END]),
+        }
+    }
+}
+
+///
+/// **Midi Writer**
+///
+/// Encodes events into an outbound MIDI byte stream. With running status
+/// enabled the status byte is left out when it matches the previous channel
+/// message; System Common and System Exclusive messages cancel running status
+/// while System Realtime messages do not.
+///
+#[derive(Debug, Clone)]
+pub struct MidiWriter {
+    running_status: bool,
+    status: Option<u8>, // Current running status
+}
+
+impl Default for MidiWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiWriter {
+    pub fn new() -> Self {
+        Self {
+            running_status: false,
+            status: None,
+        }
+    }
+
+    ///
+    /// Enable running status
+    ///
+    pub fn with_running_status(mut self) -> Self {
+        self.running_status = true;
+        self
+    }
+
+    ///
+    /// **Forget the running status**
+    ///
+    /// The next channel message is sent with a status byte. Use when the
+    /// output has been interrupted or to refresh receivers periodically.
+    ///
+    pub fn reset(&mut self) {
+        self.status = None;
+    }
+
+    ///
+    /// **Encode an event**
+    ///
+    pub fn write(&mut self, event: &MidiEvent) -> EventBytes {
+        let bytes = event.to_bytes();
+        if let MidiEvent::SystemExclusiveData(_) = event {
+            return bytes;
+        }
+        let status = bytes[0];
+
+        if status & SYSTEM_RT_MASK == SYSTEM_RT_MASK {
+            bytes
+        } else if status < 0xF0 {
+            if self.running_status && self.status == Some(status) {
+                EventBytes::new(&bytes[1..])
+            } else {
+                self.status = Some(status);
+                bytes
+            }
+        } else {
+            self.status = None;
+            bytes
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod tests {
+    use crate::{MidiEvent, MidiReader, MidiWriter, SysExID};
+    use parameterized::parameterized;
+
+    #[parameterized(
+        event = {
+            MidiEvent::NoteOff(2, 64, 127),
+            MidiEvent::NoteOn(15, 1, 2),
+            MidiEvent::PolyphonicAfterTouch(3, 4, 5),
+            MidiEvent::ControllerChange(1, 7, 100),
+            MidiEvent::AllSoundOff(3),
+            MidiEvent::ResetAllControllers(4),
+            MidiEvent::LocalControl(5, true),
+            MidiEvent::AllNotesOff(6),
+            MidiEvent::OmniMode(7, false),
+            MidiEvent::MonoMode(8, 4),
+            MidiEvent::PolyphonicMode(9),
+            MidiEvent::ProgramChange(10, 99),
+            MidiEvent::ChannelAfterTouch(11, 12),
+            MidiEvent::PitchBend(0, -8192),
+            MidiEvent::PitchBend(0, 8191),
+            MidiEvent::MTCQuarterFrame(7, 3),
+            MidiEvent::SongPositionPointer(0x1234),
+            MidiEvent::SongSelect(3),
+            MidiEvent::TuneRequest,
+            MidiEvent::Clock,
+            MidiEvent::SystemReset,
+            MidiEvent::SystemExclusiveStart(SysExID::Byte(0x43)),
+            MidiEvent::SystemExclusiveStart(SysExID::Word(0x0221)),
+        }
+    )]
+    fn to_bytes__round_trips(event: MidiEvent) {
+        let mut reader = MidiReader::new();
+
+        let actual: Vec<MidiEvent> = event
+            .to_bytes()
+            .iter()
+            .filter_map(|byte| reader.handle_byte(*byte))
+            .collect();
+
+        assert_eq!(actual, vec![event]);
+    }
+
+    #[test]
+    fn write__with_running_status() {
+        let mut target = MidiWriter::new().with_running_status();
+
+        assert_eq!(
+            &*target.write(&MidiEvent::NoteOn(0, 60, 100)),
+            &[0x90, 60, 100]
+        );
+        assert_eq!(&*target.write(&MidiEvent::NoteOn(0, 62, 100)), &[62, 100]);
+        assert_eq!(&*target.write(&MidiEvent::Clock), &[0xF8]);
+        assert_eq!(&*target.write(&MidiEvent::NoteOn(0, 60, 0)), &[60, 0]);
+        assert_eq!(&*target.write(&MidiEvent::NoteOn(1, 60, 0)), &[0x91, 60, 0]);
+        assert_eq!(&*target.write(&MidiEvent::TuneRequest), &[0xF6]);
+        assert_eq!(&*target.write(&MidiEvent::NoteOn(1, 61, 0)), &[0x91, 61, 0]);
+    }
+
+    #[test]
+    fn write__without_running_status() {
+        let mut target = MidiWriter::new();
+
+        target.write(&MidiEvent::NoteOn(0, 60, 100));
+        let actual = target.write(&MidiEvent::NoteOn(0, 62, 100));
+
+        assert_eq!(actual.status(), Some(0x90));
+    }
+}